@@ -1,25 +1,82 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 
+use binance::websocket::futures::*;
 use binance::websocket::spot::*;
 
 use core::time::Duration;
+use std::fs;
+
+const SPOT_FIXTURES: &[&str] = &[
+    "agg_trade",
+    "trade",
+    "day_ticker",
+    "day_ticker_all",
+    "kline",
+    "depth_update",
+    "partial_depth",
+    "book_ticker",
+    "execution_report",
+    "account_position",
+    "balance_update",
+    "mini_ticker",
+    "mini_ticker_all",
+    "rolling_ticker",
+    "rolling_ticker_all",
+    "avg_price",
+];
+
+const FUTURES_FIXTURES: &[&str] = &[
+    "agg_trade",
+    "mini_ticker",
+    "mini_ticker_all",
+    "book_ticker",
+    "mark_price",
+    "mark_price_all",
+    "index_price",
+    "kline",
+    "continuous_kline",
+    "index_kline",
+    "liquidation",
+    "depth_update",
+    "account_update",
+    "order_trade_update",
+    "listen_key_expired",
+    "account_config_update",
+    "margin_call",
+    "strategy_update",
+    "grid_update",
+    "conditional_order_trigger_reject",
+    "composite_index",
+    "contract_info",
+    "asset_index",
+    "asset_index_all",
+];
+
+fn fixture(market: &str, name: &str) -> String {
+    fs::read_to_string(format!("tests/mocks/websocket/{market}/{name}.json")).unwrap()
+}
 
 fn criterion_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("websockets-decoder");
-
-    let btc_symbol_json =
-        reqwest::blocking::get("https://api.binance.com/api/v3/ticker/price?symbol=BTCUSDT")
-            .unwrap()
-            .text()
-            .unwrap();
+    group.sample_size(200);
+    group.measurement_time(Duration::new(5, 0));
 
     let mut web_socket: WebSockets<'_> = WebSockets::new(|_event: WebsocketEvent| Ok(()));
+    for name in SPOT_FIXTURES {
+        let msg = fixture("spot", name);
+        group.bench_function(format!("spot handle_msg {name}"), |b| {
+            b.iter(|| web_socket.test_handle_msg(&msg))
+        });
+    }
 
-    group.sample_size(200);
-    group.measurement_time(Duration::new(35, 0));
-    group.bench_function("handle_msg BTCUSDT symbol", |b| {
-        b.iter(|| web_socket.test_handle_msg(&btc_symbol_json))
-    });
+    let mut web_socket: FuturesWebSockets<'_> =
+        FuturesWebSockets::new(|_event: FuturesWebsocketEvent| Ok(()));
+    for name in FUTURES_FIXTURES {
+        let msg = fixture("futures", name);
+        group.bench_function(format!("futures handle_msg {name}"), |b| {
+            b.iter(|| web_socket.test_handle_msg(&msg))
+        });
+    }
     group.finish();
 }
 
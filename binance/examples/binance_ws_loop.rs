@@ -16,7 +16,8 @@ fn main() {
         api_key_user,
         api_secret_user,
         &config,
-    );
+    )
+    .expect("Websocket interface not ready");
     loop {
         thread::yield_now();
     }
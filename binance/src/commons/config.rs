@@ -6,6 +6,9 @@ pub struct Config {
     pub futures_rest_api_endpoint: String,
     pub futures_ws_endpoint: String,
 
+    pub ws_api_endpoint: String,
+    pub futures_ws_api_endpoint: String,
+
     pub recv_window: u64,
 }
 
@@ -18,6 +21,9 @@ impl Default for Config {
             futures_rest_api_endpoint: "https://fapi.binance.com".into(),
             futures_ws_endpoint: "wss://fstream.binance.com".into(),
 
+            ws_api_endpoint: "wss://ws-api.binance.com:443/ws-api/v3".into(),
+            futures_ws_api_endpoint: "wss://ws-fapi.binance.com/ws-fapi/v1".into(),
+
             recv_window: 5000,
         }
     }
@@ -30,6 +36,8 @@ impl Config {
             .set_ws_endpoint("wss://testnet.binance.vision/ws")
             .set_futures_rest_api_endpoint("https://testnet.binancefuture.com")
             .set_futures_ws_endpoint("wss://fstream.binancefuture.com")
+            .set_ws_api_endpoint("wss://ws-api.testnet.binance.vision/ws-api/v3")
+            .set_futures_ws_api_endpoint("wss://testnet.binancefuture.com/ws-fapi/v1")
     }
 
     pub fn set_rest_api_endpoint<T: Into<String>>(mut self, rest_api_endpoint: T) -> Self {
@@ -54,6 +62,19 @@ impl Config {
         self
     }
 
+    pub fn set_ws_api_endpoint<T: Into<String>>(mut self, ws_api_endpoint: T) -> Self {
+        self.ws_api_endpoint = ws_api_endpoint.into();
+        self
+    }
+
+    pub fn set_futures_ws_api_endpoint<T: Into<String>>(
+        mut self,
+        futures_ws_api_endpoint: T,
+    ) -> Self {
+        self.futures_ws_api_endpoint = futures_ws_api_endpoint.into();
+        self
+    }
+
     pub fn set_recv_window(mut self, recv_window: u64) -> Self {
         self.recv_window = recv_window;
         self
@@ -58,6 +58,12 @@ pub enum BinanceError {
     #[error("Symbol not found")]
     SymbolNotFound,
 
+    #[error("Timeout: {0}")]
+    Timeout(String),
+
+    #[error("Invalid config: {0}")]
+    InvalidConfig(String),
+
     #[error("Util error: {0}")]
     Util(#[from] UtilError),
 
@@ -93,6 +99,9 @@ pub enum WebSocketError {
 
     #[error("Loop closed")]
     LoopClosed,
+
+    #[error("Event channel full ({0} events)")]
+    ChannelFull(usize),
 }
 
 #[derive(Error, Debug)]
@@ -1,3 +1,4 @@
 pub mod config;
 pub mod errors;
 pub mod util;
+pub(crate) mod workers;
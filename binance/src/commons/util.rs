@@ -1,5 +1,8 @@
 use crate::commons::errors::UtilError;
+use hex::encode as hex_encode;
+use hmac::{Hmac, Mac};
 use serde_json::Value;
+use sha2::Sha256;
 use std::collections::BTreeMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -20,17 +23,44 @@ pub fn build_signed_request(
 }
 
 pub fn build_signed_request_custom(
-    mut parameters: BTreeMap<String, String>,
+    parameters: BTreeMap<String, String>,
     recv_window: u64,
     start: SystemTime,
 ) -> Result<String, UtilError> {
+    Ok(build_request(build_signed_parameters_custom(
+        parameters,
+        recv_window,
+        start,
+    )?))
+}
+
+/// Adds `recvWindow` and `timestamp` to the parameters of a signed request
+pub fn build_signed_parameters(
+    parameters: BTreeMap<String, String>,
+    recv_window: u64,
+) -> Result<BTreeMap<String, String>, UtilError> {
+    build_signed_parameters_custom(parameters, recv_window, SystemTime::now())
+}
+
+pub fn build_signed_parameters_custom(
+    mut parameters: BTreeMap<String, String>,
+    recv_window: u64,
+    start: SystemTime,
+) -> Result<BTreeMap<String, String>, UtilError> {
     if recv_window > 0 {
         parameters.insert("recvWindow".to_string(), recv_window.to_string());
     }
 
     let timestamp = get_timestamp(start)?;
     parameters.insert("timestamp".to_string(), timestamp.to_string());
-    Ok(build_request(parameters))
+    Ok(parameters)
+}
+
+/// HMAC SHA256 signature of `payload`, hex encoded
+pub fn hmac_signature(secret_key: &str, payload: &str) -> String {
+    let mut signed_key = Hmac::<Sha256>::new_from_slice(secret_key.as_bytes()).unwrap();
+    signed_key.update(payload.as_bytes());
+    hex_encode(signed_key.finalize().into_bytes())
 }
 
 pub fn to_i64(v: &Value) -> Result<i64, UtilError> {
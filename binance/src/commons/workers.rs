@@ -0,0 +1,52 @@
+use crate::websocket::managed;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Background threads of an interface, stopped together by `shutdown`
+#[derive(Clone)]
+pub(crate) struct Workers {
+    running: Arc<AtomicBool>,
+    handles: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl Default for Workers {
+    fn default() -> Self {
+        Workers {
+            running: Arc::new(AtomicBool::new(true)),
+            handles: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl Workers {
+    /// Spawns `f` with the flag it must poll, unset on shutdown
+    pub(crate) fn spawn<F>(&self, f: F)
+    where
+        F: FnOnce(&AtomicBool) + Send + 'static,
+    {
+        let running = self.running.clone();
+        let handle = thread::spawn(move || f(&running));
+        let mut handles = self.handles.lock().unwrap();
+        handles.retain(|handle| !handle.is_finished());
+        handles.push(handle);
+    }
+
+    /// Stops the threads and waits for them, except the calling one
+    pub(crate) fn shutdown(&self) {
+        self.running.store(false, Ordering::Relaxed);
+        let handles: Vec<JoinHandle<()>> = self.handles.lock().unwrap().drain(..).collect();
+        for handle in handles {
+            if handle.thread().id() != thread::current().id() {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+/// Sleeps for `duration` or until `running` is unset, returns false once it is
+pub(crate) fn sleep(running: &AtomicBool, duration: Duration) -> bool {
+    managed::sleep(running, duration);
+    running.load(Ordering::Relaxed)
+}
@@ -0,0 +1,586 @@
+use crate::commons::errors::*;
+use crate::interfaces::usdm::UsdmInterface;
+use crate::rest::futures::account::{CustomOrderRequest, OrderType, PositionSide};
+use crate::rest::spot::account::{OrderSide, TimeInForce};
+use log::{debug, error};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: u64 = 50; // milliseconds
+
+// a child order without a final websocket update is checked over REST
+const STATUS_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+// a rejected post only child is placed again after a delay doubling up to this
+const MAX_REJECT_BACKOFF: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecutionState {
+    Running,
+    Completed,
+    /// The duration passed with `remaining_qty` not filled
+    Expired {
+        remaining_qty: f64,
+    },
+    Canceled,
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ExecutionProgress {
+    pub target_qty: f64,
+    pub filled_qty: f64,
+    pub avg_price: f64,
+    pub child_orders: u32,
+    pub working_order: Option<u64>,
+    pub state: ExecutionState,
+}
+
+impl ExecutionProgress {
+    fn new(target_qty: f64) -> ExecutionProgress {
+        ExecutionProgress {
+            target_qty,
+            filled_qty: 0.0,
+            avg_price: 0.0,
+            child_orders: 0,
+            working_order: None,
+            state: ExecutionState::Running,
+        }
+    }
+
+    pub fn remaining_qty(&self) -> f64 {
+        (self.target_qty - self.filled_qty).max(0.0)
+    }
+
+    fn add_fill(&mut self, qty: f64, price: f64) {
+        if qty <= 0.0 {
+            return;
+        }
+        let notional = self.avg_price * self.filled_qty + price * qty;
+        self.filled_qty += qty;
+        self.avg_price = notional / self.filled_qty;
+    }
+}
+
+/// Handle of a running execution algorithm
+pub struct ExecutionHandle {
+    progress: Arc<RwLock<ExecutionProgress>>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ExecutionHandle {
+    /// Get progress of the algorithm
+    pub fn get_progress(&self) -> ExecutionProgress {
+        self.progress.read().unwrap().clone()
+    }
+
+    /// Returns true while the algorithm is working the order
+    pub fn is_running(&self) -> bool {
+        self.progress.read().unwrap().state == ExecutionState::Running
+    }
+
+    /// Cancels the working child order and stops the algorithm
+    pub fn cancel(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+
+    /// Blocks until the algorithm is finished
+    pub fn wait(mut self) -> ExecutionProgress {
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("Execution thread panicked");
+            }
+        }
+        self.get_progress()
+    }
+}
+
+#[derive(Clone)]
+pub struct TwapConfig {
+    pub slices: u32,
+    pub duration: u64,
+    pub reprice_interval: u64,
+    pub limit_price: Option<f64>,
+}
+
+impl TwapConfig {
+    /// * `slices` - number of child slices
+    /// * `duration` - milliseconds over which the parent order is worked
+    pub fn new(slices: u32, duration: u64) -> TwapConfig {
+        TwapConfig {
+            slices,
+            duration,
+            reprice_interval: 1000, // milliseconds
+            limit_price: None,
+        }
+    }
+
+    pub fn set_reprice_interval(mut self, reprice_interval: u64) -> Self {
+        self.reprice_interval = reprice_interval;
+        self
+    }
+
+    pub fn set_limit_price(mut self, limit_price: f64) -> Self {
+        self.limit_price = Some(limit_price);
+        self
+    }
+}
+
+#[derive(Clone)]
+pub struct IcebergConfig {
+    pub price: f64,
+    pub display_qty: f64,
+    pub duration: Option<u64>,
+}
+
+impl IcebergConfig {
+    /// * `price` - limit price of every child order
+    /// * `display_qty` - quantity shown on the book at once
+    pub fn new(price: f64, display_qty: f64) -> IcebergConfig {
+        IcebergConfig {
+            price,
+            display_qty,
+            duration: None,
+        }
+    }
+
+    /// Milliseconds after which the working child order is canceled and the algorithm stops,
+    /// the order is worked until filled or canceled otherwise
+    pub fn set_duration(mut self, duration: u64) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+}
+
+#[derive(Clone)]
+pub struct ChaseConfig {
+    pub reprice_interval: u64,
+    pub limit_price: Option<f64>,
+}
+
+impl Default for ChaseConfig {
+    fn default() -> ChaseConfig {
+        ChaseConfig {
+            reprice_interval: 1000, // milliseconds
+            limit_price: None,
+        }
+    }
+}
+
+impl ChaseConfig {
+    pub fn set_reprice_interval(mut self, reprice_interval: u64) -> Self {
+        self.reprice_interval = reprice_interval;
+        self
+    }
+
+    /// Worst price the order is chased to
+    pub fn set_limit_price(mut self, limit_price: f64) -> Self {
+        self.limit_price = Some(limit_price);
+        self
+    }
+}
+
+/// Client side execution algorithms built on top of `UsdmInterface`.
+/// Child orders are post only, so the parent order never crosses the book
+#[derive(Clone)]
+pub struct Execution {
+    usdm: UsdmInterface,
+}
+
+impl Execution {
+    pub fn new(usdm: UsdmInterface) -> Execution {
+        Execution { usdm }
+    }
+
+    /// Splits `qty` in equal slices worked at the top of the book over `config.duration`,
+    /// quantity not filled in a slice is carried to the next one and what is left after the
+    /// last one is reported by `ExecutionState::Expired`
+    pub fn twap<S, P>(
+        &self,
+        symbol: S,
+        side: OrderSide,
+        qty: f64,
+        position_side: P,
+        config: TwapConfig,
+    ) -> Result<ExecutionHandle>
+    where
+        S: Into<String>,
+        P: Into<Option<PositionSide>>,
+    {
+        let algo = self.algo(symbol.into(), side, qty, position_side.into())?;
+        Ok(algo.spawn(move |algo| {
+            let slices = config.slices.max(1);
+            let slice_duration = config.duration / u64::from(slices);
+            let start = Instant::now();
+            for slice in 1..=slices {
+                let deadline = start + Duration::from_millis(slice_duration * u64::from(slice));
+                let target = algo.round_qty(qty * f64::from(slice) / f64::from(slices));
+                let slice_qty = target - algo.filled_qty();
+                debug!("TWAP slice {slice}/{slices} working {slice_qty}");
+                algo.work_top_of_book(
+                    slice_qty,
+                    config.limit_price,
+                    config.reprice_interval,
+                    Some(deadline),
+                )?;
+                algo.sleep_until(deadline);
+                if !algo.is_running() {
+                    break;
+                }
+            }
+            Ok(())
+        }))
+    }
+
+    /// Shows at most `config.display_qty` at `config.price`, replenishing when the child fills
+    pub fn iceberg<S, P>(
+        &self,
+        symbol: S,
+        side: OrderSide,
+        qty: f64,
+        position_side: P,
+        config: IcebergConfig,
+    ) -> Result<ExecutionHandle>
+    where
+        S: Into<String>,
+        P: Into<Option<PositionSide>>,
+    {
+        let algo = self.algo(symbol.into(), side, qty, position_side.into())?;
+        Ok(algo.spawn(move |algo| {
+            let deadline = config
+                .duration
+                .map(|duration| Instant::now() + Duration::from_millis(duration));
+            let mut backoff = Duration::from_millis(POLL_INTERVAL);
+            while algo.is_running() && deadline.is_none_or(|deadline| Instant::now() < deadline) {
+                let remaining = algo.round_qty(qty - algo.filled_qty());
+                if remaining <= 0.0 {
+                    break;
+                }
+                let display_qty = algo.round_qty(config.display_qty.min(remaining));
+                let order_id = algo.post_only(display_qty, config.price)?;
+                algo.wait_child(order_id, deadline, || false);
+                if algo.settle_child(order_id)? == 0.0 {
+                    // post only order would have crossed the book, wait for it to move away
+                    algo.wait_uncrossed(config.price, backoff, deadline);
+                    backoff = (backoff * 2).min(MAX_REJECT_BACKOFF);
+                } else {
+                    backoff = Duration::from_millis(POLL_INTERVAL);
+                }
+            }
+            Ok(())
+        }))
+    }
+
+    /// Keeps a post only order at the top of the book, re-pricing it when the book moves
+    pub fn chase<S, P>(
+        &self,
+        symbol: S,
+        side: OrderSide,
+        qty: f64,
+        position_side: P,
+        config: ChaseConfig,
+    ) -> Result<ExecutionHandle>
+    where
+        S: Into<String>,
+        P: Into<Option<PositionSide>>,
+    {
+        let algo = self.algo(symbol.into(), side, qty, position_side.into())?;
+        Ok(algo.spawn(move |algo| {
+            algo.work_top_of_book(qty, config.limit_price, config.reprice_interval, None)
+        }))
+    }
+
+    fn algo(
+        &self,
+        symbol: String,
+        side: OrderSide,
+        qty: f64,
+        position_side: Option<PositionSide>,
+    ) -> Result<Algo> {
+        let symbol_info = self.usdm.get_symbol_info(symbol.to_owned())?;
+        Ok(Algo {
+            usdm: self.usdm.to_owned(),
+            symbol,
+            side,
+            position_side,
+            qty_precision: symbol_info.quantity_precision,
+            progress: Arc::new(RwLock::new(ExecutionProgress::new(qty))),
+            running: Arc::new(AtomicBool::new(true)),
+        })
+    }
+}
+
+struct Algo {
+    usdm: UsdmInterface,
+    symbol: String,
+    side: OrderSide,
+    position_side: Option<PositionSide>,
+    qty_precision: u16,
+    progress: Arc<RwLock<ExecutionProgress>>,
+    running: Arc<AtomicBool>,
+}
+
+impl Algo {
+    fn spawn<F>(self, run: F) -> ExecutionHandle
+    where
+        F: FnOnce(&Algo) -> Result<()> + Send + 'static,
+    {
+        let progress = Arc::clone(&self.progress);
+        let running = Arc::clone(&self.running);
+        let thread = thread::spawn(move || {
+            let result = run(&self);
+            let mut progress = self.progress.write().unwrap();
+            progress.working_order = None;
+            progress.state = match result {
+                Err(err) => {
+                    error!("Execution of {} failed: {err:?}", self.symbol);
+                    ExecutionState::Failed(err.to_string())
+                }
+                Ok(()) if self.is_running() => match self.round_qty(progress.remaining_qty()) {
+                    remaining_qty if remaining_qty > 0.0 => {
+                        ExecutionState::Expired { remaining_qty }
+                    }
+                    _ => ExecutionState::Completed,
+                },
+                Ok(()) => ExecutionState::Canceled,
+            };
+        });
+        ExecutionHandle {
+            progress,
+            running,
+            thread: Some(thread),
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    fn filled_qty(&self) -> f64 {
+        self.progress.read().unwrap().filled_qty
+    }
+
+    fn round_qty(&self, qty: f64) -> f64 {
+        round_down(qty, self.qty_precision)
+    }
+
+    fn sleep_until(&self, deadline: Instant) {
+        while self.is_running() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(POLL_INTERVAL));
+        }
+    }
+
+    /// Best bid when buying, best ask when selling, capped by `limit_price`.
+    /// Read from the book ticker stream of the symbol, the depth is only requested
+    /// until the first book ticker is received
+    fn top_of_book(&self, limit_price: Option<f64>) -> Result<Option<f64>> {
+        let book_ticker = self
+            .usdm
+            .get_symbol_ws(&self.symbol)
+            .and_then(|ws_data| ws_data.get_book_ticker());
+        let top = match book_ticker {
+            Some(book_ticker) => {
+                let price = match self.side {
+                    OrderSide::Buy => book_ticker.best_bid,
+                    OrderSide::Sell => book_ticker.best_ask,
+                };
+                Some(price.parse::<f64>()?).filter(|price| *price > 0.0)
+            }
+            None => {
+                let book = self.usdm.get_custom_depth(self.symbol.to_owned(), 5)?;
+                match self.side {
+                    OrderSide::Buy => book.bids.first().map(|bid| bid.price),
+                    OrderSide::Sell => book.asks.first().map(|ask| ask.price),
+                }
+            }
+        };
+        Ok(top.filter(|price| match (self.side, limit_price) {
+            (OrderSide::Buy, Some(limit)) => *price <= limit,
+            (OrderSide::Sell, Some(limit)) => *price >= limit,
+            (_, None) => true,
+        }))
+    }
+
+    /// Whether a post only order at `price` would take the book, by the book ticker stream
+    fn crosses(&self, price: f64) -> bool {
+        let Some(book_ticker) = self
+            .usdm
+            .get_symbol_ws(&self.symbol)
+            .and_then(|ws_data| ws_data.get_book_ticker())
+        else {
+            return false;
+        };
+        match self.side {
+            OrderSide::Buy => book_ticker
+                .best_ask
+                .parse::<f64>()
+                .is_ok_and(|ask| ask > 0.0 && ask <= price),
+            OrderSide::Sell => book_ticker
+                .best_bid
+                .parse::<f64>()
+                .is_ok_and(|bid| bid >= price),
+        }
+    }
+
+    /// Sleeps `backoff`, then until a post only order at `price` no longer crosses the book
+    fn wait_uncrossed(&self, price: f64, backoff: Duration, deadline: Option<Instant>) {
+        let until = Instant::now() + backoff;
+        self.sleep_until(deadline.map_or(until, |deadline| deadline.min(until)));
+        while self.is_running()
+            && deadline.is_none_or(|deadline| Instant::now() < deadline)
+            && self.crosses(price)
+        {
+            thread::sleep(Duration::from_millis(POLL_INTERVAL));
+        }
+    }
+
+    fn post_only(&self, qty: f64, price: f64) -> Result<u64> {
+        let transaction = self.usdm.custom_order(CustomOrderRequest {
+            symbol: self.symbol.to_owned(),
+            side: self.side,
+            position_side: self.position_side,
+            order_type: OrderType::Limit,
+            time_in_force: Some(TimeInForce::GTX),
+            qty: Some(qty),
+            reduce_only: None,
+            price: Some(price),
+            stop_price: None,
+            close_position: None,
+            activation_price: None,
+            callback_rate: None,
+            working_type: None,
+            price_protect: None,
+        })?;
+        let mut progress = self.progress.write().unwrap();
+        progress.child_orders += 1;
+        progress.working_order = Some(transaction.order_id);
+        Ok(transaction.order_id)
+    }
+
+    /// Waits until the child order is done, the algorithm is canceled, `deadline` passes
+    /// or `reprice` returns true
+    fn wait_child<F>(&self, order_id: u64, deadline: Option<Instant>, mut reprice: F)
+    where
+        F: FnMut() -> bool,
+    {
+        let mut last_status_check = Instant::now();
+        loop {
+            thread::sleep(Duration::from_millis(POLL_INTERVAL));
+            if let Some(order) = self.usdm.get_order_ws(order_id) {
+                if !is_working(&order.order_status) {
+                    return;
+                }
+            }
+            // the final update may have been missed while the user stream reconnected
+            if last_status_check.elapsed() >= STATUS_CHECK_INTERVAL {
+                last_status_check = Instant::now();
+                match self.usdm.get_order(self.symbol.to_owned(), order_id) {
+                    Ok(order) if !is_working(&order.status) => return,
+                    Ok(_) => {}
+                    Err(err) => error!("Unable to get child order {order_id}: {err:?}"),
+                }
+            }
+            if !self.is_running() || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return;
+            }
+            if reprice() {
+                return;
+            }
+        }
+    }
+
+    /// Cancels the child order if still open and records its fills, returns the filled quantity
+    fn settle_child(&self, order_id: u64) -> Result<f64> {
+        if !self.usdm.is_filled_orders_ws(order_id) && !self.usdm.is_canceled_orders_ws(order_id) {
+            if let Err(err) = self.usdm.cancel_order(self.symbol.to_owned(), order_id) {
+                // the order may have been filled meanwhile
+                debug!("Unable to cancel child order {order_id}: {err:?}");
+            }
+        }
+        let order = self.usdm.get_order(self.symbol.to_owned(), order_id)?;
+        let mut progress = self.progress.write().unwrap();
+        progress.add_fill(order.executed_qty, order.avg_price);
+        progress.working_order = None;
+        Ok(order.executed_qty)
+    }
+
+    /// Works `qty` with post only orders at the top of the book until it is filled,
+    /// the algorithm is canceled or `deadline` passes
+    fn work_top_of_book(
+        &self,
+        qty: f64,
+        limit_price: Option<f64>,
+        reprice_interval: u64,
+        deadline: Option<Instant>,
+    ) -> Result<()> {
+        let target = self.filled_qty() + qty;
+        while self.is_running() && deadline.is_none_or(|deadline| Instant::now() < deadline) {
+            let remaining = self.round_qty(target - self.filled_qty());
+            if remaining <= 0.0 {
+                break;
+            }
+            let price = match self.top_of_book(limit_price)? {
+                Some(price) => price,
+                None => {
+                    // top of the book is beyond the limit price
+                    thread::sleep(Duration::from_millis(reprice_interval));
+                    continue;
+                }
+            };
+            let order_id = self.post_only(remaining, price)?;
+            let mut last_check = Instant::now();
+            self.wait_child(order_id, deadline, || {
+                if last_check.elapsed() < Duration::from_millis(reprice_interval) {
+                    return false;
+                }
+                last_check = Instant::now();
+                match self.top_of_book(limit_price) {
+                    Ok(top) => top != Some(price),
+                    Err(err) => {
+                        error!("Unable to get top of the book: {err:?}");
+                        false
+                    }
+                }
+            });
+            self.settle_child(order_id)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_working(status: &str) -> bool {
+    status == "NEW" || status == "PARTIALLY_FILLED"
+}
+
+fn round_down(qty: f64, precision: u16) -> f64 {
+    let factor = 10f64.powi(i32::from(precision));
+    // small epsilon so values like 0.3 / 0.1 are not rounded one step down
+    ((qty * factor) + 1e-9).floor() / factor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_down() {
+        assert_eq!(round_down(0.123456, 3), 0.123);
+        assert_eq!(round_down(0.1 + 0.2, 1), 0.3);
+        assert_eq!(round_down(10.0 / 3.0, 0), 3.0);
+    }
+
+    #[test]
+    fn test_progress_fills() {
+        let mut progress = ExecutionProgress::new(3.0);
+        progress.add_fill(1.0, 100.0);
+        progress.add_fill(2.0, 103.0);
+        progress.add_fill(0.0, 1000.0);
+        assert_eq!(progress.filled_qty, 3.0);
+        assert_eq!(progress.avg_price, 102.0);
+        assert_eq!(progress.remaining_qty(), 0.0);
+        assert_eq!(progress.state, ExecutionState::Running);
+    }
+}
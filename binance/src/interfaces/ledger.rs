@@ -0,0 +1,406 @@
+use crate::rest::futures::model::{Income, OrderUpdate, PositionRisk};
+use crate::rest::model::{EventBalance, EventPosition};
+use crate::websocket::futures::usdm_events::UsdmEvent;
+use indexmap::IndexMap;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+// https://binance-docs.github.io/apidocs/futures/en/#get-income-history-user_data
+const REALIZED_PNL: &str = "REALIZED_PNL";
+const COMMISSION: &str = "COMMISSION";
+const FUNDING_FEE: &str = "FUNDING_FEE";
+const TRADE: &str = "TRADE";
+// trades of the history this close to its end may still arrive from the user stream
+const SEED_OVERLAP: u64 = 60000; // milliseconds
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerPosition {
+    pub position_side: String,
+    /// Negative when short
+    pub amount: f64,
+    pub entry_price: f64,
+}
+
+/// Position and PnL of one symbol, amounts in its margin asset
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolLedger {
+    pub symbol: String,
+    /// Open positions by side, at most a BOTH one in one-way mode
+    pub positions: Vec<LedgerPosition>,
+    pub mark_price: Option<f64>,
+    pub realized_pnl: f64,
+    /// Commissions by asset, e.g. BNB with the fee discount
+    pub fees_paid: BTreeMap<String, f64>,
+    /// Negative when more funding was received than paid
+    pub funding_paid: f64,
+}
+
+impl SymbolLedger {
+    /// Sum of the positions, negative when short
+    pub fn position_amount(&self) -> f64 {
+        self.positions.iter().map(|position| position.amount).sum()
+    }
+
+    /// Unrealized PnL at the last mark price, 0 without one
+    pub fn unrealized_pnl(&self) -> f64 {
+        self.mark_price.map_or(0.0, |mark_price| {
+            self.positions
+                .iter()
+                .map(|position| position.amount * (mark_price - position.entry_price))
+                .sum()
+        })
+    }
+
+    fn set_position(&mut self, position_side: &str, amount: f64, entry_price: f64) {
+        self.positions
+            .retain(|position| position.position_side != position_side);
+        if amount != 0.0 {
+            self.positions.push(LedgerPosition {
+                position_side: position_side.to_owned(),
+                amount,
+                entry_price,
+            });
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct LedgerState {
+    seeded: bool,
+    // events received before the seed, applied after it
+    pending: Vec<UsdmEvent>,
+    symbols: IndexMap<String, SymbolLedger>,
+    unattributed_funding: f64,
+    // trades at the end of the history, counted once if the user stream reports them too
+    seeded_trades: HashSet<(String, i64)>,
+    funded_until: u64,
+}
+
+/// Positions, realized and unrealized PnL, fees and funding of USDⓈ-M trading, fed with
+/// `UsdmEvent`s. Events are held until `seed` is called, with an empty history to start
+/// from scratch. Position amounts and entry prices are the ones Binance reports
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    state: Arc<RwLock<LedgerState>>,
+}
+
+impl Ledger {
+    /// Applies a fill, a position, a mark price or a funding fee, other events are ignored
+    pub fn apply(&self, event: &UsdmEvent) {
+        let mut state = self.state.write().unwrap();
+        if state.seeded {
+            state.apply(event);
+        } else {
+            state.pending.push(event.clone());
+        }
+    }
+
+    /// Replaces the state with the realized PnL, commission and funding fee `incomes` and the
+    /// current `positions`, then applies the events received since the ledger was created
+    pub fn seed(&self, incomes: &[Income], positions: &[PositionRisk]) {
+        let mut state = self.state.write().unwrap();
+        state.symbols.clear();
+        state.unattributed_funding = 0.0;
+        state.seeded_trades.clear();
+        let last_time = incomes.iter().map(|income| income.time).max().unwrap_or(0);
+        for income in incomes {
+            let ledger = state.symbol(&income.symbol);
+            match income.income_type.as_str() {
+                REALIZED_PNL => ledger.realized_pnl += income.income,
+                COMMISSION => {
+                    *ledger.fees_paid.entry(income.asset.to_owned()).or_default() -= income.income
+                }
+                FUNDING_FEE => {
+                    ledger.funding_paid -= income.income;
+                    state.funded_until = state.funded_until.max(income.time);
+                    continue;
+                }
+                _ => continue,
+            }
+            if let Ok(trade_id) = income.trade_id.parse() {
+                if income.time + SEED_OVERLAP >= last_time {
+                    state
+                        .seeded_trades
+                        .insert((income.symbol.to_owned(), trade_id));
+                }
+            }
+        }
+        for position in positions {
+            let ledger = state.symbol(&position.symbol);
+            ledger.set_position(
+                &position.position_side,
+                position.position_amount,
+                position.entry_price,
+            );
+            if position.mark_price > 0.0 {
+                ledger.mark_price = Some(position.mark_price);
+            }
+        }
+        state.seeded = true;
+        for event in std::mem::take(&mut state.pending) {
+            state.apply(&event);
+        }
+    }
+
+    /// Holds the events until the next `seed`, e.g. while the history is fetched again
+    pub fn hold(&self) {
+        self.state.write().unwrap().seeded = false;
+    }
+
+    /// Applies the events held since `hold` to the current state, when seeding again failed
+    pub fn resume(&self) {
+        let mut state = self.state.write().unwrap();
+        state.seeded = true;
+        for event in std::mem::take(&mut state.pending) {
+            state.apply(&event);
+        }
+    }
+
+    pub fn get_symbol(&self, symbol: &str) -> Option<SymbolLedger> {
+        self.state
+            .read()
+            .unwrap()
+            .symbols
+            .get(&symbol.to_uppercase())
+            .cloned()
+    }
+
+    pub fn get_symbols(&self) -> Vec<SymbolLedger> {
+        self.state
+            .read()
+            .unwrap()
+            .symbols
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Funding paid in cross margin while several positions were open, the user stream
+    /// does not tell which one paid it. The next `seed` attributes it
+    pub fn get_unattributed_funding(&self) -> f64 {
+        self.state.read().unwrap().unattributed_funding
+    }
+}
+
+impl LedgerState {
+    fn symbol(&mut self, symbol: &str) -> &mut SymbolLedger {
+        self.symbols
+            .entry(symbol.to_owned())
+            .or_insert_with(|| SymbolLedger {
+                symbol: symbol.to_owned(),
+                ..SymbolLedger::default()
+            })
+    }
+
+    fn apply(&mut self, event: &UsdmEvent) {
+        match event {
+            UsdmEvent::Fill(order) => self.apply_fill(order),
+            UsdmEvent::Position(position) => self.apply_position(position),
+            UsdmEvent::MarkPrice(mark_price) => {
+                if let Ok(price) = mark_price.price.parse() {
+                    self.symbol(&mark_price.pair).mark_price = Some(price);
+                }
+            }
+            UsdmEvent::FundingFee {
+                symbol,
+                balance,
+                event_time,
+            } => self.apply_funding_fee(symbol.as_deref(), balance, *event_time),
+            _ => {}
+        }
+    }
+
+    fn apply_fill(&mut self, order: &OrderUpdate) {
+        if order.execution_type != TRADE
+            || self
+                .seeded_trades
+                .remove(&(order.symbol.to_owned(), order.trade_id))
+        {
+            return;
+        }
+        let ledger = self.symbol(&order.symbol);
+        ledger.realized_pnl += parse(&order.realized_profit);
+        if let (Some(asset), Some(commission)) = (&order.asset_commisioned, &order.commission) {
+            *ledger.fees_paid.entry(asset.to_owned()).or_default() += parse(commission);
+        }
+    }
+
+    fn apply_position(&mut self, position: &EventPosition) {
+        self.symbol(&position.symbol).set_position(
+            &position.position_side,
+            parse(&position.position_amount),
+            parse(&position.entry_price),
+        );
+    }
+
+    fn apply_funding_fee(&mut self, symbol: Option<&str>, balance: &EventBalance, time: u64) {
+        if time <= self.funded_until {
+            return;
+        }
+        let paid = -parse(&balance.balance_change);
+        let symbol = symbol.map(str::to_owned).or_else(|| {
+            let mut open = self
+                .symbols
+                .values()
+                .filter(|ledger| !ledger.positions.is_empty());
+            match (open.next(), open.next()) {
+                (Some(ledger), None) => Some(ledger.symbol.to_owned()),
+                _ => None,
+            }
+        });
+        match symbol {
+            Some(symbol) => self.symbol(&symbol).funding_paid += paid,
+            None => self.unattributed_funding += paid,
+        }
+    }
+}
+
+fn parse(value: &str) -> f64 {
+    value.parse().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rest::model::IndexPriceEvent;
+
+    fn fill(trade_id: i64, side: &str, realized_profit: &str, commission: &str) -> UsdmEvent {
+        let mut order: OrderUpdate = serde_json::from_str(
+            r#"{"s":"BTCUSDT","c":"abc","S":"BUY","o":"LIMIT","f":"GTC","q":"1","p":"100",
+            "ap":"100","sp":"0","x":"TRADE","X":"FILLED","i":1,"l":"1","z":"1","L":"100",
+            "N":"USDT","n":"0","T":1,"t":0,"b":"0","a":"0","m":false,"R":false,
+            "wt":"CONTRACT_PRICE","ot":"LIMIT","ps":"BOTH","cp":false,"AP":"0","cr":"",
+            "pP":false,"si":0,"ss":0,"rp":"0"}"#,
+        )
+        .unwrap();
+        order.trade_id = trade_id;
+        order.side = side.to_owned();
+        order.realized_profit = realized_profit.to_owned();
+        order.commission = Some(commission.to_owned());
+        UsdmEvent::Fill(order)
+    }
+
+    fn position(amount: &str, entry_price: &str) -> UsdmEvent {
+        UsdmEvent::Position(EventPosition {
+            symbol: "BTCUSDT".into(),
+            position_amount: amount.into(),
+            entry_price: entry_price.into(),
+            accumulated_realized: "0".into(),
+            unrealized_pnl: "0".into(),
+            margin_type: "cross".into(),
+            isolated_wallet: "0".into(),
+            position_side: "BOTH".into(),
+        })
+    }
+
+    fn mark_price(price: &str) -> UsdmEvent {
+        UsdmEvent::MarkPrice(IndexPriceEvent {
+            event_type: "markPriceUpdate".into(),
+            event_time: 0,
+            pair: "BTCUSDT".into(),
+            price: price.into(),
+        })
+    }
+
+    fn funding_fee(symbol: Option<&str>, balance_change: &str, event_time: u64) -> UsdmEvent {
+        UsdmEvent::FundingFee {
+            symbol: symbol.map(str::to_owned),
+            balance: EventBalance {
+                asset: "USDT".into(),
+                wallet_balance: "1000".into(),
+                cross_wallet_balance: "1000".into(),
+                balance_change: balance_change.into(),
+            },
+            event_time,
+        }
+    }
+
+    fn income(income_type: &str, income: f64, time: u64, trade_id: &str) -> Income {
+        Income {
+            symbol: "BTCUSDT".into(),
+            income_type: income_type.into(),
+            income,
+            asset: "USDT".into(),
+            info: String::new(),
+            time,
+            tran_id: time,
+            trade_id: trade_id.into(),
+        }
+    }
+
+    #[test]
+    fn tracks_fills_positions_and_funding() {
+        let ledger = Ledger::default();
+        ledger.seed(&[], &[]);
+        ledger.apply(&fill(1, "BUY", "0", "0.04"));
+        ledger.apply(&position("2", "100"));
+        ledger.apply(&mark_price("110"));
+        ledger.apply(&fill(2, "SELL", "15", "0.02"));
+        ledger.apply(&position("1", "100"));
+        ledger.apply(&funding_fee(None, "-0.5", 10));
+
+        let btc = ledger.get_symbol("btcusdt").unwrap();
+        assert_eq!(btc.position_amount(), 1.0);
+        assert_eq!(btc.unrealized_pnl(), 10.0);
+        assert_eq!(btc.realized_pnl, 15.0);
+        assert!((btc.fees_paid["USDT"] - 0.06).abs() < 1e-9);
+        assert_eq!(btc.funding_paid, 0.5);
+
+        ledger.apply(&position("0", "0"));
+        let btc = ledger.get_symbol("BTCUSDT").unwrap();
+        assert!(btc.positions.is_empty());
+        assert_eq!(btc.unrealized_pnl(), 0.0);
+        // no open position to attribute it to
+        ledger.apply(&funding_fee(None, "0.25", 20));
+        assert_eq!(ledger.get_unattributed_funding(), -0.25);
+    }
+
+    #[test]
+    fn seeds_from_income_history_without_counting_twice() {
+        let ledger = Ledger::default();
+        // received while the history was requested
+        ledger.apply(&fill(7, "SELL", "5", "0.01"));
+        ledger.apply(&fill(8, "SELL", "3", "0.01"));
+        ledger.apply(&funding_fee(Some("BTCUSDT"), "-1", 100));
+
+        ledger.seed(
+            &[
+                income(REALIZED_PNL, 20.0, 10, "6"),
+                income(COMMISSION, -0.1, 10, "6"),
+                income(REALIZED_PNL, 5.0, 90, "7"),
+                income(COMMISSION, -0.01, 90, "7"),
+                income(FUNDING_FEE, -1.0, 100, ""),
+                income("TRANSFER", 1000.0, 1, ""),
+            ],
+            &[],
+        );
+        let btc = ledger.get_symbol("BTCUSDT").unwrap();
+        assert_eq!(btc.realized_pnl, 28.0);
+        assert!((btc.fees_paid["USDT"] - 0.12).abs() < 1e-9);
+        assert_eq!(btc.funding_paid, 1.0);
+    }
+
+    #[test]
+    fn seeds_again_after_a_hold() {
+        let ledger = Ledger::default();
+        ledger.seed(&[income(REALIZED_PNL, 20.0, 10, "6")], &[]);
+        ledger.hold();
+        // the fill of the missed trade 7 is reported by the new history
+        ledger.apply(&fill(8, "SELL", "3", "0.01"));
+        assert_eq!(ledger.get_symbol("BTCUSDT").unwrap().realized_pnl, 20.0);
+        ledger.seed(
+            &[
+                income(REALIZED_PNL, 20.0, 10, "6"),
+                income(REALIZED_PNL, 5.0, 90, "7"),
+            ],
+            &[],
+        );
+        assert_eq!(ledger.get_symbol("BTCUSDT").unwrap().realized_pnl, 28.0);
+
+        // without a new history the held events are applied as they are
+        ledger.hold();
+        ledger.apply(&fill(9, "SELL", "2", "0.01"));
+        ledger.resume();
+        assert_eq!(ledger.get_symbol("BTCUSDT").unwrap().realized_pnl, 30.0);
+    }
+}
@@ -1,2 +1,8 @@
+pub mod execution;
+pub mod ledger;
+pub mod paper;
+pub mod reconcile;
+pub mod retry;
+pub mod spot;
 pub mod usdm;
 pub mod usdm_data;
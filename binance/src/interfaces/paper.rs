@@ -0,0 +1,1496 @@
+use crate::commons::errors::*;
+use crate::interfaces::usdm::timestamp;
+use crate::rest::futures::model::{ChangeLeverageResponse, Order, OrderUpdate, PositionRisk};
+use crate::rest::model::{EventBalance, EventPosition};
+use crate::websocket::futures::usdm::WsInterface;
+use crate::websocket::futures::usdm_events::UsdmEvent;
+use indexmap::IndexMap;
+use log::warn;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+// https://binance-docs.github.io/apidocs/futures/en/#error-codes
+const MANDATORY_PARAMETER: i16 = -1102;
+const INVALID_SYMBOL: i16 = -1121;
+const UNKNOWN_ORDER: i16 = -2011;
+const NO_SUCH_ORDER: i16 = -2013;
+const MARGIN_INSUFFICIENT: i16 = -2019;
+const WOULD_IMMEDIATELY_TRIGGER: i16 = -2021;
+const INVALID_LEVERAGE: i16 = -4028;
+const NO_NEED_TO_CHANGE_POSITION_SIDE: i16 = -4059;
+const POSITION_SIDE_MISMATCH: i16 = -4061;
+const POSITION_SIDE_OPEN_ORDERS: i16 = -4067;
+const POSITION_SIDE_POSITION: i16 = -4068;
+
+const MARKET: &str = "MARKET";
+const LIMIT: &str = "LIMIT";
+const STOP: &str = "STOP";
+const STOP_MARKET: &str = "STOP_MARKET";
+const TAKE_PROFIT: &str = "TAKE_PROFIT";
+const TAKE_PROFIT_MARKET: &str = "TAKE_PROFIT_MARKET";
+const TRAILING_STOP_MARKET: &str = "TRAILING_STOP_MARKET";
+const LIQUIDATION: &str = "LIQUIDATION";
+const NEW: &str = "NEW";
+const TRADE: &str = "TRADE";
+const PARTIALLY_FILLED: &str = "PARTIALLY_FILLED";
+const FILLED: &str = "FILLED";
+const CANCELED: &str = "CANCELED";
+const EXPIRED: &str = "EXPIRED";
+const BOTH: &str = "BOTH";
+const LONG: &str = "LONG";
+const SHORT: &str = "SHORT";
+const MARK_PRICE: &str = "MARK_PRICE";
+const CONTRACT_PRICE: &str = "CONTRACT_PRICE";
+
+const MAX_LEVERAGE: u8 = 125;
+// filled, canceled and expired orders kept for queries
+const CLOSED_ORDERS_SIZE: usize = 1000;
+// remaining quantities below are filled
+const EPSILON: f64 = 1e-9;
+
+/// Account simulated by the paper trading mode, in one margin asset and cross margin.
+///
+/// Orders that cross on placement or trigger take the best ask, or bid when selling, and
+/// the last trade price until a book ticker was received. A post-only order expires if it
+/// would take. A resting limit order fills in full as maker at its price once the opposite
+/// side of the book touches it, or partly when an aggregated trade prints through it
+#[derive(Debug, Clone)]
+pub struct PaperConfig {
+    pub asset: String,
+    pub initial_balance: f64,
+    /// Leverage of the symbols until `change_initial_leverage`
+    pub leverage: u8,
+    /// Rates of the notional
+    pub maker_fee: f64,
+    pub taker_fee: f64,
+    pub maintenance_margin_rate: f64,
+}
+
+impl Default for PaperConfig {
+    fn default() -> PaperConfig {
+        PaperConfig {
+            asset: "USDT".into(),
+            initial_balance: 10000.0,
+            leverage: 20,
+            maker_fee: 0.0002,
+            taker_fee: 0.0005,
+            maintenance_margin_rate: 0.004,
+        }
+    }
+}
+
+impl PaperConfig {
+    pub fn set_balance(mut self, asset: &str, initial_balance: f64) -> Self {
+        self.asset = asset.to_owned();
+        self.initial_balance = initial_balance;
+        self
+    }
+
+    pub fn set_leverage(mut self, leverage: u8) -> Self {
+        self.leverage = leverage;
+        self
+    }
+
+    /// Rates of the notional charged to maker and taker fills
+    pub fn set_fees(mut self, maker_fee: f64, taker_fee: f64) -> Self {
+        self.maker_fee = maker_fee;
+        self.taker_fee = taker_fee;
+        self
+    }
+
+    /// Rate of the position notional below which the margin balance liquidates the account
+    pub fn set_maintenance_margin_rate(mut self, maintenance_margin_rate: f64) -> Self {
+        self.maintenance_margin_rate = maintenance_margin_rate;
+        self
+    }
+}
+
+pub(crate) enum OrderRef {
+    Id(u64),
+    ClientId(String),
+}
+
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum PaperUpdate {
+    Order(OrderUpdate),
+    Position(EventPosition),
+    Balance(EventBalance),
+}
+
+struct PaperFill {
+    qty: f64,
+    price: f64,
+    commission: f64,
+    realized: f64,
+    trade_id: i64,
+    maker: bool,
+}
+
+#[derive(Debug, Clone)]
+struct PaperOrder {
+    symbol: String,
+    order_id: u64,
+    client_order_id: String,
+    buy: bool,
+    // a triggered STOP becomes a LIMIT order, the other conditional orders a MARKET one
+    order_type: String,
+    orig_type: String,
+    position_side: String,
+    time_in_force: String,
+    qty: f64,
+    price: f64,
+    stop_price: f64,
+    activation_price: f64,
+    callback_rate: f64,
+    working_type: String,
+    reduce_only: bool,
+    close_position: bool,
+    price_protect: bool,
+    status: String,
+    executed_qty: f64,
+    cum_quote: f64,
+    // best price of a trailing stop since its activation
+    extreme: Option<f64>,
+    time: u64,
+    update_time: u64,
+}
+
+impl PaperOrder {
+    // validates the parameters of `build_order_parameters`
+    fn parse(order: &BTreeMap<String, String>, order_id: u64, time: u64) -> Result<PaperOrder> {
+        let text = |key: &str| order.get(key).map(String::as_str).unwrap_or_default();
+        let flag = |key: &str| text(key).eq_ignore_ascii_case("true");
+        let number = |key: &str| -> Result<f64> {
+            match order.get(key) {
+                Some(value) => value.parse().map_err(|_| mandatory(key)),
+                None => Ok(0.0),
+            }
+        };
+        let order_type = text("type").to_owned();
+        let close_position = flag("closePosition");
+        let paper_order = PaperOrder {
+            symbol: text("symbol").to_uppercase(),
+            order_id,
+            client_order_id: text("newClientOrderId").to_owned(),
+            buy: text("side") == "BUY",
+            orig_type: order_type.to_owned(),
+            position_side: Some(text("positionSide"))
+                .filter(|position_side| !position_side.is_empty())
+                .unwrap_or(BOTH)
+                .to_owned(),
+            time_in_force: Some(text("timeInForce"))
+                .filter(|time_in_force| !time_in_force.is_empty())
+                .unwrap_or("GTC")
+                .to_owned(),
+            qty: number("quantity")?,
+            price: number("price")?,
+            stop_price: number("stopPrice")?,
+            activation_price: number("activationPrice")?,
+            callback_rate: number("callbackRate")?,
+            working_type: Some(text("workingType"))
+                .filter(|working_type| !working_type.is_empty())
+                .unwrap_or(CONTRACT_PRICE)
+                .to_owned(),
+            reduce_only: flag("reduceOnly"),
+            close_position,
+            price_protect: flag("priceProtect"),
+            status: NEW.into(),
+            executed_qty: 0.0,
+            cum_quote: 0.0,
+            extreme: None,
+            time,
+            update_time: time,
+            order_type,
+        };
+        if !matches!(text("side"), "BUY" | "SELL") {
+            return Err(mandatory("side"));
+        }
+        match paper_order.order_type.as_str() {
+            MARKET | STOP_MARKET | TAKE_PROFIT_MARKET => {}
+            LIMIT | STOP | TAKE_PROFIT if paper_order.price <= 0.0 => {
+                return Err(mandatory("price"))
+            }
+            LIMIT | STOP | TAKE_PROFIT => {}
+            TRAILING_STOP_MARKET if paper_order.callback_rate <= 0.0 => {
+                return Err(mandatory("callbackRate"))
+            }
+            TRAILING_STOP_MARKET => {}
+            _ => return Err(mandatory("type")),
+        }
+        if paper_order.is_conditional()
+            && paper_order.order_type != TRAILING_STOP_MARKET
+            && paper_order.stop_price <= 0.0
+        {
+            return Err(mandatory("stopPrice"));
+        }
+        if !close_position && paper_order.qty <= 0.0 {
+            return Err(mandatory("quantity"));
+        }
+        Ok(paper_order)
+    }
+
+    fn side(&self) -> &'static str {
+        if self.buy {
+            "BUY"
+        } else {
+            "SELL"
+        }
+    }
+
+    fn remaining(&self) -> f64 {
+        self.qty - self.executed_qty
+    }
+
+    fn avg_price(&self) -> f64 {
+        if self.executed_qty > 0.0 {
+            self.cum_quote / self.executed_qty
+        } else {
+            0.0
+        }
+    }
+
+    fn is_conditional(&self) -> bool {
+        matches!(
+            self.order_type.as_str(),
+            STOP | STOP_MARKET | TAKE_PROFIT | TAKE_PROFIT_MARKET | TRAILING_STOP_MARKET
+        )
+    }
+
+    // a closing order never opens a position, it takes no margin
+    fn is_closing(&self) -> bool {
+        self.reduce_only
+            || self.close_position
+            || (self.position_side == LONG && !self.buy)
+            || (self.position_side == SHORT && self.buy)
+    }
+
+    // whether a conditional order triggers at `price`, moves the trailing stop along
+    fn triggers(&mut self, price: f64) -> bool {
+        match self.order_type.as_str() {
+            STOP | STOP_MARKET if self.buy => price >= self.stop_price,
+            STOP | STOP_MARKET => price <= self.stop_price,
+            TAKE_PROFIT | TAKE_PROFIT_MARKET if self.buy => price <= self.stop_price,
+            TAKE_PROFIT | TAKE_PROFIT_MARKET => price >= self.stop_price,
+            TRAILING_STOP_MARKET => {
+                let activated = self.extreme.is_some()
+                    || (self.buy && price <= self.activation_price)
+                    || (!self.buy && price >= self.activation_price);
+                if !activated {
+                    return false;
+                }
+                let rate = self.callback_rate / 100.0;
+                let extreme = self.extreme.map_or(price, |extreme| {
+                    if self.buy {
+                        extreme.min(price)
+                    } else {
+                        extreme.max(price)
+                    }
+                });
+                self.extreme = Some(extreme);
+                if self.buy {
+                    price >= extreme * (1.0 + rate)
+                } else {
+                    price <= extreme * (1.0 - rate)
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn to_order(&self) -> Order {
+        Order {
+            client_order_id: self.client_order_id.to_owned(),
+            cum_qty: self.executed_qty,
+            cum_quote: self.cum_quote,
+            executed_qty: self.executed_qty,
+            order_id: self.order_id,
+            avg_price: self.avg_price(),
+            orig_qty: self.qty,
+            price: self.price,
+            side: self.side().into(),
+            reduce_only: self.reduce_only,
+            position_side: self.position_side.to_owned(),
+            status: self.status.to_owned(),
+            stop_price: self.stop_price,
+            close_position: self.close_position,
+            symbol: self.symbol.to_owned(),
+            time_in_force: self.time_in_force.to_owned(),
+            order_type: self.order_type.to_owned(),
+            orig_type: self.orig_type.to_owned(),
+            activation_price: self.activation_price,
+            price_rate: self.callback_rate,
+            update_time: self.update_time,
+            working_type: self.working_type.to_owned(),
+            price_protect: self.price_protect,
+        }
+    }
+
+    fn to_update(
+        &self,
+        execution_type: &str,
+        fill: Option<&PaperFill>,
+        asset: &str,
+    ) -> OrderUpdate {
+        OrderUpdate {
+            symbol: self.symbol.to_owned(),
+            new_client_order_id: self.client_order_id.to_owned(),
+            side: self.side().into(),
+            order_type: self.order_type.to_owned(),
+            time_in_force: self.time_in_force.to_owned(),
+            qty: self.qty.to_string(),
+            price: self.price.to_string(),
+            average_price: self.avg_price().to_string(),
+            stop_price: self.stop_price.to_string(),
+            execution_type: execution_type.to_owned(),
+            order_status: self.status.to_owned(),
+            order_id: self.order_id,
+            qty_last_filled_trade: fill.map_or(0.0, |fill| fill.qty).to_string(),
+            accumulated_qty_filled_trades: self.executed_qty.to_string(),
+            price_last_filled_trade: fill.map_or(0.0, |fill| fill.price).to_string(),
+            asset_commisioned: fill.map(|_| asset.to_owned()),
+            commission: fill.map(|fill| fill.commission.to_string()),
+            trade_order_time: self.update_time,
+            trade_id: fill.map_or(0, |fill| fill.trade_id),
+            bids_notional: "0".into(),
+            ask_notional: "0".into(),
+            is_buyer_maker: fill.is_some_and(|fill| fill.maker),
+            is_reduce_only: self.reduce_only,
+            stop_price_working_type: self.working_type.to_owned(),
+            original_order_type: self.orig_type.to_owned(),
+            position_side: self.position_side.to_owned(),
+            close_all: Some(self.close_position),
+            activation_price: Some(self.activation_price.to_string()),
+            callback_rate: Some(self.callback_rate.to_string()),
+            pp_ignore: self.price_protect,
+            si_ignore: 0,
+            ss_ignore: 0,
+            realized_profit: fill.map_or(0.0, |fill| fill.realized).to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Market {
+    last_price: Option<f64>,
+    mark_price: Option<f64>,
+    // best bid and ask
+    book: Option<(f64, f64)>,
+}
+
+impl Market {
+    fn price(&self, working_type: &str) -> Option<f64> {
+        if working_type == MARK_PRICE {
+            self.mark_price.or(self.last_price)
+        } else {
+            self.last_price.or(self.mark_price)
+        }
+    }
+
+    fn mark(&self) -> Option<f64> {
+        self.price(MARK_PRICE)
+    }
+
+    // price a taker order fills at
+    fn taker_price(&self, buy: bool) -> Option<f64> {
+        self.book
+            .map(|(bid, ask)| if buy { ask } else { bid })
+            .or_else(|| self.price(CONTRACT_PRICE))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Position {
+    // negative when short
+    amount: f64,
+    entry_price: f64,
+    accumulated_realized: f64,
+}
+
+impl Position {
+    // adds a signed quantity traded at `price`, returns the PnL realized by the closed part
+    fn trade(&mut self, qty: f64, price: f64) -> f64 {
+        let mut realized = 0.0;
+        if self.amount * qty < 0.0 {
+            let closed = qty.abs().min(self.amount.abs());
+            realized = closed * (price - self.entry_price) * self.amount.signum();
+            self.accumulated_realized += realized;
+        }
+        let amount = self.amount + qty;
+        if amount.abs() <= EPSILON {
+            self.entry_price = 0.0;
+            self.amount = 0.0;
+            return realized;
+        }
+        if self.amount * qty >= 0.0 {
+            self.entry_price =
+                (self.amount.abs() * self.entry_price + qty.abs() * price) / amount.abs();
+        } else if self.amount * amount < 0.0 {
+            // the rest opens a position on the other side
+            self.entry_price = price;
+        }
+        self.amount = amount;
+        realized
+    }
+}
+
+/// Matching engine and account of the paper trading mode, also behind `FakeBinance`
+pub(crate) struct PaperState {
+    config: PaperConfig,
+    wallet: f64,
+    dual_side: bool,
+    next_order_id: u64,
+    next_trade_id: i64,
+    markets: HashMap<String, Market>,
+    leverages: HashMap<String, u8>,
+    // in placement order, which is the priority of orders at the same price
+    open_orders: IndexMap<u64, PaperOrder>,
+    closed_orders: IndexMap<u64, PaperOrder>,
+    // by symbol and position side
+    positions: IndexMap<(String, String), Position>,
+    // published in this order
+    updates: VecDeque<PaperUpdate>,
+}
+
+impl PaperState {
+    pub(crate) fn new(config: PaperConfig) -> PaperState {
+        let mut state = PaperState {
+            wallet: config.initial_balance,
+            config,
+            dual_side: false,
+            next_order_id: 1,
+            next_trade_id: 1,
+            markets: HashMap::new(),
+            leverages: HashMap::new(),
+            open_orders: IndexMap::new(),
+            closed_orders: IndexMap::new(),
+            positions: IndexMap::new(),
+            updates: VecDeque::new(),
+        };
+        state.push_balance();
+        state
+    }
+
+    fn leverage(&self, symbol: &str) -> u8 {
+        self.leverages
+            .get(symbol)
+            .copied()
+            .unwrap_or(self.config.leverage)
+    }
+
+    fn mark_price(&self, symbol: &str) -> Option<f64> {
+        self.markets.get(symbol).and_then(Market::mark)
+    }
+
+    // price the margin of an order is computed at
+    fn order_price(&self, order: &PaperOrder) -> f64 {
+        [order.price, order.stop_price, order.activation_price]
+            .into_iter()
+            .find(|price| *price > 0.0)
+            .or_else(|| self.mark_price(&order.symbol))
+            .unwrap_or_default()
+    }
+
+    fn position_amount(&self, symbol: &str, position_side: &str) -> f64 {
+        self.positions
+            .get(&(symbol.to_owned(), position_side.to_owned()))
+            .map_or(0.0, |position| position.amount)
+    }
+
+    fn unrealized_pnl(&self, symbol: &str, position: &Position) -> f64 {
+        self.mark_price(symbol).map_or(0.0, |mark_price| {
+            position.amount * (mark_price - position.entry_price)
+        })
+    }
+
+    // margin balance left for new positions and orders
+    fn available_margin(&self) -> f64 {
+        let positions: f64 = self
+            .positions
+            .iter()
+            .map(|((symbol, _), position)| {
+                let mark_price = self.mark_price(symbol).unwrap_or(position.entry_price);
+                self.unrealized_pnl(symbol, position)
+                    - position.amount.abs() * mark_price / self.leverage(symbol) as f64
+            })
+            .sum();
+        let orders: f64 = self
+            .open_orders
+            .values()
+            .filter(|order| !order.is_closing())
+            .map(|order| {
+                order.remaining() * self.order_price(order) / self.leverage(&order.symbol) as f64
+            })
+            .sum();
+        self.wallet + positions - orders
+    }
+
+    // quantity an order can fill now, a closing order is capped at its position
+    fn fillable(&self, order: &PaperOrder) -> f64 {
+        let remaining = if order.close_position {
+            f64::INFINITY
+        } else {
+            order.remaining()
+        };
+        if !order.is_closing() {
+            return remaining;
+        }
+        let amount = self.position_amount(&order.symbol, &order.position_side);
+        let closable = if order.buy { -amount } else { amount };
+        remaining.min(closable.max(0.0))
+    }
+
+    pub(crate) fn place(&mut self, order: &BTreeMap<String, String>) -> Result<Order> {
+        let mut order = PaperOrder::parse(order, self.next_order_id, timestamp())?;
+        let Some(market) = self.markets.get(&order.symbol).copied() else {
+            return Err(rejected(INVALID_SYMBOL, "Invalid symbol."));
+        };
+        if (order.position_side == BOTH) == self.dual_side {
+            return Err(rejected(
+                POSITION_SIDE_MISMATCH,
+                "Order's position side does not match user's setting.",
+            ));
+        }
+        let trigger_price = market.price(&order.working_type).unwrap_or_default();
+        if order.order_type == TRAILING_STOP_MARKET {
+            if order.activation_price == 0.0 {
+                order.extreme = Some(trigger_price);
+            }
+        } else if order.is_conditional() && order.triggers(trigger_price) {
+            return Err(rejected(
+                WOULD_IMMEDIATELY_TRIGGER,
+                "Order would immediately trigger.",
+            ));
+        }
+        if !order.is_closing() {
+            let price = match order.order_type.as_str() {
+                MARKET => market.taker_price(order.buy).unwrap_or_default(),
+                _ => self.order_price(&order),
+            };
+            let margin = order.qty * price / self.leverage(&order.symbol) as f64;
+            if margin > self.available_margin() + EPSILON {
+                return Err(rejected(MARGIN_INSUFFICIENT, "Margin is insufficient."));
+            }
+        }
+        self.next_order_id += 1;
+        self.updates.push_back(PaperUpdate::Order(order.to_update(
+            NEW,
+            None,
+            &self.config.asset,
+        )));
+        let accepted = order.to_order();
+        let order_id = order.order_id;
+        self.open_orders.insert(order_id, order);
+        self.execute(order_id, &market);
+        Ok(accepted)
+    }
+
+    // fills what an accepted or triggered order takes from the market
+    fn execute(&mut self, order_id: u64, market: &Market) {
+        let Some(order) = self.open_orders.get(&order_id) else {
+            return;
+        };
+        let taker_price = market.taker_price(order.buy).unwrap_or_default();
+        match order.order_type.as_str() {
+            MARKET => self.fill_taker(order_id, taker_price),
+            LIMIT => {
+                let crosses = if order.buy {
+                    taker_price <= order.price
+                } else {
+                    taker_price >= order.price
+                };
+                match (crosses, order.time_in_force.as_str()) {
+                    // post only
+                    (true, "GTX") => self.finish(order_id, EXPIRED),
+                    (true, _) => self.fill_taker(order_id, taker_price),
+                    (false, "IOC" | "FOK") => self.finish(order_id, EXPIRED),
+                    (false, _) => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn fill_taker(&mut self, order_id: u64, price: f64) {
+        let Some(order) = self.open_orders.get(&order_id) else {
+            return;
+        };
+        let qty = self.fillable(order);
+        if qty > EPSILON {
+            self.fill(order_id, qty, price, false);
+        }
+        // the rest of an order capped at its position
+        if self.open_orders.contains_key(&order_id) {
+            self.finish(order_id, EXPIRED);
+        }
+    }
+
+    fn fill(&mut self, order_id: u64, qty: f64, price: f64, maker: bool) {
+        let Some(order) = self.open_orders.get_mut(&order_id) else {
+            return;
+        };
+        let fee_rate = if maker {
+            self.config.maker_fee
+        } else {
+            self.config.taker_fee
+        };
+        let key = (order.symbol.to_owned(), order.position_side.to_owned());
+        let position = self.positions.entry(key.to_owned()).or_default();
+        let realized = position.trade(if order.buy { qty } else { -qty }, price);
+        let commission = qty * price * fee_rate;
+        self.wallet += realized - commission;
+        order.executed_qty += qty;
+        order.cum_quote += qty * price;
+        order.update_time = timestamp();
+        let filled = order.close_position || order.remaining() <= EPSILON;
+        order.status = if filled { FILLED } else { PARTIALLY_FILLED }.into();
+        let fill = PaperFill {
+            qty,
+            price,
+            commission,
+            realized,
+            trade_id: self.next_trade_id,
+            maker,
+        };
+        self.next_trade_id += 1;
+        let update = order.to_update(TRADE, Some(&fill), &self.config.asset);
+        self.updates.push_back(PaperUpdate::Order(update));
+        self.push_position(&key);
+        self.push_balance();
+        if filled {
+            self.close(order_id);
+        }
+    }
+
+    // cancels or expires an open order
+    fn finish(&mut self, order_id: u64, status: &str) {
+        let Some(order) = self.open_orders.get_mut(&order_id) else {
+            return;
+        };
+        order.status = status.to_owned();
+        order.update_time = timestamp();
+        let update = order.to_update(status, None, &self.config.asset);
+        self.updates.push_back(PaperUpdate::Order(update));
+        self.close(order_id);
+    }
+
+    fn close(&mut self, order_id: u64) {
+        if let Some(order) = self.open_orders.shift_remove(&order_id) {
+            self.closed_orders.insert(order_id, order);
+        }
+        while self.closed_orders.len() > CLOSED_ORDERS_SIZE {
+            self.closed_orders.shift_remove_index(0);
+        }
+    }
+
+    pub(crate) fn on_trade(&mut self, symbol: &str, price: f64, qty: f64) {
+        self.markets
+            .entry(symbol.to_owned())
+            .or_default()
+            .last_price = Some(price);
+        self.check_triggers(symbol);
+        // a trade prints through an order
+        self.match_resting(symbol, true, qty, |order_price| price < order_price);
+        self.match_resting(symbol, false, qty, |order_price| price > order_price);
+    }
+
+    pub(crate) fn on_book(&mut self, symbol: &str, bid: f64, ask: f64) {
+        self.markets.entry(symbol.to_owned()).or_default().book = Some((bid, ask));
+        // the opposite side of the book touches an order
+        self.match_resting(symbol, true, f64::INFINITY, |order_price| {
+            ask <= order_price
+        });
+        self.match_resting(symbol, false, f64::INFINITY, |order_price| {
+            bid >= order_price
+        });
+    }
+
+    // fills the resting orders on one side whose price is crossed, best price first,
+    // each taking from `qty`
+    fn match_resting<F>(&mut self, symbol: &str, buy: bool, qty: f64, crossed: F)
+    where
+        F: Fn(f64) -> bool,
+    {
+        let mut crossed: Vec<(u64, f64)> = self
+            .open_orders
+            .values()
+            .filter(|order| order.symbol == symbol && order.order_type == LIMIT)
+            .filter(|order| order.buy == buy && crossed(order.price))
+            .map(|order| (order.order_id, order.price))
+            .collect();
+        // stable, orders at the same price stay in placement order
+        crossed.sort_by(|(_, a), (_, b)| if buy { b.total_cmp(a) } else { a.total_cmp(b) });
+        let mut available = qty;
+        for (order_id, order_price) in crossed {
+            if available <= EPSILON {
+                break;
+            }
+            let Some(order) = self.open_orders.get(&order_id) else {
+                continue;
+            };
+            let fillable = self.fillable(order);
+            if fillable <= EPSILON {
+                self.finish(order_id, EXPIRED);
+                continue;
+            }
+            let fill_qty = fillable.min(available);
+            available -= fill_qty;
+            self.fill(order_id, fill_qty, order_price, true);
+        }
+    }
+
+    pub(crate) fn on_mark_price(&mut self, symbol: &str, mark_price: f64) {
+        self.markets
+            .entry(symbol.to_owned())
+            .or_default()
+            .mark_price = Some(mark_price);
+        self.check_triggers(symbol);
+        self.check_liquidation();
+    }
+
+    fn check_triggers(&mut self, symbol: &str) {
+        let Some(market) = self.markets.get(symbol).copied() else {
+            return;
+        };
+        let triggered: Vec<u64> = self
+            .open_orders
+            .values_mut()
+            .filter(|order| order.symbol == symbol && order.is_conditional())
+            .filter_map(|order| {
+                let price = market.price(&order.working_type)?;
+                order.triggers(price).then_some(order.order_id)
+            })
+            .collect();
+        for order_id in triggered {
+            let Some(order) = self.open_orders.get_mut(&order_id) else {
+                continue;
+            };
+            order.order_type = match order.order_type.as_str() {
+                STOP | TAKE_PROFIT => LIMIT,
+                _ => MARKET,
+            }
+            .into();
+            if order.close_position {
+                order.qty = 0.0;
+            }
+            order.update_time = timestamp();
+            let update = order.to_update(NEW, None, &self.config.asset);
+            self.updates.push_back(PaperUpdate::Order(update));
+            self.execute(order_id, &market);
+        }
+    }
+
+    // cross margin, every position is closed at its mark price once the margin balance
+    // falls to the maintenance margin
+    fn check_liquidation(&mut self) {
+        let (margin_balance, maintenance_margin) = self.positions.iter().fold(
+            (self.wallet, 0.0),
+            |(margin_balance, maintenance_margin), ((symbol, _), position)| {
+                let mark_price = self.mark_price(symbol).unwrap_or(position.entry_price);
+                (
+                    margin_balance + self.unrealized_pnl(symbol, position),
+                    maintenance_margin
+                        + position.amount.abs() * mark_price * self.config.maintenance_margin_rate,
+                )
+            },
+        );
+        if maintenance_margin == 0.0 || margin_balance > maintenance_margin {
+            return;
+        }
+        warn!("Paper account liquidated, margin balance {margin_balance}");
+        let order_ids: Vec<u64> = self.open_orders.keys().copied().collect();
+        for order_id in order_ids {
+            self.finish(order_id, CANCELED);
+        }
+        let positions: Vec<(String, String, f64, f64)> = self
+            .positions
+            .iter()
+            .filter(|(_, position)| position.amount != 0.0)
+            .map(|((symbol, position_side), position)| {
+                let mark_price = self.mark_price(symbol).unwrap_or(position.entry_price);
+                (
+                    symbol.to_owned(),
+                    position_side.to_owned(),
+                    position.amount,
+                    mark_price,
+                )
+            })
+            .collect();
+        for (symbol, position_side, amount, mark_price) in positions {
+            let time = timestamp();
+            let parameters = BTreeMap::from([
+                ("symbol".to_owned(), symbol),
+                (
+                    "side".to_owned(),
+                    if amount > 0.0 { "SELL" } else { "BUY" }.to_owned(),
+                ),
+                ("type".to_owned(), MARKET.to_owned()),
+                ("positionSide".to_owned(), position_side),
+                ("quantity".to_owned(), amount.abs().to_string()),
+                (
+                    "newClientOrderId".to_owned(),
+                    format!("autoclose-{time}-{}", self.next_order_id),
+                ),
+            ]);
+            let Ok(mut order) = PaperOrder::parse(&parameters, self.next_order_id, time) else {
+                continue;
+            };
+            self.next_order_id += 1;
+            order.orig_type = LIQUIDATION.into();
+            order.time_in_force = "IOC".into();
+            let order_id = order.order_id;
+            self.updates.push_back(PaperUpdate::Order(order.to_update(
+                NEW,
+                None,
+                &self.config.asset,
+            )));
+            self.open_orders.insert(order_id, order);
+            self.fill(order_id, amount.abs(), mark_price, false);
+        }
+        // the insurance fund covers a negative balance
+        if self.wallet < 0.0 {
+            self.wallet = 0.0;
+            self.push_balance();
+        }
+    }
+
+    fn find(&self, symbol: &str, order: &OrderRef) -> Option<&PaperOrder> {
+        let symbol = symbol.to_uppercase();
+        let matches = |paper_order: &&PaperOrder| {
+            paper_order.symbol == symbol
+                && match order {
+                    OrderRef::Id(order_id) => paper_order.order_id == *order_id,
+                    OrderRef::ClientId(client_order_id) => {
+                        paper_order.client_order_id == *client_order_id
+                    }
+                }
+        };
+        self.open_orders
+            .values()
+            .find(matches)
+            .or_else(|| self.closed_orders.values().rev().find(matches))
+    }
+
+    pub(crate) fn get_order(&self, symbol: &str, order: &OrderRef) -> Result<Order> {
+        self.find(symbol, order)
+            .map(PaperOrder::to_order)
+            .ok_or_else(|| rejected(NO_SUCH_ORDER, "Order does not exist."))
+    }
+
+    pub(crate) fn get_open_orders(&self, symbol: &str) -> Vec<Order> {
+        let symbol = symbol.to_uppercase();
+        self.open_orders
+            .values()
+            .filter(|order| order.symbol == symbol)
+            .map(PaperOrder::to_order)
+            .collect()
+    }
+
+    /// Open and closed orders placed since `start_time`, oldest first
+    pub(crate) fn get_all_orders(&self, symbol: &str, start_time: u64) -> Vec<Order> {
+        let symbol = symbol.to_uppercase();
+        let mut orders: Vec<&PaperOrder> = self
+            .closed_orders
+            .values()
+            .chain(self.open_orders.values())
+            .filter(|order| order.symbol == symbol && order.time >= start_time)
+            .collect();
+        orders.sort_by_key(|order| order.order_id);
+        orders.into_iter().map(PaperOrder::to_order).collect()
+    }
+
+    pub(crate) fn take_updates(&mut self) -> Vec<PaperUpdate> {
+        self.updates.drain(..).collect()
+    }
+
+    pub(crate) fn cancel(&mut self, symbol: &str, order: &OrderRef) -> Result<Order> {
+        let order_id = self
+            .find(symbol, order)
+            .filter(|paper_order| self.open_orders.contains_key(&paper_order.order_id))
+            .map(|paper_order| paper_order.order_id)
+            .ok_or_else(|| rejected(UNKNOWN_ORDER, "Unknown order sent."))?;
+        self.finish(order_id, CANCELED);
+        Ok(self.closed_orders[&order_id].to_order())
+    }
+
+    pub(crate) fn cancel_all(&mut self, symbol: &str) {
+        let symbol = symbol.to_uppercase();
+        let order_ids: Vec<u64> = self
+            .open_orders
+            .values()
+            .filter(|order| order.symbol == symbol)
+            .map(|order| order.order_id)
+            .collect();
+        for order_id in order_ids {
+            self.finish(order_id, CANCELED);
+        }
+    }
+
+    pub(crate) fn position_information(&self, symbol: &str) -> Vec<PositionRisk> {
+        let symbol = symbol.to_uppercase();
+        let position_sides = if self.dual_side {
+            vec![LONG, SHORT]
+        } else {
+            vec![BOTH]
+        };
+        let mark_price = self.mark_price(&symbol).unwrap_or_default();
+        position_sides
+            .into_iter()
+            .map(|position_side| {
+                let position = self
+                    .positions
+                    .get(&(symbol.to_owned(), position_side.to_owned()))
+                    .cloned()
+                    .unwrap_or_default();
+                PositionRisk {
+                    entry_price: position.entry_price,
+                    margin_type: "cross".into(),
+                    is_auto_add_margin: false,
+                    isolated_margin: 0.0,
+                    leverage: self.leverage(&symbol).to_string(),
+                    // not simulated per position in cross margin
+                    liquidation_price: 0.0,
+                    mark_price,
+                    max_notional_value: 0.0,
+                    position_amount: position.amount,
+                    symbol: symbol.to_owned(),
+                    unrealized_profit: self.unrealized_pnl(&symbol, &position),
+                    position_side: position_side.into(),
+                    notional: position.amount * mark_price,
+                    isolated_wallet: 0.0,
+                    update_time: timestamp(),
+                }
+            })
+            .collect()
+    }
+
+    pub(crate) fn change_initial_leverage(
+        &mut self,
+        symbol: &str,
+        leverage: u8,
+    ) -> Result<ChangeLeverageResponse> {
+        if leverage == 0 || leverage > MAX_LEVERAGE {
+            return Err(rejected(
+                INVALID_LEVERAGE,
+                format!("Leverage {leverage} is not valid"),
+            ));
+        }
+        let symbol = symbol.to_uppercase();
+        self.leverages.insert(symbol.to_owned(), leverage);
+        Ok(ChangeLeverageResponse {
+            leverage,
+            max_notional_value: 0.0,
+            symbol,
+        })
+    }
+
+    pub(crate) fn change_position_mode(&mut self, dual_side: bool) -> Result<()> {
+        if dual_side == self.dual_side {
+            return Err(rejected(
+                NO_NEED_TO_CHANGE_POSITION_SIDE,
+                "No need to change position side.",
+            ));
+        }
+        if !self.open_orders.is_empty() {
+            return Err(rejected(
+                POSITION_SIDE_OPEN_ORDERS,
+                "Position side cannot be changed if there exists open orders.",
+            ));
+        }
+        if self
+            .positions
+            .values()
+            .any(|position| position.amount != 0.0)
+        {
+            return Err(rejected(
+                POSITION_SIDE_POSITION,
+                "Position side cannot be changed if there exists position.",
+            ));
+        }
+        self.dual_side = dual_side;
+        Ok(())
+    }
+
+    fn push_position(&mut self, key: &(String, String)) {
+        let Some(position) = self.positions.get(key) else {
+            return;
+        };
+        let (symbol, position_side) = key;
+        let event = EventPosition {
+            symbol: symbol.to_owned(),
+            position_amount: position.amount.to_string(),
+            entry_price: position.entry_price.to_string(),
+            accumulated_realized: position.accumulated_realized.to_string(),
+            unrealized_pnl: self.unrealized_pnl(symbol, position).to_string(),
+            margin_type: "cross".into(),
+            isolated_wallet: "0".into(),
+            position_side: position_side.to_owned(),
+        };
+        self.updates.push_back(PaperUpdate::Position(event));
+    }
+
+    fn push_balance(&mut self) {
+        self.updates.push_back(PaperUpdate::Balance(EventBalance {
+            asset: self.config.asset.to_owned(),
+            wallet_balance: self.wallet.to_string(),
+            cross_wallet_balance: self.wallet.to_string(),
+            balance_change: "0".into(),
+        }));
+    }
+}
+
+/// Simulated account of the paper trading mode. Orders are matched against the aggregated
+/// trades, book tickers and mark prices of a `WsInterface`, as described in `PaperConfig`,
+/// which receives the order, position and balance updates the user stream would send.
+/// There is no funding
+#[derive(Clone)]
+pub(crate) struct PaperExchange {
+    state: Arc<Mutex<PaperState>>,
+    publishing: Arc<AtomicBool>,
+    ws: WsInterface,
+}
+
+impl PaperExchange {
+    pub(crate) fn new(config: PaperConfig, ws: WsInterface) -> PaperExchange {
+        let exchange = PaperExchange {
+            state: Arc::new(Mutex::new(PaperState::new(config))),
+            publishing: Arc::new(AtomicBool::new(false)),
+            ws,
+        };
+        // the initial balance
+        exchange.publish();
+        exchange
+    }
+
+    /// Feeds the aggregated trades, book tickers and mark prices to the matching
+    pub(crate) fn apply(&self, event: &UsdmEvent) {
+        match event {
+            UsdmEvent::AggrTrade(trade) => {
+                let (Ok(price), Ok(qty)) = (trade.price.parse(), trade.qty.parse()) else {
+                    return;
+                };
+                self.with_state(|state| state.on_trade(&trade.symbol, price, qty));
+            }
+            UsdmEvent::MarkPrice(mark_price) => {
+                let Ok(price) = mark_price.price.parse() else {
+                    return;
+                };
+                self.with_state(|state| state.on_mark_price(&mark_price.pair, price));
+            }
+            UsdmEvent::BookTicker(book_ticker) => {
+                let (Ok(bid), Ok(ask)) =
+                    (book_ticker.best_bid.parse(), book_ticker.best_ask.parse())
+                else {
+                    return;
+                };
+                self.with_state(|state| state.on_book(&book_ticker.symbol, bid, ask));
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the accepted order, its fills are published as updates
+    pub(crate) fn place_order(&self, order: &BTreeMap<String, String>) -> Result<Order> {
+        self.with_state(|state| state.place(order))
+    }
+
+    pub(crate) fn cancel_order(&self, symbol: &str, order: OrderRef) -> Result<Order> {
+        self.with_state(|state| state.cancel(symbol, &order))
+    }
+
+    pub(crate) fn cancel_all_open_orders(&self, symbol: &str) {
+        self.with_state(|state| state.cancel_all(symbol));
+    }
+
+    pub(crate) fn get_order(&self, symbol: &str, order: OrderRef) -> Result<Order> {
+        self.with_state(|state| state.get_order(symbol, &order))
+    }
+
+    pub(crate) fn get_all_open_orders(&self, symbol: &str) -> Vec<Order> {
+        self.with_state(|state| state.get_open_orders(symbol))
+    }
+
+    /// The last `limit` orders placed since `start_time`
+    pub(crate) fn get_all_orders(&self, symbol: &str, start_time: u64, limit: usize) -> Vec<Order> {
+        let mut orders = self.with_state(|state| state.get_all_orders(symbol, start_time));
+        orders.drain(..orders.len().saturating_sub(limit));
+        orders
+    }
+
+    pub(crate) fn position_information(&self, symbol: &str) -> Vec<PositionRisk> {
+        self.with_state(|state| state.position_information(symbol))
+    }
+
+    pub(crate) fn change_initial_leverage(
+        &self,
+        symbol: &str,
+        leverage: u8,
+    ) -> Result<ChangeLeverageResponse> {
+        self.with_state(|state| state.change_initial_leverage(symbol, leverage))
+    }
+
+    pub(crate) fn change_position_mode(&self, dual_side: bool) -> Result<()> {
+        self.with_state(|state| state.change_position_mode(dual_side))
+    }
+
+    fn with_state<T>(&self, f: impl FnOnce(&mut PaperState) -> T) -> T {
+        let result = f(&mut self.state.lock().unwrap());
+        self.publish();
+        result
+    }
+
+    // applies the queued updates outside the state lock, as an observer may place orders.
+    // Updates queued while another call publishes are applied by that call, in order
+    fn publish(&self) {
+        while !self.publishing.swap(true, Ordering::Acquire) {
+            loop {
+                let updates = self.state.lock().unwrap().take_updates();
+                if updates.is_empty() {
+                    break;
+                }
+                for update in updates {
+                    match update {
+                        PaperUpdate::Order(order) => self.ws.apply_order_update(order),
+                        PaperUpdate::Position(position) => self.ws.apply_position(position),
+                        PaperUpdate::Balance(balance) => self.ws.apply_balances(&[balance]),
+                    }
+                }
+            }
+            self.publishing.store(false, Ordering::Release);
+            if self.state.lock().unwrap().updates.is_empty() {
+                return;
+            }
+        }
+    }
+}
+
+pub(crate) fn rejected(code: i16, msg: impl Into<String>) -> BinanceError {
+    BinanceError::BinanceError {
+        response: BinanceContentError {
+            code,
+            msg: msg.into(),
+        },
+    }
+}
+
+pub(crate) fn mandatory(parameter: &str) -> BinanceError {
+    rejected(
+        MANDATORY_PARAMETER,
+        format!("Mandatory parameter '{parameter}' was not sent, was empty/null, or malformed."),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> PaperState {
+        let mut state = PaperState::new(
+            PaperConfig::default()
+                .set_balance("USDT", 1000.0)
+                .set_leverage(10),
+        );
+        state.on_mark_price("BTCUSDT", 100.0);
+        state.on_trade("BTCUSDT", 100.0, 1.0);
+        state.updates.clear();
+        state
+    }
+
+    fn order(parameters: &[(&str, &str)]) -> BTreeMap<String, String> {
+        let mut order: BTreeMap<String, String> = parameters
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        order.insert("symbol".into(), "BTCUSDT".into());
+        order
+    }
+
+    fn order_updates(state: &mut PaperState) -> Vec<OrderUpdate> {
+        state
+            .updates
+            .drain(..)
+            .filter_map(|update| match update {
+                PaperUpdate::Order(order) => Some(order),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn error_code(result: Result<Order>) -> i16 {
+        match result {
+            Err(BinanceError::BinanceError { response }) => response.code,
+            other => panic!("Unexpected result {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_market_order_fills_at_last_price_with_taker_fee() {
+        let mut state = state();
+        let accepted = state
+            .place(&order(&[
+                ("side", "BUY"),
+                ("type", "MARKET"),
+                ("quantity", "2"),
+            ]))
+            .unwrap();
+        assert_eq!(accepted.status, NEW);
+
+        let updates = order_updates(&mut state);
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[1].execution_type, TRADE);
+        assert_eq!(updates[1].order_status, FILLED);
+        assert_eq!(updates[1].price_last_filled_trade, "100");
+        assert_eq!(updates[1].commission.as_deref(), Some("0.1"));
+        assert!(!updates[1].is_buyer_maker);
+        assert_eq!(state.wallet, 999.9);
+        assert_eq!(state.position_amount("BTCUSDT", BOTH), 2.0);
+    }
+
+    #[test]
+    fn test_resting_limit_order_fills_when_a_trade_prints_through() {
+        let mut state = state();
+        state
+            .place(&order(&[
+                ("side", "BUY"),
+                ("type", "MARKET"),
+                ("quantity", "1"),
+            ]))
+            .unwrap();
+        let sell = order(&[
+            ("side", "SELL"),
+            ("type", "LIMIT"),
+            ("timeInForce", "GTC"),
+            ("quantity", "1"),
+            ("price", "110"),
+        ]);
+        let accepted = state.place(&sell).unwrap();
+        state.on_trade("BTCUSDT", 110.0, 5.0);
+        assert_eq!(state.open_orders.len(), 1);
+
+        state.updates.clear();
+        state.on_trade("BTCUSDT", 111.0, 0.4);
+        state.on_trade("BTCUSDT", 111.0, 5.0);
+        let fills: Vec<OrderUpdate> = order_updates(&mut state);
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[0].qty_last_filled_trade, "0.4");
+        assert_eq!(fills[0].order_status, PARTIALLY_FILLED);
+        assert_eq!(fills[1].qty_last_filled_trade, "0.6");
+        assert_eq!(fills[1].price_last_filled_trade, "110");
+        assert_eq!(fills[1].order_status, FILLED);
+        assert!(fills[1].is_buyer_maker);
+        assert_eq!(fills[1].realized_profit, "6");
+        assert!(state.open_orders.is_empty());
+        assert_eq!(state.position_amount("BTCUSDT", BOTH), 0.0);
+        // 0.05 taker fee, 0.022 maker fees
+        assert!((state.wallet - (1000.0 + 10.0 - 0.05 - 0.022)).abs() < 1e-9);
+
+        let order = state
+            .find("BTCUSDT", &OrderRef::Id(accepted.order_id))
+            .unwrap();
+        assert_eq!(order.status, FILLED);
+    }
+
+    #[test]
+    fn test_post_only_order_crossing_the_last_price_expires() {
+        let mut state = state();
+        let buy = order(&[
+            ("side", "BUY"),
+            ("type", "LIMIT"),
+            ("timeInForce", "GTX"),
+            ("quantity", "1"),
+            ("price", "101"),
+        ]);
+        state.place(&buy).unwrap();
+        let updates = order_updates(&mut state);
+        assert_eq!(updates.last().unwrap().order_status, EXPIRED);
+        assert_eq!(state.position_amount("BTCUSDT", BOTH), 0.0);
+    }
+
+    #[test]
+    fn test_orders_take_the_book() {
+        let mut state = state();
+        state.on_book("BTCUSDT", 99.0, 100.5);
+        // below the last price, but it would take the ask
+        let post_only = order(&[
+            ("side", "BUY"),
+            ("type", "LIMIT"),
+            ("timeInForce", "GTX"),
+            ("quantity", "1"),
+            ("price", "100.5"),
+        ]);
+        state.place(&post_only).unwrap();
+        assert_eq!(
+            order_updates(&mut state).last().unwrap().order_status,
+            EXPIRED
+        );
+
+        state
+            .place(&order(&[
+                ("side", "SELL"),
+                ("type", "MARKET"),
+                ("quantity", "1"),
+            ]))
+            .unwrap();
+        let updates = order_updates(&mut state);
+        assert_eq!(updates[1].price_last_filled_trade, "99");
+        assert_eq!(state.position_amount("BTCUSDT", BOTH), -1.0);
+    }
+
+    #[test]
+    fn test_resting_limit_order_fills_when_the_book_touches_it() {
+        let mut state = state();
+        state.on_book("BTCUSDT", 99.0, 101.0);
+        let buy = order(&[
+            ("side", "BUY"),
+            ("type", "LIMIT"),
+            ("timeInForce", "GTX"),
+            ("quantity", "2"),
+            ("price", "100"),
+        ]);
+        state.place(&buy).unwrap();
+        state.on_book("BTCUSDT", 99.0, 100.5);
+        assert_eq!(state.open_orders.len(), 1);
+
+        state.updates.clear();
+        // no trade printed through it
+        state.on_book("BTCUSDT", 99.0, 100.0);
+        let fills = order_updates(&mut state);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].qty_last_filled_trade, "2");
+        assert_eq!(fills[0].price_last_filled_trade, "100");
+        assert_eq!(fills[0].order_status, FILLED);
+        assert!(fills[0].is_buyer_maker);
+        assert_eq!(state.position_amount("BTCUSDT", BOTH), 2.0);
+    }
+
+    #[test]
+    fn test_liquidation_orders_have_unique_client_ids() {
+        let mut state = state();
+        state.change_position_mode(true).unwrap();
+        for position_side in [LONG, SHORT] {
+            state
+                .place(&order(&[
+                    ("side", if position_side == LONG { "BUY" } else { "SELL" }),
+                    ("positionSide", position_side),
+                    ("type", "MARKET"),
+                    ("quantity", "40"),
+                ]))
+                .unwrap();
+        }
+        state.updates.clear();
+        state.wallet = 10.0;
+        state.on_mark_price("BTCUSDT", 100.0);
+        let client_ids: Vec<String> = order_updates(&mut state)
+            .into_iter()
+            .filter(|update| update.execution_type == NEW)
+            .map(|update| update.new_client_order_id)
+            .collect();
+        assert_eq!(client_ids.len(), 2);
+        assert_ne!(client_ids[0], client_ids[1]);
+    }
+
+    #[test]
+    fn test_stop_market_close_triggers_on_mark_price() {
+        let mut state = state();
+        state
+            .place(&order(&[
+                ("side", "BUY"),
+                ("type", "MARKET"),
+                ("quantity", "1"),
+            ]))
+            .unwrap();
+        let stop = order(&[
+            ("side", "SELL"),
+            ("type", "STOP_MARKET"),
+            ("stopPrice", "95"),
+            ("closePosition", "TRUE"),
+            ("workingType", "MARK_PRICE"),
+        ]);
+        let immediate = order(&[
+            ("side", "SELL"),
+            ("type", "STOP_MARKET"),
+            ("stopPrice", "101"),
+            ("closePosition", "TRUE"),
+        ]);
+        assert_eq!(
+            error_code(state.place(&immediate)),
+            WOULD_IMMEDIATELY_TRIGGER
+        );
+        state.place(&stop).unwrap();
+        // the trades do not trigger a mark price stop
+        state.on_trade("BTCUSDT", 94.0, 1.0);
+        assert_eq!(state.position_amount("BTCUSDT", BOTH), 1.0);
+
+        state.on_mark_price("BTCUSDT", 94.5);
+        assert_eq!(state.position_amount("BTCUSDT", BOTH), 0.0);
+        assert!(state.open_orders.is_empty());
+        let fill = order_updates(&mut state).pop().unwrap();
+        assert_eq!(fill.order_type, MARKET);
+        assert_eq!(fill.original_order_type, STOP_MARKET);
+        assert_eq!(fill.price_last_filled_trade, "94");
+        assert_eq!(fill.realized_profit, "-6");
+    }
+
+    #[test]
+    fn test_margin_and_reduce_only_checks() {
+        let mut state = state();
+        // 1000 of margin at 10x leverage buys 100 at most
+        let too_large = order(&[("side", "BUY"), ("type", "MARKET"), ("quantity", "101")]);
+        assert_eq!(error_code(state.place(&too_large)), MARGIN_INSUFFICIENT);
+        state
+            .place(&order(&[
+                ("side", "BUY"),
+                ("type", "MARKET"),
+                ("quantity", "2"),
+            ]))
+            .unwrap();
+
+        let reduce = order(&[
+            ("side", "SELL"),
+            ("type", "MARKET"),
+            ("quantity", "5"),
+            ("reduceOnly", "TRUE"),
+        ]);
+        state.updates.clear();
+        state.place(&reduce).unwrap();
+        let updates = order_updates(&mut state);
+        assert_eq!(updates[1].qty_last_filled_trade, "2");
+        assert_eq!(updates[2].order_status, EXPIRED);
+        assert_eq!(state.position_amount("BTCUSDT", BOTH), 0.0);
+
+        let hedge = order(&[
+            ("side", "BUY"),
+            ("type", "MARKET"),
+            ("quantity", "1"),
+            ("positionSide", "LONG"),
+        ]);
+        assert_eq!(error_code(state.place(&hedge)), POSITION_SIDE_MISMATCH);
+        state.change_position_mode(true).unwrap();
+        state.place(&hedge).unwrap();
+        assert_eq!(state.position_amount("BTCUSDT", LONG), 1.0);
+        assert!(state.change_position_mode(false).is_err());
+    }
+
+    #[test]
+    fn test_liquidation_closes_the_positions_at_mark_price() {
+        let mut state = state();
+        state
+            .place(&order(&[
+                ("side", "BUY"),
+                ("type", "MARKET"),
+                ("quantity", "90"),
+            ]))
+            .unwrap();
+        state.on_mark_price("BTCUSDT", 95.0);
+        assert_eq!(state.position_amount("BTCUSDT", BOTH), 90.0);
+
+        state.on_mark_price("BTCUSDT", 88.0);
+        assert_eq!(state.position_amount("BTCUSDT", BOTH), 0.0);
+        // the loss is larger than the wallet
+        assert_eq!(state.wallet, 0.0);
+        let liquidation = order_updates(&mut state).pop().unwrap();
+        assert_eq!(liquidation.original_order_type, LIQUIDATION);
+        assert!(liquidation.new_client_order_id.starts_with("autoclose-"));
+    }
+}
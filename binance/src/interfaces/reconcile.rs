@@ -0,0 +1,307 @@
+use crate::rest::futures::model::{Order, OrderUpdate, PositionRisk};
+use crate::rest::model::EventPosition;
+use crate::websocket::futures::usdm_data::WsData;
+use crate::websocket::futures::usdm_events::Discrepancy;
+use std::collections::HashMap;
+
+// not a Binance execution type, marks the order updates built from REST
+pub const RECONCILED: &str = "RECONCILED";
+const EPSILON: f64 = 1e-9;
+
+/// Outcome of a reconciliation, every discrepancy was corrected
+#[derive(Debug, Clone, Default)]
+pub struct ReconciliationReport {
+    pub orders_checked: usize,
+    pub positions_checked: usize,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+impl ReconciliationReport {
+    pub fn is_consistent(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+}
+
+/// Change to apply to the websocket data
+#[derive(Debug, Clone)]
+pub(crate) enum Correction {
+    Order(Box<OrderUpdate>),
+    RemoveOrder(u64),
+    Position(EventPosition),
+}
+
+/// Orders of the websocket data of `symbol` that differ from `orders`, and the open ones
+/// in `unknown` that Binance did not find
+pub(crate) fn diff_orders(
+    symbol: &str,
+    ws_data: &WsData,
+    orders: &HashMap<u64, Order>,
+    unknown: &[u64],
+) -> Vec<(Discrepancy, Correction)> {
+    let mut diffs = vec![];
+    for order in orders.values() {
+        let ws_order = ws_data
+            .get_open_order(order.order_id)
+            .or_else(|| ws_data.get_filled_order(order.order_id))
+            .or_else(|| ws_data.get_canceled_order(order.order_id));
+        if let Some(ws_order) = &ws_order {
+            // an update received after the REST snapshot is kept
+            let up_to_date = ws_order.order_status == order.status
+                && parse(&ws_order.accumulated_qty_filled_trades) == Some(order.executed_qty);
+            if up_to_date || ws_order.trade_order_time > order.update_time {
+                continue;
+            }
+        }
+        diffs.push((
+            Discrepancy::Order {
+                symbol: symbol.to_owned(),
+                order_id: order.order_id,
+                ws_status: ws_order.map(|ws_order| ws_order.order_status),
+                rest_status: order.status.to_owned(),
+            },
+            Correction::Order(Box::new(order_update(order))),
+        ));
+    }
+    for order_id in unknown {
+        if ws_data.get_open_order(*order_id).is_some() {
+            diffs.push((
+                Discrepancy::UnknownOrder {
+                    symbol: symbol.to_owned(),
+                    order_id: *order_id,
+                },
+                Correction::RemoveOrder(*order_id),
+            ));
+        }
+    }
+    diffs
+}
+
+/// Positions of the websocket data that differ from `positions`
+pub(crate) fn diff_positions(
+    ws_data: &WsData,
+    positions: &[PositionRisk],
+) -> Vec<(Discrepancy, Correction)> {
+    let mut diffs = vec![];
+    for position in positions {
+        let ws_position = ws_data
+            .get_positions_event()
+            .into_iter()
+            .find(|ws_position| ws_position.position_side == position.position_side);
+        let up_to_date = match &ws_position {
+            Some(ws_position) => {
+                same(&ws_position.position_amount, position.position_amount)
+                    && (position.position_amount == 0.0
+                        || same(&ws_position.entry_price, position.entry_price))
+            }
+            None => position.position_amount == 0.0,
+        };
+        if up_to_date {
+            continue;
+        }
+        diffs.push((
+            Discrepancy::Position {
+                symbol: position.symbol.to_owned(),
+                position_side: position.position_side.to_owned(),
+                ws_amount: ws_position
+                    .as_ref()
+                    .map(|ws_position| ws_position.position_amount.to_owned()),
+                rest_amount: position.position_amount.to_string(),
+            },
+            Correction::Position(event_position(position, ws_position.as_ref())),
+        ));
+    }
+    diffs
+}
+
+/// Order update of the REST state of `order`, with no last trade
+pub(crate) fn order_update(order: &Order) -> OrderUpdate {
+    OrderUpdate {
+        symbol: order.symbol.to_owned(),
+        new_client_order_id: order.client_order_id.to_owned(),
+        side: order.side.to_owned(),
+        order_type: order.order_type.to_owned(),
+        time_in_force: order.time_in_force.to_owned(),
+        qty: order.orig_qty.to_string(),
+        price: order.price.to_string(),
+        average_price: order.avg_price.to_string(),
+        stop_price: order.stop_price.to_string(),
+        execution_type: RECONCILED.to_owned(),
+        order_status: order.status.to_owned(),
+        order_id: order.order_id,
+        qty_last_filled_trade: "0".to_owned(),
+        accumulated_qty_filled_trades: order.executed_qty.to_string(),
+        price_last_filled_trade: "0".to_owned(),
+        asset_commisioned: None,
+        commission: None,
+        trade_order_time: order.update_time,
+        trade_id: 0,
+        bids_notional: "0".to_owned(),
+        ask_notional: "0".to_owned(),
+        is_buyer_maker: false,
+        is_reduce_only: order.reduce_only,
+        stop_price_working_type: order.working_type.to_owned(),
+        original_order_type: order.orig_type.to_owned(),
+        position_side: order.position_side.to_owned(),
+        close_all: Some(order.close_position),
+        activation_price: Some(order.activation_price.to_string()),
+        callback_rate: Some(order.price_rate.to_string()),
+        pp_ignore: order.price_protect,
+        si_ignore: 0,
+        ss_ignore: 0,
+        realized_profit: "0".to_owned(),
+    }
+}
+
+// the realized profit is not part of the position risk, the last known one is kept
+fn event_position(position: &PositionRisk, previous: Option<&EventPosition>) -> EventPosition {
+    EventPosition {
+        symbol: position.symbol.to_owned(),
+        position_amount: position.position_amount.to_string(),
+        entry_price: position.entry_price.to_string(),
+        accumulated_realized: previous
+            .map(|previous| previous.accumulated_realized.to_owned())
+            .unwrap_or_else(|| "0".to_owned()),
+        unrealized_pnl: position.unrealized_profit.to_string(),
+        margin_type: position.margin_type.to_owned(),
+        isolated_wallet: position.isolated_wallet.to_string(),
+        position_side: position.position_side.to_owned(),
+    }
+}
+
+fn parse(value: &str) -> Option<f64> {
+    value.parse().ok()
+}
+
+fn same(value: &str, expected: f64) -> bool {
+    parse(value).is_some_and(|value| (value - expected).abs() < EPSILON)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(order_id: u64, status: &str, executed_qty: f64, update_time: u64) -> Order {
+        let mut order: Order = serde_json::from_str(
+            r#"{"avgPrice":"0.00000","clientOrderId":"abc","cumQuote":"0","executedQty":"0",
+            "orderId":1,"origQty":"0.40","origType":"LIMIT","price":"20000","reduceOnly":false,
+            "side":"BUY","positionSide":"BOTH","status":"NEW","stopPrice":"0",
+            "closePosition":false,"symbol":"BTCUSDT","timeInForce":"GTC","type":"LIMIT",
+            "updateTime":0,"workingType":"CONTRACT_PRICE","priceProtect":false}"#,
+        )
+        .unwrap();
+        order.order_id = order_id;
+        order.status = status.to_owned();
+        order.executed_qty = executed_qty;
+        order.cum_qty = executed_qty;
+        order.update_time = update_time;
+        order
+    }
+
+    fn position(position_amount: f64, entry_price: f64) -> PositionRisk {
+        let mut position: PositionRisk = serde_json::from_str(
+            r#"{"entryPrice":"0","marginType":"cross","isAutoAddMargin":"false",
+            "isolatedMargin":"0","leverage":"10","liquidationPrice":"0","markPrice":"20000",
+            "maxNotionalValue":"250000","positionAmt":"0","symbol":"BTCUSDT",
+            "unRealizedProfit":"0","positionSide":"BOTH","notional":"0","isolatedWallet":"0",
+            "updateTime":0}"#,
+        )
+        .unwrap();
+        position.position_amount = position_amount;
+        position.entry_price = entry_price;
+        position
+    }
+
+    fn orders(orders: Vec<Order>) -> HashMap<u64, Order> {
+        orders
+            .into_iter()
+            .map(|order| (order.order_id, order))
+            .collect()
+    }
+
+    #[test]
+    fn corrects_missed_order_updates() {
+        let ws_data = WsData::default();
+        ws_data.add_order(order_update(&order(1, "NEW", 0.0, 10)));
+        ws_data.add_order(order_update(&order(2, "NEW", 0.0, 10)));
+        ws_data.add_order(order_update(&order(3, "NEW", 0.0, 10)));
+
+        // 1 unchanged, 2 filled and 4 placed while disconnected, 3 gone
+        let rest = orders(vec![
+            order(1, "NEW", 0.0, 10),
+            order(2, "FILLED", 0.4, 20),
+            order(4, "NEW", 0.0, 20),
+        ]);
+        let diffs = diff_orders("BTCUSDT", &ws_data, &rest, &[3]);
+        let mut discrepancies: Vec<Discrepancy> = diffs
+            .iter()
+            .map(|(discrepancy, _)| discrepancy.clone())
+            .collect();
+        discrepancies.sort_by_key(|discrepancy| match discrepancy {
+            Discrepancy::Order { order_id, .. } | Discrepancy::UnknownOrder { order_id, .. } => {
+                *order_id
+            }
+            Discrepancy::Position { .. } => 0,
+        });
+        assert_eq!(
+            discrepancies,
+            vec![
+                Discrepancy::Order {
+                    symbol: "BTCUSDT".into(),
+                    order_id: 2,
+                    ws_status: Some("NEW".into()),
+                    rest_status: "FILLED".into(),
+                },
+                Discrepancy::UnknownOrder {
+                    symbol: "BTCUSDT".into(),
+                    order_id: 3,
+                },
+                Discrepancy::Order {
+                    symbol: "BTCUSDT".into(),
+                    order_id: 4,
+                    ws_status: None,
+                    rest_status: "NEW".into(),
+                },
+            ]
+        );
+        let filled = diffs
+            .iter()
+            .find_map(|(_, correction)| match correction {
+                Correction::Order(order) if order.order_id == 2 => Some(order),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(filled.accumulated_qty_filled_trades, "0.4");
+        assert_eq!(filled.execution_type, RECONCILED);
+    }
+
+    #[test]
+    fn keeps_updates_newer_than_the_snapshot() {
+        let ws_data = WsData::default();
+        ws_data.add_order(order_update(&order(1, "FILLED", 0.4, 30)));
+        let rest = orders(vec![order(1, "NEW", 0.0, 20)]);
+        assert!(diff_orders("BTCUSDT", &ws_data, &rest, &[]).is_empty());
+    }
+
+    #[test]
+    fn corrects_positions() {
+        let ws_data = WsData::default();
+        assert!(diff_positions(&ws_data, &[position(0.0, 0.0)]).is_empty());
+
+        let diffs = diff_positions(&ws_data, &[position(0.5, 20000.0)]);
+        assert_eq!(
+            diffs[0].0,
+            Discrepancy::Position {
+                symbol: "BTCUSDT".into(),
+                position_side: "BOTH".into(),
+                ws_amount: None,
+                rest_amount: "0.5".into(),
+            }
+        );
+        let Correction::Position(corrected) = &diffs[0].1 else {
+            panic!("unexpected correction {:?}", diffs[0].1);
+        };
+        ws_data.update_position(corrected.clone());
+        assert!(diff_positions(&ws_data, &[position(0.5, 20000.0)]).is_empty());
+        assert_eq!(diff_positions(&ws_data, &[position(0.5, 21000.0)]).len(), 1);
+    }
+}
@@ -0,0 +1,219 @@
+use crate::commons::errors::BinanceError;
+use std::time::Duration;
+
+// https://binance-docs.github.io/apidocs/futures/en/#error-codes
+const UNKNOWN: i16 = -1000;
+const DISCONNECTED: i16 = -1001;
+const TOO_MANY_REQUESTS: i16 = -1003;
+const TIMEOUT: i16 = -1007;
+const SERVER_BUSY: i16 = -1008;
+const INVALID_TIMESTAMP: i16 = -1021;
+
+/// Class of a failed REST request, `RetryPolicy::retry_on` lists the retried ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The request or its response was lost on the way, it may have been executed
+    Network,
+    /// Binance failed or timed out internally, the request may have been executed
+    Server,
+    /// Too many requests or IP banned for a while
+    RateLimit,
+    /// Timestamp outside of the receive window, retried with a new timestamp
+    Timestamp,
+    /// Rejected by Binance or not a request error, never retried
+    Rejected,
+}
+
+impl ErrorClass {
+    pub fn of(err: &BinanceError) -> ErrorClass {
+        match err {
+            BinanceError::BinanceError { response } => match response.code {
+                UNKNOWN | DISCONNECTED | TIMEOUT | SERVER_BUSY => ErrorClass::Server,
+                TOO_MANY_REQUESTS => ErrorClass::RateLimit,
+                INVALID_TIMESTAMP => ErrorClass::Timestamp,
+                _ if response.msg == "Service Unavailable." => ErrorClass::Server,
+                _ => ErrorClass::Rejected,
+            },
+            BinanceError::ReqError(e) if e.is_decode() => ErrorClass::Rejected,
+            BinanceError::ReqError(_) | BinanceError::RequestError(_) => ErrorClass::Network,
+            BinanceError::UnkownStatusCode(status) => match status.as_u16() {
+                418 | 429 => ErrorClass::RateLimit,
+                500..=599 => ErrorClass::Server,
+                _ => ErrorClass::Rejected,
+            },
+            _ => ErrorClass::Rejected,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts of a request, the first one included
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    /// No attempt starts later than this after the first one
+    pub deadline: Duration,
+    pub retry_on: Vec<ErrorClass>,
+    /// Order placement is retried only after its generated `newClientOrderId`
+    /// shows the earlier attempt did not land
+    pub retry_orders: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(300),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2.0,
+            deadline: Duration::from_secs(30),
+            retry_on: vec![
+                ErrorClass::Network,
+                ErrorClass::Server,
+                ErrorClass::RateLimit,
+                ErrorClass::Timestamp,
+            ],
+            retry_orders: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Every request is attempted once
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            retry_on: vec![],
+            retry_orders: false,
+            ..Self::default()
+        }
+    }
+
+    pub fn set_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn set_backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn set_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn set_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    pub fn set_retry_on(mut self, retry_on: &[ErrorClass]) -> Self {
+        self.retry_on = retry_on.to_vec();
+        self
+    }
+
+    pub fn set_retry_orders(mut self, retry_orders: bool) -> Self {
+        self.retry_orders = retry_orders;
+        self
+    }
+
+    /// Delay before the `attempt`th retry (starting at 1)
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self.multiplier.powi(attempt.saturating_sub(1) as i32);
+        self.initial_backoff
+            .mul_f64(exp.min(u32::MAX as f64))
+            .min(self.max_backoff)
+    }
+
+    /// Delay before retrying `err` after `attempts` attempts started `elapsed` ago,
+    /// `None` if it is not retried
+    pub fn next_retry(
+        &self,
+        err: &BinanceError,
+        attempts: u32,
+        elapsed: Duration,
+    ) -> Option<Duration> {
+        if attempts >= self.max_attempts || !self.retry_on.contains(&ErrorClass::of(err)) {
+            return None;
+        }
+        let delay = self.backoff(attempts);
+        (elapsed + delay <= self.deadline).then_some(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commons::errors::BinanceContentError;
+    use reqwest::StatusCode;
+
+    fn api_error(code: i16) -> BinanceError {
+        BinanceError::BinanceError {
+            response: BinanceContentError {
+                code,
+                msg: String::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn classifies_errors() {
+        assert_eq!(ErrorClass::of(&api_error(-1001)), ErrorClass::Server);
+        assert_eq!(ErrorClass::of(&api_error(-1003)), ErrorClass::RateLimit);
+        assert_eq!(ErrorClass::of(&api_error(-1021)), ErrorClass::Timestamp);
+        assert_eq!(ErrorClass::of(&api_error(-2019)), ErrorClass::Rejected);
+        assert_eq!(
+            ErrorClass::of(&BinanceError::UnkownStatusCode(
+                StatusCode::TOO_MANY_REQUESTS
+            )),
+            ErrorClass::RateLimit
+        );
+        assert_eq!(
+            ErrorClass::of(&BinanceError::UnkownStatusCode(StatusCode::BAD_GATEWAY)),
+            ErrorClass::Server
+        );
+        assert_eq!(
+            ErrorClass::of(&BinanceError::RequestError("reset".into())),
+            ErrorClass::Network
+        );
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_cap() {
+        let policy = RetryPolicy::default()
+            .set_backoff(Duration::from_millis(100), Duration::from_millis(350))
+            .set_multiplier(2.0);
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn stops_at_max_attempts_deadline_or_unlisted_class() {
+        let policy = RetryPolicy::default()
+            .set_max_attempts(3)
+            .set_backoff(Duration::from_millis(100), Duration::from_secs(1))
+            .set_deadline(Duration::from_secs(1))
+            .set_retry_on(&[ErrorClass::Server]);
+        let err = api_error(-1000);
+        assert_eq!(
+            policy.next_retry(&err, 1, Duration::ZERO),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(policy.next_retry(&err, 3, Duration::ZERO), None);
+        assert_eq!(policy.next_retry(&err, 1, Duration::from_millis(950)), None);
+        assert_eq!(
+            policy.next_retry(&api_error(-1003), 1, Duration::ZERO),
+            None
+        );
+        assert_eq!(
+            RetryPolicy::none().next_retry(&err, 1, Duration::ZERO),
+            None
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use crate::commons::config::Config;
+use crate::commons::errors::*;
+use crate::rest::api::Binance;
+use crate::rest::model::{AggrTradesEvent, Balance, BookTickerEvent, OrderTradeEvent};
+use crate::rest::spot::account::Account;
+use crate::rest::spot::market::Market;
+use crate::websocket::spot::interface::{SpotWsInterface, DEFAULT_READY_TIMEOUT};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Spot counterpart of `UsdmInterface`, REST through `account` and `market`
+/// and websocket cached balances, orders, trades and book ticker
+#[derive(Clone)]
+pub struct SpotInterface {
+    symbol: String,
+    pub account: Account,
+    pub market: Market,
+    pub ws: SpotWsInterface,
+}
+
+impl SpotInterface {
+    /// Binance spot interface,
+    /// subscribes to @aggTrade and @bookTicker and, with an api key, to the user data stream
+    /// * `symbol` - String
+    /// * `api_key` - Option<String>
+    /// * `api_secret` - Option<String>
+    /// * `config` - Config
+    ///
+    /// Returns a `Timeout` error if the websockets are not ready within `DEFAULT_READY_TIMEOUT`
+    pub fn new(
+        symbol: String,
+        api_key: Option<String>,
+        api_secret: Option<String>,
+        config: &Config,
+    ) -> Result<SpotInterface> {
+        SpotInterface::with_ready_timeout(
+            symbol,
+            api_key,
+            api_secret,
+            config,
+            DEFAULT_READY_TIMEOUT,
+        )
+    }
+
+    /// Binance spot interface
+    /// * `ready_timeout` - time to receive a book ticker and, with an `api_key`,
+    ///   to connect the user stream
+    pub fn with_ready_timeout(
+        symbol: String,
+        api_key: Option<String>,
+        api_secret: Option<String>,
+        config: &Config,
+        ready_timeout: Duration,
+    ) -> Result<SpotInterface> {
+        Ok(SpotInterface {
+            symbol: symbol.to_owned(),
+            account: Binance::new_with_config(api_key.to_owned(), api_secret.to_owned(), config),
+            market: Binance::new_with_config(api_key.to_owned(), api_secret.to_owned(), config),
+            ws: SpotWsInterface::with_ready_timeout(
+                symbol,
+                api_key,
+                api_secret,
+                config,
+                ready_timeout,
+            )?,
+        })
+    }
+
+    /// Stops the websockets and their threads and closes the listen key, blocks until done.
+    /// Orders on the exchange are left untouched
+    pub fn shutdown(&self) {
+        self.ws.shutdown();
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// Get aggr_trades
+    pub fn get_aggr_trades_ws(&self) -> VecDeque<AggrTradesEvent> {
+        self.ws.get_aggr_trades()
+    }
+
+    /// Get last price from websocket
+    pub fn get_last_price_ws(&self) -> Option<f64> {
+        if let Some(trade) = self.ws.get_last_aggr_trade() {
+            return Some(trade.price.parse().unwrap());
+        }
+        None
+    }
+
+    /// Get book ticker
+    pub fn get_book_ticker_ws(&self) -> Option<BookTickerEvent> {
+        self.ws.get_book_ticker()
+    }
+
+    /// Get best bid price
+    pub fn get_best_bid_ws(&self) -> Option<f64> {
+        if let Some(book_ticker) = self.get_book_ticker_ws() {
+            return Some(book_ticker.best_bid.parse().unwrap());
+        }
+        None
+    }
+
+    /// Get best ask price
+    pub fn get_best_ask_ws(&self) -> Option<f64> {
+        if let Some(book_ticker) = self.get_book_ticker_ws() {
+            return Some(book_ticker.best_ask.parse().unwrap());
+        }
+        None
+    }
+
+    /// Get balances
+    pub fn get_balances_ws(&self) -> Vec<Balance> {
+        self.ws.get_balances()
+    }
+
+    /// Get balance of an asset
+    pub fn get_balance_ws(&self, asset: &str) -> Option<Balance> {
+        self.ws.get_balance(asset)
+    }
+
+    /// Get free balance of an asset
+    pub fn get_balance_free_ws(&self, asset: &str) -> Option<f64> {
+        if let Some(balance) = self.get_balance_ws(asset) {
+            return Some(balance.free.parse().unwrap());
+        }
+        None
+    }
+
+    /// Get balance of an asset locked in open orders
+    pub fn get_balance_locked_ws(&self, asset: &str) -> Option<f64> {
+        if let Some(balance) = self.get_balance_ws(asset) {
+            return Some(balance.locked.parse().unwrap());
+        }
+        None
+    }
+
+    /// Get open orders
+    pub fn get_open_orders_ws(&self) -> VecDeque<OrderTradeEvent> {
+        self.ws.get_open_orders()
+    }
+
+    /// Get filled orders
+    pub fn get_filled_orders_ws(&self) -> VecDeque<OrderTradeEvent> {
+        self.ws.get_filled_orders()
+    }
+
+    /// Get canceled orders
+    pub fn get_canceled_orders_ws(&self) -> VecDeque<OrderTradeEvent> {
+        self.ws.get_canceled_orders()
+    }
+
+    /// Returns true of order is open, false otherwise
+    pub fn is_open_orders_ws(&self, order_id: u64) -> bool {
+        self.get_open_orders_ws()
+            .iter()
+            .any(|ord| ord.order_id == order_id)
+    }
+
+    /// Returns true if order is filled, false otherwise
+    pub fn is_filled_orders_ws(&self, order_id: u64) -> bool {
+        self.get_filled_orders_ws()
+            .iter()
+            .any(|ord| ord.order_id == order_id)
+    }
+
+    /// Returns true if order is canceled, false otherwise
+    pub fn is_canceled_orders_ws(&self, order_id: u64) -> bool {
+        self.get_canceled_orders_ws()
+            .iter()
+            .any(|ord| ord.order_id == order_id)
+    }
+
+    /// Get order
+    ///
+    /// * `order_id` - id of order
+    pub fn get_order_ws(&self, order_id: u64) -> Option<OrderTradeEvent> {
+        self.ws.get_order(order_id)
+    }
+
+    /// Get last order filled
+    pub fn get_last_filled_order_ws(&self) -> Option<OrderTradeEvent> {
+        self.ws.get_filled_orders().back().cloned()
+    }
+
+    /// Get last order canceled
+    pub fn get_last_canceled_order_ws(&self) -> Option<OrderTradeEvent> {
+        self.ws.get_canceled_orders().back().cloned()
+    }
+
+    /// Get last open order
+    pub fn get_last_open_order_ws(&self) -> Option<OrderTradeEvent> {
+        self.ws.get_open_orders().back().cloned()
+    }
+}
@@ -1,15 +1,21 @@
 use crate::commons::config::Config;
 use crate::commons::errors::*;
 use crate::commons::util::{build_request, build_signed_request};
+use crate::commons::workers::{sleep, Workers};
+use crate::interfaces::ledger::Ledger;
+use crate::interfaces::paper::{OrderRef, PaperExchange};
+use crate::interfaces::reconcile::{diff_orders, diff_positions, Correction, ReconciliationReport};
 use crate::interfaces::usdm_data::{UsdmConfig, UsdmData};
 use crate::rest::api::{Futures, API};
 use crate::rest::client::Client;
-use crate::rest::futures::account::{CustomOrderRequest, OrderRequest, OrderType};
+use crate::rest::futures::account::{
+    build_order_parameters, CustomOrderRequest, OrderRequest, OrderType, PositionSide, WorkingType,
+};
 use crate::rest::futures::model::{
     AccountBalance, AccountInformation, AggTrades, CanceledOrder, ChangeLeverageResponse,
-    ComissionRate, ExchangeInformation, FundingRateHist, LiquidationOrders, MarkPrice, MarkPrices,
-    OpenInterest, OpenInterestHist, Order, OrderBook, OrderUpdate, PositionRisk, PriceStats,
-    Symbol, Trades, Transaction,
+    ComissionRate, CountdownCancelAll, ExchangeInformation, FundingRateHist, Income,
+    LiquidationOrders, MarkPrice, MarkPrices, OpenInterest, OpenInterestHist, Order, OrderBook,
+    OrderUpdate, PositionRisk, PriceStats, Symbol, Trades, Transaction,
 };
 use crate::rest::model::KlineSummaries::AllKlineSummaries;
 use crate::rest::model::{
@@ -17,14 +23,41 @@ use crate::rest::model::{
     KlineSummaries, KlineSummary, LiquidationOrder, Prices, ServerTime, SymbolPrice, Tickers,
 };
 use crate::rest::spot::account::{OrderSide, TimeInForce};
+use crate::websocket::candles::{Candle, CandleInterval};
+use crate::websocket::channel::{EventReceiver, OverflowPolicy};
+use crate::websocket::feed::LatencyStats;
 use crate::websocket::futures::usdm::WsInterface;
-use log::error;
+use crate::websocket::futures::usdm_data::WsData;
+use crate::websocket::futures::usdm_events::{UsdmEvent, UsdmEventKind};
+use indexmap::IndexMap;
+use log::{debug, error, warn};
 use serde::de::DeserializeOwned;
 use serde_json::Value;
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::convert::TryInto;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// observer id of a bracket whose watch is not registered yet
+const UNREGISTERED: u64 = u64::MAX;
+const SYMBOLS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(10);
+const RECONCILE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+// orders placed shortly before the last reconciliation are checked again, covers clock skew
+const RECONCILE_OVERLAP: u64 = 60000; // milliseconds
+
+// https://binance-docs.github.io/apidocs/futures/en/#error-codes
+const ORDER_NOT_FOUND: i16 = -2013;
+// recvWindow of a signed request without one, milliseconds
+const DEFAULT_RECV_WINDOW: u64 = 5000;
+// a timestamp may be ahead of the Binance clock by this much
+const MAX_TIMESTAMP_AHEAD: u64 = 1000; // milliseconds
+const ORDER_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+const INCOME_PAGE_SIZE: u16 = 1000;
+const ALL_ORDERS_PAGE_SIZE: u16 = 1000;
+const ALL_ORDERS_LIMIT: u16 = 500; // default of allOrders
 
 enum RequestType {
     Get,
@@ -33,14 +66,54 @@ enum RequestType {
     DeleteSigned,
 }
 
+/// Entry and exit orders of `UsdmInterface::bracket_order`, the handle to `cancel_bracket`
+#[derive(Debug, Clone)]
+pub struct BracketOrder {
+    pub entry: Transaction,
+    pub take_profit: Transaction,
+    pub stop_loss: Transaction,
+    watch: BracketWatch,
+}
+
+impl BracketOrder {
+    /// False once a leg closed the bracket and the other legs were canceled,
+    /// or once the bracket was canceled
+    pub fn is_active(&self) -> bool {
+        !self.watch.resolved.load(Ordering::Acquire)
+    }
+}
+
+// order status observer of a bracket, shared by its clones
+#[derive(Debug, Clone)]
+struct BracketWatch {
+    observer_id: Arc<AtomicU64>,
+    resolved: Arc<AtomicBool>,
+}
+
+impl Default for BracketWatch {
+    fn default() -> BracketWatch {
+        BracketWatch {
+            observer_id: Arc::new(AtomicU64::new(UNREGISTERED)),
+            resolved: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+// uppercase symbol to its data, the first symbol backs the single symbol getters
+type SymbolsData = Arc<RwLock<IndexMap<String, UsdmData>>>;
+
 #[derive(Clone)]
 pub struct UsdmInterface {
-    symbol: String,
+    symbols: SymbolsData,
     pub api: Client,
     recv_window: u64,
     pub ws: WsInterface,
-    data: UsdmData,
     config: UsdmConfig,
+    workers: Workers,
+    // orders placed since this time are pulled by the next reconciliation, milliseconds
+    reconciled_at: Arc<RwLock<u64>>,
+    // simulated account of the paper trading mode
+    paper: Option<PaperExchange>,
 }
 
 impl UsdmInterface {
@@ -50,44 +123,200 @@ impl UsdmInterface {
     /// * `api_key` - Option<String>
     /// * `api_secret` - Option<String>
     /// * `config` - Config
+    ///
+    /// Returns a `Timeout` error if the websockets or the klines are not ready
+    /// within `config.ready_timeout`, an `InvalidConfig` error if `config` does not validate
     pub fn new(
         symbol: String,
         api_key: Option<String>,
         api_secret: Option<String>,
         client_config: &Config,
         config: UsdmConfig,
-    ) -> UsdmInterface {
+    ) -> Result<UsdmInterface> {
+        UsdmInterface::new_multi(vec![symbol], api_key, api_secret, client_config, config)
+    }
+
+    /// Binance USDM futures interface for several symbols, with one combined market stream,
+    /// one user data stream and one klines polling thread
+    /// * `symbols` - at least one symbol, the first one backs the single symbol getters
+    /// * `api_key` - Option<String>
+    /// * `api_secret` - Option<String>
+    /// * `config` - Config
+    pub fn new_multi(
+        symbols: Vec<String>,
+        api_key: Option<String>,
+        api_secret: Option<String>,
+        client_config: &Config,
+        config: UsdmConfig,
+    ) -> Result<UsdmInterface> {
+        config.validate()?;
+        let ready_timeout = Duration::from_millis(config.ready_timeout);
+        // a paper account never reaches the real one
+        let (api_key, api_secret) = match config.paper_trading {
+            Some(_) => (None, None),
+            None => (api_key, api_secret),
+        };
+        // reconciliation needs the signed endpoints
+        let reconciles = api_key.is_some();
         let client = Client::new(
             api_key.to_owned(),
             api_secret.to_owned(),
             client_config.futures_rest_api_endpoint.clone(),
         );
         let usdm_int = UsdmInterface {
-            symbol: symbol.to_owned(),
+            symbols: Arc::new(RwLock::new(
+                symbols
+                    .iter()
+                    .map(|symbol| (symbol.to_uppercase(), UsdmData::default()))
+                    .collect(),
+            )),
             api: client,
             recv_window: client_config.recv_window,
-            ws: WsInterface::new(symbol, api_key, api_secret, client_config),
-            data: UsdmData::default(),
+            ws: WsInterface::with_data_config(
+                symbols,
+                api_key,
+                api_secret,
+                client_config,
+                ready_timeout,
+                config.ws_data,
+            )?,
             config,
+            workers: Workers::default(),
+            reconciled_at: Arc::new(RwLock::new(timestamp())),
+            paper: None,
         };
+        let usdm_int = match usdm_int.config.paper_trading.to_owned() {
+            Some(paper_config) => {
+                let paper = PaperExchange::new(paper_config, usdm_int.ws.to_owned());
+                let exchange = paper.to_owned();
+                usdm_int.ws.on_event(
+                    &[
+                        UsdmEventKind::AggrTrade,
+                        UsdmEventKind::MarkPrice,
+                        UsdmEventKind::BookTicker,
+                    ],
+                    move |event| exchange.apply(event),
+                );
+                UsdmInterface {
+                    paper: Some(paper),
+                    ..usdm_int
+                }
+            }
+            None => usdm_int,
+        };
+        for symbol in usdm_int.get_symbols() {
+            if let Some(ws_data) = usdm_int.get_symbol_ws(&symbol) {
+                ws_data.set_candle_intervals(&usdm_int.config.candle_intervals);
+            }
+        }
         update_usdm_data(usdm_int.to_owned());
-        usdm_int.wait_for_data();
-        usdm_int
+        if usdm_int.paper.is_none() {
+            auto_cancel_heartbeat(usdm_int.to_owned());
+        }
+        if reconciles {
+            reconcile_worker(usdm_int.to_owned());
+        }
+        if let Err(e) = usdm_int.wait_for_data(ready_timeout) {
+            usdm_int.shutdown();
+            return Err(e);
+        }
+        Ok(usdm_int)
     }
 
-    fn wait_for_data(&self) {
+    fn wait_for_data(&self, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
         loop {
-            let AllKlineSummaries(k_lines) = self.get_last_day_klines();
-            if !k_lines.is_empty() {
-                break;
+            let missing = self
+                .symbols
+                .read()
+                .unwrap()
+                .iter()
+                .find(|(_, data)| {
+                    let AllKlineSummaries(k_lines) = data.get_last_day_klines();
+                    k_lines.is_empty()
+                })
+                .map(|(symbol, _)| symbol.to_owned());
+            let Some(symbol) = missing else {
+                return Ok(());
+            };
+            if Instant::now() >= deadline {
+                return Err(BinanceError::Timeout(format!(
+                    "No klines of {symbol} after {timeout:?}"
+                )));
             }
-            thread::yield_now();
+            thread::sleep(READY_POLL_INTERVAL);
         }
     }
 
+    /// Stops the REST polling and auto-cancel threads and the websockets,
+    /// closes the listen key, blocks until done. Orders on the exchange are left untouched
+    pub fn shutdown(&self) {
+        self.workers.shutdown();
+        self.ws.shutdown();
+    }
+
+    /// Adds `symbol` to the websocket streams, the klines polling and the auto-cancel heartbeat.
+    /// Its klines are loaded before returning, its websocket data fills as events arrive
+    pub fn add_symbol(&self, symbol: &str) -> Result<()> {
+        let symbol = symbol.to_uppercase();
+        if self.symbols.read().unwrap().contains_key(&symbol) {
+            return Ok(());
+        }
+        let klines = self.get_klines(symbol.to_owned(), "1m", 1440, None, None)?;
+        self.ws.add_symbol(&symbol)?;
+        if let Some(ws_data) = self.get_symbol_ws(&symbol) {
+            ws_data.set_candle_intervals(&self.config.candle_intervals);
+            // every trade received so far is newer than the klines
+            ws_data.seed_candles(&klines, None)?;
+        }
+        let mut data = UsdmData::default();
+        data.set_last_day_klines(klines);
+        self.symbols.write().unwrap().insert(symbol, data);
+        Ok(())
+    }
+
+    /// Removes `symbol`, its open orders are left untouched
+    pub fn remove_symbol(&self, symbol: &str) -> Result<()> {
+        let symbol = symbol.to_uppercase();
+        if self
+            .symbols
+            .write()
+            .unwrap()
+            .shift_remove(&symbol)
+            .is_none()
+        {
+            return Err(BinanceError::SymbolNotFound);
+        }
+        self.ws.remove_symbol(&symbol)
+    }
+
+    /// Get symbols, uppercase
+    pub fn get_symbols(&self) -> Vec<String> {
+        self.symbols.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Get the REST data of one symbol
+    pub fn get_symbol_data(&self, symbol: &str) -> Option<UsdmData> {
+        self.symbols
+            .read()
+            .unwrap()
+            .get(&symbol.to_uppercase())
+            .cloned()
+    }
+
+    /// Get the websocket data of one symbol
+    pub fn get_symbol_ws(&self, symbol: &str) -> Option<WsData> {
+        self.ws.get_symbol_data(symbol)
+    }
+
     /// Get last day "1m" klines
     pub fn get_last_day_klines(&self) -> KlineSummaries {
-        self.data.get_last_day_klines()
+        self.symbols
+            .read()
+            .unwrap()
+            .first()
+            .map(|(_, data)| data.get_last_day_klines())
+            .unwrap_or(AllKlineSummaries(vec![]))
     }
 
     /// Check server time
@@ -429,16 +658,18 @@ impl UsdmInterface {
     }
 
     /// Place limit buy order
+    /// * `position_side` - `None` in one-way mode, `Long` or `Short` in hedge mode
     pub fn limit_buy(
         &self,
         symbol: impl Into<String>,
         qty: impl Into<f64>,
         price: f64,
+        position_side: impl Into<Option<PositionSide>>,
     ) -> Result<Transaction> {
         let buy = OrderRequest {
             symbol: symbol.into(),
             side: OrderSide::Buy,
-            position_side: None,
+            position_side: position_side.into(),
             order_type: OrderType::Limit,
             time_in_force: Some(TimeInForce::GTC),
             qty: Some(qty.into()),
@@ -451,22 +682,23 @@ impl UsdmInterface {
             working_type: None,
             price_protect: None,
         };
-        let order = self.build_order(buy);
-        let request = build_signed_request(order, self.recv_window)?;
-        self.api_request(Futures::Order, RequestType::PostSigned, Some(request))
+        let order = build_order_parameters(buy);
+        self.place_order(order)
     }
 
     /// Place limit sell order
+    /// * `position_side` - `None` in one-way mode, `Long` or `Short` in hedge mode
     pub fn limit_sell(
         &self,
         symbol: impl Into<String>,
         qty: impl Into<f64>,
         price: f64,
+        position_side: impl Into<Option<PositionSide>>,
     ) -> Result<Transaction> {
         let sell = OrderRequest {
             symbol: symbol.into(),
             side: OrderSide::Sell,
-            position_side: None,
+            position_side: position_side.into(),
             order_type: OrderType::Limit,
             time_in_force: Some(TimeInForce::GTC),
             qty: Some(qty.into()),
@@ -479,21 +711,22 @@ impl UsdmInterface {
             working_type: None,
             price_protect: None,
         };
-        let order = self.build_order(sell);
-        let request = build_signed_request(order, self.recv_window)?;
-        self.api_request(Futures::Order, RequestType::PostSigned, Some(request))
+        let order = build_order_parameters(sell);
+        self.place_order(order)
     }
 
     /// Place a MARKET order - BUY
-    pub fn market_buy<S, F>(&self, symbol: S, qty: F) -> Result<Transaction>
+    /// * `position_side` - `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn market_buy<S, F, P>(&self, symbol: S, qty: F, position_side: P) -> Result<Transaction>
     where
         S: Into<String>,
         F: Into<f64>,
+        P: Into<Option<PositionSide>>,
     {
         let buy = OrderRequest {
             symbol: symbol.into(),
             side: OrderSide::Buy,
-            position_side: None,
+            position_side: position_side.into(),
             order_type: OrderType::Market,
             time_in_force: None,
             qty: Some(qty.into()),
@@ -506,21 +739,22 @@ impl UsdmInterface {
             working_type: None,
             price_protect: None,
         };
-        let order = self.build_order(buy);
-        let request = build_signed_request(order, self.recv_window)?;
-        self.api_request(Futures::Order, RequestType::PostSigned, Some(request))
+        let order = build_order_parameters(buy);
+        self.place_order(order)
     }
 
     /// Place a MARKET order - SELL
-    pub fn market_sell<S, F>(&self, symbol: S, qty: F) -> Result<Transaction>
+    /// * `position_side` - `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn market_sell<S, F, P>(&self, symbol: S, qty: F, position_side: P) -> Result<Transaction>
     where
         S: Into<String>,
         F: Into<f64>,
+        P: Into<Option<PositionSide>>,
     {
         let sell: OrderRequest = OrderRequest {
             symbol: symbol.into(),
             side: OrderSide::Sell,
-            position_side: None,
+            position_side: position_side.into(),
             order_type: OrderType::Market,
             time_in_force: None,
             qty: Some(qty.into()),
@@ -533,9 +767,8 @@ impl UsdmInterface {
             working_type: None,
             price_protect: None,
         };
-        let order = self.build_order(sell);
-        let request = build_signed_request(order, self.recv_window)?;
-        self.api_request(Futures::Order, RequestType::PostSigned, Some(request))
+        let order = build_order_parameters(sell);
+        self.place_order(order)
     }
 
     /// Cancel an order
@@ -543,8 +776,14 @@ impl UsdmInterface {
     where
         S: Into<String>,
     {
+        let symbol: String = symbol.into();
+        if let Some(paper) = &self.paper {
+            return paper
+                .cancel_order(&symbol, OrderRef::Id(order_id))
+                .map(CanceledOrder::from);
+        }
         let mut parameters = BTreeMap::new();
-        parameters.insert("symbol".into(), symbol.into());
+        parameters.insert("symbol".into(), symbol);
         parameters.insert("orderId".into(), order_id.to_string());
 
         let request = build_signed_request(parameters, self.recv_window)?;
@@ -560,8 +799,14 @@ impl UsdmInterface {
     where
         S: Into<String>,
     {
+        let symbol: String = symbol.into();
+        if let Some(paper) = &self.paper {
+            return paper
+                .cancel_order(&symbol, OrderRef::ClientId(orig_client_order_id))
+                .map(CanceledOrder::from);
+        }
         let mut parameters = BTreeMap::new();
-        parameters.insert("symbol".into(), symbol.into());
+        parameters.insert("symbol".into(), symbol);
         parameters.insert("origClientOrderId".into(), orig_client_order_id);
 
         let request = build_signed_request(parameters, self.recv_window)?;
@@ -569,15 +814,22 @@ impl UsdmInterface {
     }
 
     /// Place a STOP_MARKET close - BUY
-    pub fn stop_market_close_buy<S, F>(&self, symbol: S, stop_price: F) -> Result<Transaction>
+    /// * `position_side` - `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn stop_market_close_buy<S, F, P>(
+        &self,
+        symbol: S,
+        stop_price: F,
+        position_side: P,
+    ) -> Result<Transaction>
     where
         S: Into<String>,
         F: Into<f64>,
+        P: Into<Option<PositionSide>>,
     {
         let sell: OrderRequest = OrderRequest {
             symbol: symbol.into(),
             side: OrderSide::Buy,
-            position_side: None,
+            position_side: position_side.into(),
             order_type: OrderType::StopMarket,
             time_in_force: None,
             qty: None,
@@ -590,21 +842,27 @@ impl UsdmInterface {
             working_type: None,
             price_protect: None,
         };
-        let order = self.build_order(sell);
-        let request = build_signed_request(order, self.recv_window)?;
-        self.api_request(Futures::Order, RequestType::PostSigned, Some(request))
+        let order = build_order_parameters(sell);
+        self.place_order(order)
     }
 
     /// Place a STOP_MARKET close - SELL
-    pub fn stop_market_close_sell<S, F>(&self, symbol: S, stop_price: F) -> Result<Transaction>
+    /// * `position_side` - `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn stop_market_close_sell<S, F, P>(
+        &self,
+        symbol: S,
+        stop_price: F,
+        position_side: P,
+    ) -> Result<Transaction>
     where
         S: Into<String>,
         F: Into<f64>,
+        P: Into<Option<PositionSide>>,
     {
         let sell: OrderRequest = OrderRequest {
             symbol: symbol.into(),
             side: OrderSide::Sell,
-            position_side: None,
+            position_side: position_side.into(),
             order_type: OrderType::StopMarket,
             time_in_force: None,
             qty: None,
@@ -617,9 +875,498 @@ impl UsdmInterface {
             working_type: None,
             price_protect: None,
         };
-        let order = self.build_order(sell);
-        let request = build_signed_request(order, self.recv_window)?;
-        self.api_request(Futures::Order, RequestType::PostSigned, Some(request))
+        let order = build_order_parameters(sell);
+        self.place_order(order)
+    }
+
+    /// Place a TRAILING_STOP_MARKET reduce only order - BUY
+    /// * `callback_rate` - percent, from 0.1 to 5
+    /// * `activation_price` - defaults to the latest price
+    /// * `position_side` - `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn trailing_stop_market_buy<S, F, A, P>(
+        &self,
+        symbol: S,
+        qty: F,
+        callback_rate: f64,
+        activation_price: A,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        A: Into<Option<f64>>,
+        P: Into<Option<PositionSide>>,
+    {
+        let order: OrderRequest = OrderRequest {
+            symbol: symbol.into(),
+            side: OrderSide::Buy,
+            position_side: position_side.into(),
+            order_type: OrderType::TrailingStopMarket,
+            time_in_force: None,
+            qty: Some(qty.into()),
+            reduce_only: Some(true),
+            price: None,
+            stop_price: None,
+            close_position: None,
+            activation_price: activation_price.into(),
+            callback_rate: Some(callback_rate),
+            working_type: Some(working_type),
+            price_protect: None,
+        };
+        let order = build_order_parameters(order);
+        self.place_order(order)
+    }
+
+    /// Place a TRAILING_STOP_MARKET reduce only order - SELL
+    /// * `callback_rate` - percent, from 0.1 to 5
+    /// * `activation_price` - defaults to the latest price
+    /// * `position_side` - `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn trailing_stop_market_sell<S, F, A, P>(
+        &self,
+        symbol: S,
+        qty: F,
+        callback_rate: f64,
+        activation_price: A,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        A: Into<Option<f64>>,
+        P: Into<Option<PositionSide>>,
+    {
+        let order: OrderRequest = OrderRequest {
+            symbol: symbol.into(),
+            side: OrderSide::Sell,
+            position_side: position_side.into(),
+            order_type: OrderType::TrailingStopMarket,
+            time_in_force: None,
+            qty: Some(qty.into()),
+            reduce_only: Some(true),
+            price: None,
+            stop_price: None,
+            close_position: None,
+            activation_price: activation_price.into(),
+            callback_rate: Some(callback_rate),
+            working_type: Some(working_type),
+            price_protect: None,
+        };
+        let order = build_order_parameters(order);
+        self.place_order(order)
+    }
+
+    /// Place a TAKE_PROFIT_MARKET close - BUY
+    /// * `position_side` - `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn take_profit_market_close_buy<S, F, P>(
+        &self,
+        symbol: S,
+        stop_price: F,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        P: Into<Option<PositionSide>>,
+    {
+        let order: OrderRequest = OrderRequest {
+            symbol: symbol.into(),
+            side: OrderSide::Buy,
+            position_side: position_side.into(),
+            order_type: OrderType::TakeProfitMarket,
+            time_in_force: None,
+            qty: None,
+            reduce_only: None,
+            price: None,
+            stop_price: Some(stop_price.into()),
+            close_position: Some(true),
+            activation_price: None,
+            callback_rate: None,
+            working_type: Some(working_type),
+            price_protect: None,
+        };
+        let order = build_order_parameters(order);
+        self.place_order(order)
+    }
+
+    /// Place a TAKE_PROFIT_MARKET close - SELL
+    /// * `position_side` - `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn take_profit_market_close_sell<S, F, P>(
+        &self,
+        symbol: S,
+        stop_price: F,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        P: Into<Option<PositionSide>>,
+    {
+        let order: OrderRequest = OrderRequest {
+            symbol: symbol.into(),
+            side: OrderSide::Sell,
+            position_side: position_side.into(),
+            order_type: OrderType::TakeProfitMarket,
+            time_in_force: None,
+            qty: None,
+            reduce_only: None,
+            price: None,
+            stop_price: Some(stop_price.into()),
+            close_position: Some(true),
+            activation_price: None,
+            callback_rate: None,
+            working_type: Some(working_type),
+            price_protect: None,
+        };
+        let order = build_order_parameters(order);
+        self.place_order(order)
+    }
+
+    /// Place a STOP_MARKET close triggered by `working_type` - BUY
+    /// * `position_side` - `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn stop_loss_market_close_buy<S, F, P>(
+        &self,
+        symbol: S,
+        stop_price: F,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        P: Into<Option<PositionSide>>,
+    {
+        let order: OrderRequest = OrderRequest {
+            symbol: symbol.into(),
+            side: OrderSide::Buy,
+            position_side: position_side.into(),
+            order_type: OrderType::StopMarket,
+            time_in_force: None,
+            qty: None,
+            reduce_only: None,
+            price: None,
+            stop_price: Some(stop_price.into()),
+            close_position: Some(true),
+            activation_price: None,
+            callback_rate: None,
+            working_type: Some(working_type),
+            price_protect: None,
+        };
+        let order = build_order_parameters(order);
+        self.place_order(order)
+    }
+
+    /// Place a STOP_MARKET close triggered by `working_type` - SELL
+    /// * `position_side` - `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn stop_loss_market_close_sell<S, F, P>(
+        &self,
+        symbol: S,
+        stop_price: F,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        P: Into<Option<PositionSide>>,
+    {
+        let order: OrderRequest = OrderRequest {
+            symbol: symbol.into(),
+            side: OrderSide::Sell,
+            position_side: position_side.into(),
+            order_type: OrderType::StopMarket,
+            time_in_force: None,
+            qty: None,
+            reduce_only: None,
+            price: None,
+            stop_price: Some(stop_price.into()),
+            close_position: Some(true),
+            activation_price: None,
+            callback_rate: None,
+            working_type: Some(working_type),
+            price_protect: None,
+        };
+        let order = build_order_parameters(order);
+        self.place_order(order)
+    }
+
+    /// Place a TAKE_PROFIT limit reduce only order - BUY
+    /// * `position_side` - `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn take_profit_limit_buy<S, F, P>(
+        &self,
+        symbol: S,
+        qty: F,
+        price: f64,
+        stop_price: f64,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        P: Into<Option<PositionSide>>,
+    {
+        let order: OrderRequest = OrderRequest {
+            symbol: symbol.into(),
+            side: OrderSide::Buy,
+            position_side: position_side.into(),
+            order_type: OrderType::TakeProfit,
+            time_in_force: Some(TimeInForce::GTC),
+            qty: Some(qty.into()),
+            reduce_only: Some(true),
+            price: Some(price),
+            stop_price: Some(stop_price),
+            close_position: None,
+            activation_price: None,
+            callback_rate: None,
+            working_type: Some(working_type),
+            price_protect: None,
+        };
+        let order = build_order_parameters(order);
+        self.place_order(order)
+    }
+
+    /// Place a TAKE_PROFIT limit reduce only order - SELL
+    /// * `position_side` - `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn take_profit_limit_sell<S, F, P>(
+        &self,
+        symbol: S,
+        qty: F,
+        price: f64,
+        stop_price: f64,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        P: Into<Option<PositionSide>>,
+    {
+        let order: OrderRequest = OrderRequest {
+            symbol: symbol.into(),
+            side: OrderSide::Sell,
+            position_side: position_side.into(),
+            order_type: OrderType::TakeProfit,
+            time_in_force: Some(TimeInForce::GTC),
+            qty: Some(qty.into()),
+            reduce_only: Some(true),
+            price: Some(price),
+            stop_price: Some(stop_price),
+            close_position: None,
+            activation_price: None,
+            callback_rate: None,
+            working_type: Some(working_type),
+            price_protect: None,
+        };
+        let order = build_order_parameters(order);
+        self.place_order(order)
+    }
+
+    /// Place an entry order with attached take profit and stop loss.
+    /// Once one of the exit legs is filled or canceled the other one is canceled,
+    /// and both are canceled if the entry is canceled without any fill. The legs are
+    /// followed with the order updates of the user stream and the reconciliation
+    /// * `price` - limit price of the entry, `None` places a market entry
+    /// * `position_side` - `None` in one-way mode, `Long` or `Short` in hedge mode
+    #[allow(clippy::too_many_arguments)]
+    pub fn bracket_order<S, F, P>(
+        &self,
+        symbol: S,
+        side: OrderSide,
+        qty: F,
+        price: Option<f64>,
+        take_profit: f64,
+        stop_loss: f64,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<BracketOrder>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        P: Into<Option<PositionSide>>,
+    {
+        let symbol: String = symbol.into();
+        let position_side: Option<PositionSide> = position_side.into();
+        let entry = match (side, price) {
+            (OrderSide::Buy, Some(price)) => {
+                self.limit_buy(symbol.to_owned(), qty, price, position_side)?
+            }
+            (OrderSide::Buy, None) => self.market_buy(symbol.to_owned(), qty, position_side)?,
+            (OrderSide::Sell, Some(price)) => {
+                self.limit_sell(symbol.to_owned(), qty, price, position_side)?
+            }
+            (OrderSide::Sell, None) => self.market_sell(symbol.to_owned(), qty, position_side)?,
+        };
+
+        let take_profit = match side {
+            OrderSide::Buy => self.take_profit_market_close_sell(
+                symbol.to_owned(),
+                take_profit,
+                working_type,
+                position_side,
+            ),
+            OrderSide::Sell => self.take_profit_market_close_buy(
+                symbol.to_owned(),
+                take_profit,
+                working_type,
+                position_side,
+            ),
+        };
+        let take_profit = match take_profit {
+            Ok(take_profit) => take_profit,
+            Err(err) => {
+                self.cancel_bracket_legs(&symbol, &[entry.order_id]);
+                return Err(err);
+            }
+        };
+
+        let stop_loss = match side {
+            OrderSide::Buy => self.stop_loss_market_close_sell(
+                symbol.to_owned(),
+                stop_loss,
+                working_type,
+                position_side,
+            ),
+            OrderSide::Sell => self.stop_loss_market_close_buy(
+                symbol.to_owned(),
+                stop_loss,
+                working_type,
+                position_side,
+            ),
+        };
+        let stop_loss = match stop_loss {
+            Ok(stop_loss) => stop_loss,
+            Err(err) => {
+                self.cancel_bracket_legs(&symbol, &[entry.order_id, take_profit.order_id]);
+                return Err(err);
+            }
+        };
+
+        let bracket = BracketOrder {
+            entry,
+            take_profit,
+            stop_loss,
+            watch: BracketWatch::default(),
+        };
+        self.watch_bracket(&symbol, &bracket);
+        Ok(bracket)
+    }
+
+    /// Stops following `bracket` and cancels its entry and exit orders still open,
+    /// returns the first failed cancel
+    pub fn cancel_bracket(&self, bracket: &BracketOrder) -> Result<()> {
+        bracket.watch.resolved.store(true, Ordering::Release);
+        self.unwatch_bracket(bracket);
+        let symbol = &bracket.entry.symbol;
+        let mut result = Ok(());
+        for order_id in [
+            bracket.entry.order_id,
+            bracket.take_profit.order_id,
+            bracket.stop_loss.order_id,
+        ] {
+            if self.is_open_orders_ws(order_id) || self.get_order_ws(order_id).is_none() {
+                if let Err(err) = self.cancel_order(symbol, order_id) {
+                    result = result.and(Err(err));
+                }
+            }
+        }
+        result
+    }
+
+    // resolves the bracket on the status updates of its legs
+    fn watch_bracket(&self, symbol: &str, bracket: &BracketOrder) {
+        let leg_ids = [
+            bracket.entry.order_id,
+            bracket.take_profit.order_id,
+            bracket.stop_loss.order_id,
+        ];
+        let usdm_int = self.to_owned();
+        let watched = (symbol.to_owned(), bracket.to_owned());
+        let observer_id = self
+            .ws
+            .on_event(&[UsdmEventKind::OrderStatus], move |event| {
+                if let UsdmEvent::OrderStatus { order, .. } = event {
+                    if leg_ids.contains(&order.order_id) {
+                        usdm_int.resolve_bracket(&watched.0, &watched.1);
+                    }
+                }
+            });
+        bracket
+            .watch
+            .observer_id
+            .store(observer_id, Ordering::Release);
+        if !bracket.is_active() {
+            // resolved before its id was known
+            self.ws.remove_observer(observer_id);
+            return;
+        }
+        // a leg may have closed before the observer was registered
+        self.resolve_bracket(symbol, bracket);
+    }
+
+    fn unwatch_bracket(&self, bracket: &BracketOrder) {
+        let observer_id = bracket.watch.observer_id.load(Ordering::Acquire);
+        if observer_id != UNREGISTERED {
+            self.ws.remove_observer(observer_id);
+        }
+    }
+
+    // cancels the legs left once the bracket is closed, once
+    fn resolve_bracket(&self, symbol: &str, bracket: &BracketOrder) {
+        let Some(order_ids) = self.bracket_legs_to_cancel(bracket) else {
+            return;
+        };
+        if bracket.watch.resolved.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.unwatch_bracket(bracket);
+        // observers run on the websocket thread, which must not wait for REST
+        let usdm_int = self.to_owned();
+        let symbol = symbol.to_owned();
+        self.workers
+            .spawn(move |_| usdm_int.cancel_bracket_legs(&symbol, &order_ids));
+    }
+
+    // None while the bracket is live
+    fn bracket_legs_to_cancel(&self, bracket: &BracketOrder) -> Option<Vec<u64>> {
+        let entry_id = bracket.entry.order_id;
+        let take_profit_id = bracket.take_profit.order_id;
+        let stop_loss_id = bracket.stop_loss.order_id;
+        let closed =
+            |order_id| self.is_filled_orders_ws(order_id) || self.is_canceled_orders_ws(order_id);
+        if closed(take_profit_id) {
+            debug!("Bracket take profit {take_profit_id} closed, canceling stop loss");
+            return Some(vec![stop_loss_id]);
+        }
+        if closed(stop_loss_id) {
+            debug!("Bracket stop loss {stop_loss_id} closed, canceling take profit");
+            return Some(vec![take_profit_id]);
+        }
+        if self.is_canceled_orders_ws(entry_id) {
+            // a partially filled entry keeps its exits to protect the open position
+            let filled_qty = self
+                .get_order_ws(entry_id)
+                .and_then(|order| order.accumulated_qty_filled_trades.parse::<f64>().ok())
+                .unwrap_or_default();
+            if filled_qty == 0.0 {
+                debug!("Bracket entry {entry_id} canceled, canceling exit orders");
+                return Some(vec![take_profit_id, stop_loss_id]);
+            }
+        }
+        None
+    }
+
+    fn cancel_bracket_legs(&self, symbol: &str, order_ids: &[u64]) {
+        for order_id in order_ids {
+            if self.is_open_orders_ws(*order_id) || self.get_order_ws(*order_id).is_none() {
+                if let Err(err) = self.cancel_order(symbol, *order_id) {
+                    error!("Unable to cancel bracket leg {order_id}: {err:?}");
+                }
+            }
+        }
     }
 
     /// Custom order for for professional traders
@@ -640,58 +1387,8 @@ impl UsdmInterface {
             working_type: order_request.working_type,
             price_protect: order_request.price_protect,
         };
-        let order = self.build_order(order);
-        let request = build_signed_request(order, self.recv_window)?;
-        self.api_request(Futures::Order, RequestType::PostSigned, Some(request))
-    }
-
-    fn build_order(&self, order: OrderRequest) -> BTreeMap<String, String> {
-        let mut parameters = BTreeMap::new();
-        parameters.insert("symbol".into(), order.symbol);
-        parameters.insert("side".into(), order.side.to_string());
-        parameters.insert("type".into(), order.order_type.to_string());
-
-        if let Some(position_side) = order.position_side {
-            parameters.insert("positionSide".into(), position_side.to_string());
-        }
-        if let Some(time_in_force) = order.time_in_force {
-            parameters.insert("timeInForce".into(), time_in_force.to_string());
-        }
-        if let Some(qty) = order.qty {
-            parameters.insert("quantity".into(), qty.to_string());
-        }
-        if let Some(reduce_only) = order.reduce_only {
-            parameters.insert("reduceOnly".into(), reduce_only.to_string().to_uppercase());
-        }
-        if let Some(price) = order.price {
-            parameters.insert("price".into(), price.to_string());
-        }
-        if let Some(stop_price) = order.stop_price {
-            parameters.insert("stopPrice".into(), stop_price.to_string());
-        }
-        if let Some(close_position) = order.close_position {
-            parameters.insert(
-                "closePosition".into(),
-                close_position.to_string().to_uppercase(),
-            );
-        }
-        if let Some(activation_price) = order.activation_price {
-            parameters.insert("activationPrice".into(), activation_price.to_string());
-        }
-        if let Some(callback_rate) = order.callback_rate {
-            parameters.insert("callbackRate".into(), callback_rate.to_string());
-        }
-        if let Some(working_type) = order.working_type {
-            parameters.insert("workingType".into(), working_type.to_string());
-        }
-        if let Some(price_protect) = order.price_protect {
-            parameters.insert(
-                "priceProtect".into(),
-                price_protect.to_string().to_uppercase(),
-            );
-        }
-
-        parameters
+        let order = build_order_parameters(order);
+        self.place_order(order)
     }
 
     /// Get position_information
@@ -699,8 +1396,12 @@ impl UsdmInterface {
     where
         S: Into<String>,
     {
+        let symbol: String = symbol.into();
+        if let Some(paper) = &self.paper {
+            return Ok(paper.position_information(&symbol));
+        }
         let mut parameters = BTreeMap::new();
-        parameters.insert("symbol".into(), symbol.into());
+        parameters.insert("symbol".into(), symbol);
 
         let request = build_signed_request(parameters, self.recv_window)?;
         self.api_request(Futures::PositionRisk, RequestType::GetSigned, Some(request))
@@ -731,8 +1432,12 @@ impl UsdmInterface {
     where
         S: Into<String>,
     {
+        let symbol: String = symbol.into();
+        if let Some(paper) = &self.paper {
+            return paper.change_initial_leverage(&symbol, leverage);
+        }
         let mut parameters: BTreeMap<String, String> = BTreeMap::new();
-        parameters.insert("symbol".into(), symbol.into());
+        parameters.insert("symbol".into(), symbol);
         parameters.insert("leverage".into(), leverage.to_string());
 
         let request = build_signed_request(parameters, self.recv_window)?;
@@ -745,6 +1450,9 @@ impl UsdmInterface {
 
     /// Change position mode
     pub fn change_position_mode(&self, dual_side_position: bool) -> Result<()> {
+        if let Some(paper) = &self.paper {
+            return paper.change_position_mode(dual_side_position);
+        }
         let mut parameters: BTreeMap<String, String> = BTreeMap::new();
         let dual_side = if dual_side_position { "true" } else { "false" };
         parameters.insert("dualSidePosition".into(), dual_side.into());
@@ -763,8 +1471,13 @@ impl UsdmInterface {
     where
         S: Into<String>,
     {
+        let symbol: String = symbol.into();
+        if let Some(paper) = &self.paper {
+            paper.cancel_all_open_orders(&symbol);
+            return Ok(());
+        }
         let mut parameters: BTreeMap<String, String> = BTreeMap::new();
-        parameters.insert("symbol".into(), symbol.into());
+        parameters.insert("symbol".into(), symbol);
         let request = build_signed_request(parameters, self.recv_window)?;
         self.api_request::<Empty>(
             Futures::AllOpenOrders,
@@ -774,26 +1487,319 @@ impl UsdmInterface {
         .map(|_| ())
     }
 
+    /// Auto-cancel all open orders after a countdown (dead man's switch)
+    /// * `countdown_time` - milliseconds, 0 disables the countdown
+    pub fn auto_cancel_all_open_orders<S>(
+        &self,
+        symbol: S,
+        countdown_time: u64,
+    ) -> Result<CountdownCancelAll>
+    where
+        S: Into<String>,
+    {
+        let mut parameters: BTreeMap<String, String> = BTreeMap::new();
+        parameters.insert("symbol".into(), symbol.into());
+        parameters.insert("countdownTime".into(), countdown_time.to_string());
+        let request = build_signed_request(parameters, self.recv_window)?;
+        self.api_request(
+            Futures::CountdownCancelAll,
+            RequestType::PostSigned,
+            Some(request),
+        )
+    }
+
     /// Get an order
     pub fn get_order<S>(&self, symbol: S, order_id: u64) -> Result<Order>
     where
         S: Into<String>,
     {
+        let symbol: String = symbol.into();
+        if let Some(paper) = &self.paper {
+            return paper.get_order(&symbol, OrderRef::Id(order_id));
+        }
         let mut parameters = BTreeMap::new();
-        parameters.insert("symbol".into(), symbol.into());
+        parameters.insert("symbol".into(), symbol);
         parameters.insert("orderId".into(), order_id.to_string());
 
         let request = build_signed_request(parameters, self.recv_window)?;
         self.api_request(Futures::Order, RequestType::GetSigned, Some(request))
     }
 
+    /// Get an order with a given client id
+    pub fn get_order_with_client_id<S>(
+        &self,
+        symbol: S,
+        orig_client_order_id: String,
+    ) -> Result<Order>
+    where
+        S: Into<String>,
+    {
+        let symbol: String = symbol.into();
+        if let Some(paper) = &self.paper {
+            return paper.get_order(&symbol, OrderRef::ClientId(orig_client_order_id));
+        }
+        let mut parameters = BTreeMap::new();
+        parameters.insert("symbol".into(), symbol);
+        parameters.insert("origClientOrderId".into(), orig_client_order_id);
+
+        let request = build_signed_request(parameters, self.recv_window)?;
+        self.api_request(Futures::Order, RequestType::GetSigned, Some(request))
+    }
+
+    /// Get all orders, open or not, placed since `start_time`
+    /// * `limit` - 500 by default, 1000 at most
+    pub fn get_all_orders<S>(
+        &self,
+        symbol: S,
+        start_time: Option<u64>,
+        limit: Option<u16>,
+    ) -> Result<Vec<Order>>
+    where
+        S: Into<String>,
+    {
+        let symbol: String = symbol.into();
+        if let Some(paper) = &self.paper {
+            return Ok(paper.get_all_orders(
+                &symbol,
+                start_time.unwrap_or_default(),
+                limit.unwrap_or(ALL_ORDERS_LIMIT).into(),
+            ));
+        }
+        self.all_orders_page(symbol, start_time, None, limit)
+    }
+
+    // orders placed since `start_time`, or from `order_id` on when it is set
+    fn all_orders_page(
+        &self,
+        symbol: String,
+        start_time: Option<u64>,
+        order_id: Option<u64>,
+        limit: Option<u16>,
+    ) -> Result<Vec<Order>> {
+        let mut parameters: BTreeMap<String, String> = BTreeMap::new();
+        parameters.insert("symbol".into(), symbol);
+        if let Some(order_id) = order_id {
+            parameters.insert("orderId".into(), order_id.to_string());
+        } else if let Some(start_time) = start_time {
+            parameters.insert("startTime".into(), start_time.to_string());
+        }
+        if let Some(limit) = limit {
+            parameters.insert("limit".into(), limit.to_string());
+        }
+        let request = build_signed_request(parameters, self.recv_window)?;
+        self.api_request(Futures::AllOrders, RequestType::GetSigned, Some(request))
+    }
+
+    // allOrders returns the oldest orders since `start_time`, the next pages start
+    // after the last order id until one comes back short
+    fn get_all_orders_since(&self, symbol: &str, start_time: u64) -> Result<Vec<Order>> {
+        let mut orders: Vec<Order> = vec![];
+        let mut from_order_id = None;
+        loop {
+            let page = self.all_orders_page(
+                symbol.to_owned(),
+                Some(start_time),
+                from_order_id,
+                Some(ALL_ORDERS_PAGE_SIZE),
+            )?;
+            let full = page.len() == ALL_ORDERS_PAGE_SIZE as usize;
+            let last_order_id = page.iter().map(|order| order.order_id).max();
+            orders.extend(page);
+            match last_order_id {
+                Some(last_order_id) if full => from_order_id = Some(last_order_id + 1),
+                _ => return Ok(orders),
+            }
+        }
+    }
+
+    /// Pulls the open orders, the orders placed since the last reconciliation and the positions
+    /// of every symbol over REST, corrects the websocket data where it differs and notifies
+    /// a `UsdmEvent::Discrepancy` for every difference.
+    /// Runs on its own after every user stream reconnect and every `reconcile_interval`
+    pub fn reconcile(&self) -> Result<ReconciliationReport> {
+        // the paper account updates the websocket data itself
+        if self.paper.is_some() {
+            return Ok(ReconciliationReport::default());
+        }
+        let started_at = timestamp();
+        let since = self
+            .reconciled_at
+            .read()
+            .unwrap()
+            .saturating_sub(RECONCILE_OVERLAP);
+        let mut report = ReconciliationReport::default();
+        for symbol in self.get_symbols() {
+            let Some(ws_data) = self.get_symbol_ws(&symbol) else {
+                continue;
+            };
+            let mut orders: HashMap<u64, Order> = self
+                .get_all_orders_since(&symbol, since)?
+                .into_iter()
+                .chain(self.get_all_open_orders(symbol.to_owned())?)
+                .map(|order| (order.order_id, order))
+                .collect();
+            // orders open in the websocket data but placed before `since`
+            let mut unknown = vec![];
+            for ws_order in ws_data.get_open_orders() {
+                if orders.contains_key(&ws_order.order_id) {
+                    continue;
+                }
+                match self.get_order(symbol.to_owned(), ws_order.order_id) {
+                    Ok(order) => {
+                        orders.insert(order.order_id, order);
+                    }
+                    Err(BinanceError::BinanceError { response })
+                        if response.code == ORDER_NOT_FOUND =>
+                    {
+                        unknown.push(ws_order.order_id)
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            let positions = self.position_information(symbol.to_owned())?;
+            report.orders_checked += orders.len();
+            report.positions_checked += positions.len();
+
+            let diffs = diff_orders(&symbol, &ws_data, &orders, &unknown)
+                .into_iter()
+                .chain(diff_positions(&ws_data, &positions));
+            for (discrepancy, correction) in diffs {
+                warn!("Reconciled {discrepancy:?}");
+                match correction {
+                    Correction::Order(order) => self.ws.apply_order_update(*order),
+                    Correction::RemoveOrder(order_id) => {
+                        self.ws.remove_open_order(&symbol, order_id)
+                    }
+                    Correction::Position(position) => self.ws.apply_position(position),
+                }
+                self.ws
+                    .notify(UsdmEvent::Discrepancy(discrepancy.to_owned()));
+                report.discrepancies.push(discrepancy);
+            }
+        }
+        *self.reconciled_at.write().unwrap() = started_at;
+        Ok(report)
+    }
+
+    /// Get the income history (realized PnL, commissions, funding fees, transfers...)
+    /// * `income_type` - e.g. "FUNDING_FEE", every type if `None`
+    /// * `limit` - 100 by default, 1000 at most
+    pub fn get_income_history<S>(
+        &self,
+        symbol: S,
+        income_type: Option<&str>,
+        start_time: Option<u64>,
+        end_time: Option<u64>,
+        limit: Option<u16>,
+    ) -> Result<Vec<Income>>
+    where
+        S: Into<String>,
+    {
+        let mut parameters: BTreeMap<String, String> = BTreeMap::new();
+        parameters.insert("symbol".into(), symbol.into());
+        if let Some(income_type) = income_type {
+            parameters.insert("incomeType".into(), income_type.into());
+        }
+        if let Some(start_time) = start_time {
+            parameters.insert("startTime".into(), start_time.to_string());
+        }
+        if let Some(end_time) = end_time {
+            parameters.insert("endTime".into(), end_time.to_string());
+        }
+        if let Some(limit) = limit {
+            parameters.insert("limit".into(), limit.to_string());
+        }
+        let request = build_signed_request(parameters, self.recv_window)?;
+        self.api_request(Futures::Income, RequestType::GetSigned, Some(request))
+    }
+
+    /// Ledger of the positions, PnL, fees and funding of the symbols, seeded with their
+    /// income history since `start_time` and their positions, then kept up to date by the
+    /// websocket events until the interface shuts down. Binance keeps 3 months of history.
+    /// It is seeded again after a user stream reconnect and a reconciliation discrepancy
+    pub fn start_ledger(&self, start_time: u64) -> Result<Ledger> {
+        let ledger = Ledger::default();
+        let observer = ledger.clone();
+        // registered first, the events received during the seed are applied after it
+        let id = self.ws.on_event(
+            &[
+                UsdmEventKind::Fill,
+                UsdmEventKind::Position,
+                UsdmEventKind::MarkPrice,
+                UsdmEventKind::FundingFee,
+            ],
+            move |event| observer.apply(event),
+        );
+        if let Err(e) = self.seed_ledger(&ledger, start_time) {
+            self.ws.remove_observer(id);
+            return Err(e);
+        }
+        if self.paper.is_none() {
+            ledger_worker(self.to_owned(), ledger.clone(), start_time);
+        }
+        Ok(ledger)
+    }
+
+    // seeds `ledger` with the income history since `start_time` and the current positions
+    fn seed_ledger(&self, ledger: &Ledger, start_time: u64) -> Result<()> {
+        let mut incomes = vec![];
+        let mut positions = vec![];
+        for symbol in self.get_symbols() {
+            incomes.extend(self.get_all_income_history(&symbol, start_time)?);
+            positions.extend(self.position_information(symbol.to_owned())?);
+        }
+        ledger.seed(&incomes, &positions);
+        Ok(())
+    }
+
+    // pages through the income history of `symbol` since `start_time`. A page starts at
+    // the last time of the previous one, whose entries at that time may be returned again,
+    // or right after it when the whole page had that time
+    fn get_all_income_history(&self, symbol: &str, start_time: u64) -> Result<Vec<Income>> {
+        // the paper account starts without history
+        if self.paper.is_some() {
+            return Ok(vec![]);
+        }
+        let mut incomes: Vec<Income> = vec![];
+        let mut seen: HashSet<(u64, String)> = HashSet::new();
+        let mut start_time = start_time;
+        loop {
+            let page = self.get_income_history(
+                symbol,
+                None,
+                Some(start_time),
+                None,
+                Some(INCOME_PAGE_SIZE),
+            )?;
+            let full = page.len() == INCOME_PAGE_SIZE as usize;
+            let last_time = page.last().map(|income| income.time);
+            for income in page {
+                if seen.insert((income.tran_id, income.income_type.to_owned())) {
+                    incomes.push(income);
+                }
+            }
+            match last_time {
+                Some(last_time) if full && last_time > start_time => start_time = last_time,
+                Some(last_time) if full => {
+                    warn!("More than {INCOME_PAGE_SIZE} incomes of {symbol} at {last_time}");
+                    start_time = last_time + 1;
+                }
+                _ => return Ok(incomes),
+            }
+        }
+    }
+
     /// Get all open orders
     pub fn get_all_open_orders<S>(&self, symbol: S) -> Result<Vec<Order>>
     where
         S: Into<String>,
     {
+        let symbol: String = symbol.into();
+        if let Some(paper) = &self.paper {
+            return Ok(paper.get_all_open_orders(&symbol));
+        }
         let mut parameters: BTreeMap<String, String> = BTreeMap::new();
-        parameters.insert("symbol".into(), symbol.into());
+        parameters.insert("symbol".into(), symbol);
         let request = build_signed_request(parameters, self.recv_window)?;
         self.api_request(Futures::OpenOrders, RequestType::GetSigned, Some(request))
     }
@@ -811,6 +1817,59 @@ impl UsdmInterface {
         self.ws.get_mark_price_snaps()
     }
 
+    /// True if no mark price was received from websocket for `max_age`,
+    /// quoting on stale data should be avoided
+    pub fn is_stale_ws(&self, max_age: Duration) -> bool {
+        self.ws.is_stale(max_age)
+    }
+
+    /// Get exchange to local latency of mark prices from websocket
+    pub fn get_mark_price_latency_ws(&self) -> Option<LatencyStats> {
+        self.ws.get_mark_price_latency()
+    }
+
+    /// Get exchange to local latency of aggr_trades from websocket
+    pub fn get_aggr_trades_latency_ws(&self) -> Option<LatencyStats> {
+        self.ws.get_aggr_trades_latency()
+    }
+
+    /// Calls `callback` on the websocket thread when a fill, an order status, a position,
+    /// a balance, a liquidation or a mark price of `kinds` is received, `kinds` empty for
+    /// every kind. Returns the observer id
+    pub fn on_event_ws<F>(&self, kinds: &[UsdmEventKind], callback: F) -> u64
+    where
+        F: Fn(&UsdmEvent) + Send + Sync + 'static,
+    {
+        self.ws.on_event(kinds, callback)
+    }
+
+    /// Channel of the websocket events of `kinds`, e.g. `recv` wakes on the next fill
+    /// with `&[UsdmEventKind::Fill]`. Buffers up to `capacity` events, handled by `overflow`
+    /// once full
+    pub fn subscribe_ws(
+        &self,
+        kinds: &[UsdmEventKind],
+        capacity: usize,
+        overflow: OverflowPolicy,
+    ) -> EventReceiver<UsdmEvent> {
+        self.ws.subscribe(kinds, capacity, overflow)
+    }
+
+    /// Removes the observer added with `on_event_ws`
+    pub fn remove_observer_ws(&self, id: u64) -> bool {
+        self.ws.remove_observer(id)
+    }
+
+    /// Get candles of `interval` built from aggr_trades, the last one is in progress
+    pub fn get_candles_ws(&self, interval: CandleInterval) -> VecDeque<Candle> {
+        self.ws.get_candles(interval)
+    }
+
+    /// Get candle of `interval` in progress from websocket
+    pub fn get_current_candle_ws(&self, interval: CandleInterval) -> Option<Candle> {
+        self.ws.get_current_candle(interval)
+    }
+
     /// Get liquidations
     pub fn get_liquidations_ws(&self) -> VecDeque<LiquidationOrder> {
         self.ws.get_liquidations()
@@ -857,29 +1916,17 @@ impl UsdmInterface {
 
     /// Returns true of order is open, false otherwise
     pub fn is_open_orders_ws(&self, order_id: u64) -> bool {
-        self.get_open_orders_ws()
-            .into_iter()
-            .filter(|ord| ord.order_id == order_id)
-            .count()
-            > 0
+        self.ws.is_open_order(order_id)
     }
 
     /// Returns true if order is filled, false otherwise
     pub fn is_filled_orders_ws(&self, order_id: u64) -> bool {
-        self.get_filled_orders_ws()
-            .into_iter()
-            .filter(|ord| ord.order_id == order_id)
-            .count()
-            > 0
+        self.ws.is_filled_order(order_id)
     }
 
     /// Get canceled orders, false otherwise
     pub fn is_canceled_orders_ws(&self, order_id: u64) -> bool {
-        self.get_canceled_orders_ws()
-            .into_iter()
-            .filter(|ord| ord.order_id == order_id)
-            .count()
-            > 0
+        self.ws.is_canceled_order(order_id)
     }
 
     /// Get order
@@ -916,6 +1963,40 @@ impl UsdmInterface {
         None
     }
 
+    /// Get position of one side, `PositionSide::Both` in one-way mode
+    pub fn get_position_side_ws(&self, position_side: PositionSide) -> Option<EventPosition> {
+        self.ws.get_position_side(position_side)
+    }
+
+    /// Get positions of every side
+    pub fn get_positions_ws(&self) -> Vec<EventPosition> {
+        self.ws.get_positions()
+    }
+
+    /// Get position size of one side
+    pub fn get_position_side_size_ws(&self, position_side: PositionSide) -> Option<f64> {
+        if let Some(position) = self.get_position_side_ws(position_side) {
+            return Some(position.position_amount.parse().unwrap());
+        }
+        None
+    }
+
+    /// Get position entry price of one side
+    pub fn get_position_side_entry_ws(&self, position_side: PositionSide) -> Option<f64> {
+        if let Some(position) = self.get_position_side_ws(position_side) {
+            return Some(position.entry_price.parse().unwrap());
+        }
+        None
+    }
+
+    /// Get position unrealized pnl of one side
+    pub fn get_position_side_upnl_ws(&self, position_side: PositionSide) -> Option<f64> {
+        if let Some(position) = self.get_position_side_ws(position_side) {
+            return Some(position.unrealized_pnl.parse().unwrap());
+        }
+        None
+    }
+
     /// Get position size
     pub fn get_position_size_ws(&self) -> Option<f64> {
         if let Some(position) = self.get_position_ws() {
@@ -964,69 +2045,273 @@ impl UsdmInterface {
         None
     }
 
+    // order placement is not idempotent, an attempt with an unknown outcome is repeated
+    // only once its client order id shows that it did not land. An attempt still in flight
+    // is not found either, it is looked up until Binance would reject it for its recvWindow
+    fn place_order(&self, mut order: BTreeMap<String, String>) -> Result<Transaction> {
+        let symbol = order["symbol"].to_owned();
+        let client_order_id = order
+            .entry("newClientOrderId".into())
+            .or_insert_with(new_client_order_id)
+            .to_owned();
+        if let Some(paper) = &self.paper {
+            return paper.place_order(&order).map(Transaction::from);
+        }
+        let start = Instant::now();
+        let mut attempts = 1;
+        loop {
+            let sent_at = Instant::now();
+            let request = build_signed_request(order.clone(), self.recv_window)?;
+            let result = self.api.post_signed(API::Futures(Futures::Order), request);
+            let Err(err) = &result else {
+                return result;
+            };
+            let Some(delay) = self
+                .next_retry(err, attempts, start)
+                .filter(|_| self.config.retry_policy.retry_orders)
+            else {
+                return result;
+            };
+            warn!("Order {client_order_id} failed, checking it in {delay:?}: {err}");
+            thread::sleep(delay);
+            let expires_at = sent_at + self.order_expiry();
+            loop {
+                let checked_at = Instant::now();
+                match self.get_order_with_client_id(symbol.to_owned(), client_order_id.to_owned()) {
+                    Ok(landed) => return Ok(landed.into()),
+                    Err(BinanceError::BinanceError { response })
+                        if response.code == ORDER_NOT_FOUND => {}
+                    Err(e) => {
+                        error!("Unable to check order {client_order_id}: {e}");
+                        return result;
+                    }
+                }
+                if checked_at >= expires_at {
+                    break;
+                }
+                thread::sleep(ORDER_CHECK_INTERVAL.min(expires_at - checked_at));
+            }
+            attempts += 1;
+        }
+    }
+
+    // time after which Binance rejects an order request that did not reach it yet
+    fn order_expiry(&self) -> Duration {
+        let recv_window = match self.recv_window {
+            0 => DEFAULT_RECV_WINDOW,
+            recv_window => recv_window,
+        };
+        Duration::from_millis(recv_window + MAX_TIMESTAMP_AHEAD)
+    }
+
     fn api_request<T: DeserializeOwned>(
         &self,
         endpoint: Futures,
         req_type: RequestType,
         req: Option<String>,
     ) -> Result<T> {
-        let result: Result<T> = match req_type {
-            RequestType::Get => self.api.get(API::Futures(endpoint), req.to_owned()),
-            RequestType::GetSigned => self.api.get_signed(API::Futures(endpoint), req.to_owned()),
-            RequestType::PostSigned => self
-                .api
-                .post_signed(API::Futures(endpoint), req.to_owned().unwrap()),
-            RequestType::DeleteSigned => self
-                .api
-                .delete_signed(API::Futures(endpoint), req.to_owned()),
-        };
+        let start = Instant::now();
+        let mut attempts = 1;
+        let mut req = req;
+        loop {
+            let result: Result<T> = match req_type {
+                RequestType::Get => self.api.get(API::Futures(endpoint), req.to_owned()),
+                RequestType::GetSigned => {
+                    self.api.get_signed(API::Futures(endpoint), req.to_owned())
+                }
+                RequestType::PostSigned => self
+                    .api
+                    .post_signed(API::Futures(endpoint), req.to_owned().unwrap()),
+                RequestType::DeleteSigned => self
+                    .api
+                    .delete_signed(API::Futures(endpoint), req.to_owned()),
+            };
+            let Err(err) = &result else {
+                return result;
+            };
+            let Some(delay) = self.next_retry(err, attempts, start) else {
+                return result;
+            };
+            let path = String::from(API::Futures(endpoint));
+            warn!("Request to {path} failed, retrying in {delay:?}: {err}");
+            thread::sleep(delay);
+            if !matches!(req_type, RequestType::Get) {
+                req = req.map(|req| self.sign_again(&req)).transpose()?;
+            }
+            attempts += 1;
+        }
+    }
 
-        if result.is_err() && self.config.retry_on_err {
-            self.err_handler(endpoint, req_type, req, result)
-        } else {
-            result
+    fn next_retry(&self, err: &BinanceError, attempts: u32, start: Instant) -> Option<Duration> {
+        if !self.config.retry_on_err {
+            return None;
         }
+        self.config
+            .retry_policy
+            .next_retry(err, attempts, start.elapsed())
     }
 
-    fn err_handler<T: DeserializeOwned>(
-        &self,
-        endpoint: Futures,
-        req_type: RequestType,
-        req: Option<String>,
-        result: Result<T>,
-    ) -> Result<T> {
-        match &result {
-            Err(BinanceError::BinanceError { response: err }) => {
-                if err.msg == "Request occur unknown error."
-                    || err.msg == "Service Unavailable."
-                    || err.msg
-                        == "Internal error; unable to process your request. Please try again."
+    // a retried request gets a new timestamp to stay within the receive window
+    fn sign_again(&self, request: &str) -> Result<String> {
+        let parameters = request
+            .split('&')
+            .filter_map(|parameter| parameter.split_once('='))
+            .filter(|(key, _)| *key != "timestamp")
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+            .collect();
+        Ok(build_signed_request(parameters, self.recv_window)?)
+    }
+}
+
+// unique per process and time, within the 36 characters Binance accepts
+fn new_client_order_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_nanos())
+        .unwrap_or_default();
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("rs-{nanos:x}-{}-{count:x}", std::process::id())
+}
+
+fn update_usdm_data(usdm_int: UsdmInterface) {
+    let workers = usdm_int.workers.clone();
+    workers.spawn(move |running| {
+        // symbols are updated on their own schedule, a failed one is retried sooner
+        let mut next_updates: HashMap<String, Instant> = HashMap::new();
+        while running.load(Ordering::Relaxed) {
+            let symbols = usdm_int.get_symbols();
+            next_updates.retain(|symbol, _| symbols.contains(symbol));
+            for symbol in symbols {
+                if next_updates
+                    .get(&symbol)
+                    .is_some_and(|next_update| Instant::now() < *next_update)
                 {
-                    thread::sleep(Duration::from_millis(self.config.retry_timeout));
-                    return self.api_request(endpoint, req_type, req);
+                    continue;
                 }
-                result
+                let ws_data = usdm_int.get_symbol_ws(&symbol);
+                let last_trade_id = ws_data
+                    .as_ref()
+                    .and_then(|ws_data| ws_data.get_last_aggr_trade_id());
+                let delay = match usdm_int.get_klines(symbol.to_owned(), "1m", 1440, None, None) {
+                    Ok(kline_data) => {
+                        // the klines also correct candles missed while disconnected
+                        if let Some(ws_data) = ws_data {
+                            if let Err(e) = ws_data.seed_candles(&kline_data, last_trade_id) {
+                                error!("Unable to seed the candles of {symbol}: {e}");
+                            }
+                        }
+                        if let Some(mut data) = usdm_int.get_symbol_data(&symbol) {
+                            data.set_last_day_klines(kline_data);
+                        }
+                        usdm_int.config.rest_update_interval
+                    }
+                    Err(err) => {
+                        error!("{err:?}");
+                        usdm_int.config.retry_timeout
+                    }
+                };
+                next_updates.insert(symbol, Instant::now() + Duration::from_millis(delay));
             }
-            Err(BinanceError::RequestError(_)) => {
-                thread::sleep(Duration::from_millis(self.config.retry_timeout));
-                self.api_request(endpoint, req_type, req)
+            let next_update = next_updates.values().min().copied();
+            let wait = next_update
+                .map(|next_update| next_update.saturating_duration_since(Instant::now()))
+                .unwrap_or(SYMBOLS_POLL_INTERVAL);
+            // wakes up to pick up added symbols
+            sleep(running, wait.min(SYMBOLS_POLL_INTERVAL));
+        }
+    });
+}
+
+fn auto_cancel_heartbeat(usdm_int: UsdmInterface) {
+    if usdm_int.config.auto_cancel_countdown == 0 {
+        return;
+    }
+    let workers = usdm_int.workers.clone();
+    workers.spawn(move |running| loop {
+        let mut armed = true;
+        for symbol in usdm_int.get_symbols() {
+            match usdm_int.auto_cancel_all_open_orders(
+                symbol.to_owned(),
+                usdm_int.config.auto_cancel_countdown,
+            ) {
+                Ok(countdown) => debug!("Auto-cancel countdown of {symbol} armed {countdown:?}"),
+                Err(err) => {
+                    error!("{err:?}");
+                    armed = false;
+                }
             }
-            _ => result,
         }
-    }
+        let delay = if armed {
+            usdm_int.config.auto_cancel_interval
+        } else {
+            usdm_int.config.retry_timeout
+        };
+        if !sleep(running, Duration::from_millis(delay)) {
+            break;
+        }
+    });
 }
 
-fn update_usdm_data(mut usdm_int: UsdmInterface) {
-    thread::spawn(move || loop {
-        match usdm_int.get_klines(usdm_int.symbol.to_owned(), "1m", 1440, None, None) {
-            Ok(kline_data) => {
-                usdm_int.data.set_last_day_klines(kline_data);
-                thread::sleep(Duration::from_millis(usdm_int.config.rest_update_interval));
+// fills missed while the user stream was down, or found by a reconciliation, come without
+// their PnL and fees, the ledger is seeded again from the income history
+fn ledger_worker(usdm_int: UsdmInterface, ledger: Ledger, start_time: u64) {
+    let gaps = usdm_int.ws.subscribe(
+        &[UsdmEventKind::Reconnected, UsdmEventKind::Discrepancy],
+        1,
+        OverflowPolicy::DropOldest,
+    );
+    let workers = usdm_int.workers.clone();
+    workers.spawn(move |running| {
+        while running.load(Ordering::Relaxed) {
+            match gaps.recv_timeout(RECONCILE_POLL_INTERVAL) {
+                Ok(Some(_)) => {}
+                Ok(None) => continue,
+                Err(_) => break,
             }
-            Err(err) => {
-                error!("{err:?}");
-                thread::sleep(Duration::from_millis(usdm_int.config.retry_timeout));
+            ledger.hold();
+            if let Err(e) = usdm_int.seed_ledger(&ledger, start_time) {
+                error!("Unable to seed the ledger again: {e}");
+                ledger.resume();
+            }
+        }
+    });
+}
+
+fn reconcile_worker(usdm_int: UsdmInterface) {
+    // only whether a reconnect happened matters
+    let reconnects =
+        usdm_int
+            .ws
+            .subscribe(&[UsdmEventKind::Reconnected], 1, OverflowPolicy::DropOldest);
+    let interval = Duration::from_millis(usdm_int.config.reconcile_interval);
+    let workers = usdm_int.workers.clone();
+    workers.spawn(move |running| {
+        let mut next_run = Instant::now() + interval;
+        while running.load(Ordering::Relaxed) {
+            let reconnected = match reconnects.recv_timeout(RECONCILE_POLL_INTERVAL) {
+                Ok(event) => event.is_some(),
+                Err(_) => break,
+            };
+            let due = !interval.is_zero() && Instant::now() >= next_run;
+            if !reconnected && !due {
+                continue;
             }
+            // reconnects in a row are reconciled once
+            while let Ok(Some(_)) = reconnects.try_recv() {}
+            match usdm_int.reconcile() {
+                Ok(report) if report.is_consistent() => debug!("Reconciled {report:?}"),
+                Ok(report) => warn!("Reconciled {} discrepancies", report.discrepancies.len()),
+                Err(err) => error!("Unable to reconcile: {err:?}"),
+            }
+            next_run = Instant::now() + interval;
         }
     });
 }
+
+pub(crate) fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_millis() as u64)
+        .unwrap_or_default()
+}
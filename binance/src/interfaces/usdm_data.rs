@@ -1,4 +1,9 @@
+use crate::commons::errors::*;
+use crate::interfaces::paper::PaperConfig;
+use crate::interfaces::retry::RetryPolicy;
 use crate::rest::model::KlineSummaries;
+use crate::websocket::candles::CandleInterval;
+use crate::websocket::futures::usdm_data::WsDataConfig;
 use std::sync::{Arc, RwLock};
 
 type KlineData = Arc<RwLock<KlineSummaries>>;
@@ -6,26 +11,66 @@ type KlineData = Arc<RwLock<KlineSummaries>>;
 #[derive(Clone)]
 pub struct UsdmConfig {
     pub retry_on_err: bool,
+    pub retry_policy: RetryPolicy,
     pub retry_timeout: u64,
     pub rest_update_interval: u64,
+    pub auto_cancel_countdown: u64,
+    pub auto_cancel_interval: u64,
+    pub ready_timeout: u64,
+    pub reconcile_interval: u64,
+    pub candle_intervals: Vec<CandleInterval>,
+    pub paper_trading: Option<PaperConfig>,
+    pub ws_data: WsDataConfig,
 }
 
 impl Default for UsdmConfig {
     fn default() -> UsdmConfig {
         UsdmConfig {
             retry_on_err: true,
+            retry_policy: RetryPolicy::default(),
             retry_timeout: 300,          // milliseconds
             rest_update_interval: 60000, // milliseconds
+            auto_cancel_countdown: 0,    // milliseconds, 0 disables the countdown
+            auto_cancel_interval: 10000, // milliseconds
+            ready_timeout: 30000,        // milliseconds
+            reconcile_interval: 60000,   // milliseconds, 0 reconciles only after reconnects
+            candle_intervals: vec![CandleInterval::OneMinute],
+            paper_trading: None,
+            ws_data: WsDataConfig::default(),
         }
     }
 }
 
 impl UsdmConfig {
+    /// Checks the settings that would misbehave at runtime, called by the interface constructor
+    pub fn validate(&self) -> Result<()> {
+        if self.auto_cancel_countdown > 0
+            && (self.auto_cancel_interval == 0
+                || self.auto_cancel_interval > self.auto_cancel_countdown / 2)
+        {
+            // the countdown would fire between two heartbeats and cancel all orders
+            return Err(BinanceError::InvalidConfig(format!(
+                "auto_cancel_interval {} must be positive and at most half of \
+                 auto_cancel_countdown {}",
+                self.auto_cancel_interval, self.auto_cancel_countdown
+            )));
+        }
+        Ok(())
+    }
+
     pub fn set_retry_on_err(mut self, retry_on_err: bool) -> Self {
         self.retry_on_err = retry_on_err;
         self
     }
 
+    /// Attempts, backoff and deadline of failed REST requests, and which ones are retried
+    pub fn set_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Delay before a background thread retries a failed REST update, e.g. of the klines.
+    /// Requests made by the caller follow `retry_policy` instead
     pub fn set_retry_timeout(mut self, retry_timeout: u64) -> Self {
         self.retry_timeout = retry_timeout;
         self
@@ -35,6 +80,54 @@ impl UsdmConfig {
         self.rest_update_interval = rest_update_interval;
         self
     }
+
+    /// Keeps re-arming an exchange side countdown that cancels all open orders of the symbol
+    /// if it is not refreshed within `auto_cancel_countdown` milliseconds, every
+    /// `auto_cancel_interval` milliseconds, at most half of the countdown
+    pub fn set_auto_cancel(
+        mut self,
+        auto_cancel_countdown: u64,
+        auto_cancel_interval: u64,
+    ) -> Self {
+        self.auto_cancel_countdown = auto_cancel_countdown;
+        self.auto_cancel_interval = auto_cancel_interval;
+        self
+    }
+
+    /// Interval of the REST reconciliation of orders and positions,
+    /// which also runs after every user stream reconnect
+    pub fn set_reconcile_interval(mut self, reconcile_interval: u64) -> Self {
+        self.reconcile_interval = reconcile_interval;
+        self
+    }
+
+    /// Intervals of the candles built from the aggregated trades of every symbol,
+    /// seeded with the "1m" klines, so a "1s" candle starts with the next trade
+    pub fn set_candle_intervals(mut self, candle_intervals: &[CandleInterval]) -> Self {
+        self.candle_intervals = candle_intervals.to_vec();
+        self
+    }
+
+    /// Simulates the account: orders are matched locally against the aggregated trades and
+    /// mark prices, and their updates are published as the user stream would. The API keys
+    /// are not used, the account endpoints that are not simulated fail
+    pub fn set_paper_trading(mut self, paper_trading: PaperConfig) -> Self {
+        self.paper_trading = Some(paper_trading);
+        self
+    }
+
+    /// Time the interface constructor waits for each of its data sources before failing
+    pub fn set_ready_timeout(mut self, ready_timeout: u64) -> Self {
+        self.ready_timeout = ready_timeout;
+        self
+    }
+
+    /// How many aggregated trades, liquidations, mark price snaps, orders and candles
+    /// of every symbol are kept, by count or by age
+    pub fn set_ws_data(mut self, ws_data: WsDataConfig) -> Self {
+        self.ws_data = ws_data;
+        self
+    }
 }
 
 #[derive(Clone)]
@@ -61,3 +154,31 @@ impl UsdmData {
         *self.last_day_klines.write().unwrap() = klines;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_auto_cancel() {
+        assert!(UsdmConfig::default().validate().is_ok());
+        assert!(UsdmConfig::default()
+            .set_auto_cancel(60000, 10000)
+            .validate()
+            .is_ok());
+        assert!(UsdmConfig::default()
+            .set_auto_cancel(60000, 30000)
+            .validate()
+            .is_ok());
+        assert!(matches!(
+            UsdmConfig::default()
+                .set_auto_cancel(10000, 10000)
+                .validate(),
+            Err(BinanceError::InvalidConfig(_))
+        ));
+        assert!(UsdmConfig::default()
+            .set_auto_cancel(10000, 0)
+            .validate()
+            .is_err());
+    }
+}
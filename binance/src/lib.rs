@@ -1,4 +1,6 @@
 pub mod commons;
 pub mod interfaces;
 pub mod rest;
+#[cfg(feature = "test-support")]
+pub mod testing;
 pub mod websocket;
@@ -72,6 +72,7 @@ pub enum Futures {
     BookTicker,
     AllForceOrders,
     AllOpenOrders,
+    AllOrders,
     Order,
     PositionRisk,
     Balance,
@@ -89,6 +90,8 @@ pub enum Futures {
     OpenOrders,
     UserDataStream,
     ComissionRate,
+    CountdownCancelAll,
+    Income,
 }
 
 impl From<API> for String {
@@ -143,6 +146,7 @@ impl From<API> for String {
                 Futures::BookTicker => "/fapi/v1/ticker/bookTicker",
                 Futures::AllForceOrders => "/fapi/v1/allForceOrders",
                 Futures::AllOpenOrders => "/fapi/v1/allOpenOrders",
+                Futures::AllOrders => "/fapi/v1/allOrders",
                 Futures::PositionSide => "/fapi/v1/positionSide/dual",
                 Futures::Order => "/fapi/v1/order",
                 Futures::PositionRisk => "/fapi/v2/positionRisk",
@@ -160,6 +164,8 @@ impl From<API> for String {
                 Futures::OpenOrders => "/fapi/v1/openOrders",
                 Futures::UserDataStream => "/fapi/v1/listenKey",
                 Futures::ComissionRate => "/fapi/v1/commissionRate",
+                Futures::CountdownCancelAll => "/fapi/v1/countdownCancelAll",
+                Futures::Income => "/fapi/v1/income",
             },
         })
     }
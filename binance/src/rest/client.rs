@@ -1,12 +1,10 @@
 use crate::commons::errors::*;
+use crate::commons::util::hmac_signature;
 use crate::rest::api::API;
-use hex::encode as hex_encode;
-use hmac::{Hmac, Mac};
 use reqwest::blocking::Response;
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, USER_AGENT};
 use reqwest::StatusCode;
 use serde::de::DeserializeOwned;
-use sha2::Sha256;
 
 #[derive(Clone)]
 pub struct Client {
@@ -125,17 +123,12 @@ impl Client {
     fn sign_request(&self, endpoint: API, request: Option<String>) -> String {
         match request {
             Some(request) => {
-                let mut signed_key =
-                    Hmac::<Sha256>::new_from_slice(self.secret_key.as_bytes()).unwrap();
-                signed_key.update(request.as_bytes());
-                let signature = hex_encode(signed_key.finalize().into_bytes());
+                let signature = hmac_signature(&self.secret_key, &request);
                 let request_body: String = format!("{request}&signature={signature}");
                 format!("{}{}?{}", self.host, String::from(endpoint), request_body)
             }
             None => {
-                let signed_key =
-                    Hmac::<Sha256>::new_from_slice(self.secret_key.as_bytes()).unwrap();
-                let signature = hex_encode(signed_key.finalize().into_bytes());
+                let signature = hmac_signature(&self.secret_key, "");
                 let request_body: String = format!("&signature={signature}");
                 format!("{}{}?{}", self.host, String::from(endpoint), request_body)
             }
@@ -2,8 +2,8 @@ use std::collections::BTreeMap;
 use std::fmt::Display;
 
 use super::model::{
-    AccountBalance, AccountInformation, CanceledOrder, ChangeLeverageResponse, PositionRisk,
-    Transaction,
+    AccountBalance, AccountInformation, CanceledOrder, ChangeLeverageResponse, CountdownCancelAll,
+    PositionRisk, Transaction,
 };
 use crate::commons::errors::*;
 use crate::commons::util::build_signed_request;
@@ -39,6 +39,7 @@ impl From<ContractType> for String {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PositionSide {
     Both,
     Long,
@@ -55,6 +56,7 @@ impl Display for PositionSide {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OrderType {
     Limit,
     Market,
@@ -79,6 +81,7 @@ impl Display for OrderType {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WorkingType {
     MarkPrice,
     ContractPrice,
@@ -328,6 +331,304 @@ impl FuturesAccount {
             .post_signed(API::Futures(Futures::Order), request)
     }
 
+    // Place a TRAILING_STOP_MARKET reduce only order - BUY. `callback_rate` in percent (0.1 to 5)
+    // `position_side` is `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn trailing_stop_market_buy<S, F, A, P>(
+        &self,
+        symbol: S,
+        qty: F,
+        callback_rate: f64,
+        activation_price: A,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        A: Into<Option<f64>>,
+        P: Into<Option<PositionSide>>,
+    {
+        let order: OrderRequest = OrderRequest {
+            symbol: symbol.into(),
+            side: OrderSide::Buy,
+            position_side: position_side.into(),
+            order_type: OrderType::TrailingStopMarket,
+            time_in_force: None,
+            qty: Some(qty.into()),
+            reduce_only: Some(true),
+            price: None,
+            stop_price: None,
+            close_position: None,
+            activation_price: activation_price.into(),
+            callback_rate: Some(callback_rate),
+            working_type: Some(working_type),
+            price_protect: None,
+        };
+        let order = self.build_order(order);
+        let request = build_signed_request(order, self.recv_window)?;
+        self.client
+            .post_signed(API::Futures(Futures::Order), request)
+    }
+
+    // Place a TRAILING_STOP_MARKET reduce only order - SELL. `callback_rate` in percent (0.1 to 5)
+    // `position_side` is `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn trailing_stop_market_sell<S, F, A, P>(
+        &self,
+        symbol: S,
+        qty: F,
+        callback_rate: f64,
+        activation_price: A,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        A: Into<Option<f64>>,
+        P: Into<Option<PositionSide>>,
+    {
+        let order: OrderRequest = OrderRequest {
+            symbol: symbol.into(),
+            side: OrderSide::Sell,
+            position_side: position_side.into(),
+            order_type: OrderType::TrailingStopMarket,
+            time_in_force: None,
+            qty: Some(qty.into()),
+            reduce_only: Some(true),
+            price: None,
+            stop_price: None,
+            close_position: None,
+            activation_price: activation_price.into(),
+            callback_rate: Some(callback_rate),
+            working_type: Some(working_type),
+            price_protect: None,
+        };
+        let order = self.build_order(order);
+        let request = build_signed_request(order, self.recv_window)?;
+        self.client
+            .post_signed(API::Futures(Futures::Order), request)
+    }
+
+    // Place a TAKE_PROFIT_MARKET close - BUY
+    // `position_side` is `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn take_profit_market_close_buy<S, F, P>(
+        &self,
+        symbol: S,
+        stop_price: F,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        P: Into<Option<PositionSide>>,
+    {
+        let order: OrderRequest = OrderRequest {
+            symbol: symbol.into(),
+            side: OrderSide::Buy,
+            position_side: position_side.into(),
+            order_type: OrderType::TakeProfitMarket,
+            time_in_force: None,
+            qty: None,
+            reduce_only: None,
+            price: None,
+            stop_price: Some(stop_price.into()),
+            close_position: Some(true),
+            activation_price: None,
+            callback_rate: None,
+            working_type: Some(working_type),
+            price_protect: None,
+        };
+        let order = self.build_order(order);
+        let request = build_signed_request(order, self.recv_window)?;
+        self.client
+            .post_signed(API::Futures(Futures::Order), request)
+    }
+
+    // Place a TAKE_PROFIT_MARKET close - SELL
+    // `position_side` is `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn take_profit_market_close_sell<S, F, P>(
+        &self,
+        symbol: S,
+        stop_price: F,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        P: Into<Option<PositionSide>>,
+    {
+        let order: OrderRequest = OrderRequest {
+            symbol: symbol.into(),
+            side: OrderSide::Sell,
+            position_side: position_side.into(),
+            order_type: OrderType::TakeProfitMarket,
+            time_in_force: None,
+            qty: None,
+            reduce_only: None,
+            price: None,
+            stop_price: Some(stop_price.into()),
+            close_position: Some(true),
+            activation_price: None,
+            callback_rate: None,
+            working_type: Some(working_type),
+            price_protect: None,
+        };
+        let order = self.build_order(order);
+        let request = build_signed_request(order, self.recv_window)?;
+        self.client
+            .post_signed(API::Futures(Futures::Order), request)
+    }
+
+    // Place a STOP_MARKET close triggered by `working_type` - BUY
+    // `position_side` is `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn stop_loss_market_close_buy<S, F, P>(
+        &self,
+        symbol: S,
+        stop_price: F,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        P: Into<Option<PositionSide>>,
+    {
+        let order: OrderRequest = OrderRequest {
+            symbol: symbol.into(),
+            side: OrderSide::Buy,
+            position_side: position_side.into(),
+            order_type: OrderType::StopMarket,
+            time_in_force: None,
+            qty: None,
+            reduce_only: None,
+            price: None,
+            stop_price: Some(stop_price.into()),
+            close_position: Some(true),
+            activation_price: None,
+            callback_rate: None,
+            working_type: Some(working_type),
+            price_protect: None,
+        };
+        let order = self.build_order(order);
+        let request = build_signed_request(order, self.recv_window)?;
+        self.client
+            .post_signed(API::Futures(Futures::Order), request)
+    }
+
+    // Place a STOP_MARKET close triggered by `working_type` - SELL
+    // `position_side` is `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn stop_loss_market_close_sell<S, F, P>(
+        &self,
+        symbol: S,
+        stop_price: F,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        P: Into<Option<PositionSide>>,
+    {
+        let order: OrderRequest = OrderRequest {
+            symbol: symbol.into(),
+            side: OrderSide::Sell,
+            position_side: position_side.into(),
+            order_type: OrderType::StopMarket,
+            time_in_force: None,
+            qty: None,
+            reduce_only: None,
+            price: None,
+            stop_price: Some(stop_price.into()),
+            close_position: Some(true),
+            activation_price: None,
+            callback_rate: None,
+            working_type: Some(working_type),
+            price_protect: None,
+        };
+        let order = self.build_order(order);
+        let request = build_signed_request(order, self.recv_window)?;
+        self.client
+            .post_signed(API::Futures(Futures::Order), request)
+    }
+
+    // Place a TAKE_PROFIT limit reduce only order - BUY
+    // `position_side` is `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn take_profit_limit_buy<S, F, P>(
+        &self,
+        symbol: S,
+        qty: F,
+        price: f64,
+        stop_price: f64,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        P: Into<Option<PositionSide>>,
+    {
+        let order: OrderRequest = OrderRequest {
+            symbol: symbol.into(),
+            side: OrderSide::Buy,
+            position_side: position_side.into(),
+            order_type: OrderType::TakeProfit,
+            time_in_force: Some(TimeInForce::GTC),
+            qty: Some(qty.into()),
+            reduce_only: Some(true),
+            price: Some(price),
+            stop_price: Some(stop_price),
+            close_position: None,
+            activation_price: None,
+            callback_rate: None,
+            working_type: Some(working_type),
+            price_protect: None,
+        };
+        let order = self.build_order(order);
+        let request = build_signed_request(order, self.recv_window)?;
+        self.client
+            .post_signed(API::Futures(Futures::Order), request)
+    }
+
+    // Place a TAKE_PROFIT limit reduce only order - SELL
+    // `position_side` is `None` in one-way mode, `Long` or `Short` in hedge mode
+    pub fn take_profit_limit_sell<S, F, P>(
+        &self,
+        symbol: S,
+        qty: F,
+        price: f64,
+        stop_price: f64,
+        working_type: WorkingType,
+        position_side: P,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+        P: Into<Option<PositionSide>>,
+    {
+        let order: OrderRequest = OrderRequest {
+            symbol: symbol.into(),
+            side: OrderSide::Sell,
+            position_side: position_side.into(),
+            order_type: OrderType::TakeProfit,
+            time_in_force: Some(TimeInForce::GTC),
+            qty: Some(qty.into()),
+            reduce_only: Some(true),
+            price: Some(price),
+            stop_price: Some(stop_price),
+            close_position: None,
+            activation_price: None,
+            callback_rate: None,
+            working_type: Some(working_type),
+            price_protect: None,
+        };
+        let order = self.build_order(order);
+        let request = build_signed_request(order, self.recv_window)?;
+        self.client
+            .post_signed(API::Futures(Futures::Order), request)
+    }
+
     // Custom order for for professional traders
     pub fn custom_order(&self, order_request: CustomOrderRequest) -> Result<Transaction> {
         let order: OrderRequest = OrderRequest {
@@ -353,52 +654,7 @@ impl FuturesAccount {
     }
 
     fn build_order(&self, order: OrderRequest) -> BTreeMap<String, String> {
-        let mut parameters = BTreeMap::new();
-        parameters.insert("symbol".into(), order.symbol);
-        parameters.insert("side".into(), order.side.to_string());
-        parameters.insert("type".into(), order.order_type.to_string());
-
-        if let Some(position_side) = order.position_side {
-            parameters.insert("positionSide".into(), position_side.to_string());
-        }
-        if let Some(time_in_force) = order.time_in_force {
-            parameters.insert("timeInForce".into(), time_in_force.to_string());
-        }
-        if let Some(qty) = order.qty {
-            parameters.insert("quantity".into(), qty.to_string());
-        }
-        if let Some(reduce_only) = order.reduce_only {
-            parameters.insert("reduceOnly".into(), reduce_only.to_string().to_uppercase());
-        }
-        if let Some(price) = order.price {
-            parameters.insert("price".into(), price.to_string());
-        }
-        if let Some(stop_price) = order.stop_price {
-            parameters.insert("stopPrice".into(), stop_price.to_string());
-        }
-        if let Some(close_position) = order.close_position {
-            parameters.insert(
-                "closePosition".into(),
-                close_position.to_string().to_uppercase(),
-            );
-        }
-        if let Some(activation_price) = order.activation_price {
-            parameters.insert("activationPrice".into(), activation_price.to_string());
-        }
-        if let Some(callback_rate) = order.callback_rate {
-            parameters.insert("callbackRate".into(), callback_rate.to_string());
-        }
-        if let Some(working_type) = order.working_type {
-            parameters.insert("workingType".into(), working_type.to_string());
-        }
-        if let Some(price_protect) = order.price_protect {
-            parameters.insert(
-                "priceProtect".into(),
-                price_protect.to_string().to_uppercase(),
-            );
-        }
-
-        parameters
+        build_order_parameters(order)
     }
 
     pub fn position_information<S>(&self, symbol: S) -> Result<Vec<PositionRisk>>
@@ -469,6 +725,24 @@ impl FuturesAccount {
             .map(|_| ())
     }
 
+    // Cancels all open orders of the symbol once `countdown_time` (milliseconds) elapses,
+    // unless the countdown is refreshed before. A `countdown_time` of 0 disables it.
+    pub fn auto_cancel_all_open_orders<S>(
+        &self,
+        symbol: S,
+        countdown_time: u64,
+    ) -> Result<CountdownCancelAll>
+    where
+        S: Into<String>,
+    {
+        let mut parameters: BTreeMap<String, String> = BTreeMap::new();
+        parameters.insert("symbol".into(), symbol.into());
+        parameters.insert("countdownTime".into(), countdown_time.to_string());
+        let request = build_signed_request(parameters, self.recv_window)?;
+        self.client
+            .post_signed(API::Futures(Futures::CountdownCancelAll), request)
+    }
+
     pub fn get_order<S>(&self, symbol: S, order_id: u64) -> Result<Order>
     where
         S: Into<String>,
@@ -505,3 +779,58 @@ impl FuturesAccount {
             .get_signed(API::Futures(Futures::ComissionRate), Some(request))
     }
 }
+
+// Parameters of an order, shared with the websocket API
+pub(crate) fn build_order_parameters(order: OrderRequest) -> BTreeMap<String, String> {
+    let mut parameters = BTreeMap::new();
+    parameters.insert("symbol".into(), order.symbol);
+    parameters.insert("side".into(), order.side.to_string());
+    parameters.insert("type".into(), order.order_type.to_string());
+
+    if let Some(position_side) = order.position_side {
+        parameters.insert("positionSide".into(), position_side.to_string());
+    }
+    if let Some(time_in_force) = order.time_in_force {
+        parameters.insert("timeInForce".into(), time_in_force.to_string());
+    }
+    if let Some(qty) = order.qty {
+        parameters.insert("quantity".into(), qty.to_string());
+    }
+    // in hedge mode the position side tells the closing orders, Binance rejects reduceOnly
+    let hedge_mode = matches!(
+        order.position_side,
+        Some(PositionSide::Long) | Some(PositionSide::Short)
+    );
+    if let Some(reduce_only) = order.reduce_only.filter(|_| !hedge_mode) {
+        parameters.insert("reduceOnly".into(), reduce_only.to_string().to_uppercase());
+    }
+    if let Some(price) = order.price {
+        parameters.insert("price".into(), price.to_string());
+    }
+    if let Some(stop_price) = order.stop_price {
+        parameters.insert("stopPrice".into(), stop_price.to_string());
+    }
+    if let Some(close_position) = order.close_position {
+        parameters.insert(
+            "closePosition".into(),
+            close_position.to_string().to_uppercase(),
+        );
+    }
+    if let Some(activation_price) = order.activation_price {
+        parameters.insert("activationPrice".into(), activation_price.to_string());
+    }
+    if let Some(callback_rate) = order.callback_rate {
+        parameters.insert("callbackRate".into(), callback_rate.to_string());
+    }
+    if let Some(working_type) = order.working_type {
+        parameters.insert("workingType".into(), working_type.to_string());
+    }
+    if let Some(price_protect) = order.price_protect {
+        parameters.insert(
+            "priceProtect".into(),
+            price_protect.to_string().to_uppercase(),
+        );
+    }
+
+    parameters
+}
@@ -190,7 +190,7 @@ pub struct FundingRateHist {
     pub funding_time: i64,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Order {
     pub client_order_id: String,
@@ -266,6 +266,35 @@ pub struct Transaction {
     price_protect: bool,
 }
 
+impl From<Order> for Transaction {
+    fn from(order: Order) -> Self {
+        Transaction {
+            client_order_id: order.client_order_id,
+            cum_qty: order.cum_qty,
+            cum_quote: order.cum_quote,
+            executed_qty: order.executed_qty,
+            order_id: order.order_id,
+            avg_price: order.avg_price,
+            orig_qty: order.orig_qty,
+            reduce_only: order.reduce_only,
+            side: order.side,
+            position_side: order.position_side,
+            status: order.status,
+            stop_price: order.stop_price,
+            close_position: order.close_position,
+            symbol: order.symbol,
+            time_in_force: order.time_in_force,
+            type_name: order.order_type,
+            orig_type: order.orig_type,
+            activate_price: Some(order.activation_price),
+            price_rate: Some(order.price_rate),
+            update_time: order.update_time,
+            working_type: order.working_type,
+            price_protect: order.price_protect,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct CanceledOrder {
@@ -304,6 +333,35 @@ pub struct CanceledOrder {
     price_protect: bool,
 }
 
+impl From<Order> for CanceledOrder {
+    fn from(order: Order) -> Self {
+        CanceledOrder {
+            client_order_id: order.client_order_id,
+            cum_qty: order.cum_qty,
+            cum_quote: order.cum_quote,
+            executed_qty: order.executed_qty,
+            order_id: order.order_id,
+            orig_qty: order.orig_qty,
+            orig_type: order.orig_type,
+            price: order.price,
+            reduce_only: order.reduce_only,
+            side: order.side,
+            position_side: order.position_side,
+            status: order.status,
+            stop_price: order.stop_price,
+            close_position: order.close_position,
+            symbol: order.symbol,
+            time_in_force: order.time_in_force,
+            type_name: order.order_type,
+            activate_price: Some(order.activation_price),
+            price_rate: Some(order.price_rate),
+            update_time: order.update_time,
+            working_type: order.working_type,
+            price_protect: order.price_protect,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PositionRisk {
@@ -474,6 +532,28 @@ pub struct ComissionRate {
     pub taker_commission_rate: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CountdownCancelAll {
+    pub symbol: String,
+    pub countdown_time: String,
+}
+
+/// Entry of the income history, `symbol` and `trade_id` are empty when they do not apply
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Income {
+    pub symbol: String,
+    pub income_type: String,
+    #[serde(with = "string_or_float")]
+    pub income: f64,
+    pub asset: String,
+    pub info: String,
+    pub time: u64,
+    pub tran_id: u64,
+    pub trade_id: String,
+}
+
 fn default_stop_price() -> f64 {
     0.0
 }
@@ -532,7 +612,7 @@ pub struct OrderUpdate {
     #[serde(rename = "L")]
     pub price_last_filled_trade: String,
 
-    #[serde(skip, rename = "N")]
+    #[serde(rename = "N")]
     pub asset_commisioned: Option<String>,
 
     #[serde(rename = "n")]
@@ -602,3 +682,193 @@ pub struct OrderTradeEvent {
     #[serde(rename = "o")]
     pub order: OrderUpdate,
 }
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AccountConfigUpdateEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+
+    #[serde(rename = "E")]
+    pub event_time: u64,
+
+    #[serde(rename = "T")]
+    pub transaction_time: u64,
+
+    /// Set when the leverage of a symbol changed
+    #[serde(rename = "ac")]
+    pub leverage: Option<LeverageConfig>,
+
+    /// Set when the multi-assets mode changed
+    #[serde(rename = "ai")]
+    pub asset_mode: Option<AssetModeConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LeverageConfig {
+    #[serde(rename = "s")]
+    pub symbol: String,
+
+    #[serde(rename = "l")]
+    pub leverage: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AssetModeConfig {
+    #[serde(rename = "j")]
+    pub multi_assets_margin: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MarginCallEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+
+    #[serde(rename = "E")]
+    pub event_time: u64,
+
+    /// Only pushed for crossed positions
+    #[serde(rename = "cw", default)]
+    pub cross_wallet_balance: Option<String>,
+
+    #[serde(rename = "p")]
+    pub positions: Vec<MarginCallPosition>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MarginCallPosition {
+    #[serde(rename = "s")]
+    pub symbol: String,
+
+    #[serde(rename = "ps")]
+    pub position_side: String,
+
+    #[serde(rename = "pa")]
+    pub position_amount: String,
+
+    #[serde(rename = "mt")]
+    pub margin_type: String,
+
+    #[serde(rename = "iw")]
+    pub isolated_wallet: String,
+
+    #[serde(rename = "mp")]
+    pub mark_price: String,
+
+    #[serde(rename = "up")]
+    pub unrealized_profit: String,
+
+    #[serde(rename = "mm")]
+    pub maintenance_margin_required: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StrategyUpdateEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+
+    #[serde(rename = "E")]
+    pub event_time: u64,
+
+    #[serde(rename = "T")]
+    pub transaction_time: u64,
+
+    #[serde(rename = "su")]
+    pub strategy: StrategyUpdate,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StrategyUpdate {
+    #[serde(rename = "si")]
+    pub strategy_id: u64,
+
+    #[serde(rename = "st")]
+    pub strategy_type: String,
+
+    #[serde(rename = "ss")]
+    pub strategy_status: String,
+
+    #[serde(rename = "s")]
+    pub symbol: String,
+
+    #[serde(rename = "ut")]
+    pub update_time: u64,
+
+    /// Operation code, e.g. 8007 when the strategy is stopped by the user
+    #[serde(rename = "c")]
+    pub op_code: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GridUpdateEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+
+    #[serde(rename = "E")]
+    pub event_time: u64,
+
+    #[serde(rename = "T")]
+    pub transaction_time: u64,
+
+    #[serde(rename = "gu")]
+    pub grid: GridUpdate,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GridUpdate {
+    #[serde(rename = "si")]
+    pub strategy_id: u64,
+
+    #[serde(rename = "st")]
+    pub strategy_type: String,
+
+    #[serde(rename = "ss")]
+    pub strategy_status: String,
+
+    #[serde(rename = "s")]
+    pub symbol: String,
+
+    #[serde(rename = "r")]
+    pub realized_pnl: String,
+
+    #[serde(rename = "up")]
+    pub unmatched_average_price: String,
+
+    #[serde(rename = "uq")]
+    pub unmatched_qty: String,
+
+    #[serde(rename = "uf")]
+    pub unmatched_fee: String,
+
+    #[serde(rename = "mp")]
+    pub matched_pnl: String,
+
+    #[serde(rename = "ut")]
+    pub update_time: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConditionalOrderTriggerRejectEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+
+    #[serde(rename = "E")]
+    pub event_time: u64,
+
+    #[serde(rename = "T")]
+    pub transaction_time: u64,
+
+    #[serde(rename = "or")]
+    pub reject: OrderReject,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OrderReject {
+    #[serde(rename = "s")]
+    pub symbol: String,
+
+    #[serde(rename = "i")]
+    pub order_id: u64,
+
+    #[serde(rename = "r")]
+    pub reason: String,
+}
@@ -140,6 +140,8 @@ pub struct AccountInformation {
     pub can_withdraw: bool,
     pub can_deposit: bool,
     pub balances: Vec<Balance>,
+    #[serde(default)]
+    pub update_time: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -475,6 +477,56 @@ pub struct BalanceUpdateEvent {
     pub last_account_update_time: u64,
 }
 
+/// Spot `outboundAccountPosition`, sent with the assets whose balance changed
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountPositionEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+
+    #[serde(rename = "E")]
+    pub event_time: u64,
+
+    #[serde(rename = "u")]
+    pub last_update_time: u64,
+
+    #[serde(rename = "B")]
+    pub balances: Vec<AccountPositionBalance>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountPositionBalance {
+    #[serde(rename = "a")]
+    pub asset: String,
+
+    #[serde(rename = "f")]
+    pub free: String,
+
+    #[serde(rename = "l")]
+    pub locked: String,
+}
+
+/// Spot `balanceUpdate`, sent on deposits, withdrawals and transfers
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceDeltaEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+
+    #[serde(rename = "E")]
+    pub event_time: u64,
+
+    #[serde(rename = "a")]
+    pub asset: String,
+
+    #[serde(rename = "d")]
+    pub balance_delta: String,
+
+    #[serde(rename = "T")]
+    pub clear_time: u64,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct OrderTradeEvent {
@@ -875,6 +927,237 @@ pub struct MiniTickerEvent {
     pub quote_volume: String,
 }
 
+/// Spot current average price, stream \<symbol\>@avgPrice
+///
+/// <https://developers.binance.com/docs/binance-spot-api-docs/web-socket-streams#average-price>
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AvgPriceEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+
+    #[serde(rename = "E")]
+    pub event_time: u64,
+
+    #[serde(rename = "s")]
+    pub symbol: String,
+
+    #[serde(rename = "i")]
+    pub interval: String,
+
+    #[serde(rename = "w")]
+    pub avg_price: String,
+
+    #[serde(rename = "T")]
+    pub last_trade_time: u64,
+}
+
+/// Spot rolling window ticker, streams \<symbol\>@ticker_\<window\> with window 1h, 4h or 1d
+///
+/// <https://developers.binance.com/docs/binance-spot-api-docs/web-socket-streams#individual-symbol-rolling-window-statistics-streams>
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RollingTickerEvent {
+    /// 1hTicker, 4hTicker or 1dTicker
+    #[serde(rename = "e")]
+    pub event_type: String,
+
+    #[serde(rename = "E")]
+    pub event_time: u64,
+
+    #[serde(rename = "s")]
+    pub symbol: String,
+
+    #[serde(rename = "p")]
+    pub price_change: String,
+
+    #[serde(rename = "P")]
+    pub price_change_percent: String,
+
+    #[serde(rename = "o")]
+    pub open: String,
+
+    #[serde(rename = "h")]
+    pub high: String,
+
+    #[serde(rename = "l")]
+    pub low: String,
+
+    #[serde(rename = "c")]
+    pub close: String,
+
+    #[serde(rename = "w")]
+    pub weighted_avg_price: String,
+
+    #[serde(rename = "v")]
+    pub volume: String,
+
+    #[serde(rename = "q")]
+    pub quote_volume: String,
+
+    #[serde(rename = "O")]
+    pub open_time: u64,
+
+    #[serde(rename = "C")]
+    pub close_time: u64,
+
+    #[serde(rename = "F")]
+    pub first_trade_id: i64,
+
+    #[serde(rename = "L")]
+    pub last_trade_id: i64,
+
+    #[serde(rename = "n")]
+    pub num_trades: u64,
+}
+
+/// Futures composite index, stream \<symbol\>@compositeIndex
+///
+/// <https://developers.binance.com/docs/derivatives/usds-margined-futures/websocket-market-streams/Composite-Index-Symbol-Information-Streams>
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CompositeIndexEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+
+    #[serde(rename = "E")]
+    pub event_time: u64,
+
+    #[serde(rename = "s")]
+    pub symbol: String,
+
+    #[serde(rename = "p")]
+    pub price: String,
+
+    #[serde(rename = "C")]
+    pub composition_type: String,
+
+    #[serde(rename = "c")]
+    pub composition: Vec<IndexComposition>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexComposition {
+    #[serde(rename = "b")]
+    pub base_asset: String,
+
+    #[serde(rename = "q")]
+    pub quote_asset: String,
+
+    #[serde(rename = "w")]
+    pub weight_in_quantity: String,
+
+    #[serde(rename = "W")]
+    pub weight_in_percentage: String,
+
+    #[serde(rename = "i")]
+    pub index_price: String,
+}
+
+/// Futures contract info, stream !contractInfo
+///
+/// <https://developers.binance.com/docs/derivatives/usds-margined-futures/websocket-market-streams/Contract-Info-Stream>
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractInfoEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+
+    #[serde(rename = "E")]
+    pub event_time: u64,
+
+    #[serde(rename = "s")]
+    pub symbol: String,
+
+    #[serde(rename = "ps")]
+    pub pair: String,
+
+    #[serde(rename = "ct")]
+    pub contract_type: String,
+
+    #[serde(rename = "dt")]
+    pub delivery_date: u64,
+
+    #[serde(rename = "ot")]
+    pub onboard_date: u64,
+
+    #[serde(rename = "cs")]
+    pub contract_status: String,
+
+    /// Only sent when the brackets are updated
+    #[serde(rename = "bks", default)]
+    pub brackets: Vec<ContractBracket>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractBracket {
+    #[serde(rename = "bs")]
+    pub bracket: u64,
+
+    #[serde(rename = "bnf")]
+    pub notional_floor: f64,
+
+    #[serde(rename = "bnc")]
+    pub notional_cap: f64,
+
+    #[serde(rename = "mmr")]
+    pub maint_margin_ratio: f64,
+
+    #[serde(rename = "cf")]
+    pub cum: f64,
+
+    #[serde(rename = "mi")]
+    pub min_leverage: u64,
+
+    #[serde(rename = "ma")]
+    pub max_leverage: u64,
+}
+
+/// Futures multi-assets mode asset index, streams \<assetSymbol\>@assetIndex and !assetIndex@arr
+///
+/// <https://developers.binance.com/docs/derivatives/usds-margined-futures/websocket-market-streams/Multi-Assets-Mode-Asset-Index>
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetIndexEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+
+    #[serde(rename = "E")]
+    pub event_time: u64,
+
+    #[serde(rename = "s")]
+    pub symbol: String,
+
+    #[serde(rename = "i")]
+    pub index_price: String,
+
+    #[serde(rename = "b")]
+    pub bid_buffer: String,
+
+    #[serde(rename = "a")]
+    pub ask_buffer: String,
+
+    #[serde(rename = "B")]
+    pub bid_rate: String,
+
+    #[serde(rename = "A")]
+    pub ask_rate: String,
+
+    #[serde(rename = "q")]
+    pub auto_exchange_bid_buffer: String,
+
+    #[serde(rename = "g")]
+    pub auto_exchange_ask_buffer: String,
+
+    #[serde(rename = "Q")]
+    pub auto_exchange_bid_rate: String,
+
+    #[serde(rename = "G")]
+    pub auto_exchange_ask_rate: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct KlineEvent {
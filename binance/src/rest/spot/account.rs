@@ -14,7 +14,7 @@ pub struct Account {
     pub recv_window: u64,
 }
 
-struct OrderRequest {
+pub(crate) struct OrderRequest {
     pub symbol: String,
     pub qty: f64,
     pub price: f64,
@@ -51,6 +51,7 @@ impl Display for OrderType {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OrderSide {
     Buy,
     Sell,
@@ -66,10 +67,13 @@ impl Display for OrderSide {
 }
 
 #[allow(clippy::all)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TimeInForce {
     GTC,
     IOC,
     FOK,
+    // Good till crossing (post only), futures only
+    GTX,
 }
 
 impl Display for TimeInForce {
@@ -78,6 +82,7 @@ impl Display for TimeInForce {
             Self::GTC => write!(f, "GTC"),
             Self::IOC => write!(f, "IOC"),
             Self::FOK => write!(f, "FOK"),
+            Self::GTX => write!(f, "GTX"),
         }
     }
 }
@@ -713,27 +718,7 @@ impl Account {
     }
 
     fn build_order(&self, order: OrderRequest) -> BTreeMap<String, String> {
-        let mut order_parameters: BTreeMap<String, String> = BTreeMap::new();
-
-        order_parameters.insert("symbol".into(), order.symbol);
-        order_parameters.insert("side".into(), order.order_side.to_string());
-        order_parameters.insert("type".into(), order.order_type.to_string());
-        order_parameters.insert("quantity".into(), order.qty.to_string());
-
-        if let Some(stop_price) = order.stop_price {
-            order_parameters.insert("stopPrice".into(), stop_price.to_string());
-        }
-
-        if order.price != 0.0 {
-            order_parameters.insert("price".into(), order.price.to_string());
-            order_parameters.insert("timeInForce".into(), order.time_in_force.to_string());
-        }
-
-        if let Some(client_order_id) = order.new_client_order_id {
-            order_parameters.insert("newClientOrderId".into(), client_order_id);
-        }
-
-        order_parameters
+        build_order_parameters(order)
     }
 
     fn build_quote_quantity_order(
@@ -759,3 +744,28 @@ impl Account {
         order_parameters
     }
 }
+
+// Parameters of an order, shared with the websocket API
+pub(crate) fn build_order_parameters(order: OrderRequest) -> BTreeMap<String, String> {
+    let mut order_parameters: BTreeMap<String, String> = BTreeMap::new();
+
+    order_parameters.insert("symbol".into(), order.symbol);
+    order_parameters.insert("side".into(), order.order_side.to_string());
+    order_parameters.insert("type".into(), order.order_type.to_string());
+    order_parameters.insert("quantity".into(), order.qty.to_string());
+
+    if let Some(stop_price) = order.stop_price {
+        order_parameters.insert("stopPrice".into(), stop_price.to_string());
+    }
+
+    if order.price != 0.0 {
+        order_parameters.insert("price".into(), order.price.to_string());
+        order_parameters.insert("timeInForce".into(), order.time_in_force.to_string());
+    }
+
+    if let Some(client_order_id) = order.new_client_order_id {
+        order_parameters.insert("newClientOrderId".into(), client_order_id);
+    }
+
+    order_parameters
+}
@@ -0,0 +1,820 @@
+use crate::commons::config::Config;
+use crate::commons::errors::*;
+use crate::commons::workers::{sleep, Workers};
+use crate::interfaces::paper::{
+    mandatory, rejected, OrderRef, PaperConfig, PaperState, PaperUpdate,
+};
+use crate::interfaces::usdm::{timestamp, UsdmInterface};
+use crate::interfaces::usdm_data::UsdmConfig;
+use crate::rest::api::{Futures, API};
+use crate::rest::futures::model::{
+    CanceledOrder, ExchangeInformation, Filters, Symbol, Transaction,
+};
+use crate::testing::http::{serve_connection, HttpRequest};
+use indexmap::IndexMap;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tungstenite::handshake::server::{Request, Response};
+use tungstenite::Message;
+
+// https://binance-docs.github.io/apidocs/futures/en/#error-codes
+const UNKNOWN_ERROR: i16 = -1000;
+const INVALID_SYMBOL: i16 = -1121;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const MARK_PRICE_INTERVAL: Duration = Duration::from_secs(1);
+const FUNDING_INTERVAL: u64 = 8 * 3600 * 1000; // milliseconds
+const KLINES_LIMIT: u64 = 500;
+const MAX_KLINES_LIMIT: u64 = 1500;
+const ALL_ORDERS_LIMIT: usize = 500;
+const AGGR_TRADE: &str = "aggTrade";
+const MARK_PRICE: &str = "markPrice";
+const BOOK_TICKER: &str = "bookTicker";
+
+#[derive(Debug, Clone, Copy)]
+struct FakeMarket {
+    last_price: f64,
+    mark_price: f64,
+    next_trade_id: u64,
+    // best bid and ask, set with `set_book`
+    book: Option<(f64, f64)>,
+    book_update_id: u64,
+}
+
+#[derive(Clone)]
+struct MarketConnection {
+    streams: Arc<Mutex<Vec<String>>>,
+    sender: Sender<String>,
+}
+
+struct FakeState {
+    account: PaperState,
+    asset: String,
+    markets: IndexMap<String, FakeMarket>,
+    listen_key: Option<String>,
+    listen_keys_created: u64,
+    orders_placed: u64,
+    market_connections: Vec<MarketConnection>,
+    user_connections: Vec<Sender<String>>,
+    user_connections_accepted: u64,
+    requests: Vec<String>,
+    // the websocket connections of an older generation close
+    generation: u64,
+}
+
+/// In-process stand-in of the Binance USDⓈ-M futures API for offline tests, on local ports.
+///
+/// The REST API serves exchangeInfo, klines, depth, time, listenKey, the order endpoints, open
+/// and all orders, positionRisk, leverage, position mode, countdownCancelAll and an empty income
+/// history. The websocket serves the combined market streams, with SUBSCRIBE, UNSUBSCRIBE and
+/// LIST_SUBSCRIPTIONS, and the user stream of any listen key. Orders are matched by the paper
+/// trading engine against the trades and mark prices set with `trade` and `set_mark_price`,
+/// their updates are sent on the user stream. The book only has the best bid and ask set with
+/// `set_book`, it does not fill orders. Signatures and API keys are not checked, the
+/// countdown does not cancel anything and the klines are flat at the last price.
+///
+/// Stops when dropped
+pub struct FakeBinance {
+    state: Arc<Mutex<FakeState>>,
+    rest_endpoint: String,
+    ws_endpoint: String,
+    workers: Workers,
+}
+
+impl FakeBinance {
+    /// Serves `symbols` at `price` with a default paper account
+    pub fn start(symbols: &[&str], price: f64) -> Result<FakeBinance> {
+        FakeBinance::with_account(symbols, price, PaperConfig::default())
+    }
+
+    /// Serves `symbols` at `price` with the balance, leverage and fees of `account`
+    pub fn with_account(symbols: &[&str], price: f64, account: PaperConfig) -> Result<FakeBinance> {
+        let rest_listener = TcpListener::bind("127.0.0.1:0")?;
+        let ws_listener = TcpListener::bind("127.0.0.1:0")?;
+        let rest_endpoint = format!("http://{}", rest_listener.local_addr()?);
+        let ws_endpoint = format!("ws://{}", ws_listener.local_addr()?);
+        let asset = account.asset.to_owned();
+        let mut account = PaperState::new(account);
+        let markets = symbols
+            .iter()
+            .map(|symbol| {
+                let symbol = symbol.to_uppercase();
+                account.on_mark_price(&symbol, price);
+                let market = FakeMarket {
+                    last_price: price,
+                    mark_price: price,
+                    next_trade_id: 1,
+                    book: None,
+                    book_update_id: 0,
+                };
+                (symbol, market)
+            })
+            .collect();
+        // Binance does not send the balance on connect either
+        account.take_updates();
+        let state = Arc::new(Mutex::new(FakeState {
+            account,
+            asset,
+            markets,
+            listen_key: None,
+            listen_keys_created: 0,
+            orders_placed: 0,
+            market_connections: vec![],
+            user_connections: vec![],
+            user_connections_accepted: 0,
+            requests: vec![],
+            generation: 0,
+        }));
+        let workers = Workers::default();
+
+        let rest_state = state.clone();
+        accept_connections(rest_listener, &workers, move |stream, running| {
+            if stream.set_read_timeout(Some(POLL_INTERVAL)).is_ok() {
+                serve_connection(stream, running, |request| {
+                    rest_state.lock().unwrap().handle(request)
+                });
+            }
+        })?;
+        let ws_state = state.clone();
+        accept_connections(ws_listener, &workers, move |stream, running| {
+            serve_websocket(&ws_state, stream, running)
+        })?;
+        let ticker_state = state.clone();
+        workers.spawn(move |running| {
+            while sleep(running, MARK_PRICE_INTERVAL) {
+                ticker_state.lock().unwrap().publish_mark_prices();
+            }
+        });
+
+        Ok(FakeBinance {
+            state,
+            rest_endpoint,
+            ws_endpoint,
+            workers,
+        })
+    }
+
+    /// Default config with the futures REST and websocket endpoints of the stand-in
+    pub fn config(&self) -> Config {
+        Config::default()
+            .set_futures_rest_api_endpoint(self.rest_endpoint.to_owned())
+            .set_futures_ws_endpoint(self.ws_endpoint.to_owned())
+    }
+
+    pub fn rest_endpoint(&self) -> &str {
+        &self.rest_endpoint
+    }
+
+    pub fn ws_endpoint(&self) -> &str {
+        &self.ws_endpoint
+    }
+
+    /// Prints a trade on the aggTrade stream, resting orders it goes through are filled.
+    /// Ignored for a symbol that is not served
+    pub fn trade(&self, symbol: &str, price: f64, qty: f64) {
+        self.state
+            .lock()
+            .unwrap()
+            .trade(&symbol.to_uppercase(), price, qty);
+    }
+
+    /// Sends the mark price right away and then every second, it triggers the conditional
+    /// orders working on the mark price and the liquidation
+    pub fn set_mark_price(&self, symbol: &str, price: f64) {
+        self.state
+            .lock()
+            .unwrap()
+            .set_mark_price(&symbol.to_uppercase(), price);
+    }
+
+    /// Sends the best bid and ask on the bookTicker stream, the depth snapshot has them as
+    /// its only levels. Orders then take them and resting orders they touch fill
+    pub fn set_book(&self, symbol: &str, bid: f64, ask: f64) {
+        self.state
+            .lock()
+            .unwrap()
+            .set_book(&symbol.to_uppercase(), bid, ask);
+    }
+
+    /// `UsdmInterface` of `symbol` on the fake, with the default `UsdmConfig`
+    pub fn usdm_interface(&self, symbol: &str) -> Result<UsdmInterface> {
+        UsdmInterface::new(
+            symbol.into(),
+            Some("api-key".into()),
+            Some("api-secret".into()),
+            &self.config(),
+            UsdmConfig::default(),
+        )
+    }
+
+    /// Method and path of every REST request received, e.g. "POST /fapi/v1/order"
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.to_owned()
+    }
+
+    /// Number of user stream connections accepted, including the closed ones
+    pub fn user_connections(&self) -> u64 {
+        self.state.lock().unwrap().user_connections_accepted
+    }
+
+    /// Closes every websocket connection, new ones are accepted
+    pub fn drop_connections(&self) {
+        self.state.lock().unwrap().generation += 1;
+    }
+
+    /// Stops serving and waits for the connections to close
+    pub fn shutdown(&self) {
+        self.workers.shutdown();
+    }
+}
+
+impl Drop for FakeBinance {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl FakeState {
+    fn handle(&mut self, request: &HttpRequest) -> (u16, String) {
+        self.requests
+            .push(format!("{} {}", request.method, request.path));
+        let response = self.route(request);
+        self.publish_account();
+        match response {
+            Ok(body) => (200, body.to_string()),
+            Err(BinanceError::BinanceError { response }) => (
+                400,
+                json!({"code": response.code, "msg": response.msg}).to_string(),
+            ),
+            Err(BinanceError::UnkownStatusCode(status)) => (
+                status.as_u16(),
+                json!({"code": UNKNOWN_ERROR, "msg": status.to_string()}).to_string(),
+            ),
+            Err(e) => (
+                500,
+                json!({"code": UNKNOWN_ERROR, "msg": e.to_string()}).to_string(),
+            ),
+        }
+    }
+
+    fn route(&mut self, request: &HttpRequest) -> Result<Value> {
+        let params = &request.params;
+        let symbol = params
+            .get("symbol")
+            .map(|symbol| symbol.to_uppercase())
+            .unwrap_or_default();
+        let Some(endpoint) = futures_endpoint(&request.path) else {
+            return Err(BinanceError::UnkownStatusCode(StatusCode::NOT_FOUND));
+        };
+        let value = match (request.method.as_str(), endpoint) {
+            ("GET", Futures::Ping) => json!({}),
+            ("GET", Futures::Time) => json!({"serverTime": timestamp()}),
+            ("GET", Futures::ExchangeInfo) => serde_json::to_value(self.exchange_info())?,
+            ("GET", Futures::Klines) => self.klines(&symbol, params)?,
+            ("GET", Futures::Depth) => self.depth(&symbol)?,
+            ("POST", Futures::UserDataStream) => {
+                if self.listen_key.is_none() {
+                    self.listen_keys_created += 1;
+                    self.listen_key = Some(format!("fake-listen-key-{}", self.listen_keys_created));
+                }
+                json!({"listenKey": self.listen_key})
+            }
+            ("PUT", Futures::UserDataStream) => json!({}),
+            ("DELETE", Futures::UserDataStream) => {
+                self.listen_key = None;
+                json!({})
+            }
+            ("POST", Futures::Order) => {
+                let mut order = params.to_owned();
+                self.orders_placed += 1;
+                order
+                    .entry("newClientOrderId".into())
+                    .or_insert_with(|| format!("fake-{}", self.orders_placed));
+                serde_json::to_value(Transaction::from(self.account.place(&order)?))?
+            }
+            ("GET", Futures::Order) => {
+                serde_json::to_value(self.account.get_order(&symbol, &order_ref(params)?)?)?
+            }
+            ("DELETE", Futures::Order) => {
+                let order = self.account.cancel(&symbol, &order_ref(params)?)?;
+                serde_json::to_value(CanceledOrder::from(order))?
+            }
+            ("GET", Futures::OpenOrders) => {
+                serde_json::to_value(self.account.get_open_orders(&symbol))?
+            }
+            ("DELETE", Futures::AllOpenOrders) => {
+                self.account.cancel_all(&symbol);
+                json!({"code": 200, "msg": "The operation of cancel all open order is done."})
+            }
+            ("GET", Futures::AllOrders) => {
+                let start_time = number(params, "startTime")?.unwrap_or_default();
+                let limit = number(params, "limit")?.unwrap_or(ALL_ORDERS_LIMIT);
+                let mut orders = self.account.get_all_orders(&symbol, start_time);
+                orders.drain(..orders.len().saturating_sub(limit));
+                serde_json::to_value(orders)?
+            }
+            ("GET", Futures::PositionRisk) => {
+                let symbols = match symbol.is_empty() {
+                    true => self.markets.keys().cloned().collect(),
+                    false => vec![symbol],
+                };
+                let positions: Vec<_> = symbols
+                    .iter()
+                    .flat_map(|symbol| self.account.position_information(symbol))
+                    .collect();
+                serde_json::to_value(positions)?
+            }
+            ("POST", Futures::ChangeInitialLeverage) => {
+                let leverage = number(params, "leverage")?.ok_or_else(|| mandatory("leverage"))?;
+                serde_json::to_value(self.account.change_initial_leverage(&symbol, leverage)?)?
+            }
+            ("POST", Futures::PositionSide) => {
+                let dual_side = params
+                    .get("dualSidePosition")
+                    .is_some_and(|dual_side| dual_side == "true");
+                self.account.change_position_mode(dual_side)?;
+                json!({"code": 200, "msg": "success"})
+            }
+            ("POST", Futures::CountdownCancelAll) => json!({
+                "symbol": symbol,
+                "countdownTime": params.get("countdownTime").cloned().unwrap_or_default(),
+            }),
+            ("GET", Futures::Income) => json!([]),
+            _ => return Err(BinanceError::UnkownStatusCode(StatusCode::NOT_FOUND)),
+        };
+        Ok(value)
+    }
+
+    fn exchange_info(&self) -> ExchangeInformation {
+        let symbols = self
+            .markets
+            .keys()
+            .map(|symbol| Symbol {
+                symbol: symbol.to_owned(),
+                status: "TRADING".into(),
+                maint_margin_percent: "2.5000".into(),
+                required_margin_percent: "5.0000".into(),
+                base_asset: symbol
+                    .strip_suffix(&self.asset)
+                    .unwrap_or(symbol)
+                    .to_owned(),
+                quote_asset: self.asset.to_owned(),
+                price_precision: 2,
+                quantity_precision: 3,
+                base_asset_precision: 8,
+                quote_precision: 8,
+                filters: vec![
+                    Filters::PriceFilter {
+                        min_price: "0.01".into(),
+                        max_price: "1000000".into(),
+                        tick_size: "0.01".into(),
+                    },
+                    Filters::LotSize {
+                        min_qty: "0.001".into(),
+                        max_qty: "1000".into(),
+                        step_size: "0.001".into(),
+                    },
+                ],
+                order_types: [
+                    "LIMIT",
+                    "MARKET",
+                    "STOP",
+                    "STOP_MARKET",
+                    "TAKE_PROFIT",
+                    "TAKE_PROFIT_MARKET",
+                    "TRAILING_STOP_MARKET",
+                ]
+                .map(String::from)
+                .to_vec(),
+                time_in_force: ["GTC", "IOC", "FOK", "GTX"].map(String::from).to_vec(),
+            })
+            .collect();
+        ExchangeInformation {
+            timezone: "UTC".into(),
+            server_time: timestamp(),
+            rate_limits: vec![],
+            exchange_filters: vec![],
+            symbols,
+        }
+    }
+
+    // flat at the last price, the last one is open
+    fn klines(&self, symbol: &str, params: &BTreeMap<String, String>) -> Result<Value> {
+        let market = self
+            .markets
+            .get(symbol)
+            .ok_or_else(|| rejected(INVALID_SYMBOL, "Invalid symbol."))?;
+        let interval = params
+            .get("interval")
+            .and_then(|interval| interval_millis(interval))
+            .ok_or_else(|| mandatory("interval"))?;
+        let limit = number(params, "limit")?
+            .unwrap_or(KLINES_LIMIT)
+            .clamp(1, MAX_KLINES_LIMIT);
+        let end_time = number(params, "endTime")?.unwrap_or_else(timestamp);
+        let last_open = end_time - end_time % interval;
+        let first_open = match number::<u64>(params, "startTime")? {
+            Some(start_time) => start_time.div_ceil(interval) * interval,
+            None => last_open.saturating_sub((limit - 1) * interval),
+        };
+        let price = market.last_price.to_string();
+        let klines: Vec<Value> = (0..limit)
+            .map(|index| first_open + index * interval)
+            .take_while(|open_time| *open_time <= last_open)
+            .map(|open_time| {
+                json!([
+                    open_time,
+                    price,
+                    price,
+                    price,
+                    price,
+                    "0",
+                    open_time + interval - 1,
+                    "0",
+                    0,
+                    "0",
+                    "0",
+                    "0"
+                ])
+            })
+            .collect();
+        Ok(json!(klines))
+    }
+
+    fn depth(&self, symbol: &str) -> Result<Value> {
+        let market = self
+            .markets
+            .get(symbol)
+            .ok_or_else(|| rejected(INVALID_SYMBOL, "Invalid symbol."))?;
+        let time = timestamp();
+        let (bids, asks) = match market.book {
+            Some((bid, ask)) => (
+                json!([[bid.to_string(), "1"]]),
+                json!([[ask.to_string(), "1"]]),
+            ),
+            None => (json!([]), json!([])),
+        };
+        Ok(json!({
+            "lastUpdateId": market.book_update_id,
+            "E": time,
+            "T": time,
+            "bids": bids,
+            "asks": asks,
+        }))
+    }
+
+    fn trade(&mut self, symbol: &str, price: f64, qty: f64) {
+        let Some(market) = self.markets.get_mut(symbol) else {
+            return;
+        };
+        let time = timestamp();
+        let trade_id = market.next_trade_id;
+        let is_buyer_maker = price < market.last_price;
+        market.next_trade_id += 1;
+        market.last_price = price;
+        let data = json!({
+            "e": AGGR_TRADE,
+            "E": time,
+            "s": symbol,
+            "a": trade_id,
+            "p": price.to_string(),
+            "q": qty.to_string(),
+            "f": trade_id,
+            "l": trade_id,
+            "T": time,
+            "m": is_buyer_maker,
+        });
+        self.publish_market(symbol, AGGR_TRADE, data);
+        self.account.on_trade(symbol, price, qty);
+        self.publish_account();
+    }
+
+    fn set_mark_price(&mut self, symbol: &str, price: f64) {
+        let Some(market) = self.markets.get_mut(symbol) else {
+            return;
+        };
+        market.mark_price = price;
+        self.publish_market(symbol, MARK_PRICE, mark_price_event(symbol, price));
+        self.account.on_mark_price(symbol, price);
+        self.publish_account();
+    }
+
+    fn set_book(&mut self, symbol: &str, bid: f64, ask: f64) {
+        let Some(market) = self.markets.get_mut(symbol) else {
+            return;
+        };
+        market.book = Some((bid, ask));
+        market.book_update_id += 1;
+        let data = book_ticker_event(symbol, market);
+        self.publish_market(symbol, BOOK_TICKER, data);
+        self.account.on_book(symbol, bid, ask);
+        self.publish_account();
+    }
+
+    fn publish_mark_prices(&mut self) {
+        let mark_prices: Vec<(String, f64)> = self
+            .markets
+            .iter()
+            .map(|(symbol, market)| (symbol.to_owned(), market.mark_price))
+            .collect();
+        for (symbol, mark_price) in mark_prices {
+            self.publish_market(&symbol, MARK_PRICE, mark_price_event(&symbol, mark_price));
+        }
+    }
+
+    // sends `data` to the connections subscribed to a `kind` stream of `symbol`
+    fn publish_market(&mut self, symbol: &str, kind: &str, data: Value) {
+        let prefix = format!("{}@{kind}", symbol.to_lowercase());
+        self.market_connections.retain(|connection| {
+            let stream = connection
+                .streams
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|stream| stream.starts_with(&prefix))
+                .cloned();
+            match stream {
+                Some(stream) => {
+                    let message = json!({"stream": stream, "data": data}).to_string();
+                    connection.sender.send(message).is_ok()
+                }
+                None => true,
+            }
+        });
+    }
+
+    // sends the updates of the account as the user stream events
+    fn publish_account(&mut self) {
+        for update in self.account.take_updates() {
+            let time = timestamp();
+            let event = match update {
+                PaperUpdate::Order(order) => json!({
+                    "e": "ORDER_TRADE_UPDATE",
+                    "E": time,
+                    "T": time,
+                    "o": order,
+                }),
+                PaperUpdate::Position(position) => json!({
+                    "e": "ACCOUNT_UPDATE",
+                    "E": time,
+                    "T": time,
+                    "a": {"m": "ORDER", "B": [], "P": [position]},
+                }),
+                PaperUpdate::Balance(balance) => json!({
+                    "e": "ACCOUNT_UPDATE",
+                    "E": time,
+                    "T": time,
+                    "a": {"m": "ORDER", "B": [balance], "P": []},
+                }),
+            };
+            let message = event.to_string();
+            self.user_connections
+                .retain(|sender| sender.send(message.to_owned()).is_ok());
+        }
+    }
+
+    // like Binance, a subscribed mark price stream gets the mark price right away,
+    // a book ticker stream gets the book if one is set
+    fn subscribe(&mut self, connection: &MarketConnection, streams: &[String]) {
+        for stream in streams {
+            let mut subscribed = connection.streams.lock().unwrap();
+            if subscribed.contains(stream) {
+                continue;
+            }
+            subscribed.push(stream.to_owned());
+            let symbol = stream.split('@').next().unwrap_or_default().to_uppercase();
+            let Some(market) = self.markets.get(&symbol) else {
+                continue;
+            };
+            let data = if stream.contains(&format!("@{MARK_PRICE}")) {
+                mark_price_event(&symbol, market.mark_price)
+            } else if stream.contains(&format!("@{BOOK_TICKER}")) && market.book.is_some() {
+                book_ticker_event(&symbol, market)
+            } else {
+                continue;
+            };
+            let message = json!({"stream": stream, "data": data}).to_string();
+            let _ = connection.sender.send(message);
+        }
+    }
+
+    // returns the response to a SUBSCRIBE, UNSUBSCRIBE or LIST_SUBSCRIPTIONS request
+    fn stream_request(&mut self, connection: &MarketConnection, request: &str) -> Option<String> {
+        let request: Value = serde_json::from_str(request).ok()?;
+        let streams: Vec<String> = request["params"]
+            .as_array()
+            .map(|params| {
+                params
+                    .iter()
+                    .filter_map(|stream| stream.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let result = match request["method"].as_str()? {
+            "SUBSCRIBE" => {
+                self.subscribe(connection, &streams);
+                Value::Null
+            }
+            "UNSUBSCRIBE" => {
+                connection
+                    .streams
+                    .lock()
+                    .unwrap()
+                    .retain(|stream| !streams.contains(stream));
+                Value::Null
+            }
+            "LIST_SUBSCRIPTIONS" => json!(*connection.streams.lock().unwrap()),
+            _ => return None,
+        };
+        Some(json!({"result": result, "id": request["id"]}).to_string())
+    }
+}
+
+// accepts on a background thread, every connection is served on its own
+fn accept_connections<F>(listener: TcpListener, workers: &Workers, serve: F) -> Result<()>
+where
+    F: Fn(TcpStream, &AtomicBool) + Clone + Send + 'static,
+{
+    // polled, a blocking accept would not see the shutdown
+    listener.set_nonblocking(true)?;
+    let connections = workers.clone();
+    workers.spawn(move |running| {
+        while running.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if stream.set_nonblocking(false).is_err() {
+                        continue;
+                    }
+                    let serve = serve.clone();
+                    connections.spawn(move |running| serve(stream, running));
+                }
+                Err(_) => {
+                    sleep(running, POLL_INTERVAL);
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[allow(clippy::result_large_err)]
+fn serve_websocket(state: &Arc<Mutex<FakeState>>, stream: TcpStream, running: &AtomicBool) {
+    let mut path = String::new();
+    let Ok(mut socket) =
+        tungstenite::accept_hdr(stream, |request: &Request, response: Response| {
+            path = request.uri().to_string();
+            Ok(response)
+        })
+    else {
+        return;
+    };
+    if socket
+        .get_ref()
+        .set_read_timeout(Some(POLL_INTERVAL))
+        .is_err()
+    {
+        return;
+    }
+    let (sender, receiver) = mpsc::channel();
+    let (generation, connection) = {
+        let mut state = state.lock().unwrap();
+        let connection = match path.strip_prefix("/stream?streams=") {
+            Some(streams) => {
+                let connection = MarketConnection {
+                    streams: Arc::new(Mutex::new(vec![])),
+                    sender,
+                };
+                let streams: Vec<String> = streams.split('/').map(String::from).collect();
+                state.subscribe(&connection, &streams);
+                state.market_connections.push(connection.clone());
+                Some(connection)
+            }
+            // the user stream of any listen key
+            None => {
+                state.user_connections.push(sender);
+                state.user_connections_accepted += 1;
+                None
+            }
+        };
+        (state.generation, connection)
+    };
+    while running.load(Ordering::Relaxed) && state.lock().unwrap().generation == generation {
+        for message in receiver.try_iter() {
+            if socket.send(Message::text(message)).is_err() {
+                return;
+            }
+        }
+        match socket.read() {
+            Ok(Message::Text(request)) => {
+                let response = connection.as_ref().and_then(|connection| {
+                    state
+                        .lock()
+                        .unwrap()
+                        .stream_request(connection, request.as_str())
+                });
+                if let Some(response) = response {
+                    if socket.send(Message::text(response)).is_err() {
+                        return;
+                    }
+                }
+            }
+            Ok(Message::Close(_)) => return,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => return,
+        }
+    }
+    let _ = socket.close(None);
+    let _ = socket.flush();
+}
+
+fn futures_endpoint(path: &str) -> Option<Futures> {
+    [
+        Futures::Ping,
+        Futures::Time,
+        Futures::ExchangeInfo,
+        Futures::Klines,
+        Futures::Depth,
+        Futures::UserDataStream,
+        Futures::Order,
+        Futures::OpenOrders,
+        Futures::AllOpenOrders,
+        Futures::AllOrders,
+        Futures::PositionRisk,
+        Futures::ChangeInitialLeverage,
+        Futures::PositionSide,
+        Futures::CountdownCancelAll,
+        Futures::Income,
+    ]
+    .into_iter()
+    .find(|endpoint| String::from(API::Futures(*endpoint)) == path)
+}
+
+fn order_ref(params: &BTreeMap<String, String>) -> Result<OrderRef> {
+    if let Some(order_id) = number(params, "orderId")? {
+        return Ok(OrderRef::Id(order_id));
+    }
+    params
+        .get("origClientOrderId")
+        .map(|client_order_id| OrderRef::ClientId(client_order_id.to_owned()))
+        .ok_or_else(|| mandatory("orderId"))
+}
+
+fn number<T: std::str::FromStr>(params: &BTreeMap<String, String>, key: &str) -> Result<Option<T>> {
+    params
+        .get(key)
+        .map(|value| value.parse().map_err(|_| mandatory(key)))
+        .transpose()
+}
+
+// "1m", "4h", "1d"... in milliseconds
+fn interval_millis(interval: &str) -> Option<u64> {
+    let (count, unit) = interval.split_at(interval.len().checked_sub(1)?);
+    let unit = match unit {
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 3600 * 1000,
+        "d" => 24 * 3600 * 1000,
+        "w" => 7 * 24 * 3600 * 1000,
+        _ => return None,
+    };
+    count.parse::<u64>().ok().map(|count| count * unit)
+}
+
+fn mark_price_event(symbol: &str, price: f64) -> Value {
+    let time = timestamp();
+    json!({
+        "e": "markPriceUpdate",
+        "E": time,
+        "s": symbol,
+        "p": price.to_string(),
+        "i": price.to_string(),
+        "P": price.to_string(),
+        "r": "0.00010000",
+        "T": time - time % FUNDING_INTERVAL + FUNDING_INTERVAL,
+    })
+}
+
+fn book_ticker_event(symbol: &str, market: &FakeMarket) -> Value {
+    let (bid, ask) = market.book.unwrap_or_default();
+    let time = timestamp();
+    json!({
+        "e": BOOK_TICKER,
+        "u": market.book_update_id,
+        "E": time,
+        "T": time,
+        "s": symbol,
+        "b": bid.to_string(),
+        "B": "1",
+        "a": ask.to_string(),
+        "A": "1",
+    })
+}
@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use url::form_urlencoded;
+
+const HEADERS_END: &[u8] = b"\r\n\r\n";
+
+/// Request of the REST API, the query string and the form body merged in `params`
+#[derive(Debug, Clone)]
+pub(crate) struct HttpRequest {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    pub(crate) params: BTreeMap<String, String>,
+}
+
+/// Serves the requests of one keep-alive connection until it closes or `running` is unset,
+/// `stream` must have a read timeout to poll `running`
+pub(crate) fn serve_connection<F>(mut stream: TcpStream, running: &AtomicBool, mut handler: F)
+where
+    F: FnMut(&HttpRequest) -> (u16, String),
+{
+    let mut buffer: Vec<u8> = vec![];
+    let mut chunk = [0; 4096];
+    while running.load(Ordering::Relaxed) {
+        if let Some(request) = parse_request(&mut buffer) {
+            let (status, body) = handler(&request);
+            let response = format!(
+                "HTTP/1.1 {status} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+                reason(status),
+                body.len()
+            );
+            if stream.write_all(response.as_bytes()).is_err() {
+                return;
+            }
+            continue;
+        }
+        match stream.read(&mut chunk) {
+            Ok(0) => return,
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+// takes the first complete request out of `buffer`
+fn parse_request(buffer: &mut Vec<u8>) -> Option<HttpRequest> {
+    let headers_end = buffer
+        .windows(HEADERS_END.len())
+        .position(|window| window == HEADERS_END)?;
+    let head = String::from_utf8_lossy(&buffer[..headers_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_owned();
+    let target = request_line.next().unwrap_or_default();
+    let content_length: usize = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .unwrap_or_default();
+    let body_start = headers_end + HEADERS_END.len();
+    if buffer.len() < body_start + content_length {
+        return None;
+    }
+    let body = String::from_utf8_lossy(&buffer[body_start..body_start + content_length]);
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let params = parse_form(query).chain(parse_form(&body)).collect();
+    let request = HttpRequest {
+        method,
+        path: path.to_owned(),
+        params,
+    };
+    buffer.drain(..body_start + content_length);
+    Some(request)
+}
+
+fn parse_form(form: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    form_urlencoded::parse(form.as_bytes())
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request_waits_for_the_body() {
+        let mut buffer =
+            b"POST /fapi/v1/order?symbol=BTCUSDT HTTP/1.1\r\nContent-Length: 8\r\n\r\nside=BU"
+                .to_vec();
+        assert!(parse_request(&mut buffer).is_none());
+
+        buffer.extend_from_slice(b"YGET /fapi/v1/time HTTP/1.1\r\n\r\n");
+        let request = parse_request(&mut buffer).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/fapi/v1/order");
+        assert_eq!(request.params["symbol"], "BTCUSDT");
+        assert_eq!(request.params["side"], "BUY");
+
+        let request = parse_request(&mut buffer).unwrap();
+        assert_eq!(request.path, "/fapi/v1/time");
+        assert!(request.params.is_empty());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_parse_request_decodes_the_parameters() {
+        let mut buffer = b"POST /fapi/v1/order?newClientOrderId=a%2Fb%3Dc HTTP/1.1\r\n\
+            Content-Length: 21\r\n\r\nactivationPrice=1%2B2"
+            .to_vec();
+        let request = parse_request(&mut buffer).unwrap();
+        assert_eq!(request.params["newClientOrderId"], "a/b=c");
+        assert_eq!(request.params["activationPrice"], "1+2");
+    }
+}
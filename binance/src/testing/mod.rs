@@ -0,0 +1,14 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub mod fake_binance;
+mod http;
+
+/// Polls `condition` until it holds, panics after 5 seconds
+pub fn wait_until<F: Fn() -> bool>(condition: F) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !condition() {
+        assert!(Instant::now() < deadline, "timed out");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
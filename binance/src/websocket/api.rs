@@ -0,0 +1,610 @@
+use crate::commons::config::Config;
+use crate::commons::errors::*;
+use crate::commons::util::{build_request, build_signed_parameters, hmac_signature};
+use crate::rest::futures;
+use crate::rest::futures::account::{
+    build_order_parameters as futures_order_parameters, CustomOrderRequest, OrderType, PositionSide,
+};
+use crate::rest::futures::model::{
+    AccountBalance, CanceledOrder, Order as FuturesOrder, Transaction as FuturesTransaction,
+};
+use crate::rest::model::{Order, OrderCanceled, Transaction};
+use crate::rest::spot::account::{
+    build_order_parameters, OrderRequest, OrderSide, OrderType as SpotOrderType, TimeInForce,
+};
+use crate::websocket::{is_read_timeout, set_read_timeout};
+use log::debug;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+use tungstenite::protocol::WebSocket;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{connect, Message};
+use url::Url;
+
+// https://developers.binance.com/docs/binance-spot-api-docs/websocket-api/general-api-information
+// https://developers.binance.com/docs/derivatives/usds-margined-futures/websocket-api-general-info
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// parameters sent as numbers, every other value is sent as a string
+const INTEGER_PARAMETERS: [&str; 4] = ["orderId", "recvWindow", "timestamp", "limit"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WsApiMarket {
+    Spot,
+    Usdm,
+}
+
+#[derive(Deserialize)]
+struct WsApiResponse {
+    id: Option<String>,
+    status: u16,
+    #[serde(default)]
+    result: Value,
+    #[serde(default)]
+    error: Option<BinanceContentError>,
+}
+
+type Signer = Box<dyn Fn(&str) -> String + Send>;
+
+/// Request/response client of the Binance WebSocket API
+pub struct WsApiClient {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    api_key: String,
+    secret_key: String,
+    signer: Option<Signer>,
+    recv_window: u64,
+    timeout: Duration,
+    next_id: u64,
+    // sent and not yet answered, responses to other ids are dropped
+    pending: HashSet<String>,
+    responses: HashMap<String, WsApiResponse>,
+    logged_on: bool,
+}
+
+impl WsApiClient {
+    /// Connects to `config.ws_api_endpoint` or `config.futures_ws_api_endpoint`
+    pub fn connect(
+        market: WsApiMarket,
+        api_key: Option<String>,
+        secret_key: Option<String>,
+        config: &Config,
+    ) -> Result<WsApiClient> {
+        let endpoint = match market {
+            WsApiMarket::Spot => &config.ws_api_endpoint,
+            WsApiMarket::Usdm => &config.futures_ws_api_endpoint,
+        };
+        let url = Url::parse(endpoint)?;
+        let (socket, _) = connect(url.as_str())
+            .map_err(|e| BinanceError::WebSocket(WebSocketError::ConnectionError(e.to_string())))?;
+        Ok(WsApiClient {
+            socket,
+            api_key: api_key.unwrap_or_default(),
+            secret_key: secret_key.unwrap_or_default(),
+            signer: None,
+            recv_window: config.recv_window,
+            timeout: DEFAULT_TIMEOUT,
+            next_id: 1,
+            pending: HashSet::new(),
+            responses: HashMap::new(),
+            logged_on: false,
+        })
+    }
+
+    /// Maximum time to wait for a response
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Signs requests with a custom function instead of HMAC SHA256,
+    /// required for `logon` which only accepts Ed25519 keys
+    pub fn set_signer<F>(&mut self, signer: F)
+    where
+        F: Fn(&str) -> String + Send + 'static,
+    {
+        self.signer = Some(Box::new(signer));
+    }
+
+    pub fn is_logged_on(&self) -> bool {
+        self.logged_on
+    }
+
+    /// Responses received and not yet taken with `wait`
+    pub fn buffered_responses(&self) -> usize {
+        self.responses.len()
+    }
+
+    /// Authenticates the connection, signed requests no longer need `apiKey` and `signature`
+    pub fn logon(&mut self) -> Result<Value> {
+        let mut parameters = BTreeMap::new();
+        parameters.insert("apiKey".into(), self.api_key.clone());
+        let parameters = self.sign(parameters)?;
+        let id = self.send("session.logon", parameters)?;
+        let result = self.wait(&id)?;
+        self.logged_on = true;
+        Ok(result)
+    }
+
+    pub fn logout(&mut self) -> Result<Value> {
+        let result = self.request("session.logout", BTreeMap::new())?;
+        self.logged_on = false;
+        Ok(result)
+    }
+
+    pub fn session_status(&mut self) -> Result<Value> {
+        self.request("session.status", BTreeMap::new())
+    }
+
+    /// Sends an unsigned request and waits for its result
+    pub fn request<T: DeserializeOwned>(
+        &mut self,
+        method: &str,
+        parameters: BTreeMap<String, String>,
+    ) -> Result<T> {
+        let id = self.send(method, parameters)?;
+        self.wait(&id)
+    }
+
+    /// Sends a signed request and waits for its result
+    pub fn request_signed<T: DeserializeOwned>(
+        &mut self,
+        method: &str,
+        parameters: BTreeMap<String, String>,
+    ) -> Result<T> {
+        let id = self.send_signed(method, parameters)?;
+        self.wait(&id)
+    }
+
+    /// Sends a signed request without waiting and returns its id
+    pub fn send_signed(
+        &mut self,
+        method: &str,
+        parameters: BTreeMap<String, String>,
+    ) -> Result<String> {
+        let parameters = if self.logged_on {
+            build_signed_parameters(parameters, self.recv_window)?
+        } else {
+            let mut parameters = parameters;
+            parameters.insert("apiKey".into(), self.api_key.clone());
+            self.sign(parameters)?
+        };
+        self.send(method, parameters)
+    }
+
+    /// Sends a request without waiting and returns its id
+    pub fn send(&mut self, method: &str, parameters: BTreeMap<String, String>) -> Result<String> {
+        let id = self.next_id.to_string();
+        self.next_id += 1;
+
+        let params: Map<String, Value> = parameters
+            .into_iter()
+            .map(|(key, value)| {
+                let value = match value.parse::<i64>() {
+                    Ok(number) if INTEGER_PARAMETERS.contains(&key.as_str()) => number.into(),
+                    _ => Value::String(value),
+                };
+                (key, value)
+            })
+            .collect();
+        let mut request = Map::new();
+        request.insert("id".into(), Value::String(id.clone()));
+        request.insert("method".into(), Value::String(method.into()));
+        if !params.is_empty() {
+            request.insert("params".into(), Value::Object(params));
+        }
+
+        debug!("WebSocket API request {method} ({id})");
+        self.socket
+            .send(Message::text(Value::Object(request).to_string()))?;
+        self.pending.insert(id.clone());
+        Ok(id)
+    }
+
+    /// Waits for the response of request `id`, responses of other requests are kept.
+    /// A request is abandoned once its wait times out, its response is dropped
+    pub fn wait<T: DeserializeOwned>(&mut self, id: &str) -> Result<T> {
+        if !self.pending.contains(id) && !self.responses.contains_key(id) {
+            return Err(BinanceError::WebSocket(WebSocketError::MessageError(
+                format!("No pending request {id}"),
+            )));
+        }
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(response) = self.responses.remove(id) {
+                return match response.error {
+                    Some(error) => Err(BinanceError::BinanceError { response: error }),
+                    None if response.status == 200 => Ok(serde_json::from_value(response.result)?),
+                    None => Err(BinanceError::WebSocket(WebSocketError::MessageError(
+                        format!("Request {id} failed with status {}", response.status),
+                    ))),
+                };
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                self.pending.remove(id);
+                return Err(BinanceError::WebSocket(WebSocketError::MessageError(
+                    format!("Timeout waiting for response to request {id}"),
+                )));
+            }
+            set_read_timeout(&mut self.socket, Some(deadline - now))?;
+            match self.socket.read() {
+                Ok(Message::Text(msg)) => {
+                    let response: WsApiResponse = serde_json::from_str(&msg)?;
+                    match response.id.clone() {
+                        Some(response_id) if self.pending.remove(&response_id) => {
+                            self.responses.insert(response_id, response);
+                        }
+                        Some(response_id) => {
+                            debug!("Dropping response to abandoned request {response_id}");
+                        }
+                        // requests that could not be parsed have no id
+                        None => {
+                            if let Some(error) = response.error {
+                                return Err(BinanceError::BinanceError { response: error });
+                            }
+                        }
+                    }
+                }
+                Ok(Message::Ping(data)) => self.socket.send(Message::Pong(data))?,
+                Ok(Message::Close(_)) => {
+                    return Err(BinanceError::WebSocket(WebSocketError::Disconnected))
+                }
+                Ok(_) => (),
+                Err(e) if is_read_timeout(&e) => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub fn disconnect(&mut self) -> Result<()> {
+        self.socket.close(None)?;
+        Ok(())
+    }
+
+    fn sign(&self, parameters: BTreeMap<String, String>) -> Result<BTreeMap<String, String>> {
+        let mut parameters = build_signed_parameters(parameters, self.recv_window)?;
+        let payload = build_request(parameters.clone());
+        let signature = match self.signer {
+            Some(ref signer) => signer(&payload),
+            None => hmac_signature(&self.secret_key, &payload),
+        };
+        parameters.insert("signature".into(), signature);
+        Ok(parameters)
+    }
+}
+
+/// Spot order entry over the WebSocket API
+pub struct SpotWsApi {
+    pub client: WsApiClient,
+}
+
+impl SpotWsApi {
+    pub fn connect(
+        api_key: Option<String>,
+        secret_key: Option<String>,
+        config: &Config,
+    ) -> Result<SpotWsApi> {
+        Ok(SpotWsApi {
+            client: WsApiClient::connect(WsApiMarket::Spot, api_key, secret_key, config)?,
+        })
+    }
+
+    pub fn limit_buy<S, F>(&mut self, symbol: S, qty: F, price: f64) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+    {
+        self.custom_order(
+            symbol,
+            qty,
+            price,
+            None,
+            OrderSide::Buy,
+            SpotOrderType::Limit,
+            TimeInForce::GTC,
+            None,
+        )
+    }
+
+    pub fn limit_sell<S, F>(&mut self, symbol: S, qty: F, price: f64) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+    {
+        self.custom_order(
+            symbol,
+            qty,
+            price,
+            None,
+            OrderSide::Sell,
+            SpotOrderType::Limit,
+            TimeInForce::GTC,
+            None,
+        )
+    }
+
+    pub fn market_buy<S, F>(&mut self, symbol: S, qty: F) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+    {
+        self.custom_order(
+            symbol,
+            qty,
+            0.0,
+            None,
+            OrderSide::Buy,
+            SpotOrderType::Market,
+            TimeInForce::GTC,
+            None,
+        )
+    }
+
+    pub fn market_sell<S, F>(&mut self, symbol: S, qty: F) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+    {
+        self.custom_order(
+            symbol,
+            qty,
+            0.0,
+            None,
+            OrderSide::Sell,
+            SpotOrderType::Market,
+            TimeInForce::GTC,
+            None,
+        )
+    }
+
+    /// Same parameters as `Account::custom_order`
+    #[allow(clippy::too_many_arguments)]
+    pub fn custom_order<S, F>(
+        &mut self,
+        symbol: S,
+        qty: F,
+        price: f64,
+        stop_price: Option<f64>,
+        order_side: OrderSide,
+        order_type: SpotOrderType,
+        time_in_force: TimeInForce,
+        new_client_order_id: Option<String>,
+    ) -> Result<Transaction>
+    where
+        S: Into<String>,
+        F: Into<f64>,
+    {
+        let order = build_order_parameters(OrderRequest {
+            symbol: symbol.into(),
+            qty: qty.into(),
+            price,
+            stop_price,
+            order_side,
+            order_type,
+            time_in_force,
+            new_client_order_id,
+        });
+        self.client.request_signed("order.place", order)
+    }
+
+    pub fn cancel_order<S>(&mut self, symbol: S, order_id: u64) -> Result<OrderCanceled>
+    where
+        S: Into<String>,
+    {
+        self.client
+            .request_signed("order.cancel", order_parameters(symbol, order_id))
+    }
+
+    pub fn order_status<S>(&mut self, symbol: S, order_id: u64) -> Result<Order>
+    where
+        S: Into<String>,
+    {
+        self.client
+            .request_signed("order.status", order_parameters(symbol, order_id))
+    }
+
+    pub fn get_open_orders<S>(&mut self, symbol: S) -> Result<Vec<Order>>
+    where
+        S: Into<String>,
+    {
+        let mut parameters = BTreeMap::new();
+        parameters.insert("symbol".into(), symbol.into());
+        self.client.request_signed("openOrders.status", parameters)
+    }
+}
+
+/// USDM futures order entry over the WebSocket API
+pub struct FuturesWsApi {
+    pub client: WsApiClient,
+}
+
+impl FuturesWsApi {
+    pub fn connect(
+        api_key: Option<String>,
+        secret_key: Option<String>,
+        config: &Config,
+    ) -> Result<FuturesWsApi> {
+        Ok(FuturesWsApi {
+            client: WsApiClient::connect(WsApiMarket::Usdm, api_key, secret_key, config)?,
+        })
+    }
+
+    pub fn limit_buy(
+        &mut self,
+        symbol: impl Into<String>,
+        qty: impl Into<f64>,
+        price: f64,
+        time_in_force: TimeInForce,
+        position_side: impl Into<Option<PositionSide>>,
+    ) -> Result<FuturesTransaction> {
+        self.custom_order(limit_order(
+            symbol.into(),
+            OrderSide::Buy,
+            qty.into(),
+            price,
+            time_in_force,
+            position_side.into(),
+        ))
+    }
+
+    pub fn limit_sell(
+        &mut self,
+        symbol: impl Into<String>,
+        qty: impl Into<f64>,
+        price: f64,
+        time_in_force: TimeInForce,
+        position_side: impl Into<Option<PositionSide>>,
+    ) -> Result<FuturesTransaction> {
+        self.custom_order(limit_order(
+            symbol.into(),
+            OrderSide::Sell,
+            qty.into(),
+            price,
+            time_in_force,
+            position_side.into(),
+        ))
+    }
+
+    pub fn market_buy(
+        &mut self,
+        symbol: impl Into<String>,
+        qty: impl Into<f64>,
+        position_side: impl Into<Option<PositionSide>>,
+    ) -> Result<FuturesTransaction> {
+        self.custom_order(market_order(
+            symbol.into(),
+            OrderSide::Buy,
+            qty.into(),
+            position_side.into(),
+        ))
+    }
+
+    pub fn market_sell(
+        &mut self,
+        symbol: impl Into<String>,
+        qty: impl Into<f64>,
+        position_side: impl Into<Option<PositionSide>>,
+    ) -> Result<FuturesTransaction> {
+        self.custom_order(market_order(
+            symbol.into(),
+            OrderSide::Sell,
+            qty.into(),
+            position_side.into(),
+        ))
+    }
+
+    /// Same parameters as `FuturesAccount::custom_order`
+    pub fn custom_order(&mut self, order: CustomOrderRequest) -> Result<FuturesTransaction> {
+        self.client
+            .request_signed("order.place", custom_order_parameters(order))
+    }
+
+    /// Sends an order without waiting for the response, returns the request id
+    /// to be passed to `client.wait`
+    pub fn send_custom_order(&mut self, order: CustomOrderRequest) -> Result<String> {
+        self.client
+            .send_signed("order.place", custom_order_parameters(order))
+    }
+
+    pub fn cancel_order<S>(&mut self, symbol: S, order_id: u64) -> Result<CanceledOrder>
+    where
+        S: Into<String>,
+    {
+        self.client
+            .request_signed("order.cancel", order_parameters(symbol, order_id))
+    }
+
+    pub fn get_order<S>(&mut self, symbol: S, order_id: u64) -> Result<FuturesOrder>
+    where
+        S: Into<String>,
+    {
+        self.client
+            .request_signed("order.status", order_parameters(symbol, order_id))
+    }
+
+    pub fn account_balance(&mut self) -> Result<Vec<AccountBalance>> {
+        self.client
+            .request_signed("v2/account.balance", BTreeMap::new())
+    }
+}
+
+fn custom_order_parameters(order: CustomOrderRequest) -> BTreeMap<String, String> {
+    futures_order_parameters(futures::account::OrderRequest {
+        symbol: order.symbol,
+        side: order.side,
+        position_side: order.position_side,
+        order_type: order.order_type,
+        time_in_force: order.time_in_force,
+        qty: order.qty,
+        reduce_only: order.reduce_only,
+        price: order.price,
+        stop_price: order.stop_price,
+        close_position: order.close_position,
+        activation_price: order.activation_price,
+        callback_rate: order.callback_rate,
+        working_type: order.working_type,
+        price_protect: order.price_protect,
+    })
+}
+
+fn order_parameters<S: Into<String>>(symbol: S, order_id: u64) -> BTreeMap<String, String> {
+    let mut parameters = BTreeMap::new();
+    parameters.insert("symbol".into(), symbol.into());
+    parameters.insert("orderId".into(), order_id.to_string());
+    parameters
+}
+
+fn limit_order(
+    symbol: String,
+    side: OrderSide,
+    qty: f64,
+    price: f64,
+    time_in_force: TimeInForce,
+    position_side: Option<PositionSide>,
+) -> CustomOrderRequest {
+    CustomOrderRequest {
+        symbol,
+        side,
+        position_side,
+        order_type: OrderType::Limit,
+        time_in_force: Some(time_in_force),
+        qty: Some(qty),
+        reduce_only: None,
+        price: Some(price),
+        stop_price: None,
+        close_position: None,
+        activation_price: None,
+        callback_rate: None,
+        working_type: None,
+        price_protect: None,
+    }
+}
+
+fn market_order(
+    symbol: String,
+    side: OrderSide,
+    qty: f64,
+    position_side: Option<PositionSide>,
+) -> CustomOrderRequest {
+    CustomOrderRequest {
+        symbol,
+        side,
+        position_side,
+        order_type: OrderType::Market,
+        time_in_force: None,
+        qty: Some(qty),
+        reduce_only: None,
+        price: None,
+        stop_price: None,
+        close_position: None,
+        activation_price: None,
+        callback_rate: None,
+        working_type: None,
+        price_protect: None,
+    }
+}
@@ -0,0 +1,402 @@
+use crate::commons::errors::*;
+use crate::rest::model::{AggrTradesEvent, KlineSummary};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fmt;
+
+/// Interval of the candles built from aggregated trades
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CandleInterval {
+    OneSecond,
+    OneMinute,
+    ThreeMinutes,
+    FiveMinutes,
+    FifteenMinutes,
+    ThirtyMinutes,
+    OneHour,
+    TwoHours,
+    FourHours,
+    SixHours,
+    EightHours,
+    TwelveHours,
+    OneDay,
+}
+
+impl CandleInterval {
+    pub fn millis(&self) -> u64 {
+        const MINUTE: u64 = 60 * 1000;
+        match self {
+            Self::OneSecond => 1000,
+            Self::OneMinute => MINUTE,
+            Self::ThreeMinutes => 3 * MINUTE,
+            Self::FiveMinutes => 5 * MINUTE,
+            Self::FifteenMinutes => 15 * MINUTE,
+            Self::ThirtyMinutes => 30 * MINUTE,
+            Self::OneHour => 60 * MINUTE,
+            Self::TwoHours => 2 * 60 * MINUTE,
+            Self::FourHours => 4 * 60 * MINUTE,
+            Self::SixHours => 6 * 60 * MINUTE,
+            Self::EightHours => 8 * 60 * MINUTE,
+            Self::TwelveHours => 12 * 60 * MINUTE,
+            Self::OneDay => 24 * 60 * MINUTE,
+        }
+    }
+
+    /// Open time of the candle that contains `time`, milliseconds
+    pub fn open_time(&self, time: u64) -> u64 {
+        time - time % self.millis()
+    }
+}
+
+impl fmt::Display for CandleInterval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::OneSecond => write!(f, "1s"),
+            Self::OneMinute => write!(f, "1m"),
+            Self::ThreeMinutes => write!(f, "3m"),
+            Self::FiveMinutes => write!(f, "5m"),
+            Self::FifteenMinutes => write!(f, "15m"),
+            Self::ThirtyMinutes => write!(f, "30m"),
+            Self::OneHour => write!(f, "1h"),
+            Self::TwoHours => write!(f, "2h"),
+            Self::FourHours => write!(f, "4h"),
+            Self::SixHours => write!(f, "6h"),
+            Self::EightHours => write!(f, "8h"),
+            Self::TwelveHours => write!(f, "12h"),
+            Self::OneDay => write!(f, "1d"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candle {
+    pub open_time: u64,
+    /// Last millisecond of the interval, as in klines
+    pub close_time: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    pub quote_volume: f64,
+    /// Trades of the matching engine, an aggregated trade counts as the trades it merges
+    pub trade_count: u64,
+    pub taker_buy_volume: f64,
+    pub taker_buy_quote_volume: f64,
+}
+
+impl Candle {
+    /// Volume weighted average price, `None` without volume
+    pub fn vwap(&self) -> Option<f64> {
+        (self.volume > 0.0).then(|| self.quote_volume / self.volume)
+    }
+
+    fn new(open_time: u64, interval: CandleInterval, price: f64) -> Candle {
+        Candle {
+            open_time,
+            close_time: open_time + interval.millis() - 1,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: 0.0,
+            quote_volume: 0.0,
+            trade_count: 0,
+            taker_buy_volume: 0.0,
+            taker_buy_quote_volume: 0.0,
+        }
+    }
+
+    fn add(&mut self, trade: &Trade) {
+        self.high = self.high.max(trade.price);
+        self.low = self.low.min(trade.price);
+        self.close = trade.price;
+        self.volume += trade.qty;
+        self.quote_volume += trade.price * trade.qty;
+        self.trade_count += trade.count;
+        // the buyer is the taker when the maker sold
+        if !trade.is_buyer_maker {
+            self.taker_buy_volume += trade.qty;
+            self.taker_buy_quote_volume += trade.price * trade.qty;
+        }
+    }
+
+    // appends the next candle of the same interval, e.g. a kline of a smaller interval
+    fn merge(&mut self, next: &Candle) {
+        self.high = self.high.max(next.high);
+        self.low = self.low.min(next.low);
+        self.close = next.close;
+        self.volume += next.volume;
+        self.quote_volume += next.quote_volume;
+        self.trade_count += next.trade_count;
+        self.taker_buy_volume += next.taker_buy_volume;
+        self.taker_buy_quote_volume += next.taker_buy_quote_volume;
+    }
+}
+
+impl TryFrom<&KlineSummary> for Candle {
+    type Error = BinanceError;
+
+    fn try_from(kline: &KlineSummary) -> Result<Self> {
+        Ok(Candle {
+            open_time: kline.open_time as u64,
+            close_time: kline.close_time as u64,
+            open: kline.open.parse()?,
+            high: kline.high.parse()?,
+            low: kline.low.parse()?,
+            close: kline.close.parse()?,
+            volume: kline.volume.parse()?,
+            quote_volume: kline.quote_asset_volume.parse()?,
+            trade_count: kline.number_of_trades as u64,
+            taker_buy_volume: kline.taker_buy_base_asset_volume.parse()?,
+            taker_buy_quote_volume: kline.taker_buy_quote_asset_volume.parse()?,
+        })
+    }
+}
+
+struct Trade {
+    time: u64,
+    price: f64,
+    qty: f64,
+    count: u64,
+    is_buyer_maker: bool,
+}
+
+impl TryFrom<&AggrTradesEvent> for Trade {
+    type Error = BinanceError;
+
+    fn try_from(trade: &AggrTradesEvent) -> Result<Self> {
+        Ok(Trade {
+            time: trade.trade_order_time,
+            price: trade.price.parse()?,
+            qty: trade.qty.parse()?,
+            count: trade
+                .last_break_trade_id
+                .saturating_sub(trade.first_break_trade_id)
+                + 1,
+            is_buyer_maker: trade.is_buyer_maker,
+        })
+    }
+}
+
+/// Candles of one interval built from aggregated trades, the last one is in progress.
+/// Intervals without trades have no candle, and the first candle is partial unless seeded
+#[derive(Debug, Clone)]
+pub struct CandleBuilder {
+    interval: CandleInterval,
+    capacity: usize,
+    candles: VecDeque<Candle>,
+}
+
+impl CandleBuilder {
+    /// Keeps the last `capacity` candles
+    pub fn new(interval: CandleInterval, capacity: usize) -> CandleBuilder {
+        CandleBuilder {
+            interval,
+            capacity: capacity.max(1),
+            candles: VecDeque::new(),
+        }
+    }
+
+    pub fn interval(&self) -> CandleInterval {
+        self.interval
+    }
+
+    pub fn get_candles(&self) -> VecDeque<Candle> {
+        self.candles.clone()
+    }
+
+    pub fn get_current_candle(&self) -> Option<Candle> {
+        self.candles.back().cloned()
+    }
+
+    /// Adds `trade` to its candle, a trade older than every kept candle is ignored
+    pub fn add_trade(&mut self, trade: &AggrTradesEvent) -> Result<()> {
+        self.add(&Trade::try_from(trade)?);
+        Ok(())
+    }
+
+    /// Replaces the candles covered by `klines` (of an interval dividing this one, oldest
+    /// first), then adds the `trades` received after the klines were requested to them.
+    /// Klines of a larger interval are ignored, as are the leading ones that do not start
+    /// a candle
+    pub fn seed<'a, I>(&mut self, klines: &[KlineSummary], trades: I) -> Result<()>
+    where
+        I: IntoIterator<Item = &'a AggrTradesEvent>,
+    {
+        let mut seeded: BTreeMap<u64, Candle> = BTreeMap::new();
+        for kline in klines {
+            let mut candle = Candle::try_from(kline)?;
+            if candle.close_time + 1 - candle.open_time > self.interval.millis() {
+                return Ok(());
+            }
+            let open_time = self.interval.open_time(candle.open_time);
+            match seeded.get_mut(&open_time) {
+                Some(seeded) => seeded.merge(&candle),
+                None if candle.open_time == open_time => {
+                    candle.close_time = open_time + self.interval.millis() - 1;
+                    seeded.insert(open_time, candle);
+                }
+                None => (),
+            }
+        }
+        let replaced: HashSet<u64> = seeded.keys().copied().collect();
+        let mut candles: BTreeMap<u64, Candle> = self
+            .candles
+            .drain(..)
+            .map(|candle| (candle.open_time, candle))
+            .collect();
+        candles.extend(seeded);
+        self.candles = candles.into_values().collect();
+        for trade in trades {
+            let trade = Trade::try_from(trade)?;
+            if replaced.contains(&self.interval.open_time(trade.time)) {
+                self.add(&trade);
+            }
+        }
+        self.trim();
+        Ok(())
+    }
+
+    fn add(&mut self, trade: &Trade) {
+        let open_time = self.interval.open_time(trade.time);
+        match self.candles.back_mut() {
+            Some(candle) if candle.open_time == open_time => candle.add(trade),
+            Some(candle) if candle.open_time > open_time => {
+                if let Some(candle) = self
+                    .candles
+                    .iter_mut()
+                    .rev()
+                    .find(|candle| candle.open_time == open_time)
+                {
+                    candle.add(trade);
+                }
+            }
+            _ => {
+                let mut candle = Candle::new(open_time, self.interval, trade.price);
+                candle.add(trade);
+                self.candles.push_back(candle);
+                self.trim();
+            }
+        }
+    }
+
+    fn trim(&mut self) {
+        while self.candles.len() > self.capacity {
+            self.candles.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(id: u64, time: u64, price: &str, qty: &str, is_buyer_maker: bool) -> AggrTradesEvent {
+        AggrTradesEvent {
+            event_type: "aggTrade".into(),
+            event_time: time,
+            symbol: "BTCUSDT".into(),
+            aggregated_trade_id: id,
+            price: price.into(),
+            qty: qty.into(),
+            first_break_trade_id: id * 10,
+            last_break_trade_id: id * 10 + 1,
+            trade_order_time: time,
+            is_buyer_maker,
+            m_ignore: false,
+        }
+    }
+
+    fn kline(open_time: u64, open: &str, high: &str, low: &str, close: &str) -> KlineSummary {
+        KlineSummary {
+            open_time: open_time as i64,
+            open: open.into(),
+            high: high.into(),
+            low: low.into(),
+            close: close.into(),
+            volume: "2".into(),
+            close_time: (open_time + 59_999) as i64,
+            quote_asset_volume: "200".into(),
+            number_of_trades: 4,
+            taker_buy_base_asset_volume: "1".into(),
+            taker_buy_quote_asset_volume: "100".into(),
+        }
+    }
+
+    #[test]
+    fn builds_candles_from_trades() {
+        let mut builder = CandleBuilder::new(CandleInterval::OneMinute, 2);
+        builder
+            .add_trade(&trade(1, 60_000, "100", "1", false))
+            .unwrap();
+        builder
+            .add_trade(&trade(2, 60_500, "110", "2", true))
+            .unwrap();
+        builder
+            .add_trade(&trade(3, 119_999, "90", "1", false))
+            .unwrap();
+
+        let candle = builder.get_current_candle().unwrap();
+        assert_eq!(candle.open_time, 60_000);
+        assert_eq!(candle.close_time, 119_999);
+        assert_eq!(
+            (candle.open, candle.high, candle.low, candle.close),
+            (100.0, 110.0, 90.0, 90.0)
+        );
+        assert_eq!(candle.volume, 4.0);
+        assert_eq!(candle.vwap(), Some(410.0 / 4.0));
+        assert_eq!(candle.trade_count, 6);
+        assert_eq!(candle.taker_buy_volume, 2.0);
+        assert_eq!(candle.taker_buy_quote_volume, 190.0);
+
+        builder
+            .add_trade(&trade(4, 120_000, "95", "1", false))
+            .unwrap();
+        builder
+            .add_trade(&trade(5, 180_000, "96", "1", false))
+            .unwrap();
+        let candles = builder.get_candles();
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[0].open_time, 120_000);
+        assert_eq!(candles[1].open, 96.0);
+    }
+
+    #[test]
+    fn seeds_larger_intervals_and_replays_later_trades() {
+        let mut builder = CandleBuilder::new(CandleInterval::FiveMinutes, 10);
+        // partial candle built before the seed
+        builder
+            .add_trade(&trade(1, 310_000, "101", "1", false))
+            .unwrap();
+
+        // the first minute does not start a 5m candle
+        let klines = vec![
+            kline(240_000, "90", "95", "85", "92"),
+            kline(300_000, "100", "105", "99", "101"),
+            kline(360_000, "101", "108", "100", "107"),
+        ];
+        let after_snapshot = trade(2, 400_000, "110", "1", true);
+        builder.seed(&klines, [&after_snapshot]).unwrap();
+
+        let candles = builder.get_candles();
+        assert_eq!(candles.len(), 1);
+        let candle = &candles[0];
+        assert_eq!((candle.open_time, candle.close_time), (300_000, 599_999));
+        assert_eq!(
+            (candle.open, candle.high, candle.low, candle.close),
+            (100.0, 110.0, 99.0, 110.0)
+        );
+        assert_eq!(candle.volume, 5.0);
+        assert_eq!(candle.trade_count, 10);
+        assert_eq!(candle.taker_buy_volume, 2.0);
+    }
+
+    #[test]
+    fn does_not_seed_smaller_intervals() {
+        let mut builder = CandleBuilder::new(CandleInterval::OneSecond, 10);
+        builder
+            .seed(&[kline(60_000, "1", "1", "1", "1")], [])
+            .unwrap();
+        assert!(builder.get_candles().is_empty());
+    }
+}
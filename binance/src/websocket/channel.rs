@@ -0,0 +1,400 @@
+use crate::commons::config::Config;
+use crate::commons::errors::*;
+use crate::websocket::futures::{FuturesWebSockets, FuturesWebsocketEvent};
+use crate::websocket::managed::{ManagedSocket, ManagedWebSockets, ReconnectPolicy};
+use crate::websocket::spot::{WebSockets, WebsocketEvent};
+use crate::websocket::subscription::StreamController;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// What the socket thread does when the channel is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Wait for the consumer, the socket is not read meanwhile
+    #[default]
+    Block,
+    /// Discard the oldest buffered event
+    DropOldest,
+    /// Stop the socket, the receiver returns `ChannelFull` once drained
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChannelConfig {
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+    /// How often the socket thread checks for a shutdown on quiet streams
+    pub read_timeout: Duration,
+    pub policy: ReconnectPolicy,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            overflow: OverflowPolicy::Block,
+            read_timeout: Duration::from_secs(1),
+            policy: ReconnectPolicy::default(),
+        }
+    }
+}
+
+impl ChannelConfig {
+    pub fn set_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    pub fn set_overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn set_read_timeout(mut self, read_timeout: Duration) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    pub fn set_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+struct QueueState<E> {
+    events: VecDeque<E>,
+    closed: bool,
+    overflowed: bool,
+    dropped: u64,
+}
+
+pub(crate) struct EventQueue<E> {
+    state: Mutex<QueueState<E>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+    overflow: OverflowPolicy,
+}
+
+impl<E> EventQueue<E> {
+    fn new(capacity: usize, overflow: OverflowPolicy) -> Self {
+        EventQueue {
+            state: Mutex::new(QueueState {
+                events: VecDeque::with_capacity(capacity),
+                closed: false,
+                overflowed: false,
+                dropped: 0,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity,
+            overflow,
+        }
+    }
+
+    pub(crate) fn push(&self, event: E) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.closed {
+                return Err(BinanceError::WebSocket(WebSocketError::LoopClosed));
+            }
+            if state.events.len() < self.capacity {
+                state.events.push_back(event);
+                self.not_empty.notify_one();
+                return Ok(());
+            }
+            match self.overflow {
+                OverflowPolicy::Block => state = self.not_full.wait(state).unwrap(),
+                OverflowPolicy::DropOldest => {
+                    state.events.pop_front();
+                    state.dropped += 1;
+                }
+                OverflowPolicy::Error => {
+                    state.overflowed = true;
+                    state.closed = true;
+                    self.not_empty.notify_all();
+                    return Err(BinanceError::WebSocket(WebSocketError::ChannelFull(
+                        self.capacity,
+                    )));
+                }
+            }
+        }
+    }
+
+    /// `None` on timeout
+    fn pop(&self, timeout: Option<Duration>) -> Result<Option<E>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(event) = state.events.pop_front() {
+                self.not_full.notify_one();
+                return Ok(Some(event));
+            }
+            if state.closed {
+                return Err(BinanceError::WebSocket(if state.overflowed {
+                    WebSocketError::ChannelFull(self.capacity)
+                } else {
+                    WebSocketError::Disconnected
+                }));
+            }
+            state = match deadline {
+                None => self.not_empty.wait(state).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Ok(None);
+                    }
+                    self.not_empty
+                        .wait_timeout(state, deadline - now)
+                        .unwrap()
+                        .0
+                }
+            };
+        }
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+}
+
+/// Bounded queue and its receiving end, the receiver closes the queue when dropped
+pub(crate) fn event_queue<E>(
+    capacity: usize,
+    overflow: OverflowPolicy,
+) -> (Arc<EventQueue<E>>, EventReceiver<E>) {
+    let queue = Arc::new(EventQueue::new(capacity.max(1), overflow));
+    (queue.clone(), EventReceiver { queue })
+}
+
+/// Receiving end of a websocket running on its own thread, iterating blocks until the next event
+/// and ends once the socket is shut down and the buffered events are consumed
+pub struct EventReceiver<E> {
+    queue: Arc<EventQueue<E>>,
+}
+
+impl<E> EventReceiver<E> {
+    /// Blocks until the next event, fails once the channel is closed and drained
+    pub fn recv(&self) -> Result<E> {
+        self.queue.pop(None).map(|event| event.expect("no timeout"))
+    }
+
+    /// `None` if no event was received within `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Option<E>> {
+        self.queue.pop(Some(timeout))
+    }
+
+    pub fn try_recv(&self) -> Result<Option<E>> {
+        self.queue.pop(Some(Duration::ZERO))
+    }
+
+    /// Events discarded by `OverflowPolicy::DropOldest`
+    pub fn dropped(&self) -> u64 {
+        self.queue.state.lock().unwrap().dropped
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.state.lock().unwrap().events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<E> Iterator for EventReceiver<E> {
+    type Item = E;
+
+    fn next(&mut self) -> Option<E> {
+        self.recv().ok()
+    }
+}
+
+impl<E> Drop for EventReceiver<E> {
+    // stops the socket thread, nobody is listening anymore
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
+
+/// Stops the socket thread, dropping the handle also shuts it down
+pub struct ShutdownHandle {
+    running: Arc<AtomicBool>,
+    close: Box<dyn Fn() + Send + Sync>,
+    controller: StreamController,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl ShutdownHandle {
+    /// Live (un)subscriptions, the acks are delivered as `StreamResponse` events
+    pub fn stream_controller(&self) -> StreamController {
+        self.controller.clone()
+    }
+
+    /// False once the socket thread stopped, after a shutdown, an overflow or when giving up reconnecting
+    pub fn is_running(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+
+    /// Closes the connection and waits for the socket thread, events already buffered can still be received
+    pub fn shutdown(mut self) -> Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<()> {
+        self.running.store(false, Ordering::Relaxed);
+        (self.close)();
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .map_err(|_| BinanceError::WebSocket(WebSocketError::LoopClosed))?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ShutdownHandle {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+type Handler<E> = Box<dyn FnMut(E) -> Result<()>>;
+
+/// Runs a reconnecting spot websocket on its own thread
+pub fn spot_channel(
+    streams: Vec<String>,
+    config: &Config,
+    channel: ChannelConfig,
+) -> Result<(EventReceiver<WebsocketEvent>, ShutdownHandle)> {
+    spawn(streams, config, channel, WebSockets::new)
+}
+
+/// Runs a reconnecting USDM websocket on its own thread
+pub fn futures_channel(
+    streams: Vec<String>,
+    config: &Config,
+    channel: ChannelConfig,
+) -> Result<(EventReceiver<FuturesWebsocketEvent>, ShutdownHandle)> {
+    spawn(streams, config, channel, FuturesWebSockets::new)
+}
+
+fn spawn<E, S, F>(
+    streams: Vec<String>,
+    config: &Config,
+    channel: ChannelConfig,
+    socket: F,
+) -> Result<(EventReceiver<E>, ShutdownHandle)>
+where
+    E: Send + 'static,
+    S: ManagedSocket,
+    F: FnOnce(Handler<E>) -> S + Send + 'static,
+{
+    let (queue, receiver) = event_queue(channel.capacity, channel.overflow);
+    let running = Arc::new(AtomicBool::new(true));
+    let config = config.clone();
+    let (controller_tx, controller_rx) = mpsc::channel();
+
+    let thread = {
+        let queue = queue.clone();
+        let running = running.clone();
+        thread::spawn(move || {
+            let producer = queue.clone();
+            let stop = running.clone();
+            let mut socket = socket(Box::new(move |event| {
+                producer
+                    .push(event)
+                    .inspect_err(|_| stop.store(false, Ordering::Relaxed))
+            }));
+            socket.set_read_timeout(Some(channel.read_timeout))?;
+            let _ = controller_tx.send(socket.stream_controller());
+
+            let result = ManagedWebSockets::new(socket, streams, &config)
+                .set_policy(channel.policy)
+                .event_loop(&running);
+            queue.close();
+            result
+        })
+    };
+
+    let controller = controller_rx
+        .recv()
+        .map_err(|_| BinanceError::WebSocket(WebSocketError::LoopClosed))?;
+    let close_queue = queue.clone();
+    Ok((
+        receiver,
+        ShutdownHandle {
+            running,
+            close: Box::new(move || close_queue.close()),
+            controller,
+            thread: Some(thread),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop_oldest() {
+        let queue = EventQueue::new(2, OverflowPolicy::DropOldest);
+        for i in 0..5 {
+            queue.push(i).unwrap();
+        }
+        assert_eq!(queue.pop(None).unwrap(), Some(3));
+        assert_eq!(queue.pop(None).unwrap(), Some(4));
+        assert_eq!(queue.pop(Some(Duration::ZERO)).unwrap(), None);
+        assert_eq!(queue.state.lock().unwrap().dropped, 3);
+    }
+
+    #[test]
+    fn test_overflow_error() {
+        let queue = EventQueue::new(2, OverflowPolicy::Error);
+        queue.push(1).unwrap();
+        queue.push(2).unwrap();
+        assert!(matches!(
+            queue.push(3),
+            Err(BinanceError::WebSocket(WebSocketError::ChannelFull(2)))
+        ));
+        // buffered events are drained before the error
+        assert_eq!(queue.pop(None).unwrap(), Some(1));
+        assert_eq!(queue.pop(None).unwrap(), Some(2));
+        assert!(matches!(
+            queue.pop(None),
+            Err(BinanceError::WebSocket(WebSocketError::ChannelFull(2)))
+        ));
+    }
+
+    #[test]
+    fn test_block_until_consumed() {
+        let queue = Arc::new(EventQueue::new(1, OverflowPolicy::Block));
+        queue.push(1).unwrap();
+        let producer = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.push(2))
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(queue.state.lock().unwrap().events.len(), 1);
+        assert_eq!(queue.pop(None).unwrap(), Some(1));
+        producer.join().unwrap().unwrap();
+        assert_eq!(queue.pop(None).unwrap(), Some(2));
+
+        // closing wakes a blocked producer
+        queue.push(3).unwrap();
+        let producer = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.push(4))
+        };
+        thread::sleep(Duration::from_millis(50));
+        queue.close();
+        assert!(producer.join().unwrap().is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use std::borrow::Cow;
+
+/// Fields needed to route a message, the payload is left undecoded
+#[derive(Deserialize, Default)]
+struct Header<'a> {
+    #[serde(rename = "e", borrow, default)]
+    event_type: Option<Cow<'a, str>>,
+    #[serde(borrow, default)]
+    stream: Option<Cow<'a, str>>,
+    #[serde(borrow, default)]
+    data: Option<&'a RawValue>,
+    #[serde(default)]
+    id: Option<u64>,
+}
+
+/// A message of a raw or combined stream
+pub(crate) struct Frame<'a> {
+    /// `e` of the payload, of its first element for arrays
+    pub event_type: Option<Cow<'a, str>>,
+    /// Stream name of combined streams
+    pub stream: Option<Cow<'a, str>>,
+    /// Payload, `data` of combined streams
+    pub payload: &'a str,
+    pub is_array: bool,
+    /// Subscription acks carry the request id
+    pub is_ack: bool,
+}
+
+impl<'a> Frame<'a> {
+    pub(crate) fn parse(msg: &'a str) -> serde_json::Result<Frame<'a>> {
+        if is_array(msg) {
+            return Frame::array(msg, None);
+        }
+        let header: Header<'a> = serde_json::from_str(msg)?;
+        match header.data {
+            Some(data) if is_array(data.get()) => Frame::array(data.get(), header.stream),
+            Some(data) => {
+                let inner: Header<'a> = serde_json::from_str(data.get())?;
+                Ok(Frame {
+                    event_type: inner.event_type,
+                    stream: header.stream,
+                    payload: data.get(),
+                    is_array: false,
+                    is_ack: false,
+                })
+            }
+            None => Ok(Frame {
+                is_ack: header.id.is_some() && header.event_type.is_none(),
+                event_type: header.event_type,
+                stream: None,
+                payload: msg,
+                is_array: false,
+            }),
+        }
+    }
+
+    fn array(payload: &'a str, stream: Option<Cow<'a, str>>) -> serde_json::Result<Frame<'a>> {
+        let items: Vec<&'a RawValue> = serde_json::from_str(payload)?;
+        let event_type = match items.first() {
+            Some(item) => serde_json::from_str::<Header<'a>>(item.get())?.event_type,
+            None => None,
+        };
+        Ok(Frame {
+            event_type,
+            stream,
+            payload,
+            is_array: true,
+            is_ack: false,
+        })
+    }
+
+    /// True if the stream name contains `pattern`, e.g. `@depth`
+    pub(crate) fn stream_has(&self, pattern: &str) -> bool {
+        self.stream
+            .as_deref()
+            .is_some_and(|stream| stream.contains(pattern))
+    }
+}
+
+fn is_array(msg: &str) -> bool {
+    msg.trim_start().starts_with('[')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames() {
+        let frame = Frame::parse(r#"{"e":"aggTrade","E":1,"s":"BTCUSDT"}"#).unwrap();
+        assert_eq!(frame.event_type.as_deref(), Some("aggTrade"));
+        assert!(!frame.is_array && !frame.is_ack);
+
+        let frame = Frame::parse(
+            r#"{"stream":"btcusdt@depth5","data":{"lastUpdateId":1,"bids":[],"asks":[]}}"#,
+        )
+        .unwrap();
+        assert_eq!(frame.event_type, None);
+        assert!(frame.stream_has("@depth"));
+        assert_eq!(frame.payload, r#"{"lastUpdateId":1,"bids":[],"asks":[]}"#);
+
+        let frame = Frame::parse(
+            r#"{"stream":"!markPrice@arr","data":[{"e":"markPriceUpdate","E":1},{"e":"markPriceUpdate","E":2}]}"#,
+        )
+        .unwrap();
+        assert_eq!(frame.event_type.as_deref(), Some("markPriceUpdate"));
+        assert!(frame.is_array);
+
+        let frame = Frame::parse(r#"{"result":null,"id":3}"#).unwrap();
+        assert!(frame.is_ack);
+    }
+}
@@ -0,0 +1,234 @@
+use crate::websocket::subscription::StreamController;
+use log::{debug, warn};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// latency samples kept per stream
+const LATENCY_SAMPLES: usize = 1000;
+
+#[derive(Debug, Clone, Default)]
+struct StreamStats {
+    last_received: Option<Instant>,
+    last_event_time: u64,
+    // exchange to local latency in milliseconds, negative with clock skew
+    latencies: VecDeque<i64>,
+}
+
+/// Exchange to local latency of a stream over its last samples, in milliseconds
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyStats {
+    pub min: i64,
+    pub avg: f64,
+    pub p99: i64,
+    pub samples: usize,
+}
+
+/// Called with the stream and the time since its last message
+pub type StaleCallback = Arc<dyn Fn(&str, Duration) + Send + Sync>;
+
+#[derive(Clone)]
+pub enum StaleAction {
+    /// Reconnects the socket set with `FeedMonitor::set_stream_controller`
+    Reconnect,
+    Callback(StaleCallback),
+}
+
+impl fmt::Debug for StaleAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Reconnect => write!(f, "Reconnect"),
+            Self::Callback(_) => write!(f, "Callback"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Threshold {
+    stream: String,
+    max_age: Duration,
+    action: StaleAction,
+    added: Instant,
+    // fired once per stale period
+    triggered: bool,
+}
+
+/// Receive times and latencies per stream, and the actions to run when one goes stale
+#[derive(Debug, Clone, Default)]
+pub struct FeedMonitor {
+    streams: Arc<RwLock<HashMap<String, StreamStats>>>,
+    thresholds: Arc<Mutex<Vec<Threshold>>>,
+    controller: Arc<RwLock<Option<StreamController>>>,
+}
+
+impl FeedMonitor {
+    /// Records a message of `stream` received now
+    /// * `event_time` - exchange event time in milliseconds
+    pub fn record(&self, stream: &str, event_time: u64) {
+        let latency = now_millis() as i64 - event_time as i64;
+        let mut streams = self.streams.write().unwrap();
+        let stats = streams.entry(stream.to_string()).or_default();
+        stats.last_received = Some(Instant::now());
+        stats.last_event_time = event_time;
+        stats.latencies.push_back(latency);
+        if stats.latencies.len() > LATENCY_SAMPLES {
+            stats.latencies.pop_front();
+        }
+    }
+
+    /// Streams that received at least one message
+    pub fn get_streams(&self) -> Vec<String> {
+        self.streams.read().unwrap().keys().cloned().collect()
+    }
+
+    pub fn get_last_received(&self, stream: &str) -> Option<Instant> {
+        self.streams.read().unwrap().get(stream)?.last_received
+    }
+
+    /// Exchange event time of the last message, in milliseconds
+    pub fn get_last_event_time(&self, stream: &str) -> Option<u64> {
+        Some(self.streams.read().unwrap().get(stream)?.last_event_time)
+    }
+
+    /// Time since the last message of `stream`, `None` if none was received
+    pub fn get_staleness(&self, stream: &str) -> Option<Duration> {
+        Some(self.get_last_received(stream)?.elapsed())
+    }
+
+    /// True if `stream` received nothing for `max_age`, or nothing at all
+    pub fn is_stale(&self, stream: &str, max_age: Duration) -> bool {
+        self.get_staleness(stream)
+            .is_none_or(|staleness| staleness > max_age)
+    }
+
+    pub fn get_latency(&self, stream: &str) -> Option<LatencyStats> {
+        let streams = self.streams.read().unwrap();
+        let latencies = &streams.get(stream)?.latencies;
+        if latencies.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<i64> = latencies.iter().copied().collect();
+        sorted.sort_unstable();
+        let p99 = (sorted.len() * 99).div_ceil(100) - 1;
+        Some(LatencyStats {
+            min: sorted[0],
+            avg: sorted.iter().sum::<i64>() as f64 / sorted.len() as f64,
+            p99: sorted[p99],
+            samples: sorted.len(),
+        })
+    }
+
+    /// Controller of the socket reconnected by `StaleAction::Reconnect`
+    pub fn set_stream_controller(&self, controller: StreamController) {
+        *self.controller.write().unwrap() = Some(controller);
+    }
+
+    /// Runs `action` when `stream` receives nothing for `max_age`, counted from now if it
+    /// received nothing yet. Thresholds are evaluated by `check_thresholds`
+    pub fn add_threshold(&self, stream: &str, max_age: Duration, action: StaleAction) {
+        self.thresholds.lock().unwrap().push(Threshold {
+            stream: stream.to_string(),
+            max_age,
+            action,
+            added: Instant::now(),
+            triggered: false,
+        });
+    }
+
+    /// Runs the actions of the streams that went stale since the last check,
+    /// to be called periodically
+    pub fn check_thresholds(&self) {
+        let mut thresholds = self.thresholds.lock().unwrap();
+        for threshold in thresholds.iter_mut() {
+            let age = self
+                .get_staleness(&threshold.stream)
+                .unwrap_or_else(|| threshold.added.elapsed());
+            if age <= threshold.max_age {
+                threshold.triggered = false;
+                continue;
+            }
+            if threshold.triggered {
+                continue;
+            }
+            threshold.triggered = true;
+            warn!("{} is stale for {age:?}", threshold.stream);
+            match threshold.action {
+                StaleAction::Reconnect => match *self.controller.read().unwrap() {
+                    Some(ref controller) => {
+                        if let Err(e) = controller.reconnect() {
+                            warn!("Unable to reconnect {}: {e}", threshold.stream);
+                        }
+                    }
+                    None => debug!("No socket to reconnect for {}", threshold.stream),
+                },
+                StaleAction::Callback(ref callback) => callback(&threshold.stream, age),
+            }
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_latency() {
+        let monitor = FeedMonitor::default();
+        assert!(monitor.is_stale("btcusdt@aggTrade", Duration::from_secs(5)));
+        assert!(monitor.get_latency("btcusdt@aggTrade").is_none());
+
+        let now = now_millis();
+        for delay in 1..=100 {
+            monitor.record("btcusdt@aggTrade", now - delay);
+        }
+        let latency = monitor.get_latency("btcusdt@aggTrade").unwrap();
+        assert!(latency.min >= 1 && latency.min < 50);
+        assert!(latency.p99 >= 99);
+        assert!(latency.avg > 50.0);
+        assert_eq!(latency.samples, 100);
+        assert_eq!(
+            monitor.get_last_event_time("btcusdt@aggTrade"),
+            Some(now - 100)
+        );
+        assert!(!monitor.is_stale("btcusdt@aggTrade", Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let monitor = FeedMonitor::default();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        monitor.add_threshold(
+            "btcusdt@markPrice@1s",
+            Duration::from_millis(20),
+            StaleAction::Callback(Arc::new(move |stream, _| {
+                assert_eq!(stream, "btcusdt@markPrice@1s");
+                counter.fetch_add(1, Ordering::Relaxed);
+            })),
+        );
+
+        monitor.check_thresholds();
+        assert_eq!(calls.load(Ordering::Relaxed), 0);
+        thread::sleep(Duration::from_millis(30));
+        monitor.check_thresholds();
+        monitor.check_thresholds();
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        // fresh again, then stale again
+        monitor.record("btcusdt@markPrice@1s", now_millis());
+        monitor.check_thresholds();
+        thread::sleep(Duration::from_millis(30));
+        monitor.check_thresholds();
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+}
@@ -1,12 +1,24 @@
 use crate::commons::config::Config;
 use crate::commons::errors::*;
-use crate::rest::futures::model::{OrderBook, OrderTradeEvent};
+use crate::rest::futures::model::{
+    AccountConfigUpdateEvent, ConditionalOrderTriggerRejectEvent, GridUpdateEvent, MarginCallEvent,
+    OrderBook, OrderTradeEvent, StrategyUpdateEvent,
+};
 use crate::rest::model::{
-    AccountUpdateEvent, AggrTradesEvent, BookTickerEvent, ContinuousKlineEvent, DayTickerEvent,
-    DepthOrderBookEvent, IndexKlineEvent, IndexPriceEvent, KlineEvent, LiquidationEvent,
-    MarkPriceEvent, MiniTickerEvent, TradeEvent, UserDataStreamExpiredEvent,
+    AccountUpdateEvent, AggrTradesEvent, AssetIndexEvent, BookTickerEvent, CompositeIndexEvent,
+    ContinuousKlineEvent, ContractInfoEvent, DayTickerEvent, DepthOrderBookEvent, IndexKlineEvent,
+    IndexPriceEvent, KlineEvent, LiquidationEvent, MarkPriceEvent, MiniTickerEvent, TradeEvent,
+    UserDataStreamExpiredEvent,
+};
+use crate::websocket::decode::Frame;
+use crate::websocket::recorder::Recorder;
+use crate::websocket::subscription::{
+    command_poll_timeout, StreamController, StreamMethod, StreamResponse, Subscriptions,
 };
+use crate::websocket::{is_read_timeout, set_read_timeout};
+use log::warn;
 use serde::{Deserialize, Serialize};
+use serde_json::from_str;
 use std::net::TcpStream;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
@@ -18,6 +30,8 @@ use url::Url;
 
 pub mod usdm;
 pub mod usdm_data;
+pub mod usdm_events;
+pub mod user_stream_runner;
 pub mod userstream;
 
 #[allow(clippy::all)]
@@ -27,6 +41,7 @@ enum FuturesWebsocketAPI {
     Custom(String),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FuturesMarket {
     USDM,
     COINM,
@@ -56,8 +71,14 @@ impl FuturesWebsocketAPI {
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum FuturesWebsocketEvent {
+    StreamResponse(StreamResponse),
     AccountUpdate(AccountUpdateEvent),
     OrderTrade(OrderTradeEvent),
+    AccountConfigUpdate(AccountConfigUpdateEvent),
+    MarginCall(MarginCallEvent),
+    StrategyUpdate(StrategyUpdateEvent),
+    GridUpdate(GridUpdateEvent),
+    ConditionalOrderTriggerReject(ConditionalOrderTriggerRejectEvent),
     AggrTrades(AggrTradesEvent),
     Trade(TradeEvent),
     OrderBook(OrderBook),
@@ -75,35 +96,20 @@ pub enum FuturesWebsocketEvent {
     DepthOrderBook(DepthOrderBookEvent),
     BookTicker(BookTickerEvent),
     UserDataStreamExpiredEvent(UserDataStreamExpiredEvent),
+    CompositeIndex(CompositeIndexEvent),
+    ContractInfo(ContractInfoEvent),
+    AssetIndex(AssetIndexEvent),
+    AssetIndexAll(Vec<AssetIndexEvent>),
 }
 
 pub struct FuturesWebSockets<'a> {
     pub socket: Option<(WebSocket<MaybeTlsStream<TcpStream>>, Response)>,
     handler: Box<dyn FnMut(FuturesWebsocketEvent) -> Result<()> + 'a>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(untagged)]
-enum FuturesEvents {
-    Vec(Vec<DayTickerEvent>),
-    DayTickerEvent(DayTickerEvent),
-    BookTickerEvent(BookTickerEvent),
-    MiniTickerEvent(MiniTickerEvent),
-    VecMiniTickerEvent(Vec<MiniTickerEvent>),
-    AccountUpdateEvent(AccountUpdateEvent),
-    OrderTradeEvent(OrderTradeEvent),
-    AggrTradesEvent(AggrTradesEvent),
-    IndexPriceEvent(IndexPriceEvent),
-    MarkPriceEvent(MarkPriceEvent),
-    VecMarkPriceEvent(Vec<MarkPriceEvent>),
-    TradeEvent(TradeEvent),
-    KlineEvent(KlineEvent),
-    ContinuousKlineEvent(ContinuousKlineEvent),
-    IndexKlineEvent(IndexKlineEvent),
-    LiquidationEvent(LiquidationEvent),
-    OrderBook(OrderBook),
-    DepthOrderBookEvent(DepthOrderBookEvent),
-    UserDataStreamExpiredEvent(UserDataStreamExpiredEvent),
+    subscriptions: Subscriptions,
+    awaiting: Option<u64>,
+    awaited: Option<StreamResponse>,
+    read_timeout: Option<Duration>,
+    recorder: Option<Recorder>,
 }
 
 impl<'a> FuturesWebSockets<'a> {
@@ -114,11 +120,19 @@ impl<'a> FuturesWebSockets<'a> {
         FuturesWebSockets {
             socket: None,
             handler: Box::new(handler),
+            subscriptions: Subscriptions::default(),
+            awaiting: None,
+            awaited: None,
+            read_timeout: None,
+            recorder: None,
         }
     }
 
     pub fn connect(&mut self, market: FuturesMarket, subscription: &'a str) -> Result<()> {
-        self.connect_wss(FuturesWebsocketAPI::Default.params(market, subscription))
+        self.connect_wss(
+            FuturesWebsocketAPI::Default.params(market, subscription),
+            &[subscription.to_string()],
+        )
     }
 
     pub fn connect_with_config(
@@ -130,6 +144,7 @@ impl<'a> FuturesWebSockets<'a> {
         self.connect_wss(
             FuturesWebsocketAPI::Custom(config.futures_ws_endpoint.clone())
                 .params(market, subscription),
+            &[subscription.to_string()],
         )
     }
 
@@ -142,14 +157,17 @@ impl<'a> FuturesWebSockets<'a> {
         self.connect_wss(
             FuturesWebsocketAPI::MultiStream(config.futures_ws_endpoint.clone())
                 .params(market, &endpoints.join("/")),
+            endpoints,
         )
     }
 
-    fn connect_wss(&mut self, wss: String) -> Result<()> {
+    fn connect_wss(&mut self, wss: String, streams: &[String]) -> Result<()> {
         let url = Url::parse(&wss)?;
         match connect(url.as_str()) {
             Ok(answer) => {
                 self.socket = Some(answer);
+                self.subscriptions.connected(streams);
+                self.set_read_timeout(self.read_timeout)?;
                 Ok(())
             }
             Err(e) => Err(BinanceError::WebSocket(WebSocketError::ConnectionError(
@@ -158,6 +176,20 @@ impl<'a> FuturesWebSockets<'a> {
         }
     }
 
+    /// Read timeout of the socket, lets the event loop check `running` on quiet streams
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.read_timeout = timeout;
+        if let Some(ref mut socket) = self.socket {
+            set_read_timeout(&mut socket.0, timeout)?;
+        }
+        Ok(())
+    }
+
+    /// Appends every text frame received to `recorder`
+    pub fn set_recorder(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
     pub fn disconnect(&mut self) -> Result<()> {
         if let Some(ref mut socket) = self.socket {
             socket.0.close(None)?;
@@ -166,60 +198,157 @@ impl<'a> FuturesWebSockets<'a> {
         Err(BinanceError::WebSocket(WebSocketError::Disconnected))
     }
 
+    /// Subscribes to `streams` on the open connection and returns the request id,
+    /// the ack is delivered to the handler as `StreamResponse`
+    pub fn subscribe(&mut self, streams: &[String]) -> Result<u64> {
+        self.send_request(StreamMethod::Subscribe, streams)
+    }
+
+    /// Unsubscribes from `streams` on the open connection and returns the request id
+    pub fn unsubscribe(&mut self, streams: &[String]) -> Result<u64> {
+        self.send_request(StreamMethod::Unsubscribe, streams)
+    }
+
+    /// Requests the active streams of the connection and returns the request id
+    pub fn list_subscriptions(&mut self) -> Result<u64> {
+        self.send_request(StreamMethod::ListSubscriptions, &[])
+    }
+
+    /// Controller to (un)subscribe from another thread while the event loop runs,
+    /// requests are sent between reads
+    pub fn stream_controller(&self) -> StreamController {
+        self.subscriptions.controller()
+    }
+
+    /// Streams of the connection, updated with every successful ack
+    pub fn subscribed_streams(&self) -> &[String] {
+        self.subscriptions.active()
+    }
+
+    /// Reads the socket until the ack of request `id` arrives, other events are passed to the
+    /// handler. The timeout is checked between messages
+    pub fn wait_for_response(&mut self, id: u64, timeout: Duration) -> Result<StreamResponse> {
+        self.awaiting = Some(id);
+        let deadline = Instant::now() + timeout;
+        let response = loop {
+            if let Some(response) = self.awaited.take() {
+                break Ok(response);
+            }
+            if Instant::now() >= deadline {
+                break Err(BinanceError::WebSocket(WebSocketError::MessageError(
+                    format!("No response to request {id}"),
+                )));
+            }
+            let message = match self.socket {
+                Some(ref mut socket) => socket.0.read(),
+                None => break Err(BinanceError::WebSocket(WebSocketError::Disconnected)),
+            };
+            let handled = match message {
+                Ok(Message::Text(msg)) => {
+                    self.record(&msg);
+                    self.handle_msg(&msg)
+                }
+                Ok(Message::Ping(data)) => self.send_message(Message::Pong(data)),
+                Ok(Message::Close(_)) => Err(BinanceError::WebSocket(WebSocketError::Disconnected)),
+                Ok(_) => Ok(()),
+                Err(e) if is_read_timeout(&e) => Ok(()),
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = handled {
+                break Err(e);
+            }
+        };
+        self.awaiting = None;
+        response
+    }
+
+    fn send_request(&mut self, method: StreamMethod, streams: &[String]) -> Result<u64> {
+        let (id, payload) = self.subscriptions.request(method, streams);
+        self.send_message(Message::text(payload))?;
+        Ok(id)
+    }
+
+    fn send_message(&mut self, message: Message) -> Result<()> {
+        if let Some(ref mut socket) = self.socket {
+            socket.0.send(message)?;
+            return Ok(());
+        }
+        Err(BinanceError::WebSocket(WebSocketError::Disconnected))
+    }
+
+    fn record(&self, msg: &str) {
+        if let Some(ref recorder) = self.recorder {
+            if let Err(e) = recorder.record(msg) {
+                warn!("Unable to record frame: {e}");
+            }
+        }
+    }
+
     pub fn test_handle_msg(&mut self, msg: &str) -> Result<()> {
         self.handle_msg(msg)
     }
 
+    // an unknown event type is skipped, a known one that does not decode is an error
     fn handle_msg(&mut self, msg: &str) -> Result<()> {
-        let value: serde_json::Value = serde_json::from_str(msg)?;
+        let frame = Frame::parse(msg)?;
 
-        if let Some(data) = value.get("data") {
-            self.handle_msg(&data.to_string())?;
+        if frame.is_ack {
+            let value: serde_json::Value = serde_json::from_str(msg)?;
+            if let Some(response) = self.subscriptions.handle_response(&value) {
+                if self.awaiting == Some(response.id) {
+                    self.awaited = Some(response);
+                    return Ok(());
+                }
+                return (self.handler)(FuturesWebsocketEvent::StreamResponse(response));
+            }
             return Ok(());
         }
 
-        if let Ok(events) = serde_json::from_value::<FuturesEvents>(value) {
-            let action = match events {
-                FuturesEvents::Vec(v) => FuturesWebsocketEvent::DayTickerAll(v),
-                FuturesEvents::DayTickerEvent(v) => FuturesWebsocketEvent::DayTicker(v),
-                FuturesEvents::BookTickerEvent(v) => FuturesWebsocketEvent::BookTicker(v),
-                FuturesEvents::MiniTickerEvent(v) => FuturesWebsocketEvent::MiniTicker(v),
-                FuturesEvents::VecMiniTickerEvent(v) => FuturesWebsocketEvent::MiniTickerAll(v),
-                FuturesEvents::AccountUpdateEvent(v) => FuturesWebsocketEvent::AccountUpdate(v),
-                FuturesEvents::OrderTradeEvent(v) => FuturesWebsocketEvent::OrderTrade(v),
-                FuturesEvents::IndexPriceEvent(v) => FuturesWebsocketEvent::IndexPrice(v),
-                FuturesEvents::MarkPriceEvent(v) => FuturesWebsocketEvent::MarkPrice(v),
-                FuturesEvents::VecMarkPriceEvent(v) => FuturesWebsocketEvent::MarkPriceAll(v),
-                FuturesEvents::TradeEvent(v) => FuturesWebsocketEvent::Trade(v),
-                FuturesEvents::ContinuousKlineEvent(v) => FuturesWebsocketEvent::ContinuousKline(v),
-                FuturesEvents::IndexKlineEvent(v) => FuturesWebsocketEvent::IndexKline(v),
-                FuturesEvents::LiquidationEvent(v) => FuturesWebsocketEvent::Liquidation(v),
-                FuturesEvents::KlineEvent(v) => FuturesWebsocketEvent::Kline(v),
-                FuturesEvents::OrderBook(v) => FuturesWebsocketEvent::OrderBook(v),
-                FuturesEvents::DepthOrderBookEvent(v) => FuturesWebsocketEvent::DepthOrderBook(v),
-                FuturesEvents::AggrTradesEvent(v) => FuturesWebsocketEvent::AggrTrades(v),
-                FuturesEvents::UserDataStreamExpiredEvent(v) => {
-                    FuturesWebsocketEvent::UserDataStreamExpiredEvent(v)
-                }
-            };
-            (self.handler)(action)?;
+        match decode_event(&frame)? {
+            Some(event) => (self.handler)(event),
+            None => Ok(()),
         }
-        Ok(())
     }
 
     pub fn event_loop(&mut self, running: &AtomicBool) -> Result<()> {
+        self.event_loop_for(running, Duration::from_secs(8 * 3600))?;
+        Err(BinanceError::WebSocket(WebSocketError::LoopClosed))
+    }
+
+    /// Runs the event loop until `running` is unset or the connection has been open for
+    /// `max_duration`, in which case the socket is closed and `Ok` is returned
+    pub fn event_loop_for(&mut self, running: &AtomicBool, max_duration: Duration) -> Result<()> {
         let start_time = Instant::now();
+        if let Some(ref mut socket) = self.socket {
+            // a quiet stream still wakes the loop to send the queued requests
+            set_read_timeout(&mut socket.0, Some(command_poll_timeout(self.read_timeout)))?;
+        }
         while running.load(Ordering::Relaxed) {
             if let Some(ref mut socket) = self.socket {
-                if Instant::now().duration_since(start_time) >= Duration::from_secs(8 * 3600) {
+                if Instant::now().duration_since(start_time) >= max_duration {
                     socket.0.close(None)?;
                     break;
                 }
-                let message = socket.0.read()?;
+                for payload in self.subscriptions.queued() {
+                    socket.0.send(Message::text(payload))?;
+                }
+                if self.subscriptions.take_reconnect() {
+                    return Err(BinanceError::WebSocket(WebSocketError::ConnectionError(
+                        "Reconnect requested".into(),
+                    )));
+                }
+                let message = match socket.0.read() {
+                    Ok(message) => message,
+                    Err(e) if is_read_timeout(&e) => continue,
+                    Err(e) => return Err(e.into()),
+                };
                 match message {
-                    Message::Text(msg) => self.handle_msg(&msg)?,
+                    Message::Text(msg) => {
+                        self.record(&msg);
+                        self.handle_msg(&msg)?
+                    }
                     Message::Ping(data) => {
-                        socket.0.send(Message::Pong(data)).unwrap();
+                        socket.0.send(Message::Pong(data))?;
                     }
                     Message::Pong(_) | Message::Binary(_) => (),
                     Message::Close(_) => {
@@ -229,6 +358,63 @@ impl<'a> FuturesWebSockets<'a> {
                 }
             }
         }
-        Err(BinanceError::WebSocket(WebSocketError::LoopClosed))
+        Ok(())
     }
 }
+
+fn decode_event(frame: &Frame) -> serde_json::Result<Option<FuturesWebsocketEvent>> {
+    let payload = frame.payload;
+    let event = match (frame.event_type.as_deref(), frame.is_array) {
+        (Some("aggTrade"), false) => FuturesWebsocketEvent::AggrTrades(from_str(payload)?),
+        (Some("trade"), false) => FuturesWebsocketEvent::Trade(from_str(payload)?),
+        (Some("24hrTicker"), false) => FuturesWebsocketEvent::DayTicker(from_str(payload)?),
+        (Some("24hrTicker"), true) => FuturesWebsocketEvent::DayTickerAll(from_str(payload)?),
+        (Some("24hrMiniTicker"), false) => FuturesWebsocketEvent::MiniTicker(from_str(payload)?),
+        (Some("24hrMiniTicker"), true) => FuturesWebsocketEvent::MiniTickerAll(from_str(payload)?),
+        (Some("bookTicker"), false) => FuturesWebsocketEvent::BookTicker(from_str(payload)?),
+        (Some("markPriceUpdate"), false) => FuturesWebsocketEvent::MarkPrice(from_str(payload)?),
+        (Some("markPriceUpdate"), true) => FuturesWebsocketEvent::MarkPriceAll(from_str(payload)?),
+        (Some("indexPriceUpdate"), false) => FuturesWebsocketEvent::IndexPrice(from_str(payload)?),
+        (Some("kline"), false) => FuturesWebsocketEvent::Kline(from_str(payload)?),
+        (Some("continuous_kline"), false) => {
+            FuturesWebsocketEvent::ContinuousKline(from_str(payload)?)
+        }
+        (Some("indexPrice_kline" | "markPrice_kline"), false) => {
+            FuturesWebsocketEvent::IndexKline(from_str(payload)?)
+        }
+        (Some("compositeIndex"), false) => {
+            FuturesWebsocketEvent::CompositeIndex(from_str(payload)?)
+        }
+        (Some("contractInfo"), false) => FuturesWebsocketEvent::ContractInfo(from_str(payload)?),
+        (Some("assetIndexUpdate"), false) => FuturesWebsocketEvent::AssetIndex(from_str(payload)?),
+        (Some("assetIndexUpdate"), true) => {
+            FuturesWebsocketEvent::AssetIndexAll(from_str(payload)?)
+        }
+        (Some("forceOrder"), false) => FuturesWebsocketEvent::Liquidation(from_str(payload)?),
+        (Some("depthUpdate"), false) => FuturesWebsocketEvent::DepthOrderBook(from_str(payload)?),
+        (Some("ACCOUNT_UPDATE"), false) => FuturesWebsocketEvent::AccountUpdate(from_str(payload)?),
+        (Some("ORDER_TRADE_UPDATE"), false) => {
+            FuturesWebsocketEvent::OrderTrade(from_str(payload)?)
+        }
+        (Some("ACCOUNT_CONFIG_UPDATE"), false) => {
+            FuturesWebsocketEvent::AccountConfigUpdate(from_str(payload)?)
+        }
+        (Some("MARGIN_CALL"), false) => FuturesWebsocketEvent::MarginCall(from_str(payload)?),
+        (Some("STRATEGY_UPDATE"), false) => {
+            FuturesWebsocketEvent::StrategyUpdate(from_str(payload)?)
+        }
+        (Some("GRID_UPDATE"), false) => FuturesWebsocketEvent::GridUpdate(from_str(payload)?),
+        (Some("CONDITIONAL_ORDER_TRIGGER_REJECT"), false) => {
+            FuturesWebsocketEvent::ConditionalOrderTriggerReject(from_str(payload)?)
+        }
+        (Some("listenKeyExpired"), false) => {
+            FuturesWebsocketEvent::UserDataStreamExpiredEvent(from_str(payload)?)
+        }
+        // depth snapshots have no event type
+        (None, false) if frame.stream.is_none() || frame.stream_has("@depth") => {
+            FuturesWebsocketEvent::OrderBook(from_str(payload)?)
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(event))
+}
@@ -1,299 +1,782 @@
 use crate::commons::config::Config;
-use crate::commons::errors::BinanceContentError;
-use crate::commons::errors::BinanceError;
-use crate::rest::api::Binance;
+use crate::commons::errors::*;
+use crate::commons::workers::{sleep, Workers};
+use crate::rest::futures::account::PositionSide;
 use crate::rest::futures::model::OrderUpdate;
 use crate::rest::model::{
-    AggrTradesEvent, EventBalance, EventPosition, IndexPriceEvent, LiquidationOrder,
+    AggrTradesEvent, BookTickerEvent, EventBalance, EventPosition, IndexPriceEvent,
+    LiquidationOrder,
 };
-use crate::websocket::futures::usdm_data::WsData;
-use crate::websocket::futures::userstream::FuturesUserStream;
-use crate::websocket::futures::{FuturesMarket, FuturesWebSockets, FuturesWebsocketEvent};
+use crate::websocket::candles::{Candle, CandleInterval};
+use crate::websocket::channel::{EventReceiver, OverflowPolicy};
+use crate::websocket::feed::{FeedMonitor, LatencyStats, StaleAction};
+use crate::websocket::futures::usdm_data::{WsData, WsDataConfig};
+use crate::websocket::futures::usdm_events::{order_events, Observers, UsdmEvent, UsdmEventKind};
+use crate::websocket::futures::user_stream_runner::UserStreamRunner;
+use crate::websocket::futures::{FuturesWebSockets, FuturesWebsocketEvent};
+use crate::websocket::managed::{ConnectionEvent, ManagedWebSockets};
+use crate::websocket::subscription::StreamController;
+use indexmap::IndexMap;
 use log::{debug, error, warn};
 use std::collections::VecDeque;
-use std::sync::atomic::AtomicBool;
-use std::sync::mpsc;
-use std::sync::mpsc::Receiver;
+use std::sync::{Arc, RwLock};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+const THRESHOLD_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(10);
+pub const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(30);
+// reason of the account updates of funding fees
+const FUNDING_FEE: &str = "FUNDING_FEE";
+
+// uppercase symbol to its data, the first symbol backs the single symbol getters
+type SymbolsWs = Arc<RwLock<IndexMap<String, WsData>>>;
+type ControllerWs = Arc<RwLock<Option<StreamController>>>;
+type UserStreamWs = Arc<RwLock<UserStreamStatus>>;
+
+#[derive(Debug, Default)]
+struct UserStreamStatus {
+    connected: bool,
+    last_error: Option<String>,
+}
 
 #[derive(Clone)]
 pub struct WsInterface {
-    ws_data: WsData,
+    symbols: SymbolsWs,
+    controller: ControllerWs,
+    observers: Observers,
+    user_stream: UserStreamWs,
+    workers: Workers,
+    data_config: WsDataConfig,
 }
 
 impl WsInterface {
     /// Binance USDM futures interface,
-    /// subscribes to @aggTrade, @markPrice@1s and @forceOrder and user data stream
+    /// subscribes to @aggTrade, @markPrice@1s, @forceOrder and @bookTicker and user data stream
     /// * `symbol` - String
     /// * `api_key` - Option<String>
     /// * `api_secret` - Option<String>
     /// * `config` - Config
+    ///
+    /// Returns a `Timeout` error if no mark price is received, or the user stream
+    /// does not connect with an `api_key`, within `DEFAULT_READY_TIMEOUT`
     pub fn new(
         symbol: String,
         api_key: Option<String>,
         api_secret: Option<String>,
         config: &Config,
-    ) -> WsInterface {
-        let ws_data = WsData::default();
-        user_stream_websocket(
-            symbol.to_lowercase(),
+    ) -> Result<WsInterface> {
+        WsInterface::new_multi(
+            vec![symbol],
             api_key,
             api_secret,
-            config.to_owned(),
-            ws_data.clone(),
-        );
-        market_websocket(symbol, config.to_owned(), ws_data.clone());
+            config,
+            DEFAULT_READY_TIMEOUT,
+        )
+    }
+
+    /// Binance USDM futures interface for several symbols,
+    /// with one combined market stream and one user data stream
+    /// * `symbols` - at least one symbol, the first one backs the single symbol getters
+    /// * `api_key` - Option<String>
+    /// * `api_secret` - Option<String>
+    /// * `config` - Config
+    /// * `ready_timeout` - time to receive a mark price of every symbol and,
+    ///   with an `api_key`, to connect the user stream
+    pub fn new_multi(
+        symbols: Vec<String>,
+        api_key: Option<String>,
+        api_secret: Option<String>,
+        config: &Config,
+        ready_timeout: Duration,
+    ) -> Result<WsInterface> {
+        WsInterface::with_data_config(
+            symbols,
+            api_key,
+            api_secret,
+            config,
+            ready_timeout,
+            WsDataConfig::default(),
+        )
+    }
+
+    /// Binance USDM futures interface for several symbols, keeping the events of the
+    /// streams of every symbol as set in `data_config`
+    pub fn with_data_config(
+        symbols: Vec<String>,
+        api_key: Option<String>,
+        api_secret: Option<String>,
+        config: &Config,
+        ready_timeout: Duration,
+        data_config: WsDataConfig,
+    ) -> Result<WsInterface> {
         let ws_int = WsInterface {
-            ws_data: ws_data.clone(),
+            symbols: Arc::new(RwLock::new(
+                symbols
+                    .into_iter()
+                    .map(|symbol| (symbol.to_uppercase(), WsData::new(data_config)))
+                    .collect(),
+            )),
+            controller: Arc::new(RwLock::new(None)),
+            observers: Observers::default(),
+            user_stream: Arc::new(RwLock::new(UserStreamStatus::default())),
+            workers: Workers::default(),
+            data_config,
         };
-        ws_int.wait_for_data();
-        fill_mark_price_snaps(ws_data);
-        ws_int
+        let requires_user_stream = api_key.is_some();
+        user_stream_websocket(ws_int.clone(), api_key, api_secret, config.to_owned());
+        market_websocket(ws_int.clone(), config.to_owned());
+        if let Err(e) = ws_int.wait_for_data(ready_timeout, requires_user_stream) {
+            ws_int.shutdown();
+            return Err(e);
+        }
+        fill_mark_price_snaps(ws_int.clone());
+        check_feed_thresholds(ws_int.clone());
+        Ok(ws_int)
     }
 
-    fn wait_for_data(&self) {
+    fn wait_for_data(&self, timeout: Duration, requires_user_stream: bool) -> Result<()> {
         debug!("Waiting for data");
-        while self.ws_data.get_mark_price_event().is_none() {
-            thread::yield_now();
+        let deadline = Instant::now() + timeout;
+        loop {
+            let missing = self
+                .symbols
+                .read()
+                .unwrap()
+                .iter()
+                .find(|(_, ws_data)| ws_data.get_mark_price_event().is_none())
+                .map(|(symbol, _)| symbol.to_owned());
+            let user_stream = self.user_stream.read().unwrap();
+            let user_stream_ready = !requires_user_stream || user_stream.connected;
+            if missing.is_none() && user_stream_ready {
+                break;
+            }
+            if Instant::now() >= deadline {
+                return Err(BinanceError::Timeout(match missing {
+                    Some(symbol) => format!("No mark price of {symbol} after {timeout:?}"),
+                    None => format!(
+                        "User stream not connected after {timeout:?}: {}",
+                        user_stream.last_error.as_deref().unwrap_or("no response")
+                    ),
+                }));
+            }
+            drop(user_stream);
+            thread::sleep(READY_POLL_INTERVAL);
         }
         debug!("Finished waiting for data");
+        Ok(())
+    }
+
+    /// Stops the websockets and their threads and closes the listen key, blocks until done.
+    /// The data received so far stays readable
+    pub fn shutdown(&self) {
+        self.workers.shutdown();
+    }
+
+    /// Subscribes to the market streams of `symbol`, its data is filled as events arrive
+    pub fn add_symbol(&self, symbol: &str) -> Result<()> {
+        let symbol = symbol.to_uppercase();
+        if self.symbols.read().unwrap().contains_key(&symbol) {
+            return Ok(());
+        }
+        let ws_data = WsData::new(self.data_config);
+        let controller = self.controller.read().unwrap().clone();
+        if let Some(ref controller) = controller {
+            ws_data
+                .get_feed_monitor()
+                .set_stream_controller(controller.clone());
+        }
+        self.symbols
+            .write()
+            .unwrap()
+            .insert(symbol.to_owned(), ws_data);
+        // without a controller the market websocket subscribes to it when it starts
+        if let Some(controller) = controller {
+            controller.subscribe(&market_streams(&symbol))?;
+        }
+        Ok(())
+    }
+
+    /// Unsubscribes from the market streams of `symbol` and drops its data
+    pub fn remove_symbol(&self, symbol: &str) -> Result<()> {
+        let symbol = symbol.to_uppercase();
+        if self
+            .symbols
+            .write()
+            .unwrap()
+            .shift_remove(&symbol)
+            .is_none()
+        {
+            return Err(BinanceError::SymbolNotFound);
+        }
+        if let Some(ref controller) = *self.controller.read().unwrap() {
+            controller.unsubscribe(&market_streams(&symbol))?;
+        }
+        Ok(())
+    }
+
+    /// Get symbols, uppercase
+    pub fn get_symbols(&self) -> Vec<String> {
+        self.symbols.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Get the data of one symbol
+    pub fn get_symbol_data(&self, symbol: &str) -> Option<WsData> {
+        self.symbols
+            .read()
+            .unwrap()
+            .get(&symbol.to_uppercase())
+            .cloned()
+    }
+
+    fn get_symbols_data(&self) -> Vec<WsData> {
+        self.symbols.read().unwrap().values().cloned().collect()
+    }
+
+    // data of the first symbol
+    fn ws_data(&self) -> WsData {
+        self.symbols
+            .read()
+            .unwrap()
+            .first()
+            .map(|(_, ws_data)| ws_data.clone())
+            .unwrap_or_default()
+    }
+
+    fn symbol(&self) -> String {
+        self.symbols
+            .read()
+            .unwrap()
+            .first()
+            .map(|(symbol, _)| symbol.to_owned())
+            .unwrap_or_default()
+    }
+
+    // calls `f` with the data of `symbol` if it is still subscribed,
+    // observers are notified after the lock is released
+    fn with_symbol<R, F: FnOnce(&WsData) -> R>(&self, symbol: &str, f: F) -> Option<R> {
+        self.symbols.read().unwrap().get(symbol).map(f)
     }
 
     /// Get mark price
     pub fn get_mark_price(&self) -> Option<IndexPriceEvent> {
-        self.ws_data.get_mark_price_event()
+        self.ws_data().get_mark_price_event()
     }
 
     /// Get mark price snaps
     pub fn get_mark_price_snaps(&self) -> VecDeque<IndexPriceEvent> {
-        self.ws_data.get_mark_price_event_snaps()
+        self.ws_data().get_mark_price_event_snaps()
+    }
+
+    /// Get mark price snaps since `time`, milliseconds
+    pub fn get_mark_price_snaps_since(&self, time: u64) -> Vec<IndexPriceEvent> {
+        self.ws_data().get_mark_price_event_snaps_since(time)
+    }
+
+    /// Get best bid and ask
+    pub fn get_book_ticker(&self) -> Option<BookTickerEvent> {
+        self.ws_data().get_book_ticker()
     }
 
     /// Get aggr_trades
     pub fn get_aggr_trades(&self) -> VecDeque<AggrTradesEvent> {
-        self.ws_data.get_aggr_trades()
+        self.ws_data().get_aggr_trades()
+    }
+
+    /// Get aggr_trades since `time`, milliseconds
+    pub fn get_aggr_trades_since(&self, time: u64) -> Vec<AggrTradesEvent> {
+        self.ws_data().get_aggr_trades_since(time)
+    }
+
+    /// Get aggr_trades of the last `window`
+    pub fn get_aggr_trades_in_last(&self, window: Duration) -> Vec<AggrTradesEvent> {
+        self.ws_data().get_aggr_trades_in_last(window)
+    }
+
+    /// Get candles of `interval` built from aggr_trades
+    pub fn get_candles(&self, interval: CandleInterval) -> VecDeque<Candle> {
+        self.ws_data().get_candles(interval)
+    }
+
+    /// Get candle of `interval` in progress
+    pub fn get_current_candle(&self, interval: CandleInterval) -> Option<Candle> {
+        self.ws_data().get_current_candle(interval)
     }
 
     /// Get liquidations
     pub fn get_liquidations(&self) -> VecDeque<LiquidationOrder> {
-        self.ws_data.get_liquidations()
+        self.ws_data().get_liquidations()
+    }
+
+    /// Get liquidations since `time`, milliseconds
+    pub fn get_liquidations_since(&self, time: u64) -> Vec<LiquidationOrder> {
+        self.ws_data().get_liquidations_since(time)
+    }
+
+    /// Get liquidations of the last `window`
+    pub fn get_liquidations_in_last(&self, window: Duration) -> Vec<LiquidationOrder> {
+        self.ws_data().get_liquidations_in_last(window)
     }
 
     /// Get position
     pub fn get_position(&self) -> Option<EventPosition> {
-        self.ws_data.get_position_event()
+        self.ws_data().get_position_event()
+    }
+
+    /// Get position of one side
+    pub fn get_position_side(&self, position_side: PositionSide) -> Option<EventPosition> {
+        self.ws_data().get_position_side_event(position_side)
+    }
+
+    /// Get positions of every side
+    pub fn get_positions(&self) -> Vec<EventPosition> {
+        self.ws_data().get_positions_event()
     }
 
     /// Get balance
     pub fn get_balance(&self) -> Option<EventBalance> {
-        self.ws_data.get_balance_event()
+        self.ws_data().get_balance_event()
     }
 
     /// Get open orders
     pub fn get_open_orders(&self) -> VecDeque<OrderUpdate> {
-        self.ws_data.get_open_orders()
+        self.ws_data().get_open_orders()
     }
 
     /// Get filled orders
     pub fn get_filled_orders(&self) -> VecDeque<OrderUpdate> {
-        self.ws_data.get_filled_orders()
+        self.ws_data().get_filled_orders()
     }
 
     /// Get canceled orders
     pub fn get_canceled_orders(&self) -> VecDeque<OrderUpdate> {
-        self.ws_data.get_canceled_orders()
+        self.ws_data().get_canceled_orders()
     }
 
-    /// Get order
+    /// Get receive times and latencies of the market streams
+    pub fn get_feed_monitor(&self) -> FeedMonitor {
+        self.ws_data().get_feed_monitor().clone()
+    }
+
+    /// True if no mark price was received for `max_age`, mark prices are pushed every second
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        self.get_feed_monitor()
+            .is_stale(&self.mark_price_stream(), max_age)
+    }
+
+    /// Get exchange to local latency of mark prices
+    pub fn get_mark_price_latency(&self) -> Option<LatencyStats> {
+        self.get_feed_monitor()
+            .get_latency(&self.mark_price_stream())
+    }
+
+    /// Get exchange to local latency of aggr_trades
+    pub fn get_aggr_trades_latency(&self) -> Option<LatencyStats> {
+        let [aggr_trades_stream, _, _, _] = market_streams(&self.symbol());
+        self.get_feed_monitor().get_latency(&aggr_trades_stream)
+    }
+
+    /// Reconnects the market websocket when no mark price is received for `max_age`
+    pub fn reconnect_on_stale(&self, max_age: Duration) {
+        self.get_feed_monitor().add_threshold(
+            &self.mark_price_stream(),
+            max_age,
+            StaleAction::Reconnect,
+        );
+    }
+
+    /// Calls `callback` with the stream and its staleness when no mark price
+    /// is received for `max_age`
+    pub fn on_stale<F>(&self, max_age: Duration, callback: F)
+    where
+        F: Fn(&str, Duration) + Send + Sync + 'static,
+    {
+        self.get_feed_monitor().add_threshold(
+            &self.mark_price_stream(),
+            max_age,
+            StaleAction::Callback(Arc::new(callback)),
+        );
+    }
+
+    fn mark_price_stream(&self) -> String {
+        let [_, mark_price_stream, _, _] = market_streams(&self.symbol());
+        mark_price_stream
+    }
+
+    /// Calls `callback` on the websocket thread after the data is updated,
+    /// `kinds` empty for every kind. Returns the observer id
+    pub fn on_event<F>(&self, kinds: &[UsdmEventKind], callback: F) -> u64
+    where
+        F: Fn(&UsdmEvent) + Send + Sync + 'static,
+    {
+        self.observers.on_event(kinds, callback)
+    }
+
+    /// Channel of the events of `kinds` after the data is updated, `kinds` empty for every kind.
+    /// See `Observers::subscribe` for `capacity` and `overflow`
+    pub fn subscribe(
+        &self,
+        kinds: &[UsdmEventKind],
+        capacity: usize,
+        overflow: OverflowPolicy,
+    ) -> EventReceiver<UsdmEvent> {
+        self.observers.subscribe(kinds, capacity, overflow)
+    }
+
+    /// Removes the observer added with `on_event`
+    pub fn remove_observer(&self, id: u64) -> bool {
+        self.observers.remove(id)
+    }
+
+    // stores an order update and notifies its events, ignored for a symbol not subscribed
+    pub(crate) fn apply_order_update(&self, order: OrderUpdate) {
+        let symbol = order.symbol.to_owned();
+        let events = self.with_symbol(&symbol, |ws_data| {
+            let order_id = order.order_id;
+            let previous = ws_data
+                .get_open_order(order_id)
+                .or_else(|| ws_data.get_filled_order(order_id))
+                .or_else(|| ws_data.get_canceled_order(order_id));
+            let events = order_events(previous.as_ref(), &order);
+            ws_data.add_order(order);
+            events
+        });
+        for event in events.unwrap_or_default() {
+            self.observers.notify(event);
+        }
+    }
+
+    pub(crate) fn apply_position(&self, position: EventPosition) {
+        let symbol = position.symbol.to_owned();
+        if let Some(position) = self.with_symbol(&symbol, |ws_data| {
+            ws_data.update_position(position.clone());
+            position
+        }) {
+            self.observers.notify(UsdmEvent::Position(position));
+        }
+    }
+
+    // stores the balance of the margin asset of every symbol, notifies each changed asset once
+    pub(crate) fn apply_balances(&self, account_balances: &[EventBalance]) {
+        let mut balances: Vec<EventBalance> = vec![];
+        for (symbol, ws_data) in self.symbols.read().unwrap().iter() {
+            let assets = balance_assets(symbol);
+            if let Some(balance) = account_balances
+                .iter()
+                .find(|event| assets.contains(&event.asset.as_str()))
+            {
+                let changed = ws_data
+                    .get_balance_event()
+                    .is_none_or(|previous| !same_balance(&previous, balance));
+                ws_data.update_balance(balance.to_owned());
+                if changed && !balances.iter().any(|event| event.asset == balance.asset) {
+                    balances.push(balance.to_owned());
+                }
+            }
+        }
+        for balance in balances {
+            self.observers.notify(UsdmEvent::Balance(balance));
+        }
+    }
+
+    pub(crate) fn remove_open_order(&self, symbol: &str, order_id: u64) {
+        self.with_symbol(symbol, |ws_data| ws_data.remove_open_order(order_id));
+    }
+
+    pub(crate) fn notify(&self, event: UsdmEvent) {
+        self.observers.notify(event);
+    }
+
+    /// Get order of any symbol
     ///
     /// * `order_id` - id of order
     pub fn get_order(&self, order_id: u64) -> Option<OrderUpdate> {
-        if let Some(order) = self.ws_data.get_open_order(order_id) {
-            return Some(order);
-        } else if let Some(order) = self.ws_data.get_filled_order(order_id) {
-            return Some(order);
-        } else if let Some(order) = self.ws_data.get_canceled_order(order_id) {
-            return Some(order);
-        }
-        None
+        self.get_symbols_data().iter().find_map(|ws_data| {
+            ws_data
+                .get_open_order(order_id)
+                .or_else(|| ws_data.get_filled_order(order_id))
+                .or_else(|| ws_data.get_canceled_order(order_id))
+        })
+    }
+
+    /// Returns true if the order of any symbol is open
+    pub fn is_open_order(&self, order_id: u64) -> bool {
+        self.get_symbols_data()
+            .iter()
+            .any(|ws_data| ws_data.get_open_order(order_id).is_some())
+    }
+
+    /// Returns true if the order of any symbol is filled
+    pub fn is_filled_order(&self, order_id: u64) -> bool {
+        self.get_symbols_data()
+            .iter()
+            .any(|ws_data| ws_data.get_filled_order(order_id).is_some())
+    }
+
+    /// Returns true if the order of any symbol is canceled or expired
+    pub fn is_canceled_order(&self, order_id: u64) -> bool {
+        self.get_symbols_data()
+            .iter()
+            .any(|ws_data| ws_data.get_canceled_order(order_id).is_some())
     }
 }
 
 fn user_stream_websocket(
-    symbol: String,
+    ws_int: WsInterface,
     api_key: Option<String>,
     api_secret: Option<String>,
     config: Config,
-    ws_data: WsData,
 ) {
-    thread::spawn(move || {
-        loop {
-            let user_stream: FuturesUserStream =
-                Binance::new_with_config(api_key.to_owned(), api_secret.to_owned(), &config);
-            let keep_running = AtomicBool::new(true); // Used to control the event loop
-
-            if let Ok(answer) = user_stream.start() {
-                let listen_key = answer.listen_key;
-                let (tx, rx) = mpsc::channel();
-
-                // launches thread to keep alive user streamn
-                user_stream_keep_alive(rx, user_stream.to_owned(), listen_key.to_owned());
-
-                let mut web_socket: FuturesWebSockets<'_> =
-                    FuturesWebSockets::new(|event: FuturesWebsocketEvent| {
-                        match event {
-                            FuturesWebsocketEvent::AccountUpdate(account_update) => {
-                                debug!("Received AccountUpdateEvent : {account_update:?}");
-                                let positions: Vec<EventPosition> = account_update
-                                    .data
-                                    .positions
-                                    .into_iter()
-                                    .filter(|event| event.symbol.to_lowercase() == symbol)
-                                    .collect();
-                                if !positions.is_empty() {
-                                    ws_data.update_position(positions[0].to_owned())
-                                }
-
-                                let mut assets = vec!["BNFCR"];
-                                if symbol.contains("USDC") {
-                                    assets.push("USDC");
-                                } else {
-                                    assets.push("USDT");
-                                };
-
-                                let balances: Vec<EventBalance> = account_update
-                                    .data
-                                    .balances
-                                    .into_iter()
-                                    .filter(|event| assets.contains(&event.asset.as_str()))
-                                    .collect();
-                                if !balances.is_empty() {
-                                    ws_data.update_balance(balances[0].to_owned())
-                                }
-                            }
-                            FuturesWebsocketEvent::OrderTrade(trade) => {
-                                debug!("Received OrderTradeEvent : {trade:?}");
-                                ws_data.add_order(trade.order);
-                            }
-                            FuturesWebsocketEvent::UserDataStreamExpiredEvent(
-                                user_stream_expired,
-                            ) => {
-                                debug!(
-                                    "Received UserDataStreamExpiredEvent : {user_stream_expired:?}"
-                                );
-                                let err = BinanceContentError {
-                                    code: -9999,
-                                    msg: "User data listen key is expired".to_string(),
-                                };
-                                return Err(BinanceError::BinanceError { response: err });
-                            }
-                            _ => {
-                                warn!("Received unhandled event : {event:?}")
-                            }
+    let workers = ws_int.workers.clone();
+    workers.spawn(move |running| {
+        let user_stream = ws_int.user_stream.clone();
+        let mut runner = UserStreamRunner::new(
+            api_key,
+            api_secret,
+            &config,
+            |event: FuturesWebsocketEvent| {
+                match event {
+                    FuturesWebsocketEvent::AccountUpdate(account_update) => {
+                        debug!("Received AccountUpdateEvent : {account_update:?}");
+                        let funding_fees = if account_update.data.reason == FUNDING_FEE {
+                            let symbol = account_update
+                                .data
+                                .positions
+                                .first()
+                                .map(|position| position.symbol.to_owned());
+                            account_update
+                                .data
+                                .balances
+                                .iter()
+                                .map(|balance| UsdmEvent::FundingFee {
+                                    symbol: symbol.to_owned(),
+                                    balance: balance.to_owned(),
+                                    event_time: account_update.event_time,
+                                })
+                                .collect()
+                        } else {
+                            vec![]
                         };
+                        // in hedge mode LONG and SHORT legs are both reported
+                        for position in account_update.data.positions {
+                            ws_int.apply_position(position);
+                        }
 
-                        Ok(())
-                    });
+                        ws_int.apply_balances(&account_update.data.balances);
+                        for funding_fee in funding_fees {
+                            ws_int.observers.notify(funding_fee);
+                        }
+                    }
+                    FuturesWebsocketEvent::OrderTrade(trade) => {
+                        debug!("Received OrderTradeEvent : {trade:?}");
+                        ws_int.apply_order_update(trade.order);
+                    }
+                    FuturesWebsocketEvent::UserDataStreamExpiredEvent(user_stream_expired) => {
+                        // the runner starts a new listen key
+                        debug!("Received UserDataStreamExpiredEvent : {user_stream_expired:?}");
+                    }
+                    FuturesWebsocketEvent::AccountConfigUpdate(_)
+                    | FuturesWebsocketEvent::MarginCall(_)
+                    | FuturesWebsocketEvent::StrategyUpdate(_)
+                    | FuturesWebsocketEvent::GridUpdate(_)
+                    | FuturesWebsocketEvent::ConditionalOrderTriggerReject(_) => {
+                        debug!("Received user data event : {event:?}");
+                    }
+                    _ => {
+                        warn!("Received unhandled event : {event:?}")
+                    }
+                };
 
-                web_socket
-                    .connect_with_config(FuturesMarket::USDM, &listen_key, &config)
-                    .unwrap(); // check error
-                if let Err(e) = web_socket.event_loop(&keep_running) {
-                    error!("Error: {e}");
-                }
-                if let Err(e) = user_stream.close(&listen_key) {
-                    error!("Error closing user stream: {e}");
+                Ok(())
+            },
+        )
+        .set_connection_handler(|event| {
+            debug!("User stream: {event:?}");
+            match event {
+                ConnectionEvent::Connected => user_stream.write().unwrap().connected = true,
+                ConnectionEvent::Disconnected(reason) => {
+                    user_stream.write().unwrap().last_error = Some(reason)
                 }
-                if let Err(e) = web_socket.disconnect() {
-                    error!("Error disconnecting from websocket: {e}");
+                ConnectionEvent::Gap { downtime } => {
+                    ws_int.notify(UsdmEvent::Reconnected { downtime })
                 }
-                let _ = tx.send(());
-                debug!("User stream closed and disconnected");
-            } else {
-                panic!("Not able to start an User Stream (Check your API_KEY)");
+                _ => {}
             }
+        });
+        if let Err(e) = runner.run(running) {
+            error!("Error: {e}");
         }
+        debug!("User stream closed and disconnected");
     });
 }
 
-fn user_stream_keep_alive(rx: Receiver<()>, user_stream: FuturesUserStream, listen_key: String) {
-    thread::spawn(move || {
-        loop {
-            // Keepalive a user data stream to prevent a time out. User data streams will close after 60 minutes. Loops every 50 minutes
-            thread::sleep(Duration::from_secs(3000));
-
-            if rx.recv_timeout(Duration::from_millis(300)).is_ok() {
-                debug!("Terminating.");
-                break;
-            }
+// assets of the balance reported for `symbol`, in order of preference
+fn balance_assets(symbol: &str) -> [&'static str; 2] {
+    if symbol.ends_with("USDC") {
+        ["BNFCR", "USDC"]
+    } else {
+        ["BNFCR", "USDT"]
+    }
+}
 
-            match user_stream.keep_alive(&listen_key) {
-                Ok(msg) => debug!("Keepalive user data stream: {msg:?}"),
-                Err(e) => warn!("Error: {e}"),
-            }
-        }
-    });
+// the balance change of an update is not compared, an update repeating the wallet is no change
+fn same_balance(previous: &EventBalance, balance: &EventBalance) -> bool {
+    previous.asset == balance.asset
+        && previous.wallet_balance == balance.wallet_balance
+        && previous.cross_wallet_balance == balance.cross_wallet_balance
 }
 
-fn market_websocket(symbol: String, config: Config, ws_data: WsData) {
-    thread::spawn(move || {
-        loop {
-            let keep_running = AtomicBool::new(true);
-            let streams = vec![
-                // taken from https://binance-docs.github.io/apidocs/futures/en/#websocket-market-streams
-                symbol.to_owned().to_lowercase() + "@aggTrade",
-                symbol.to_owned().to_lowercase() + "@markPrice@1s",
-                symbol.to_owned().to_lowercase() + "@forceOrder",
-            ];
-
-            let mut web_socket: FuturesWebSockets<'_> =
-                FuturesWebSockets::new(|event: FuturesWebsocketEvent| {
-                    match event {
-                        FuturesWebsocketEvent::AggrTrades(trade) => {
-                            debug!("Received AggrTradesEvent : {trade:?}");
-                            ws_data.add_aggr_trades(trade);
+fn market_websocket(ws_int: WsInterface, config: Config) {
+    let workers = ws_int.workers.clone();
+    workers.spawn(move |running| {
+        let streams: Vec<String> = ws_int
+            .get_symbols()
+            .iter()
+            .flat_map(|symbol| market_streams(symbol))
+            .collect();
+
+        let web_socket: FuturesWebSockets<'_> =
+            FuturesWebSockets::new(|event: FuturesWebsocketEvent| {
+                match event {
+                    FuturesWebsocketEvent::AggrTrades(trade) => {
+                        debug!("Received AggrTradesEvent : {trade:?}");
+                        let symbol = trade.symbol.to_owned();
+                        let event = ws_int.with_symbol(&symbol, |ws_data| {
+                            let [aggr_trades_stream, _, _, _] = market_streams(&symbol);
+                            ws_data
+                                .get_feed_monitor()
+                                .record(&aggr_trades_stream, trade.event_time);
+                            ws_data.add_aggr_trades(trade.clone());
+                            trade
+                        });
+                        if let Some(event) = event {
+                            ws_int.observers.notify(UsdmEvent::AggrTrade(event));
                         }
-                        FuturesWebsocketEvent::IndexPrice(mark_price) => {
-                            debug!("Received IndexPrice : {mark_price:?}");
-                            ws_data.update_mark_price(mark_price);
+                    }
+                    FuturesWebsocketEvent::MarkPrice(mark_price) => {
+                        debug!("Received MarkPrice : {mark_price:?}");
+                        let symbol = mark_price.symbol.to_owned();
+                        let event = ws_int.with_symbol(&symbol, |ws_data| {
+                            let [_, mark_price_stream, _, _] = market_streams(&symbol);
+                            ws_data
+                                .get_feed_monitor()
+                                .record(&mark_price_stream, mark_price.event_time);
+                            let event = IndexPriceEvent {
+                                event_type: mark_price.event_type,
+                                event_time: mark_price.event_time,
+                                pair: mark_price.symbol,
+                                price: mark_price.mark_price,
+                            };
+                            ws_data.update_mark_price(event.clone());
+                            if ws_int.data_config.mark_price_snap_interval.is_zero() {
+                                ws_data.add_mark_price_snap(event.clone());
+                            }
+                            event
+                        });
+                        if let Some(event) = event {
+                            ws_int.observers.notify(UsdmEvent::MarkPrice(event));
                         }
-                        FuturesWebsocketEvent::Liquidation(liquidation) => {
-                            debug!("Received LiquidationEvent : {liquidation:?}");
-                            ws_data.add_liquidation(liquidation.liquidation_order);
+                    }
+                    FuturesWebsocketEvent::Liquidation(liquidation) => {
+                        debug!("Received LiquidationEvent : {liquidation:?}");
+                        let symbol = liquidation.liquidation_order.symbol.to_owned();
+                        let event = ws_int.with_symbol(&symbol, |ws_data| {
+                            let [_, _, liquidations_stream, _] = market_streams(&symbol);
+                            ws_data
+                                .get_feed_monitor()
+                                .record(&liquidations_stream, liquidation.event_time);
+                            ws_data.add_liquidation(liquidation.liquidation_order.clone());
+                            liquidation.liquidation_order
+                        });
+                        if let Some(event) = event {
+                            ws_int.observers.notify(UsdmEvent::Liquidation(event));
                         }
-                        _ => {
-                            warn!("Received unhandled event : {event:?}")
+                    }
+                    FuturesWebsocketEvent::BookTicker(book_ticker) => {
+                        debug!("Received BookTickerEvent : {book_ticker:?}");
+                        let symbol = book_ticker.symbol.to_owned();
+                        let event = ws_int.with_symbol(&symbol, |ws_data| {
+                            ws_data.update_book_ticker(book_ticker.clone());
+                            book_ticker
+                        });
+                        if let Some(event) = event {
+                            ws_int.observers.notify(UsdmEvent::BookTicker(event));
                         }
-                    };
-
-                    Ok(())
-                });
-            web_socket
-                .connect_multiple_streams(FuturesMarket::USDM, &streams, &config)
-                .unwrap(); // check error
-            if let Err(e) = web_socket.event_loop(&keep_running) {
-                error!("Error: {e}");
-            }
-            if let Err(e) = web_socket.disconnect() {
-                error!("Error disconnecting from websocket: {e}");
-            }
-            debug!("Market websocket disconnected");
+                    }
+                    FuturesWebsocketEvent::StreamResponse(response) => {
+                        debug!("Received StreamResponse : {response:?}");
+                    }
+                    _ => {
+                        warn!("Received unhandled event : {event:?}")
+                    }
+                };
+
+                Ok(())
+            });
+        let mut web_socket = ManagedWebSockets::new(web_socket, streams, &config)
+            .set_connection_handler(|event| debug!("Market websocket: {event:?}"));
+        // lets a stale feed reconnect without waiting for a message
+        if let Err(e) = web_socket.socket().set_read_timeout(Some(READ_TIMEOUT)) {
+            warn!("Unable to set the read timeout: {e}");
+        }
+        let controller = web_socket.socket().stream_controller();
+        for ws_data in ws_int.get_symbols_data() {
+            ws_data
+                .get_feed_monitor()
+                .set_stream_controller(controller.clone());
         }
+        *ws_int.controller.write().unwrap() = Some(controller);
+        if let Err(e) = web_socket.event_loop(running) {
+            error!("Error: {e}");
+        }
+        debug!("Market websocket disconnected");
     });
 }
 
-fn fill_mark_price_snaps(ws_data: WsData) {
-    thread::spawn(move || loop {
-        match ws_data.get_mark_price_event() {
-            Some(index_price) => {
-                ws_data.add_mark_price_snap(index_price.clone());
-                debug!("Added mark price snap {index_price:?}");
-                thread::sleep(Duration::from_millis(5000));
+// taken from https://binance-docs.github.io/apidocs/futures/en/#websocket-market-streams
+fn market_streams(symbol: &str) -> [String; 4] {
+    let symbol = symbol.to_lowercase();
+    [
+        format!("{symbol}@aggTrade"),
+        format!("{symbol}@markPrice@1s"),
+        format!("{symbol}@forceOrder"),
+        format!("{symbol}@bookTicker"),
+    ]
+}
+
+fn check_feed_thresholds(ws_int: WsInterface) {
+    let workers = ws_int.workers.clone();
+    workers.spawn(move |running| {
+        while sleep(running, THRESHOLD_CHECK_INTERVAL) {
+            for ws_data in ws_int.get_symbols_data() {
+                ws_data.get_feed_monitor().check_thresholds();
             }
-            None => {
-                warn!("Unable to add mark price snap")
+        }
+    });
+}
+
+// samples the mark prices, every one is stored as it arrives with no interval
+fn fill_mark_price_snaps(ws_int: WsInterface) {
+    let interval = ws_int.data_config.mark_price_snap_interval;
+    if interval.is_zero() {
+        return;
+    }
+    let workers = ws_int.workers.clone();
+    workers.spawn(move |running| loop {
+        for (symbol, ws_data) in ws_int.symbols.read().unwrap().iter() {
+            match ws_data.get_mark_price_event() {
+                Some(index_price) => {
+                    ws_data.add_mark_price_snap(index_price.clone());
+                    debug!("Added mark price snap {index_price:?}");
+                }
+                None => {
+                    warn!("Unable to add mark price snap of {symbol}")
+                }
             }
         }
+        if !sleep(running, interval) {
+            break;
+        }
     });
 }
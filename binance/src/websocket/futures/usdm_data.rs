@@ -1,32 +1,146 @@
+use crate::commons::errors::*;
+use crate::rest::futures::account::PositionSide;
 use crate::rest::futures::model::OrderUpdate;
 use crate::rest::model::{
-    AggrTradesEvent, EventBalance, EventPosition, IndexPriceEvent, LiquidationOrder,
+    AggrTradesEvent, BookTickerEvent, EventBalance, EventPosition, IndexPriceEvent, KlineSummaries,
+    LiquidationOrder,
 };
+use crate::websocket::candles::{Candle, CandleBuilder, CandleInterval};
+use crate::websocket::feed::FeedMonitor;
 use indexmap::IndexMap;
+use log::warn;
 use std::collections::VecDeque;
 use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 type MarkPriceWs = Arc<RwLock<Option<IndexPriceEvent>>>;
+type BookTickerWs = Arc<RwLock<Option<BookTickerEvent>>>;
 type MarkPriceSnapsWs = Arc<RwLock<VecDeque<IndexPriceEvent>>>;
 type AggrTradesWs = Arc<RwLock<VecDeque<AggrTradesEvent>>>;
 type LiquidationsWs = Arc<RwLock<VecDeque<LiquidationOrder>>>;
-type PositionsWs = Arc<RwLock<Option<EventPosition>>>;
+type PositionsWs = Arc<RwLock<IndexMap<String, EventPosition>>>;
 type BalanceWs = Arc<RwLock<Option<EventBalance>>>;
 type OrdersWs = Arc<RwLock<IndexMap<u64, OrderUpdate>>>;
+type CandlesWs = Arc<RwLock<Vec<CandleBuilder>>>;
 
 const DATA_SIZE: usize = 1000;
+const MARK_PRICE_SNAP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Events of a stream kept in `WsData`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retention {
+    /// The last events, at least one
+    Count(usize),
+    /// The events up to this age from the newest one, by their exchange time.
+    /// Not bounded in count, a burst is kept whole
+    Window(Duration),
+}
+
+/// Retention of the streams of every symbol of a `WsInterface`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WsDataConfig {
+    pub aggr_trades: Retention,
+    pub liquidations: Retention,
+    pub mark_price_snaps: Retention,
+    pub mark_price_snap_interval: Duration,
+    pub orders: Retention,
+    pub candles: usize,
+}
+
+impl Default for WsDataConfig {
+    fn default() -> WsDataConfig {
+        WsDataConfig {
+            aggr_trades: Retention::Count(DATA_SIZE),
+            liquidations: Retention::Count(DATA_SIZE),
+            mark_price_snaps: Retention::Count(DATA_SIZE),
+            mark_price_snap_interval: MARK_PRICE_SNAP_INTERVAL,
+            orders: Retention::Count(DATA_SIZE),
+            candles: DATA_SIZE,
+        }
+    }
+}
+
+impl WsDataConfig {
+    pub fn set_aggr_trades(mut self, aggr_trades: Retention) -> Self {
+        self.aggr_trades = aggr_trades;
+        self
+    }
+
+    pub fn set_liquidations(mut self, liquidations: Retention) -> Self {
+        self.liquidations = liquidations;
+        self
+    }
+
+    /// The mark price is sampled every `mark_price_snap_interval`,
+    /// `Duration::ZERO` keeps every mark price received
+    pub fn set_mark_price_snaps(
+        mut self,
+        mark_price_snaps: Retention,
+        mark_price_snap_interval: Duration,
+    ) -> Self {
+        self.mark_price_snaps = mark_price_snaps;
+        self.mark_price_snap_interval = mark_price_snap_interval;
+        self
+    }
+
+    /// Filled and canceled orders, by their last update. Open orders are only capped
+    /// by a `Count`, they are never dropped for their age
+    pub fn set_orders(mut self, orders: Retention) -> Self {
+        self.orders = orders;
+        self
+    }
+
+    /// Candles kept for every interval, in progress one excluded
+    pub fn set_candles(mut self, candles: usize) -> Self {
+        self.candles = candles;
+        self
+    }
+}
+
+// exchange time of an event, milliseconds
+trait ExchangeTime {
+    fn exchange_time(&self) -> u64;
+}
+
+impl ExchangeTime for AggrTradesEvent {
+    fn exchange_time(&self) -> u64 {
+        self.trade_order_time
+    }
+}
+
+impl ExchangeTime for IndexPriceEvent {
+    fn exchange_time(&self) -> u64 {
+        self.event_time
+    }
+}
+
+impl ExchangeTime for LiquidationOrder {
+    fn exchange_time(&self) -> u64 {
+        self.order_trade_time
+    }
+}
+
+impl ExchangeTime for OrderUpdate {
+    fn exchange_time(&self) -> u64 {
+        self.trade_order_time
+    }
+}
 
 #[derive(Debug)]
 pub struct WsData {
     mark_price: MarkPriceWs,
     mark_price_snaps: MarkPriceSnapsWs,
+    book_ticker: BookTickerWs,
     aggr_trades: AggrTradesWs,
     liquidations: LiquidationsWs,
-    position: PositionsWs,
+    positions: PositionsWs,
     balance: BalanceWs,
     filled_orders: OrdersWs,
     open_orders: OrdersWs,
     canceled_orders: OrdersWs,
+    candles: CandlesWs,
+    feed: FeedMonitor,
+    config: WsDataConfig,
 }
 
 impl Clone for WsData {
@@ -34,34 +148,58 @@ impl Clone for WsData {
         WsData {
             mark_price: Arc::clone(&self.mark_price),
             mark_price_snaps: Arc::clone(&self.mark_price_snaps),
+            book_ticker: Arc::clone(&self.book_ticker),
             aggr_trades: Arc::clone(&self.aggr_trades),
             liquidations: Arc::clone(&self.liquidations),
-            position: Arc::clone(&self.position),
+            positions: Arc::clone(&self.positions),
             balance: Arc::clone(&self.balance),
             filled_orders: Arc::clone(&self.filled_orders),
             open_orders: Arc::clone(&self.open_orders),
             canceled_orders: Arc::clone(&self.canceled_orders),
+            candles: Arc::clone(&self.candles),
+            feed: self.feed.clone(),
+            config: self.config,
         }
     }
 }
 
 impl Default for WsData {
     fn default() -> WsData {
+        WsData::new(WsDataConfig::default())
+    }
+}
+
+impl WsData {
+    pub fn new(config: WsDataConfig) -> WsData {
+        let orders = capacity(config.orders);
         WsData {
             mark_price: Arc::new(RwLock::new(None)),
-            mark_price_snaps: Arc::new(RwLock::new(VecDeque::with_capacity(DATA_SIZE))),
-            aggr_trades: Arc::new(RwLock::new(VecDeque::with_capacity(DATA_SIZE))),
-            liquidations: Arc::new(RwLock::new(VecDeque::with_capacity(DATA_SIZE))),
-            position: Arc::new(RwLock::new(None)),
+            mark_price_snaps: Arc::new(RwLock::new(VecDeque::with_capacity(capacity(
+                config.mark_price_snaps,
+            )))),
+            book_ticker: Arc::new(RwLock::new(None)),
+            aggr_trades: Arc::new(RwLock::new(VecDeque::with_capacity(capacity(
+                config.aggr_trades,
+            )))),
+            liquidations: Arc::new(RwLock::new(VecDeque::with_capacity(capacity(
+                config.liquidations,
+            )))),
+            positions: Arc::new(RwLock::new(IndexMap::new())),
             balance: Arc::new(RwLock::new(None)),
-            filled_orders: Arc::new(RwLock::new(IndexMap::with_capacity(DATA_SIZE))),
-            open_orders: Arc::new(RwLock::new(IndexMap::with_capacity(DATA_SIZE))),
-            canceled_orders: Arc::new(RwLock::new(IndexMap::with_capacity(DATA_SIZE))),
+            filled_orders: Arc::new(RwLock::new(IndexMap::with_capacity(orders))),
+            open_orders: Arc::new(RwLock::new(IndexMap::with_capacity(orders))),
+            canceled_orders: Arc::new(RwLock::new(IndexMap::with_capacity(orders))),
+            candles: Arc::new(RwLock::new(Vec::new())),
+            feed: FeedMonitor::default(),
+            config,
         }
     }
-}
 
-impl WsData {
+    /// Retention of the streams
+    pub fn get_config(&self) -> WsDataConfig {
+        self.config
+    }
+
     pub fn get_mark_price_event(&self) -> Option<IndexPriceEvent> {
         self.mark_price.read().unwrap().clone()
     }
@@ -70,16 +208,88 @@ impl WsData {
         self.mark_price_snaps.read().unwrap().clone()
     }
 
+    /// Mark price snaps since `time`, milliseconds, only those are cloned
+    pub fn get_mark_price_event_snaps_since(&self, time: u64) -> Vec<IndexPriceEvent> {
+        since(&self.mark_price_snaps.read().unwrap(), time)
+    }
+
+    /// Best bid and ask
+    pub fn get_book_ticker(&self) -> Option<BookTickerEvent> {
+        self.book_ticker.read().unwrap().clone()
+    }
+
     pub fn get_aggr_trades(&self) -> VecDeque<AggrTradesEvent> {
         self.aggr_trades.read().unwrap().clone()
     }
 
+    /// Aggregated trades since `time`, milliseconds, only those are cloned
+    pub fn get_aggr_trades_since(&self, time: u64) -> Vec<AggrTradesEvent> {
+        since(&self.aggr_trades.read().unwrap(), time)
+    }
+
+    /// Aggregated trades of the last `window` by the local clock
+    pub fn get_aggr_trades_in_last(&self, window: Duration) -> Vec<AggrTradesEvent> {
+        self.get_aggr_trades_since(window_start(window))
+    }
+
+    /// Id of the last aggregated trade received
+    pub fn get_last_aggr_trade_id(&self) -> Option<u64> {
+        self.aggr_trades
+            .read()
+            .unwrap()
+            .back()
+            .map(|trade| trade.aggregated_trade_id)
+    }
+
+    /// Candles of `interval` built from the aggregated trades, empty if it is not built
+    pub fn get_candles(&self, interval: CandleInterval) -> VecDeque<Candle> {
+        self.candles
+            .read()
+            .unwrap()
+            .iter()
+            .find(|builder| builder.interval() == interval)
+            .map(|builder| builder.get_candles())
+            .unwrap_or_default()
+    }
+
+    /// Candle of `interval` in progress
+    pub fn get_current_candle(&self, interval: CandleInterval) -> Option<Candle> {
+        self.candles
+            .read()
+            .unwrap()
+            .iter()
+            .find(|builder| builder.interval() == interval)
+            .and_then(|builder| builder.get_current_candle())
+    }
+
     pub fn get_liquidations(&self) -> VecDeque<LiquidationOrder> {
         self.liquidations.read().unwrap().clone()
     }
 
+    /// Liquidations since `time`, milliseconds, only those are cloned
+    pub fn get_liquidations_since(&self, time: u64) -> Vec<LiquidationOrder> {
+        since(&self.liquidations.read().unwrap(), time)
+    }
+
+    /// Liquidations of the last `window` by the local clock
+    pub fn get_liquidations_in_last(&self, window: Duration) -> Vec<LiquidationOrder> {
+        self.get_liquidations_since(window_start(window))
+    }
+
     pub fn get_position_event(&self) -> Option<EventPosition> {
-        self.position.read().unwrap().clone()
+        self.get_position_side_event(PositionSide::Both)
+    }
+
+    pub fn get_position_side_event(&self, position_side: PositionSide) -> Option<EventPosition> {
+        self.positions
+            .read()
+            .unwrap()
+            .get(&position_side.to_string())
+            .cloned()
+    }
+
+    pub fn get_positions_event(&self) -> Vec<EventPosition> {
+        self.positions.read().unwrap().values().cloned().collect()
     }
 
     pub fn get_balance_event(&self) -> Option<EventBalance> {
@@ -123,6 +333,11 @@ impl WsData {
         get_order(canceled_orders, order_id)
     }
 
+    /// Receive times and latencies of the market streams
+    pub fn get_feed_monitor(&self) -> &FeedMonitor {
+        &self.feed
+    }
+
     pub fn update_mark_price(&self, event: IndexPriceEvent) {
         let mut mark_price: RwLockWriteGuard<Option<IndexPriceEvent>> =
             self.mark_price.write().unwrap();
@@ -130,20 +345,78 @@ impl WsData {
     }
 
     pub fn add_mark_price_snap(&self, event: IndexPriceEvent) {
-        insert_vec(self.mark_price_snaps.write().unwrap(), event);
+        insert_vec(
+            self.mark_price_snaps.write().unwrap(),
+            event,
+            self.config.mark_price_snaps,
+        );
+    }
+
+    pub fn update_book_ticker(&self, event: BookTickerEvent) {
+        *self.book_ticker.write().unwrap() = Some(event);
     }
 
     pub fn add_aggr_trades(&self, event: AggrTradesEvent) {
-        insert_vec(self.aggr_trades.write().unwrap(), event);
+        // held while the trade is stored, a seed replays it at most once
+        let mut candles = self.candles.write().unwrap();
+        for builder in candles.iter_mut() {
+            if let Err(e) = builder.add_trade(&event) {
+                warn!(
+                    "Unable to add trade {} to candles: {e}",
+                    event.aggregated_trade_id
+                );
+            }
+        }
+        insert_vec(
+            self.aggr_trades.write().unwrap(),
+            event,
+            self.config.aggr_trades,
+        );
+    }
+
+    /// Builds candles of `intervals` from the next aggregated trades,
+    /// the candles of the intervals already built are kept
+    pub fn set_candle_intervals(&self, intervals: &[CandleInterval]) {
+        let mut candles = self.candles.write().unwrap();
+        candles.retain(|builder| intervals.contains(&builder.interval()));
+        for interval in intervals {
+            if !candles
+                .iter()
+                .any(|builder| builder.interval() == *interval)
+            {
+                candles.push(CandleBuilder::new(*interval, self.config.candles));
+            }
+        }
+    }
+
+    /// Replaces the candles covered by `klines`, then replays the stored aggregated trades
+    /// after `last_trade_id`, the last one received before the klines were requested
+    pub fn seed_candles(&self, klines: &KlineSummaries, last_trade_id: Option<u64>) -> Result<()> {
+        let KlineSummaries::AllKlineSummaries(klines) = klines;
+        let mut candles = self.candles.write().unwrap();
+        let aggr_trades = self.aggr_trades.read().unwrap();
+        let trades: Vec<&AggrTradesEvent> = aggr_trades
+            .iter()
+            .filter(|trade| last_trade_id.is_none_or(|id| trade.aggregated_trade_id > id))
+            .collect();
+        for builder in candles.iter_mut() {
+            builder.seed(klines, trades.iter().copied())?;
+        }
+        Ok(())
     }
 
     pub fn add_liquidation(&self, event: LiquidationOrder) {
-        insert_vec(self.liquidations.write().unwrap(), event);
+        insert_vec(
+            self.liquidations.write().unwrap(),
+            event,
+            self.config.liquidations,
+        );
     }
 
     pub fn update_position(&self, event: EventPosition) {
-        let mut position: RwLockWriteGuard<Option<EventPosition>> = self.position.write().unwrap();
-        *position = Some(event);
+        let mut positions: RwLockWriteGuard<IndexMap<String, EventPosition>> =
+            self.positions.write().unwrap();
+        positions.insert(event.position_side.to_owned(), event);
     }
 
     pub fn update_balance(&self, event: EventBalance) {
@@ -155,27 +428,72 @@ impl WsData {
         let order_id = order.order_id;
         let order_status = order.clone().order_status;
 
-        if order_status == "NEW" {
-            insert_order_index_map(self.open_orders.write().unwrap(), order_id, order);
+        // open orders are live, they are not dropped for their age
+        let open_orders_retention = match self.config.orders {
+            Retention::Count(count) => Retention::Count(count),
+            Retention::Window(_) => Retention::Count(usize::MAX),
+        };
+        if order_status == "NEW" || order_status == "PARTIALLY_FILLED" {
+            insert_order_index_map(
+                self.open_orders.write().unwrap(),
+                order_id,
+                order,
+                open_orders_retention,
+            );
         } else if order_status == "FILLED" {
-            insert_order_index_map(self.filled_orders.write().unwrap(), order_id, order);
+            insert_order_index_map(
+                self.filled_orders.write().unwrap(),
+                order_id,
+                order,
+                self.config.orders,
+            );
             // this order could be previously open so needs to be removed from open orders
             remove_order_index_map(self.open_orders.write().unwrap(), order_id);
-        } else if order_status == "CANCELED" {
-            insert_order_index_map(self.canceled_orders.write().unwrap(), order_id, order);
+        } else if order_status == "CANCELED" || order_status == "EXPIRED" {
+            // expired orders (e.g. rejected post only orders) are never filled
+            insert_order_index_map(
+                self.canceled_orders.write().unwrap(),
+                order_id,
+                order,
+                self.config.orders,
+            );
             // this order could be previously open so needs to be removed from open orders
             remove_order_index_map(self.open_orders.write().unwrap(), order_id);
         }
     }
+
+    /// Drops an open order that will receive no more updates
+    pub fn remove_open_order(&self, order_id: u64) -> Option<OrderUpdate> {
+        self.open_orders.write().unwrap().shift_remove(&order_id)
+    }
 }
 
 fn insert_order_index_map(
     mut index_map: RwLockWriteGuard<IndexMap<u64, OrderUpdate>>,
     order_id: u64,
     order: OrderUpdate,
+    retention: Retention,
 ) {
-    if index_map.insert(order_id, order).is_none() && index_map.len() == DATA_SIZE + 1 {
-        index_map.shift_remove_index(0);
+    let time = order.exchange_time();
+    match retention {
+        Retention::Count(count) => {
+            index_map.insert(order_id, order);
+            while index_map.len() > count.max(1) {
+                index_map.shift_remove_index(0);
+            }
+        }
+        // in the order of the last update
+        Retention::Window(window) => {
+            index_map.shift_remove(&order_id);
+            index_map.insert(order_id, order);
+            let oldest = time.saturating_sub(window.as_millis() as u64);
+            while index_map
+                .first()
+                .is_some_and(|(id, order)| *id != order_id && order.exchange_time() < oldest)
+            {
+                index_map.shift_remove_index(0);
+            }
+        }
     }
 }
 
@@ -186,13 +504,53 @@ fn remove_order_index_map(
     index_map.shift_remove(&order_id);
 }
 
-fn insert_vec<T>(mut vec: RwLockWriteGuard<VecDeque<T>>, value: T) {
+fn insert_vec<T: ExchangeTime>(
+    mut vec: RwLockWriteGuard<VecDeque<T>>,
+    value: T,
+    retention: Retention,
+) {
+    let time = value.exchange_time();
     vec.push_back(value);
-    if vec.len() > DATA_SIZE {
-        vec.pop_front();
+    match retention {
+        Retention::Count(count) => {
+            while vec.len() > count.max(1) {
+                vec.pop_front();
+            }
+        }
+        Retention::Window(window) => {
+            let oldest = time.saturating_sub(window.as_millis() as u64);
+            while vec.len() > 1
+                && vec
+                    .front()
+                    .is_some_and(|event| event.exchange_time() < oldest)
+            {
+                vec.pop_front();
+            }
+        }
     }
 }
 
+// preallocated, a larger or time based retention grows on demand
+fn capacity(retention: Retention) -> usize {
+    match retention {
+        Retention::Count(count) => count.min(DATA_SIZE),
+        Retention::Window(_) => DATA_SIZE,
+    }
+}
+
+// events are in the order of their exchange time
+fn since<T: ExchangeTime + Clone>(events: &VecDeque<T>, time: u64) -> Vec<T> {
+    let start = events.partition_point(|event| event.exchange_time() < time);
+    events.range(start..).cloned().collect()
+}
+
+fn window_start(window: Duration) -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    now.saturating_sub(window).as_millis() as u64
+}
+
 fn get_order(
     index_map: RwLockReadGuard<IndexMap<u64, OrderUpdate>>,
     order_id: u64,
@@ -330,6 +688,61 @@ mod tests {
         assert_eq!(ws_data.get_canceled_orders().len(), 1);
     }
 
+    #[test]
+    fn test_order_update_partially_filled_and_expired() {
+        let json = r#"{
+        "s": "BTCUSDT",
+        "c": "web_HWhZes7Aql5iv5R6dEaa",
+        "S": "BUY",
+        "o": "LIMIT",
+        "f": "GTX",
+        "q": "0.010",
+        "p": "15000",
+        "ap": "15000",
+        "sp": "0",
+        "x": "TRADE",
+        "X": "PARTIALLY_FILLED",
+        "i": 3252769662,
+        "l": "0.004",
+        "z": "0.004",
+        "L": "15000",
+        "N": "USDT",
+        "n": "0.012",
+        "T": 1668814069559,
+        "t": 1,
+        "b": "90",
+        "a": "0",
+        "m": true,
+        "R": false,
+        "wt": "CONTRACT_PRICE",
+        "ot": "LIMIT",
+        "ps": "BOTH",
+        "cp": false,
+        "AP": "0",
+        "cr": "",
+        "pP": false,
+        "si": 0,
+        "ss": 0,
+        "rp": "0" }"#;
+        let mut v: OrderUpdate = serde_json::from_str(json).unwrap();
+        let ws_data = WsData::default();
+        ws_data.add_order(v.clone());
+        assert_eq!(ws_data.get_open_orders().len(), 1);
+        assert_eq!(
+            ws_data
+                .get_open_order(v.order_id)
+                .unwrap()
+                .accumulated_qty_filled_trades,
+            "0.004"
+        );
+
+        v.execution_type = "EXPIRED".into();
+        v.order_status = "EXPIRED".into();
+        ws_data.add_order(v);
+        assert_eq!(ws_data.get_open_orders().len(), 0);
+        assert_eq!(ws_data.get_canceled_orders().len(), 1);
+    }
+
     #[test]
     fn test_mark_price_update() {
         let json = r#"  {
@@ -394,6 +807,49 @@ mod tests {
         assert_eq!(ws_data.get_aggr_trades().len(), 2);
     }
 
+    #[test]
+    fn test_candles_seeded_from_klines() {
+        let json = r#"  {
+        "e": "aggTrade",
+        "E": 123456789,
+        "s": "BTCUSDT",
+        "a": 1,
+        "p": "100",
+        "q": "1",
+        "f": 100,
+        "l": 100,
+        "T": 60500,
+        "m": false
+    }"#;
+        let ws_data = WsData::default();
+        ws_data.set_candle_intervals(&[CandleInterval::OneMinute]);
+        let mut v: AggrTradesEvent = serde_json::from_str(json).unwrap();
+        ws_data.add_aggr_trades(v.to_owned());
+        let last_trade_id = ws_data.get_last_aggr_trade_id();
+        // received while the klines were requested
+        v.aggregated_trade_id = 2;
+        v.price = "120".into();
+        ws_data.add_aggr_trades(v);
+
+        let klines: Vec<Vec<serde_json::Value>> = serde_json::from_str(
+            r#"[[60000,"90","101","90","100","3",119999,"290",5,"2","195",""]]"#,
+        )
+        .unwrap();
+        let klines = KlineSummaries::AllKlineSummaries(
+            klines.iter().map(|row| row.try_into().unwrap()).collect(),
+        );
+        ws_data.seed_candles(&klines, last_trade_id).unwrap();
+        let candle = ws_data
+            .get_current_candle(CandleInterval::OneMinute)
+            .unwrap();
+        assert_eq!(candle.open, 90.0);
+        assert_eq!(candle.high, 120.0);
+        assert_eq!(candle.close, 120.0);
+        assert_eq!(candle.volume, 4.0);
+        assert_eq!(candle.trade_count, 6);
+        assert!(ws_data.get_candles(CandleInterval::OneHour).is_empty());
+    }
+
     #[test]
     fn test_liquidations_update() {
         let json = r#" {
@@ -460,6 +916,70 @@ mod tests {
         assert!(ws_data.get_position_event().is_some());
     }
 
+    #[test]
+    fn test_hedge_mode_account_update() {
+        let json = r#"{
+        "e": "ACCOUNT_UPDATE",
+        "E": 1564745798939,
+        "T": 1564745798938 ,
+        "a":
+        {
+            "m":"ORDER",
+            "B":[
+            {
+                "a":"USDT",
+                "wb":"122624.12345678",
+                "cw":"100.12345678",
+                "bc":"50.12345678"
+            }
+            ],
+            "P":[
+            {
+                "s":"BTCUSDT",
+                "pa":"20",
+                "ep":"6563.66500",
+                "cr":"0",
+                "up":"2850.21200",
+                "mt":"isolated",
+                "iw":"13200.70726908",
+                "ps":"LONG"
+            },
+            {
+                "s":"BTCUSDT",
+                "pa":"-10",
+                "ep":"6563.86000",
+                "cr":"-45.04000000",
+                "up":"-1423.15600",
+                "mt":"isolated",
+                "iw":"6570.42511771",
+                "ps":"SHORT"
+            }
+            ]
+        }
+    }"#;
+        let ws_data = WsData::default();
+        let v: AccountUpdateEvent = serde_json::from_str(json).unwrap();
+        for position in v.data.positions {
+            ws_data.update_position(position);
+        }
+        assert!(ws_data.get_position_event().is_none());
+        assert_eq!(ws_data.get_positions_event().len(), 2);
+        assert_eq!(
+            ws_data
+                .get_position_side_event(PositionSide::Long)
+                .unwrap()
+                .position_amount,
+            "20"
+        );
+        assert_eq!(
+            ws_data
+                .get_position_side_event(PositionSide::Short)
+                .unwrap()
+                .position_amount,
+            "-10"
+        );
+    }
+
     #[test]
     fn test_max_data_size() {
         let json = r#"  {
@@ -485,6 +1005,142 @@ mod tests {
         assert_eq!(ws_data.get_aggr_trades().len(), DATA_SIZE);
     }
 
+    #[test]
+    fn test_aggr_trades_retention() {
+        let json = r#"  {
+        "e": "aggTrade",
+        "E": 123456789,
+        "s": "BTCUSDT",
+        "a": 5933014,
+        "p": "0.001",
+        "q": "100",
+        "f": 100,
+        "l": 105,
+        "T": 0,
+        "m": true
+    }"#;
+        let v: AggrTradesEvent = serde_json::from_str(json).unwrap();
+        let trade = |time: u64| AggrTradesEvent {
+            trade_order_time: time,
+            ..v.clone()
+        };
+        let ws_data = WsData::new(WsDataConfig::default().set_aggr_trades(Retention::Count(3)));
+        for time in 0..5 {
+            ws_data.add_aggr_trades(trade(time * 1000));
+        }
+        assert_eq!(ws_data.get_aggr_trades().len(), 3);
+        assert_eq!(ws_data.get_aggr_trades_since(3000).len(), 2);
+        assert_eq!(ws_data.get_aggr_trades_since(3001).len(), 1);
+        assert!(ws_data.get_aggr_trades_since(5000).is_empty());
+
+        let window = Retention::Window(Duration::from_secs(2));
+        let ws_data = WsData::new(WsDataConfig::default().set_aggr_trades(window));
+        for time in 0..5 {
+            ws_data.add_aggr_trades(trade(time * 1000));
+        }
+        let times: Vec<u64> = ws_data
+            .get_aggr_trades()
+            .iter()
+            .map(|trade| trade.trade_order_time)
+            .collect();
+        assert_eq!(times, vec![2000, 3000, 4000]);
+        // the last one is kept however old
+        ws_data.add_aggr_trades(trade(60000));
+        assert_eq!(ws_data.get_aggr_trades().len(), 1);
+        assert_eq!(
+            ws_data
+                .get_aggr_trades_in_last(Duration::from_secs(60))
+                .len(),
+            0
+        );
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let ws_data = WsData::new(WsDataConfig::default());
+        for age in [90_000, 30_000, 1_000] {
+            ws_data.add_aggr_trades(trade(now - age));
+        }
+        let times: Vec<u64> = ws_data
+            .get_aggr_trades_in_last(Duration::from_secs(60))
+            .iter()
+            .map(|trade| trade.trade_order_time)
+            .collect();
+        assert_eq!(times, vec![now - 30_000, now - 1_000]);
+    }
+
+    #[test]
+    fn test_orders_window_retention() {
+        let json = r#"{
+        "s": "BTCUSDT",
+        "c": "web_HWhZes7Aql5iv5R6dEaa",
+        "S": "BUY",
+        "o": "LIMIT",
+        "f": "GTC",
+        "q": "0.010",
+        "p": "15000",
+        "ap": "0",
+        "sp": "0",
+        "x": "NEW",
+        "X": "NEW",
+        "i": 1,
+        "l": "0",
+        "z": "0",
+        "L": "0",
+        "N": "",
+        "n": "",
+        "T": 1000,
+        "t": 0,
+        "b": "150",
+        "a": "0",
+        "m": false,
+        "R": false,
+        "wt": "CONTRACT_PRICE",
+        "ot": "LIMIT",
+        "ps": "LONG",
+        "cp": false,
+        "AP": "0",
+        "cr": "",
+        "pP": false,
+        "si": 0,
+        "ss": 0,
+        "rp": "0" }"#;
+        let v: OrderUpdate = serde_json::from_str(json).unwrap();
+        let order = |order_id: u64, status: &str, time: u64| OrderUpdate {
+            order_id,
+            order_status: status.into(),
+            trade_order_time: time,
+            ..v.clone()
+        };
+        let window = Retention::Window(Duration::from_secs(10));
+        let ws_data = WsData::new(WsDataConfig::default().set_orders(window));
+        ws_data.add_order(order(1, "NEW", 1000));
+        ws_data.add_order(order(2, "FILLED", 1000));
+        ws_data.add_order(order(3, "FILLED", 5000));
+        ws_data.add_order(order(4, "FILLED", 12000));
+        let filled: Vec<u64> = ws_data
+            .get_filled_orders()
+            .iter()
+            .map(|order| order.order_id)
+            .collect();
+        assert_eq!(filled, vec![3, 4]);
+        // open orders are not dropped for their age
+        assert!(ws_data.get_open_order(1).is_some());
+
+        // an order updated again no longer holds back the older orders
+        ws_data.add_order(order(5, "FILLED", 12000));
+        ws_data.add_order(order(6, "FILLED", 13000));
+        ws_data.add_order(order(5, "FILLED", 20000));
+        ws_data.add_order(order(7, "FILLED", 24000));
+        let filled: Vec<u64> = ws_data
+            .get_filled_orders()
+            .iter()
+            .map(|order| order.order_id)
+            .collect();
+        assert_eq!(filled, vec![5, 7]);
+    }
+
     #[test]
     fn test_max_data_size_index_map() {
         let json = r#"{
@@ -0,0 +1,298 @@
+use crate::rest::futures::model::OrderUpdate;
+use crate::rest::model::{
+    AggrTradesEvent, BookTickerEvent, EventBalance, EventPosition, IndexPriceEvent,
+    LiquidationOrder,
+};
+use crate::websocket::channel::{event_queue, EventQueue, EventReceiver, OverflowPolicy};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// State changes of a `WsInterface`, sent after its data is updated
+#[derive(Debug, Clone)]
+pub enum UsdmEvent {
+    /// An order traded, `qty_last_filled_trade` at `price_last_filled_trade`
+    Fill(OrderUpdate),
+    /// The status of an order changed, `previous_status` is `None` for an unknown order
+    OrderStatus {
+        previous_status: Option<String>,
+        order: OrderUpdate,
+    },
+    Position(EventPosition),
+    Balance(EventBalance),
+    Liquidation(LiquidationOrder),
+    MarkPrice(IndexPriceEvent),
+    AggrTrade(AggrTradesEvent),
+    /// The best bid or ask changed
+    BookTicker(BookTickerEvent),
+    /// A funding fee was paid, negative `balance_change`, or received. Binance reports the
+    /// position only in isolated margin, `symbol` is `None` in cross margin
+    FundingFee {
+        symbol: Option<String>,
+        balance: EventBalance,
+        event_time: u64,
+    },
+    /// The user stream reconnected, order and position updates of `downtime` may be missing
+    Reconnected {
+        downtime: Duration,
+    },
+    /// A reconciliation found the data out of date and corrected it
+    Discrepancy(Discrepancy),
+}
+
+/// Difference between the data and Binance found by a reconciliation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Discrepancy {
+    /// The order changed while its updates were missed, `ws_status` is `None` for an unknown order
+    Order {
+        symbol: String,
+        order_id: u64,
+        ws_status: Option<String>,
+        rest_status: String,
+    },
+    /// An open order that Binance no longer knows, it is dropped
+    UnknownOrder { symbol: String, order_id: u64 },
+    /// The position amount or entry price differ, `ws_amount` is `None` for an unknown position
+    Position {
+        symbol: String,
+        position_side: String,
+        ws_amount: Option<String>,
+        rest_amount: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UsdmEventKind {
+    Fill,
+    OrderStatus,
+    Position,
+    Balance,
+    Liquidation,
+    MarkPrice,
+    AggrTrade,
+    BookTicker,
+    FundingFee,
+    Reconnected,
+    Discrepancy,
+}
+
+impl UsdmEvent {
+    pub fn kind(&self) -> UsdmEventKind {
+        match self {
+            Self::Fill(_) => UsdmEventKind::Fill,
+            Self::OrderStatus { .. } => UsdmEventKind::OrderStatus,
+            Self::Position(_) => UsdmEventKind::Position,
+            Self::Balance(_) => UsdmEventKind::Balance,
+            Self::Liquidation(_) => UsdmEventKind::Liquidation,
+            Self::MarkPrice(_) => UsdmEventKind::MarkPrice,
+            Self::AggrTrade(_) => UsdmEventKind::AggrTrade,
+            Self::BookTicker(_) => UsdmEventKind::BookTicker,
+            Self::FundingFee { .. } => UsdmEventKind::FundingFee,
+            Self::Reconnected { .. } => UsdmEventKind::Reconnected,
+            Self::Discrepancy(_) => UsdmEventKind::Discrepancy,
+        }
+    }
+}
+
+/// Called on the websocket thread, must not block
+pub type UsdmEventCallback = Arc<dyn Fn(&UsdmEvent) + Send + Sync>;
+
+#[derive(Clone)]
+enum Observer {
+    Callback(UsdmEventCallback),
+    Channel(Arc<EventQueue<UsdmEvent>>),
+}
+
+#[derive(Clone)]
+struct Registration {
+    id: u64,
+    // every kind if empty
+    kinds: Vec<UsdmEventKind>,
+    observer: Observer,
+}
+
+/// Callbacks and channels notified of `UsdmEvent`s
+#[derive(Clone, Default)]
+pub struct Observers {
+    next_id: Arc<AtomicU64>,
+    registrations: Arc<RwLock<Vec<Registration>>>,
+}
+
+impl Observers {
+    /// Returns the observer id, `kinds` empty for every kind
+    pub fn on_event<F>(&self, kinds: &[UsdmEventKind], callback: F) -> u64
+    where
+        F: Fn(&UsdmEvent) + Send + Sync + 'static,
+    {
+        self.register(kinds, Observer::Callback(Arc::new(callback)))
+    }
+
+    /// Channel of the events of `kinds` buffering up to `capacity` events, `kinds` empty
+    /// for every kind. `OverflowPolicy::Block` holds the websocket thread until the events
+    /// are consumed. Dropping the receiver, or an overflow with `OverflowPolicy::Error`,
+    /// removes the observer
+    pub fn subscribe(
+        &self,
+        kinds: &[UsdmEventKind],
+        capacity: usize,
+        overflow: OverflowPolicy,
+    ) -> EventReceiver<UsdmEvent> {
+        let (queue, receiver) = event_queue(capacity, overflow);
+        self.register(kinds, Observer::Channel(queue));
+        receiver
+    }
+
+    /// Returns false if there is no observer with `id`
+    pub fn remove(&self, id: u64) -> bool {
+        let mut registrations = self.registrations.write().unwrap();
+        let len = registrations.len();
+        registrations.retain(|registration| registration.id != id);
+        registrations.len() < len
+    }
+
+    pub fn notify(&self, event: UsdmEvent) {
+        let kind = event.kind();
+        // observers are called without the lock so they can register others
+        let registrations: Vec<Registration> = self
+            .registrations
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|registration| {
+                registration.kinds.is_empty() || registration.kinds.contains(&kind)
+            })
+            .cloned()
+            .collect();
+        for registration in registrations {
+            match registration.observer {
+                Observer::Callback(callback) => callback(&event),
+                Observer::Channel(queue) => {
+                    if queue.push(event.clone()).is_err() {
+                        self.remove(registration.id);
+                    }
+                }
+            }
+        }
+    }
+
+    fn register(&self, kinds: &[UsdmEventKind], observer: Observer) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.registrations.write().unwrap().push(Registration {
+            id,
+            kinds: kinds.to_vec(),
+            observer,
+        });
+        id
+    }
+}
+
+/// Events of an order update, `previous` being the last known update of the order
+pub(crate) fn order_events(previous: Option<&OrderUpdate>, order: &OrderUpdate) -> Vec<UsdmEvent> {
+    let mut events = vec![];
+    if order.execution_type == "TRADE" {
+        events.push(UsdmEvent::Fill(order.clone()));
+    }
+    let previous_status = previous.map(|previous| previous.order_status.to_owned());
+    if previous_status.as_ref() != Some(&order.order_status) {
+        events.push(UsdmEvent::OrderStatus {
+            previous_status,
+            order: order.clone(),
+        });
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    fn order(execution_type: &str, order_status: &str) -> OrderUpdate {
+        let mut order: OrderUpdate = serde_json::from_str(
+            r#"{"s":"BTCUSDT","c":"web_1","S":"BUY","o":"LIMIT","f":"GTC","q":"0.010","p":"15000",
+            "ap":"0","sp":"0","x":"NEW","X":"NEW","i":1,"l":"0","z":"0","L":"0","n":"","T":0,
+            "t":0,"b":"0","a":"0","m":false,"R":false,"wt":"CONTRACT_PRICE","ot":"LIMIT",
+            "ps":"BOTH","pP":false,"si":0,"ss":0,"rp":"0"}"#,
+        )
+        .unwrap();
+        order.execution_type = execution_type.to_string();
+        order.order_status = order_status.to_string();
+        order
+    }
+
+    #[test]
+    fn test_order_events() {
+        let new = order("NEW", "NEW");
+        let events = order_events(None, &new);
+        assert!(matches!(
+            events[..],
+            [UsdmEvent::OrderStatus {
+                previous_status: None,
+                ..
+            }]
+        ));
+
+        let partial = order("TRADE", "PARTIALLY_FILLED");
+        let events = order_events(Some(&new), &partial);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind(), UsdmEventKind::Fill);
+        match &events[1] {
+            UsdmEvent::OrderStatus {
+                previous_status,
+                order,
+            } => {
+                assert_eq!(previous_status.as_deref(), Some("NEW"));
+                assert_eq!(order.order_status, "PARTIALLY_FILLED");
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+
+        // a second partial fill is not a transition
+        let events = order_events(Some(&partial), &partial);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind(), UsdmEventKind::Fill);
+    }
+
+    #[test]
+    fn test_observers() {
+        let observers = Observers::default();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let id = observers.on_event(&[], move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+        let fills = observers.subscribe(&[UsdmEventKind::Fill], 16, OverflowPolicy::Block);
+
+        observers.notify(UsdmEvent::Fill(order("TRADE", "FILLED")));
+        observers.notify(UsdmEvent::OrderStatus {
+            previous_status: None,
+            order: order("NEW", "NEW"),
+        });
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        assert_eq!(fills.len(), 1);
+
+        assert!(observers.remove(id));
+        assert!(!observers.remove(id));
+        drop(fills);
+        observers.notify(UsdmEvent::Fill(order("TRADE", "FILLED")));
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        assert!(observers.registrations.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_subscribe_overflow() {
+        let observers = Observers::default();
+        let latest = observers.subscribe(&[], 2, OverflowPolicy::DropOldest);
+        let strict = observers.subscribe(&[], 2, OverflowPolicy::Error);
+        for _ in 0..3 {
+            observers.notify(UsdmEvent::Fill(order("TRADE", "FILLED")));
+        }
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest.dropped(), 1);
+        // the overflowed channel is drained then fails, its observer is removed
+        assert!(strict.recv().is_ok());
+        assert!(strict.recv().is_ok());
+        assert!(strict.recv().is_err());
+        assert_eq!(observers.registrations.read().unwrap().len(), 1);
+    }
+}
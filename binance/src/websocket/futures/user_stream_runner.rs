@@ -0,0 +1,236 @@
+use crate::commons::config::Config;
+use crate::commons::errors::*;
+use crate::rest::api::Binance;
+use crate::websocket::futures::userstream::FuturesUserStream;
+use crate::websocket::futures::{FuturesMarket, FuturesWebSockets, FuturesWebsocketEvent};
+use crate::websocket::managed::{sleep, ConnectionEvent, ReconnectPolicy};
+use log::{debug, error, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// listen keys expire 60 minutes after the last keep-alive
+const MAX_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(55 * 60);
+// retry delay of a failed keep-alive
+const KEEP_ALIVE_RETRY: Duration = Duration::from_secs(60);
+// the user stream can be quiet for hours, lets the loop notice a shutdown or an expired key
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+const STEP: Duration = Duration::from_millis(100);
+
+/// Futures user data stream that owns its listen key: starts it, keeps it alive,
+/// closes it on shutdown and starts a new one when it expires
+pub struct UserStreamRunner<'a> {
+    user_stream: FuturesUserStream,
+    config: Config,
+    policy: ReconnectPolicy,
+    keep_alive_interval: Duration,
+    handler: Box<dyn FnMut(FuturesWebsocketEvent) -> Result<()> + 'a>,
+    on_connection_event: Box<dyn FnMut(ConnectionEvent) + 'a>,
+}
+
+impl<'a> UserStreamRunner<'a> {
+    /// * `api_key` - Option<String>
+    /// * `api_secret` - Option<String>
+    /// * `config` - Config
+    /// * `handler` - called with every user data event, `UserDataStreamExpiredEvent` included
+    pub fn new<Callback>(
+        api_key: Option<String>,
+        api_secret: Option<String>,
+        config: &Config,
+        handler: Callback,
+    ) -> UserStreamRunner<'a>
+    where
+        Callback: FnMut(FuturesWebsocketEvent) -> Result<()> + 'a,
+    {
+        UserStreamRunner {
+            user_stream: Binance::new_with_config(api_key, api_secret, config),
+            config: config.clone(),
+            policy: ReconnectPolicy::default(),
+            keep_alive_interval: Duration::from_secs(30 * 60),
+            handler: Box::new(handler),
+            on_connection_event: Box::new(|event| debug!("User stream: {event:?}")),
+        }
+    }
+
+    pub fn set_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Capped to 55 minutes so the listen key is renewed before it expires
+    pub fn set_keep_alive_interval(mut self, keep_alive_interval: Duration) -> Self {
+        self.keep_alive_interval = keep_alive_interval.min(MAX_KEEP_ALIVE_INTERVAL);
+        self
+    }
+
+    pub fn set_connection_handler<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(ConnectionEvent) + 'a,
+    {
+        self.on_connection_event = Box::new(handler);
+        self
+    }
+
+    /// Runs the user stream until `running` is unset, then closes the listen key.
+    /// Returns an error only if `max_attempts` is reached
+    pub fn run(&mut self, running: &AtomicBool) -> Result<()> {
+        let mut attempt: u32 = 0;
+        let mut disconnected_at: Option<Instant> = None;
+
+        while running.load(Ordering::Relaxed) {
+            // returns the active listen key if there is one, and extends it
+            let failure = match self.user_stream.start() {
+                Ok(answer) => {
+                    let listen_key = answer.listen_key;
+                    let expired = AtomicBool::new(false);
+                    let session = self.session(
+                        running,
+                        &listen_key,
+                        &expired,
+                        &mut attempt,
+                        &mut disconnected_at,
+                    );
+                    if !running.load(Ordering::Relaxed) {
+                        if let Err(e) = self.user_stream.close(&listen_key) {
+                            error!("Error closing user stream: {e}");
+                        }
+                        break;
+                    }
+                    disconnected_at.get_or_insert_with(Instant::now);
+                    match session {
+                        Ok(()) if expired.load(Ordering::Relaxed) => {
+                            // a new listen key is requested right away
+                            (self.on_connection_event)(ConnectionEvent::Disconnected(
+                                "Listen key expired".into(),
+                            ));
+                            continue;
+                        }
+                        Ok(()) => {
+                            (self.on_connection_event)(ConnectionEvent::Rotating);
+                            continue;
+                        }
+                        Err(e) => e,
+                    }
+                }
+                Err(e) => {
+                    disconnected_at.get_or_insert_with(Instant::now);
+                    e
+                }
+            };
+
+            warn!("User stream disconnected: {failure}");
+            (self.on_connection_event)(ConnectionEvent::Disconnected(failure.to_string()));
+            attempt += 1;
+            if self.policy.max_attempts.is_some_and(|max| attempt > max) {
+                (self.on_connection_event)(ConnectionEvent::GaveUp);
+                return Err(BinanceError::WebSocket(WebSocketError::ConnectionError(
+                    format!(
+                        "Unable to restart the user stream after {} attempts",
+                        attempt - 1
+                    ),
+                )));
+            }
+            let delay = self.policy.backoff(attempt);
+            (self.on_connection_event)(ConnectionEvent::Reconnecting { attempt, delay });
+            sleep(running, delay);
+        }
+
+        (self.on_connection_event)(ConnectionEvent::Closed);
+        Ok(())
+    }
+
+    // one connection with `listen_key`, ends on shutdown, expiry, rotation or error
+    fn session(
+        &mut self,
+        running: &AtomicBool,
+        listen_key: &str,
+        expired: &AtomicBool,
+        attempt: &mut u32,
+        disconnected_at: &mut Option<Instant>,
+    ) -> Result<()> {
+        let session_running = AtomicBool::new(true);
+        let config = self.config.clone();
+        let mut web_socket = {
+            let handler = &mut self.handler;
+            let session_running = &session_running;
+            FuturesWebSockets::new(move |event: FuturesWebsocketEvent| {
+                if let FuturesWebsocketEvent::UserDataStreamExpiredEvent(_) = event {
+                    expired.store(true, Ordering::Relaxed);
+                    session_running.store(false, Ordering::Relaxed);
+                }
+                handler(event)
+            })
+        };
+        web_socket.connect_with_config(FuturesMarket::USDM, listen_key, &config)?;
+        web_socket.set_read_timeout(Some(READ_TIMEOUT))?;
+        *attempt = 0;
+        (self.on_connection_event)(ConnectionEvent::Connected);
+        if let Some(disconnected_at) = disconnected_at.take() {
+            (self.on_connection_event)(ConnectionEvent::Gap {
+                downtime: disconnected_at.elapsed(),
+            });
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let user_stream = &self.user_stream;
+        let keep_alive_interval = self.keep_alive_interval;
+        let session = thread::scope(|scope| {
+            scope.spawn(|| {
+                keep_alive(
+                    rx,
+                    user_stream,
+                    listen_key,
+                    keep_alive_interval,
+                    running,
+                    &session_running,
+                    expired,
+                )
+            });
+            let session = web_socket.event_loop_for(&session_running, self.policy.rotate_after);
+            let _ = tx.send(());
+            session
+        });
+        let _ = web_socket.disconnect();
+        session
+    }
+}
+
+// renews the listen key every `interval` and stops the session on shutdown or rejection
+fn keep_alive(
+    rx: Receiver<()>,
+    user_stream: &FuturesUserStream,
+    listen_key: &str,
+    interval: Duration,
+    running: &AtomicBool,
+    session_running: &AtomicBool,
+    expired: &AtomicBool,
+) {
+    let mut renew_at = Instant::now() + interval;
+    // until the session ends
+    while let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(STEP) {
+        if !running.load(Ordering::Relaxed) {
+            session_running.store(false, Ordering::Relaxed);
+            break;
+        }
+        if Instant::now() < renew_at {
+            continue;
+        }
+        match user_stream.keep_alive(listen_key) {
+            Ok(msg) => {
+                debug!("Keepalive user data stream: {msg:?}");
+                renew_at = Instant::now() + interval;
+            }
+            Err(BinanceError::BinanceError { response }) => {
+                error!("Listen key rejected: {response:?}");
+                expired.store(true, Ordering::Relaxed);
+                session_running.store(false, Ordering::Relaxed);
+                break;
+            }
+            Err(e) => {
+                warn!("Error: {e}");
+                renew_at = Instant::now() + KEEP_ALIVE_RETRY.min(interval);
+            }
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use crate::commons::config::Config;
+use crate::commons::errors::*;
+use crate::websocket::futures::{FuturesMarket, FuturesWebSockets};
+use crate::websocket::spot::WebSockets;
+use crate::websocket::subscription::StreamController;
+use log::{debug, warn};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Binance closes every connection after 24 hours
+const MAX_CONNECTION_DURATION: Duration = Duration::from_secs(24 * 3600);
+const SLEEP_STEP: Duration = Duration::from_millis(100);
+
+/// Websocket that can be (re)connected to a set of streams
+pub trait ManagedSocket {
+    fn connect_streams(&mut self, streams: &[String], config: &Config) -> Result<()>;
+
+    fn event_loop_for(&mut self, running: &AtomicBool, max_duration: Duration) -> Result<()>;
+
+    fn disconnect(&mut self) -> Result<()>;
+
+    /// Streams of the last connection, including the ones (un)subscribed at runtime
+    fn subscribed_streams(&self) -> Vec<String>;
+
+    fn stream_controller(&self) -> StreamController;
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()>;
+}
+
+impl ManagedSocket for WebSockets<'_> {
+    fn connect_streams(&mut self, streams: &[String], config: &Config) -> Result<()> {
+        self.connect_multiple_streams_with_config(streams, config)
+    }
+
+    fn event_loop_for(&mut self, running: &AtomicBool, max_duration: Duration) -> Result<()> {
+        WebSockets::event_loop_for(self, running, max_duration)
+    }
+
+    fn disconnect(&mut self) -> Result<()> {
+        WebSockets::disconnect(self)
+    }
+
+    fn subscribed_streams(&self) -> Vec<String> {
+        WebSockets::subscribed_streams(self).to_vec()
+    }
+
+    fn stream_controller(&self) -> StreamController {
+        WebSockets::stream_controller(self)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        WebSockets::set_read_timeout(self, timeout)
+    }
+}
+
+impl ManagedSocket for FuturesWebSockets<'_> {
+    /// Connects to `config.futures_ws_endpoint`, point it to dstream for COINM
+    fn connect_streams(&mut self, streams: &[String], config: &Config) -> Result<()> {
+        self.connect_multiple_streams(FuturesMarket::USDM, streams, config)
+    }
+
+    fn event_loop_for(&mut self, running: &AtomicBool, max_duration: Duration) -> Result<()> {
+        FuturesWebSockets::event_loop_for(self, running, max_duration)
+    }
+
+    fn disconnect(&mut self) -> Result<()> {
+        FuturesWebSockets::disconnect(self)
+    }
+
+    fn subscribed_streams(&self) -> Vec<String> {
+        FuturesWebSockets::subscribed_streams(self).to_vec()
+    }
+
+    fn stream_controller(&self) -> StreamController {
+        FuturesWebSockets::stream_controller(self)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        FuturesWebSockets::set_read_timeout(self, timeout)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionEvent {
+    /// Connected and subscribed to every stream
+    Connected,
+    /// Connection lost, with the reason
+    Disconnected(String),
+    /// Waiting `delay` before the `attempt`th reconnection
+    Reconnecting { attempt: u32, delay: Duration },
+    /// Connection closed on purpose before the server cutoff, a new one is opened right away
+    Rotating,
+    /// Events may have been missed while disconnected, order books and caches should resync
+    Gap { downtime: Duration },
+    /// `max_attempts` consecutive reconnections failed
+    GaveUp,
+    /// `running` was unset
+    Closed,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    /// Fraction of the backoff that is randomized, between 0 and 1
+    pub jitter: f64,
+    /// Consecutive failed attempts before giving up, `None` retries forever
+    pub max_attempts: Option<u32>,
+    /// Connections are rotated after this duration
+    pub rotate_after: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: 0.5,
+            max_attempts: None,
+            rotate_after: Duration::from_secs(23 * 3600),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    pub fn set_backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn set_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn set_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn set_max_attempts<A: Into<Option<u32>>>(mut self, max_attempts: A) -> Self {
+        self.max_attempts = max_attempts.into();
+        self
+    }
+
+    /// Capped to 24 hours, after which Binance drops the connection
+    pub fn set_rotate_after(mut self, rotate_after: Duration) -> Self {
+        self.rotate_after = rotate_after.min(MAX_CONNECTION_DURATION);
+        self
+    }
+
+    /// Delay before the `attempt`th reconnection (starting at 1)
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self.multiplier.powi(attempt.saturating_sub(1) as i32);
+        let backoff = self
+            .initial_backoff
+            .mul_f64(exp.min(u32::MAX as f64))
+            .min(self.max_backoff);
+        backoff.mul_f64(1.0 - self.jitter * random())
+    }
+}
+
+// uniform in [0, 1), RandomState is randomly seeded on every call
+fn random() -> f64 {
+    let hasher = RandomState::new().build_hasher();
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Websocket that reconnects with exponential backoff and resubscribes to the same streams,
+/// including the ones subscribed at runtime
+pub struct ManagedWebSockets<'a, S: ManagedSocket> {
+    socket: S,
+    streams: Vec<String>,
+    config: Config,
+    policy: ReconnectPolicy,
+    on_connection_event: Box<dyn FnMut(ConnectionEvent) + 'a>,
+}
+
+impl<'a, S: ManagedSocket> ManagedWebSockets<'a, S> {
+    /// * `socket` - `WebSockets` or `FuturesWebSockets` with the event handler
+    /// * `streams` - streams to subscribe to on every connection
+    /// * `config` - Config
+    pub fn new(socket: S, streams: Vec<String>, config: &Config) -> ManagedWebSockets<'a, S> {
+        ManagedWebSockets {
+            socket,
+            streams,
+            config: config.clone(),
+            policy: ReconnectPolicy::default(),
+            on_connection_event: Box::new(|event| debug!("Connection event: {event:?}")),
+        }
+    }
+
+    pub fn set_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn set_connection_handler<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(ConnectionEvent) + 'a,
+    {
+        self.on_connection_event = Box::new(handler);
+        self
+    }
+
+    pub fn streams(&self) -> &[String] {
+        &self.streams
+    }
+
+    pub fn socket(&mut self) -> &mut S {
+        &mut self.socket
+    }
+
+    /// Keeps the connection alive until `running` is unset,
+    /// returns an error only if `max_attempts` is reached
+    pub fn event_loop(&mut self, running: &AtomicBool) -> Result<()> {
+        let mut attempt: u32 = 0;
+        let mut disconnected_at: Option<Instant> = None;
+
+        while running.load(Ordering::Relaxed) {
+            match self.socket.connect_streams(&self.streams, &self.config) {
+                Ok(()) => {
+                    attempt = 0;
+                    (self.on_connection_event)(ConnectionEvent::Connected);
+                    if let Some(disconnected_at) = disconnected_at.take() {
+                        (self.on_connection_event)(ConnectionEvent::Gap {
+                            downtime: disconnected_at.elapsed(),
+                        });
+                    }
+
+                    let session = self
+                        .socket
+                        .event_loop_for(running, self.policy.rotate_after);
+                    let _ = self.socket.disconnect();
+                    disconnected_at = Some(Instant::now());
+                    // resubscribe to the streams added or removed at runtime
+                    self.streams = self.socket.subscribed_streams();
+                    match session {
+                        Ok(()) if running.load(Ordering::Relaxed) => {
+                            (self.on_connection_event)(ConnectionEvent::Rotating);
+                            continue;
+                        }
+                        Ok(()) => break,
+                        Err(e) => {
+                            warn!("Websocket disconnected: {e}");
+                            (self.on_connection_event)(ConnectionEvent::Disconnected(
+                                e.to_string(),
+                            ));
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("Websocket connection failed: {e}");
+                    if disconnected_at.is_none() {
+                        disconnected_at = Some(Instant::now());
+                    }
+                    (self.on_connection_event)(ConnectionEvent::Disconnected(e.to_string()));
+                }
+            }
+
+            attempt += 1;
+            if self.policy.max_attempts.is_some_and(|max| attempt > max) {
+                (self.on_connection_event)(ConnectionEvent::GaveUp);
+                return Err(BinanceError::WebSocket(WebSocketError::ConnectionError(
+                    format!("Unable to reconnect after {} attempts", attempt - 1),
+                )));
+            }
+            let delay = self.policy.backoff(attempt);
+            (self.on_connection_event)(ConnectionEvent::Reconnecting { attempt, delay });
+            sleep(running, delay);
+        }
+
+        (self.on_connection_event)(ConnectionEvent::Closed);
+        Ok(())
+    }
+}
+
+// sleeps for `duration` or until `running` is unset
+pub(crate) fn sleep(running: &AtomicBool, duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while running.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        thread::sleep(SLEEP_STEP.min(deadline - now));
+    }
+}
@@ -1,2 +1,39 @@
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::time::Duration;
+use tungstenite::protocol::WebSocket;
+use tungstenite::stream::MaybeTlsStream;
+
+pub mod api;
+pub mod candles;
+pub mod channel;
+pub(crate) mod decode;
+pub mod feed;
 pub mod futures;
+pub mod managed;
+pub mod orderbook;
+pub mod recorder;
 pub mod spot;
+pub mod streams;
+pub mod subscription;
+
+pub(crate) fn set_read_timeout(
+    socket: &mut WebSocket<MaybeTlsStream<TcpStream>>,
+    timeout: Option<Duration>,
+) -> std::io::Result<()> {
+    match socket.get_mut() {
+        MaybeTlsStream::Plain(stream) => stream.set_read_timeout(timeout),
+        MaybeTlsStream::NativeTls(stream) => stream.get_ref().set_read_timeout(timeout),
+        _ => Ok(()),
+    }
+}
+
+// a read that timed out can be retried, the partial frame is kept by tungstenite
+pub(crate) fn is_read_timeout(err: &tungstenite::Error) -> bool {
+    match err {
+        tungstenite::Error::Io(e) => {
+            matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,394 @@
+use crate::commons::errors::*;
+use crate::rest::futures::market::FuturesMarket;
+use crate::rest::model::{Asks, Bids, DepthOrderBookEvent};
+use crate::rest::spot::account::OrderSide;
+use crate::rest::spot::market::Market;
+use crate::rest::{futures, model};
+use log::{debug, warn};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, VecDeque};
+
+// https://developers.binance.com/docs/binance-spot-api-docs/web-socket-streams#how-to-manage-a-local-order-book-correctly
+// https://developers.binance.com/docs/derivatives/usds-margined-futures/websocket-market-streams/How-to-manage-a-local-order-book-correctly
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderBookMarket {
+    Spot,
+    Usdm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookSide {
+    Bid,
+    Ask,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderBookStatus {
+    /// Waiting for a snapshot that lines up with the buffered diff events
+    Syncing,
+    /// The book is up to date
+    Synced,
+    /// A sequence gap was detected, the book is being rebuilt
+    Resync,
+}
+
+#[derive(Debug, Clone)]
+pub struct OrderBookSnapshot {
+    pub last_update_id: u64,
+    pub bids: Vec<Bids>,
+    pub asks: Vec<Asks>,
+}
+
+impl From<model::OrderBook> for OrderBookSnapshot {
+    fn from(book: model::OrderBook) -> Self {
+        OrderBookSnapshot {
+            last_update_id: book.last_update_id,
+            bids: book.bids,
+            asks: book.asks,
+        }
+    }
+}
+
+impl From<futures::model::OrderBook> for OrderBookSnapshot {
+    fn from(book: futures::model::OrderBook) -> Self {
+        OrderBookSnapshot {
+            last_update_id: book.last_update_id,
+            bids: book.bids,
+            asks: book.asks,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Price(f64);
+
+impl PartialEq for Price {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+type SnapshotFn<'a> = Box<dyn FnMut(&str) -> Result<OrderBookSnapshot> + Send + 'a>;
+
+/// Local order book built from a depth snapshot and the `<symbol>@depth` diff stream
+pub struct LocalOrderBook<'a> {
+    symbol: String,
+    market: OrderBookMarket,
+    bids: BTreeMap<Price, f64>,
+    asks: BTreeMap<Price, f64>,
+    last_update_id: u64,
+    synced: bool,
+    snapshot_loaded: bool,
+    buffer: VecDeque<DepthOrderBookEvent>,
+    snapshot: SnapshotFn<'a>,
+    resyncs: u64,
+}
+
+impl<'a> LocalOrderBook<'a> {
+    /// * `snapshot` - fetches a depth snapshot of the symbol
+    pub fn new<S, F>(symbol: S, market: OrderBookMarket, snapshot: F) -> LocalOrderBook<'a>
+    where
+        S: Into<String>,
+        F: FnMut(&str) -> Result<OrderBookSnapshot> + Send + 'a,
+    {
+        LocalOrderBook {
+            symbol: symbol.into(),
+            market,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            last_update_id: 0,
+            synced: false,
+            snapshot_loaded: false,
+            buffer: VecDeque::new(),
+            snapshot: Box::new(snapshot),
+            resyncs: 0,
+        }
+    }
+
+    /// Spot order book, snapshots are fetched with `Market::get_custom_depth`
+    pub fn spot<S>(symbol: S, market: Market, depth: u64) -> LocalOrderBook<'a>
+    where
+        S: Into<String>,
+    {
+        LocalOrderBook::new(symbol, OrderBookMarket::Spot, move |symbol: &str| {
+            Ok(market.get_custom_depth(symbol, depth)?.into())
+        })
+    }
+
+    /// USDM futures order book, snapshots are fetched with `FuturesMarket::get_custom_depth`
+    pub fn usdm<S>(symbol: S, market: FuturesMarket, depth: u64) -> LocalOrderBook<'a>
+    where
+        S: Into<String>,
+    {
+        LocalOrderBook::new(symbol, OrderBookMarket::Usdm, move |symbol: &str| {
+            Ok(market.get_custom_depth(symbol, depth)?.into())
+        })
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+
+    pub fn last_update_id(&self) -> u64 {
+        self.last_update_id
+    }
+
+    /// Number of times a sequence gap forced the book to be rebuilt
+    pub fn resyncs(&self) -> u64 {
+        self.resyncs
+    }
+
+    /// Forces the book to be rebuilt from a new snapshot, e.g. after a reconnect
+    pub fn reset(&mut self) {
+        self.synced = false;
+        self.snapshot_loaded = false;
+        self.buffer.clear();
+        self.bids.clear();
+        self.asks.clear();
+        self.last_update_id = 0;
+    }
+
+    /// Applies a diff event of the depth stream
+    pub fn handle_event(&mut self, event: DepthOrderBookEvent) -> Result<OrderBookStatus> {
+        if !event.symbol.eq_ignore_ascii_case(&self.symbol) {
+            return Ok(self.status());
+        }
+
+        if !self.synced {
+            self.buffer.push_back(event);
+            if self.snapshot_loaded {
+                let status = self.replay_buffer();
+                if self.snapshot_loaded {
+                    return Ok(status);
+                }
+            }
+            return self.sync();
+        }
+
+        if event.final_update_id <= self.last_update_id {
+            // already part of the book
+            return Ok(OrderBookStatus::Synced);
+        }
+
+        if self.is_next(&event) {
+            self.apply(&event);
+            return Ok(OrderBookStatus::Synced);
+        }
+
+        warn!(
+            "Order book gap on {} at {} (last update id {})",
+            self.symbol, event.first_update_id, self.last_update_id
+        );
+        self.resyncs += 1;
+        self.reset();
+        self.buffer.push_back(event);
+        self.sync()?;
+        Ok(OrderBookStatus::Resync)
+    }
+
+    /// Applies a snapshot directly, buffered events are replayed on top of it
+    pub fn handle_snapshot(&mut self, snapshot: OrderBookSnapshot) -> OrderBookStatus {
+        self.load_snapshot(snapshot);
+        self.replay_buffer()
+    }
+
+    fn status(&self) -> OrderBookStatus {
+        if self.synced {
+            OrderBookStatus::Synced
+        } else {
+            OrderBookStatus::Syncing
+        }
+    }
+
+    fn sync(&mut self) -> Result<OrderBookStatus> {
+        let snapshot = (self.snapshot)(&self.symbol)?;
+        Ok(self.handle_snapshot(snapshot))
+    }
+
+    fn load_snapshot(&mut self, snapshot: OrderBookSnapshot) {
+        self.bids = snapshot
+            .bids
+            .into_iter()
+            .filter(|bid| bid.qty > 0.0)
+            .map(|bid| (Price(bid.price), bid.qty))
+            .collect();
+        self.asks = snapshot
+            .asks
+            .into_iter()
+            .filter(|ask| ask.qty > 0.0)
+            .map(|ask| (Price(ask.price), ask.qty))
+            .collect();
+        self.last_update_id = snapshot.last_update_id;
+        self.synced = false;
+        self.snapshot_loaded = true;
+    }
+
+    fn replay_buffer(&mut self) -> OrderBookStatus {
+        // drop events already contained in the snapshot
+        while let Some(event) = self.buffer.front() {
+            let stale = match self.market {
+                OrderBookMarket::Spot => event.final_update_id <= self.last_update_id,
+                OrderBookMarket::Usdm => event.final_update_id < self.last_update_id,
+            };
+            if !stale {
+                break;
+            }
+            self.buffer.pop_front();
+        }
+
+        let first = match self.buffer.front() {
+            Some(first) => first,
+            // every buffered event is in the snapshot, keep it and wait for the next one
+            None => return OrderBookStatus::Syncing,
+        };
+        let lines_up = match self.market {
+            OrderBookMarket::Spot => {
+                first.first_update_id <= self.last_update_id + 1
+                    && first.final_update_id > self.last_update_id
+            }
+            OrderBookMarket::Usdm => {
+                first.first_update_id <= self.last_update_id
+                    && first.final_update_id >= self.last_update_id
+            }
+        };
+        if !lines_up {
+            // snapshot is older than the buffered events, refetch it on the next event
+            debug!(
+                "Snapshot {} of {} is older than buffered events",
+                self.last_update_id, self.symbol
+            );
+            self.snapshot_loaded = false;
+            return OrderBookStatus::Syncing;
+        }
+
+        let first = self.buffer.pop_front().unwrap();
+        self.apply(&first);
+        while let Some(event) = self.buffer.pop_front() {
+            if !self.is_next(&event) {
+                warn!("Order book gap on {} while replaying", self.symbol);
+                self.buffer.clear();
+                self.buffer.push_back(event);
+                self.snapshot_loaded = false;
+                return OrderBookStatus::Syncing;
+            }
+            self.apply(&event);
+        }
+        self.synced = true;
+        OrderBookStatus::Synced
+    }
+
+    fn is_next(&self, event: &DepthOrderBookEvent) -> bool {
+        match self.market {
+            OrderBookMarket::Spot => event.first_update_id == self.last_update_id + 1,
+            OrderBookMarket::Usdm => event.previous_final_update_id == Some(self.last_update_id),
+        }
+    }
+
+    fn apply(&mut self, event: &DepthOrderBookEvent) {
+        for bid in &event.bids {
+            update_level(&mut self.bids, bid.price, bid.qty);
+        }
+        for ask in &event.asks {
+            update_level(&mut self.asks, ask.price, ask.qty);
+        }
+        self.last_update_id = event.final_update_id;
+    }
+
+    /// Best bid (price, qty)
+    pub fn best_bid(&self) -> Option<(f64, f64)> {
+        self.bids
+            .iter()
+            .next_back()
+            .map(|(price, qty)| (price.0, *qty))
+    }
+
+    /// Best ask (price, qty)
+    pub fn best_ask(&self) -> Option<(f64, f64)> {
+        self.asks.iter().next().map(|(price, qty)| (price.0, *qty))
+    }
+
+    pub fn mid_price(&self) -> Option<f64> {
+        match (self.best_bid(), self.best_ask()) {
+            (Some((bid, _)), Some((ask, _))) => Some((bid + ask) / 2.0),
+            _ => None,
+        }
+    }
+
+    /// Quantity resting at `price`, 0 if the level is empty
+    pub fn depth_at_price(&self, side: BookSide, price: f64) -> f64 {
+        let levels = match side {
+            BookSide::Bid => &self.bids,
+            BookSide::Ask => &self.asks,
+        };
+        levels.get(&Price(price)).copied().unwrap_or_default()
+    }
+
+    /// Up to `levels` best levels (price, qty) of one side, best first
+    pub fn levels(&self, side: BookSide, levels: usize) -> Vec<(f64, f64)> {
+        match side {
+            BookSide::Bid => self
+                .bids
+                .iter()
+                .rev()
+                .take(levels)
+                .map(|(price, qty)| (price.0, *qty))
+                .collect(),
+            BookSide::Ask => self
+                .asks
+                .iter()
+                .take(levels)
+                .map(|(price, qty)| (price.0, *qty))
+                .collect(),
+        }
+    }
+
+    /// Average price of a market order of `size` taking liquidity on the book,
+    /// `None` if the book is not deep enough
+    pub fn vwap_to_size(&self, side: OrderSide, size: f64) -> Option<f64> {
+        if size <= 0.0 {
+            return None;
+        }
+        let levels: Box<dyn Iterator<Item = (&Price, &f64)>> = match side {
+            OrderSide::Buy => Box::new(self.asks.iter()),
+            OrderSide::Sell => Box::new(self.bids.iter().rev()),
+        };
+        let mut remaining = size;
+        let mut notional = 0.0;
+        for (price, qty) in levels {
+            let fill = remaining.min(*qty);
+            notional += fill * price.0;
+            remaining -= fill;
+            if remaining <= 0.0 {
+                return Some(notional / size);
+            }
+        }
+        None
+    }
+}
+
+fn update_level(levels: &mut BTreeMap<Price, f64>, price: f64, qty: f64) {
+    if qty == 0.0 {
+        levels.remove(&Price(price));
+    } else {
+        levels.insert(Price(price), qty);
+    }
+}
@@ -0,0 +1,164 @@
+//! Recording of raw websocket frames, replayed with `WebSockets::test_handle_msg`
+
+use crate::commons::errors::*;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// the buffer is written out every FLUSH_FRAMES frames or FLUSH_INTERVAL
+const FLUSH_FRAMES: usize = 100;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Appends the text frames of a websocket to a file, one `<receive time in µs>\t<frame>`
+/// line per frame. Can be shared by several sockets
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    writer: Arc<Mutex<Writer>>,
+}
+
+#[derive(Debug)]
+struct Writer {
+    file: BufWriter<File>,
+    pending: usize,
+    flushed_at: Instant,
+}
+
+impl Recorder {
+    /// Creates the recording, or appends to it if it exists
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Recorder> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Recorder {
+            writer: Arc::new(Mutex::new(Writer {
+                file: BufWriter::new(file),
+                pending: 0,
+                flushed_at: Instant::now(),
+            })),
+        })
+    }
+
+    /// Appends a frame received now
+    pub fn record(&self, frame: &str) -> Result<()> {
+        self.record_at(now_micros(), frame)
+    }
+
+    /// Appends a frame received at `received_at`, in microseconds since the epoch
+    pub fn record_at(&self, received_at: u64, frame: &str) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        // raw newlines can only be whitespace in a JSON frame
+        writeln!(writer.file, "{received_at}\t{}", frame.replace('\n', " "))?;
+        writer.pending += 1;
+        if writer.pending >= FLUSH_FRAMES || writer.flushed_at.elapsed() >= FLUSH_INTERVAL {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes out the buffered frames, which is otherwise done every
+    /// 100 frames or second and when the last clone is dropped
+    pub fn flush(&self) -> Result<()> {
+        self.writer.lock().unwrap().flush()?;
+        Ok(())
+    }
+}
+
+impl Writer {
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.pending = 0;
+        self.flushed_at = Instant::now();
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedFrame {
+    /// Receive time in microseconds since the epoch
+    pub received_at: u64,
+    pub frame: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ReplaySpeed {
+    /// Frames are fed with their recorded spacing
+    RealTime,
+    /// Frames are fed back to back
+    #[default]
+    AsFastAsPossible,
+}
+
+/// Feeds a recording back, e.g. to `WebSockets::test_handle_msg`
+pub struct Replayer {
+    lines: std::io::Lines<BufReader<File>>,
+    speed: ReplaySpeed,
+}
+
+impl Replayer {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Replayer> {
+        Ok(Replayer {
+            lines: BufReader::new(File::open(path)?).lines(),
+            speed: ReplaySpeed::default(),
+        })
+    }
+
+    pub fn set_speed(mut self, speed: ReplaySpeed) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Calls `handler` with every frame, stops at the first error
+    /// and returns the number of frames replayed
+    pub fn run<F>(self, mut handler: F) -> Result<usize>
+    where
+        F: FnMut(&str) -> Result<()>,
+    {
+        let speed = self.speed;
+        let mut previous: Option<u64> = None;
+        let mut count = 0;
+        for frame in self {
+            let frame = frame?;
+            if let (ReplaySpeed::RealTime, Some(previous)) = (speed, previous) {
+                thread::sleep(Duration::from_micros(
+                    frame.received_at.saturating_sub(previous),
+                ));
+            }
+            previous = Some(frame.received_at);
+            handler(&frame.frame)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl Iterator for Replayer {
+    type Item = Result<RecordedFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = match self.lines.next()? {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e.into())),
+        };
+        Some(parse_line(&line))
+    }
+}
+
+fn parse_line(line: &str) -> Result<RecordedFrame> {
+    let (received_at, frame) = line
+        .split_once('\t')
+        .ok_or_else(|| BinanceError::ParseError(format!("Invalid recorded frame: {line}")))?;
+    Ok(RecordedFrame {
+        received_at: received_at
+            .parse()
+            .map_err(|_| BinanceError::ParseError(format!("Invalid receive time: {line}")))?,
+        frame: frame.to_string(),
+    })
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_micros() as u64)
+        .unwrap_or_default()
+}
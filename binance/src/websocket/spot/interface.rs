@@ -0,0 +1,335 @@
+use crate::commons::config::Config;
+use crate::commons::errors::*;
+use crate::commons::workers::{sleep, Workers};
+use crate::rest::api::Binance;
+use crate::rest::model::{AggrTradesEvent, Balance, BookTickerEvent, OrderTradeEvent};
+use crate::rest::spot::account::Account;
+use crate::websocket::managed::ManagedWebSockets;
+use crate::websocket::spot::spot_data::SpotWsData;
+use crate::websocket::spot::userstream::UserStream;
+use crate::websocket::spot::{WebSockets, WebsocketEvent};
+use log::{debug, error, warn};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// listen keys expire after 60 minutes without a keep-alive
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(1800);
+const USER_STREAM_RETRY: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(10);
+pub const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+type UserStreamWs = Arc<RwLock<UserStreamStatus>>;
+
+#[derive(Debug, Default)]
+struct UserStreamStatus {
+    connected: bool,
+    last_error: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct SpotWsInterface {
+    ws_data: SpotWsData,
+    user_stream: UserStreamWs,
+    workers: Workers,
+}
+
+impl SpotWsInterface {
+    /// Binance spot interface,
+    /// subscribes to @aggTrade and @bookTicker and, with an api key, to the user data stream
+    /// * `symbol` - String
+    /// * `api_key` - Option<String>
+    /// * `api_secret` - Option<String>
+    /// * `config` - Config
+    ///
+    /// Returns a `Timeout` error if no book ticker is received, or the user stream
+    /// does not connect with an `api_key`, within `DEFAULT_READY_TIMEOUT`
+    pub fn new(
+        symbol: String,
+        api_key: Option<String>,
+        api_secret: Option<String>,
+        config: &Config,
+    ) -> Result<SpotWsInterface> {
+        SpotWsInterface::with_ready_timeout(
+            symbol,
+            api_key,
+            api_secret,
+            config,
+            DEFAULT_READY_TIMEOUT,
+        )
+    }
+
+    /// Binance spot interface
+    /// * `ready_timeout` - time to receive a book ticker and, with an `api_key`,
+    ///   to connect the user stream
+    pub fn with_ready_timeout(
+        symbol: String,
+        api_key: Option<String>,
+        api_secret: Option<String>,
+        config: &Config,
+        ready_timeout: Duration,
+    ) -> Result<SpotWsInterface> {
+        let ws_int = SpotWsInterface {
+            ws_data: SpotWsData::default(),
+            user_stream: Arc::new(RwLock::new(UserStreamStatus::default())),
+            workers: Workers::default(),
+        };
+        let requires_user_stream = api_key.is_some();
+        if requires_user_stream {
+            user_stream_websocket(ws_int.clone(), api_key, api_secret, config.to_owned());
+        }
+        market_websocket(ws_int.clone(), symbol, config.to_owned());
+        if let Err(e) = ws_int.wait_for_data(ready_timeout, requires_user_stream) {
+            ws_int.shutdown();
+            return Err(e);
+        }
+        Ok(ws_int)
+    }
+
+    fn wait_for_data(&self, timeout: Duration, requires_user_stream: bool) -> Result<()> {
+        debug!("Waiting for data");
+        let deadline = Instant::now() + timeout;
+        loop {
+            let has_book_ticker = self.ws_data.get_book_ticker().is_some();
+            let user_stream = self.user_stream.read().unwrap();
+            let user_stream_ready = !requires_user_stream || user_stream.connected;
+            if has_book_ticker && user_stream_ready {
+                break;
+            }
+            if Instant::now() >= deadline {
+                return Err(BinanceError::Timeout(match has_book_ticker {
+                    false => format!("No book ticker after {timeout:?}"),
+                    true => format!(
+                        "User stream not connected after {timeout:?}: {}",
+                        user_stream.last_error.as_deref().unwrap_or("no response")
+                    ),
+                }));
+            }
+            drop(user_stream);
+            thread::sleep(READY_POLL_INTERVAL);
+        }
+        debug!("Finished waiting for data");
+        Ok(())
+    }
+
+    /// Stops the websockets and their threads and closes the listen key, blocks until done.
+    /// The data received so far stays readable
+    pub fn shutdown(&self) {
+        self.workers.shutdown();
+    }
+
+    /// Get aggr_trades
+    pub fn get_aggr_trades(&self) -> VecDeque<AggrTradesEvent> {
+        self.ws_data.get_aggr_trades()
+    }
+
+    /// Get last aggr_trade
+    pub fn get_last_aggr_trade(&self) -> Option<AggrTradesEvent> {
+        self.ws_data.get_last_aggr_trade()
+    }
+
+    /// Get book ticker
+    pub fn get_book_ticker(&self) -> Option<BookTickerEvent> {
+        self.ws_data.get_book_ticker()
+    }
+
+    /// Get balances
+    pub fn get_balances(&self) -> Vec<Balance> {
+        self.ws_data.get_balances()
+    }
+
+    /// Get balance of an asset
+    pub fn get_balance(&self, asset: &str) -> Option<Balance> {
+        self.ws_data.get_balance(asset)
+    }
+
+    /// Get open orders
+    pub fn get_open_orders(&self) -> VecDeque<OrderTradeEvent> {
+        self.ws_data.get_open_orders()
+    }
+
+    /// Get filled orders
+    pub fn get_filled_orders(&self) -> VecDeque<OrderTradeEvent> {
+        self.ws_data.get_filled_orders()
+    }
+
+    /// Get canceled orders
+    pub fn get_canceled_orders(&self) -> VecDeque<OrderTradeEvent> {
+        self.ws_data.get_canceled_orders()
+    }
+
+    /// Get order
+    ///
+    /// * `order_id` - id of order
+    pub fn get_order(&self, order_id: u64) -> Option<OrderTradeEvent> {
+        self.ws_data
+            .get_open_order(order_id)
+            .or_else(|| self.ws_data.get_filled_order(order_id))
+            .or_else(|| self.ws_data.get_canceled_order(order_id))
+    }
+}
+
+fn user_stream_websocket(
+    ws_int: SpotWsInterface,
+    api_key: Option<String>,
+    api_secret: Option<String>,
+    config: Config,
+) {
+    let workers = ws_int.workers.clone();
+    workers.spawn(move |running| {
+        let ws_data = ws_int.ws_data.clone();
+        while running.load(Ordering::Relaxed) {
+            let user_stream: UserStream =
+                Binance::new_with_config(api_key.to_owned(), api_secret.to_owned(), &config);
+            let keep_running = Arc::new(AtomicBool::new(true));
+
+            let listen_key = match user_stream.start() {
+                Ok(answer) => answer.listen_key,
+                Err(e) => {
+                    error!("Not able to start an User Stream (Check your API_KEY): {e}");
+                    ws_int.user_stream.write().unwrap().last_error = Some(e.to_string());
+                    sleep(running, USER_STREAM_RETRY);
+                    continue;
+                }
+            };
+            let (tx, rx) = mpsc::channel();
+            user_stream_keep_alive(
+                &ws_int.workers,
+                rx,
+                user_stream.to_owned(),
+                listen_key.to_owned(),
+                keep_running.clone(),
+            );
+
+            let mut web_socket: WebSockets<'_> = WebSockets::new(|event: WebsocketEvent| {
+                match event {
+                    WebsocketEvent::AccountPosition(account_position) => {
+                        debug!("Received AccountPositionEvent : {account_position:?}");
+                        ws_data.update_account_position(account_position);
+                    }
+                    WebsocketEvent::BalanceDelta(balance_delta) => {
+                        debug!("Received BalanceDeltaEvent : {balance_delta:?}");
+                        ws_data.apply_balance_delta(balance_delta);
+                    }
+                    WebsocketEvent::OrderTrade(trade) => {
+                        debug!("Received OrderTradeEvent : {trade:?}");
+                        ws_data.add_order(trade);
+                    }
+                    _ => {
+                        warn!("Received unhandled event : {event:?}")
+                    }
+                };
+                Ok(())
+            });
+
+            match web_socket.connect_multiple_streams_with_config(&[listen_key.to_owned()], &config)
+            {
+                Ok(()) => {
+                    // the user stream can be quiet for hours, lets the loop notice a rejected key
+                    let _ = web_socket.set_read_timeout(Some(READ_TIMEOUT));
+                    // snapshot after connecting so no update is missed in between
+                    let account: Account = Binance::new_with_config(
+                        api_key.to_owned(),
+                        api_secret.to_owned(),
+                        &config,
+                    );
+                    match account.get_account() {
+                        Ok(info) => ws_data.set_balances(info.balances, info.update_time),
+                        Err(e) => error!("Error loading balances: {e}"),
+                    }
+                    ws_int.user_stream.write().unwrap().connected = true;
+                    if let Err(e) = web_socket.event_loop(&keep_running) {
+                        error!("Error: {e}");
+                    }
+                    if let Err(e) = web_socket.disconnect() {
+                        error!("Error disconnecting from websocket: {e}");
+                    }
+                }
+                Err(e) => {
+                    error!("Error connecting to user stream: {e}");
+                    ws_int.user_stream.write().unwrap().last_error = Some(e.to_string());
+                    sleep(running, USER_STREAM_RETRY);
+                }
+            }
+            if let Err(e) = user_stream.close(&listen_key) {
+                error!("Error closing user stream: {e}");
+            }
+            let _ = tx.send(());
+            debug!("User stream closed and disconnected");
+        }
+    });
+}
+
+// keeps the listen key alive, stops the user stream loop when the key is rejected or
+// the interface shuts down
+fn user_stream_keep_alive(
+    workers: &Workers,
+    rx: Receiver<()>,
+    user_stream: UserStream,
+    listen_key: String,
+    keep_running: Arc<AtomicBool>,
+) {
+    workers.spawn(move |running| {
+        let mut last_keep_alive = Instant::now();
+        loop {
+            match rx.recv_timeout(READ_TIMEOUT) {
+                Err(RecvTimeoutError::Timeout) => {
+                    if !running.load(Ordering::Relaxed) {
+                        keep_running.store(false, Ordering::Relaxed);
+                        break;
+                    }
+                    if last_keep_alive.elapsed() < KEEP_ALIVE_INTERVAL {
+                        continue;
+                    }
+                    last_keep_alive = Instant::now();
+                    match user_stream.keep_alive(&listen_key) {
+                        Ok(msg) => debug!("Keepalive user data stream: {msg:?}"),
+                        Err(BinanceError::BinanceError { response }) => {
+                            // the listen key expired, restarts the user stream with a new one
+                            error!("Listen key rejected: {response:?}");
+                            keep_running.store(false, Ordering::Relaxed);
+                            break;
+                        }
+                        Err(e) => warn!("Error: {e}"),
+                    }
+                }
+                _ => {
+                    debug!("Terminating.");
+                    break;
+                }
+            }
+        }
+    });
+}
+
+fn market_websocket(ws_int: SpotWsInterface, symbol: String, config: Config) {
+    let workers = ws_int.workers.clone();
+    workers.spawn(move |running| {
+        let ws_data = ws_int.ws_data.clone();
+        let streams = vec![
+            symbol.to_lowercase() + "@aggTrade",
+            symbol.to_lowercase() + "@bookTicker",
+        ];
+
+        let web_socket: WebSockets<'_> = WebSockets::new(|event: WebsocketEvent| {
+            match event {
+                WebsocketEvent::AggrTrades(trade) => ws_data.add_aggr_trades(trade),
+                WebsocketEvent::BookTicker(book_ticker) => ws_data.update_book_ticker(book_ticker),
+                _ => {
+                    warn!("Received unhandled event : {event:?}")
+                }
+            };
+            Ok(())
+        });
+        let mut web_socket = ManagedWebSockets::new(web_socket, streams, &config)
+            .set_connection_handler(|event| debug!("Spot market websocket: {event:?}"));
+        if let Err(e) = web_socket.event_loop(running) {
+            error!("Error: {e}");
+        }
+        debug!("Spot market websocket disconnected");
+    });
+}
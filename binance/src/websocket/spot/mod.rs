@@ -1,10 +1,19 @@
 use crate::commons::config::Config;
 use crate::commons::errors::*;
 use crate::rest::model::{
-    AccountUpdateEvent, AggrTradesEvent, BalanceUpdateEvent, BookTickerEvent, DayTickerEvent,
-    DepthOrderBookEvent, KlineEvent, OrderBook, OrderTradeEvent, TradeEvent,
+    AccountPositionEvent, AccountUpdateEvent, AggrTradesEvent, AvgPriceEvent, BalanceDeltaEvent,
+    BalanceUpdateEvent, BookTickerEvent, DayTickerEvent, DepthOrderBookEvent, KlineEvent,
+    MiniTickerEvent, OrderBook, OrderTradeEvent, RollingTickerEvent, TradeEvent,
 };
+use crate::websocket::decode::Frame;
+use crate::websocket::recorder::Recorder;
+use crate::websocket::subscription::{
+    command_poll_timeout, StreamController, StreamMethod, StreamResponse, Subscriptions,
+};
+use crate::websocket::{is_read_timeout, set_read_timeout};
+use log::warn;
 use serde::{Deserialize, Serialize};
+use serde_json::from_str;
 use std::net::TcpStream;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
@@ -14,12 +23,15 @@ use tungstenite::stream::MaybeTlsStream;
 use tungstenite::{connect, Message};
 use url::Url;
 
+pub mod interface;
+pub mod spot_data;
 pub mod userstream;
 
 #[allow(clippy::all)]
 enum WebsocketAPI {
     Default,
     MultiStream,
+    MultiStreamCustom(String),
     Custom(String),
 }
 
@@ -30,6 +42,12 @@ impl WebsocketAPI {
             WebsocketAPI::MultiStream => {
                 format!("wss://stream.binance.com:9443/stream?streams={subscription}")
             }
+            WebsocketAPI::MultiStreamCustom(url) => {
+                format!(
+                    "{}/stream?streams={subscription}",
+                    url.trim_end_matches("/ws")
+                )
+            }
             WebsocketAPI::Custom(url) => format!("{url}/{subscription}"),
         }
     }
@@ -38,14 +56,22 @@ impl WebsocketAPI {
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum WebsocketEvent {
+    StreamResponse(StreamResponse),
     AccountUpdate(AccountUpdateEvent),
     BalanceUpdate(BalanceUpdateEvent),
+    AccountPosition(AccountPositionEvent),
+    BalanceDelta(BalanceDeltaEvent),
     OrderTrade(OrderTradeEvent),
     AggrTrades(AggrTradesEvent),
     Trade(TradeEvent),
     OrderBook(OrderBook),
     DayTicker(DayTickerEvent),
     DayTickerAll(Vec<DayTickerEvent>),
+    MiniTicker(MiniTickerEvent),
+    MiniTickerAll(Vec<MiniTickerEvent>),
+    RollingTicker(RollingTickerEvent),
+    RollingTickerAll(Vec<RollingTickerEvent>),
+    AvgPrice(AvgPriceEvent),
     Kline(KlineEvent),
     DepthOrderBook(DepthOrderBookEvent),
     BookTicker(BookTickerEvent),
@@ -54,22 +80,11 @@ pub enum WebsocketEvent {
 pub struct WebSockets<'a> {
     pub socket: Option<(WebSocket<MaybeTlsStream<TcpStream>>, Response)>,
     handler: Box<dyn FnMut(WebsocketEvent) -> Result<()> + 'a>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(untagged)]
-enum Events {
-    Vec(Vec<DayTickerEvent>),
-    BalanceUpdateEvent(BalanceUpdateEvent),
-    DayTickerEvent(DayTickerEvent),
-    BookTickerEvent(BookTickerEvent),
-    AccountUpdateEvent(AccountUpdateEvent),
-    OrderTradeEvent(OrderTradeEvent),
-    AggrTradesEvent(AggrTradesEvent),
-    TradeEvent(TradeEvent),
-    KlineEvent(KlineEvent),
-    OrderBook(OrderBook),
-    DepthOrderBookEvent(DepthOrderBookEvent),
+    subscriptions: Subscriptions,
+    awaiting: Option<u64>,
+    awaited: Option<StreamResponse>,
+    read_timeout: Option<Duration>,
+    recorder: Option<Recorder>,
 }
 
 impl<'a> WebSockets<'a> {
@@ -80,32 +95,74 @@ impl<'a> WebSockets<'a> {
         WebSockets {
             socket: None,
             handler: Box::new(handler),
+            subscriptions: Subscriptions::default(),
+            awaiting: None,
+            awaited: None,
+            read_timeout: None,
+            recorder: None,
         }
     }
 
     pub fn connect(&mut self, subscription: &str) -> Result<()> {
-        self.connect_wss(WebsocketAPI::Default.params(subscription))
+        self.connect_wss(
+            WebsocketAPI::Default.params(subscription),
+            &[subscription.to_string()],
+        )
     }
 
     pub fn connect_with_config(&mut self, subscription: &str, config: &Config) -> Result<()> {
-        self.connect_wss(WebsocketAPI::Custom(config.ws_endpoint.clone()).params(subscription))
+        self.connect_wss(
+            WebsocketAPI::Custom(config.ws_endpoint.clone()).params(subscription),
+            &[subscription.to_string()],
+        )
     }
 
     pub fn connect_multiple_streams(&mut self, endpoints: &[String]) -> Result<()> {
-        self.connect_wss(WebsocketAPI::MultiStream.params(&endpoints.join("/")))
+        self.connect_wss(
+            WebsocketAPI::MultiStream.params(&endpoints.join("/")),
+            endpoints,
+        )
+    }
+
+    pub fn connect_multiple_streams_with_config(
+        &mut self,
+        endpoints: &[String],
+        config: &Config,
+    ) -> Result<()> {
+        self.connect_wss(
+            WebsocketAPI::MultiStreamCustom(config.ws_endpoint.clone())
+                .params(&endpoints.join("/")),
+            endpoints,
+        )
     }
 
-    fn connect_wss(&mut self, wss: String) -> Result<()> {
+    fn connect_wss(&mut self, wss: String, streams: &[String]) -> Result<()> {
         let url = Url::parse(&wss)?;
         match connect(url.as_str()) {
             Ok(answer) => {
                 self.socket = Some(answer);
+                self.subscriptions.connected(streams);
+                self.set_read_timeout(self.read_timeout)?;
                 Ok(())
             }
             Err(_) => Err(BinanceError::WebSocket(WebSocketError::Disconnected)),
         }
     }
 
+    /// Read timeout of the socket, lets the event loop check `running` on quiet streams
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.read_timeout = timeout;
+        if let Some(ref mut socket) = self.socket {
+            set_read_timeout(&mut socket.0, timeout)?;
+        }
+        Ok(())
+    }
+
+    /// Appends every text frame received to `recorder`
+    pub fn set_recorder(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
     pub fn disconnect(&mut self) -> Result<()> {
         if let Some(ref mut socket) = self.socket {
             socket.0.close(None)?;
@@ -114,50 +171,156 @@ impl<'a> WebSockets<'a> {
         Err(BinanceError::WebSocket(WebSocketError::Disconnected))
     }
 
+    /// Subscribes to `streams` on the open connection and returns the request id,
+    /// the ack is delivered to the handler as `StreamResponse`
+    pub fn subscribe(&mut self, streams: &[String]) -> Result<u64> {
+        self.send_request(StreamMethod::Subscribe, streams)
+    }
+
+    /// Unsubscribes from `streams` on the open connection and returns the request id
+    pub fn unsubscribe(&mut self, streams: &[String]) -> Result<u64> {
+        self.send_request(StreamMethod::Unsubscribe, streams)
+    }
+
+    /// Requests the active streams of the connection and returns the request id
+    pub fn list_subscriptions(&mut self) -> Result<u64> {
+        self.send_request(StreamMethod::ListSubscriptions, &[])
+    }
+
+    /// Controller to (un)subscribe from another thread while the event loop runs,
+    /// requests are sent between reads
+    pub fn stream_controller(&self) -> StreamController {
+        self.subscriptions.controller()
+    }
+
+    /// Streams of the connection, updated with every successful ack
+    pub fn subscribed_streams(&self) -> &[String] {
+        self.subscriptions.active()
+    }
+
+    /// Reads the socket until the ack of request `id` arrives, other events are passed to the
+    /// handler. The timeout is checked between messages
+    pub fn wait_for_response(&mut self, id: u64, timeout: Duration) -> Result<StreamResponse> {
+        self.awaiting = Some(id);
+        let deadline = Instant::now() + timeout;
+        let response = loop {
+            if let Some(response) = self.awaited.take() {
+                break Ok(response);
+            }
+            if Instant::now() >= deadline {
+                break Err(BinanceError::WebSocket(WebSocketError::MessageError(
+                    format!("No response to request {id}"),
+                )));
+            }
+            let message = match self.socket {
+                Some(ref mut socket) => socket.0.read(),
+                None => break Err(BinanceError::WebSocket(WebSocketError::Disconnected)),
+            };
+            let handled = match message {
+                Ok(Message::Text(msg)) => {
+                    self.record(&msg);
+                    self.handle_msg(&msg)
+                }
+                Ok(Message::Ping(data)) => self.send_message(Message::Pong(data)),
+                Ok(Message::Close(_)) => Err(BinanceError::WebSocket(WebSocketError::Disconnected)),
+                Ok(_) => Ok(()),
+                Err(e) if is_read_timeout(&e) => Ok(()),
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = handled {
+                break Err(e);
+            }
+        };
+        self.awaiting = None;
+        response
+    }
+
+    fn send_request(&mut self, method: StreamMethod, streams: &[String]) -> Result<u64> {
+        let (id, payload) = self.subscriptions.request(method, streams);
+        self.send_message(Message::text(payload))?;
+        Ok(id)
+    }
+
+    fn send_message(&mut self, message: Message) -> Result<()> {
+        if let Some(ref mut socket) = self.socket {
+            socket.0.send(message)?;
+            return Ok(());
+        }
+        Err(BinanceError::WebSocket(WebSocketError::Disconnected))
+    }
+
+    fn record(&self, msg: &str) {
+        if let Some(ref recorder) = self.recorder {
+            if let Err(e) = recorder.record(msg) {
+                warn!("Unable to record frame: {e}");
+            }
+        }
+    }
+
     pub fn test_handle_msg(&mut self, msg: &str) -> Result<()> {
         self.handle_msg(msg)
     }
 
+    // an unknown event type is skipped, a known one that does not decode is an error
     fn handle_msg(&mut self, msg: &str) -> Result<()> {
-        let value: serde_json::Value = serde_json::from_str(msg)?;
+        let frame = Frame::parse(msg)?;
 
-        if let Some(data) = value.get("data") {
-            self.handle_msg(&data.to_string())?;
+        if frame.is_ack {
+            let value: serde_json::Value = serde_json::from_str(msg)?;
+            if let Some(response) = self.subscriptions.handle_response(&value) {
+                if self.awaiting == Some(response.id) {
+                    self.awaited = Some(response);
+                    return Ok(());
+                }
+                return (self.handler)(WebsocketEvent::StreamResponse(response));
+            }
             return Ok(());
         }
 
-        if let Ok(events) = serde_json::from_value::<Events>(value) {
-            let action = match events {
-                Events::Vec(v) => WebsocketEvent::DayTickerAll(v),
-                Events::BookTickerEvent(v) => WebsocketEvent::BookTicker(v),
-                Events::BalanceUpdateEvent(v) => WebsocketEvent::BalanceUpdate(v),
-                Events::AccountUpdateEvent(v) => WebsocketEvent::AccountUpdate(v),
-                Events::OrderTradeEvent(v) => WebsocketEvent::OrderTrade(v),
-                Events::AggrTradesEvent(v) => WebsocketEvent::AggrTrades(v),
-                Events::TradeEvent(v) => WebsocketEvent::Trade(v),
-                Events::DayTickerEvent(v) => WebsocketEvent::DayTicker(v),
-                Events::KlineEvent(v) => WebsocketEvent::Kline(v),
-                Events::OrderBook(v) => WebsocketEvent::OrderBook(v),
-                Events::DepthOrderBookEvent(v) => WebsocketEvent::DepthOrderBook(v),
-            };
-            (self.handler)(action)?;
+        match decode_event(&frame)? {
+            Some(event) => (self.handler)(event),
+            None => Ok(()),
         }
-        Ok(())
     }
 
     pub fn event_loop(&mut self, running: &AtomicBool) -> Result<()> {
+        self.event_loop_for(running, Duration::from_secs(8 * 3600))
+    }
+
+    /// Runs the event loop until `running` is unset or the connection has been open for
+    /// `max_duration`, in which case the socket is closed and `Ok` is returned
+    pub fn event_loop_for(&mut self, running: &AtomicBool, max_duration: Duration) -> Result<()> {
         let start_time = Instant::now();
+        if let Some(ref mut socket) = self.socket {
+            // a quiet stream still wakes the loop to send the queued requests
+            set_read_timeout(&mut socket.0, Some(command_poll_timeout(self.read_timeout)))?;
+        }
         while running.load(Ordering::Relaxed) {
             if let Some(ref mut socket) = self.socket {
-                if Instant::now().duration_since(start_time) >= Duration::from_secs(8 * 3600) {
+                if Instant::now().duration_since(start_time) >= max_duration {
                     socket.0.close(None)?;
                     break;
                 }
-                let message = socket.0.read()?;
+                for payload in self.subscriptions.queued() {
+                    socket.0.send(Message::text(payload))?;
+                }
+                if self.subscriptions.take_reconnect() {
+                    return Err(BinanceError::WebSocket(WebSocketError::ConnectionError(
+                        "Reconnect requested".into(),
+                    )));
+                }
+                let message = match socket.0.read() {
+                    Ok(message) => message,
+                    Err(e) if is_read_timeout(&e) => continue,
+                    Err(e) => return Err(e.into()),
+                };
                 match message {
-                    Message::Text(msg) => self.handle_msg(&msg)?,
+                    Message::Text(msg) => {
+                        self.record(&msg);
+                        self.handle_msg(&msg)?
+                    }
                     Message::Ping(data) => {
-                        socket.0.send(Message::Pong(data)).unwrap();
+                        socket.0.send(Message::Pong(data))?;
                     }
                     Message::Pong(_) | Message::Binary(_) => (),
                     Message::Close(_) => {
@@ -170,3 +333,47 @@ impl<'a> WebSockets<'a> {
         Ok(())
     }
 }
+
+fn decode_event(frame: &Frame) -> serde_json::Result<Option<WebsocketEvent>> {
+    let payload = frame.payload;
+    let event = match (frame.event_type.as_deref(), frame.is_array) {
+        (Some("aggTrade"), false) => WebsocketEvent::AggrTrades(from_str(payload)?),
+        (Some("trade"), false) => WebsocketEvent::Trade(from_str(payload)?),
+        (Some("24hrTicker"), false) => WebsocketEvent::DayTicker(from_str(payload)?),
+        (Some("24hrTicker"), true) => WebsocketEvent::DayTickerAll(from_str(payload)?),
+        (Some("24hrMiniTicker"), false) => WebsocketEvent::MiniTicker(from_str(payload)?),
+        (Some("24hrMiniTicker"), true) => WebsocketEvent::MiniTickerAll(from_str(payload)?),
+        (Some("1hTicker" | "4hTicker" | "1dTicker"), false) => {
+            WebsocketEvent::RollingTicker(from_str(payload)?)
+        }
+        (Some("1hTicker" | "4hTicker" | "1dTicker"), true) => {
+            WebsocketEvent::RollingTickerAll(from_str(payload)?)
+        }
+        (Some("avgPrice"), false) => WebsocketEvent::AvgPrice(from_str(payload)?),
+        (Some("kline"), false) => WebsocketEvent::Kline(from_str(payload)?),
+        (Some("depthUpdate"), false) => WebsocketEvent::DepthOrderBook(from_str(payload)?),
+        (Some("executionReport"), false) => WebsocketEvent::OrderTrade(from_str(payload)?),
+        (Some("outboundAccountPosition"), false) => {
+            WebsocketEvent::AccountPosition(from_str(payload)?)
+        }
+        (Some("balanceUpdate"), false) => WebsocketEvent::BalanceDelta(from_str(payload)?),
+        (Some("outboundAccountInfo"), false) => WebsocketEvent::BalanceUpdate(from_str(payload)?),
+        (Some("ACCOUNT_UPDATE"), false) => WebsocketEvent::AccountUpdate(from_str(payload)?),
+        // book tickers and depth snapshots have no event type
+        (None, false) if frame.stream_has("@bookTicker") => {
+            WebsocketEvent::BookTicker(from_str(payload)?)
+        }
+        (None, false) if frame.stream_has("@depth") => {
+            WebsocketEvent::OrderBook(from_str(payload)?)
+        }
+        (None, false) if frame.stream.is_none() => {
+            if let Ok(book_ticker) = from_str(payload) {
+                WebsocketEvent::BookTicker(book_ticker)
+            } else {
+                WebsocketEvent::OrderBook(from_str(payload)?)
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(event))
+}
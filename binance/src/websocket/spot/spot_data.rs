@@ -0,0 +1,328 @@
+use crate::rest::model::{
+    AccountPositionEvent, AggrTradesEvent, Balance, BalanceDeltaEvent, BookTickerEvent,
+    OrderTradeEvent,
+};
+use indexmap::IndexMap;
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+type AggrTradesWs = Arc<RwLock<VecDeque<AggrTradesEvent>>>;
+type BookTickerWs = Arc<RwLock<Option<BookTickerEvent>>>;
+// balance and time of its last update
+type BalancesWs = Arc<RwLock<IndexMap<String, (Balance, u64)>>>;
+type OrdersWs = Arc<RwLock<IndexMap<u64, OrderTradeEvent>>>;
+
+const DATA_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Default)]
+pub struct SpotWsData {
+    aggr_trades: AggrTradesWs,
+    book_ticker: BookTickerWs,
+    balances: BalancesWs,
+    filled_orders: OrdersWs,
+    open_orders: OrdersWs,
+    canceled_orders: OrdersWs,
+}
+
+impl SpotWsData {
+    pub fn get_aggr_trades(&self) -> VecDeque<AggrTradesEvent> {
+        self.aggr_trades.read().unwrap().clone()
+    }
+
+    pub fn get_last_aggr_trade(&self) -> Option<AggrTradesEvent> {
+        self.aggr_trades.read().unwrap().back().cloned()
+    }
+
+    pub fn get_book_ticker(&self) -> Option<BookTickerEvent> {
+        self.book_ticker.read().unwrap().clone()
+    }
+
+    pub fn get_balances(&self) -> Vec<Balance> {
+        self.balances
+            .read()
+            .unwrap()
+            .values()
+            .map(|(balance, _)| balance.clone())
+            .collect()
+    }
+
+    pub fn get_balance(&self, asset: &str) -> Option<Balance> {
+        self.balances
+            .read()
+            .unwrap()
+            .get(asset)
+            .map(|(balance, _)| balance.clone())
+    }
+
+    pub fn get_filled_orders(&self) -> VecDeque<OrderTradeEvent> {
+        self.filled_orders
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_open_orders(&self) -> VecDeque<OrderTradeEvent> {
+        self.open_orders.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn get_canceled_orders(&self) -> VecDeque<OrderTradeEvent> {
+        self.canceled_orders
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_filled_order(&self, order_id: u64) -> Option<OrderTradeEvent> {
+        get_order(self.filled_orders.read().unwrap(), order_id)
+    }
+
+    pub fn get_open_order(&self, order_id: u64) -> Option<OrderTradeEvent> {
+        get_order(self.open_orders.read().unwrap(), order_id)
+    }
+
+    pub fn get_canceled_order(&self, order_id: u64) -> Option<OrderTradeEvent> {
+        get_order(self.canceled_orders.read().unwrap(), order_id)
+    }
+
+    pub fn add_aggr_trades(&self, event: AggrTradesEvent) {
+        let mut aggr_trades = self.aggr_trades.write().unwrap();
+        aggr_trades.push_back(event);
+        if aggr_trades.len() > DATA_SIZE {
+            aggr_trades.pop_front();
+        }
+    }
+
+    pub fn update_book_ticker(&self, event: BookTickerEvent) {
+        *self.book_ticker.write().unwrap() = Some(event);
+    }
+
+    /// Balances of a REST account snapshot, skips the assets updated since `update_time`
+    pub fn set_balances(&self, balances: Vec<Balance>, update_time: u64) {
+        let mut stored = self.balances.write().unwrap();
+        for balance in balances {
+            match stored.get(&balance.asset) {
+                Some((_, time)) if *time > update_time => (),
+                _ => {
+                    stored.insert(balance.asset.clone(), (balance, update_time));
+                }
+            }
+        }
+    }
+
+    pub fn update_account_position(&self, event: AccountPositionEvent) {
+        let mut stored = self.balances.write().unwrap();
+        for balance in event.balances {
+            stored.insert(
+                balance.asset.clone(),
+                (
+                    Balance {
+                        asset: balance.asset,
+                        free: balance.free,
+                        locked: balance.locked,
+                    },
+                    event.last_update_time,
+                ),
+            );
+        }
+    }
+
+    /// Applies a deposit, withdrawal or transfer to the free balance,
+    /// unless an `outboundAccountPosition` already reported it
+    pub fn apply_balance_delta(&self, event: BalanceDeltaEvent) {
+        let mut stored = self.balances.write().unwrap();
+        let (balance, time) = stored.entry(event.asset.clone()).or_insert_with(|| {
+            (
+                Balance {
+                    asset: event.asset.clone(),
+                    free: "0".into(),
+                    locked: "0".into(),
+                },
+                0,
+            )
+        });
+        if *time >= event.clear_time {
+            return;
+        }
+        let free = balance.free.parse::<f64>().unwrap_or_default()
+            + event.balance_delta.parse::<f64>().unwrap_or_default();
+        balance.free = free.to_string();
+        *time = event.clear_time;
+    }
+
+    pub fn add_order(&self, order: OrderTradeEvent) {
+        let order_id = order.order_id;
+
+        match order.order_status.as_str() {
+            "NEW" | "PARTIALLY_FILLED" | "PENDING_NEW" => {
+                insert_order_index_map(self.open_orders.write().unwrap(), order_id, order);
+            }
+            "FILLED" => {
+                insert_order_index_map(self.filled_orders.write().unwrap(), order_id, order);
+                self.open_orders.write().unwrap().shift_remove(&order_id);
+            }
+            // rejected and expired orders (e.g. LIMIT_MAKER crossing the book) are never filled
+            "CANCELED" | "EXPIRED" | "EXPIRED_IN_MATCH" | "REJECTED" => {
+                insert_order_index_map(self.canceled_orders.write().unwrap(), order_id, order);
+                self.open_orders.write().unwrap().shift_remove(&order_id);
+            }
+            _ => (),
+        }
+    }
+}
+
+fn insert_order_index_map(
+    mut index_map: RwLockWriteGuard<IndexMap<u64, OrderTradeEvent>>,
+    order_id: u64,
+    order: OrderTradeEvent,
+) {
+    if index_map.insert(order_id, order).is_none() && index_map.len() == DATA_SIZE + 1 {
+        index_map.shift_remove_index(0);
+    }
+}
+
+fn get_order(
+    index_map: RwLockReadGuard<IndexMap<u64, OrderTradeEvent>>,
+    order_id: u64,
+) -> Option<OrderTradeEvent> {
+    index_map.get(&order_id).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::websocket::spot::{WebSockets, WebsocketEvent};
+
+    fn execution_report(status: &str, filled: &str) -> OrderTradeEvent {
+        let json = format!(
+            r#"{{
+            "e": "executionReport",
+            "E": 1499405658658,
+            "s": "ETHBTC",
+            "c": "mUvoqJxFIILMdfAW5iGSOW",
+            "S": "BUY",
+            "o": "LIMIT",
+            "f": "GTC",
+            "q": "1.00000000",
+            "p": "0.10264410",
+            "P": "0.00000000",
+            "F": "0.00000000",
+            "g": -1,
+            "C": "",
+            "x": "{status}",
+            "X": "{status}",
+            "r": "NONE",
+            "i": 4293153,
+            "l": "0.00000000",
+            "z": "{filled}",
+            "L": "0.00000000",
+            "n": "0",
+            "N": null,
+            "T": 1499405658657,
+            "t": -1,
+            "I": 8641984,
+            "w": true,
+            "m": false,
+            "M": false,
+            "O": 1499405658657,
+            "Z": "0.00000000",
+            "Y": "0.00000000",
+            "Q": "0.00000000",
+            "W": 1499405658657,
+            "V": "NONE"
+            }}"#
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn test_order_transitions() {
+        let ws_data = SpotWsData::default();
+        ws_data.add_order(execution_report("NEW", "0.00000000"));
+        assert_eq!(ws_data.get_open_orders().len(), 1);
+
+        ws_data.add_order(execution_report("PARTIALLY_FILLED", "0.40000000"));
+        assert_eq!(ws_data.get_open_orders().len(), 1);
+        assert_eq!(
+            ws_data
+                .get_open_order(4293153)
+                .unwrap()
+                .accumulated_qty_filled_trades,
+            "0.40000000"
+        );
+
+        ws_data.add_order(execution_report("FILLED", "1.00000000"));
+        assert!(ws_data.get_open_orders().is_empty());
+        assert!(ws_data.get_filled_order(4293153).is_some());
+
+        ws_data.add_order(execution_report("EXPIRED", "0.00000000"));
+        assert_eq!(ws_data.get_canceled_orders().len(), 1);
+    }
+
+    #[test]
+    fn test_balances() {
+        let ws_data = SpotWsData::default();
+        let position: AccountPositionEvent = serde_json::from_str(
+            r#"{"e":"outboundAccountPosition","E":1564034571105,"u":1564034571073,
+            "B":[{"a":"ETH","f":"10000.000000","l":"0.000000"}]}"#,
+        )
+        .unwrap();
+        ws_data.update_account_position(position);
+
+        // an older REST snapshot does not overwrite the websocket balance
+        ws_data.set_balances(
+            vec![
+                Balance {
+                    asset: "ETH".into(),
+                    free: "1.0".into(),
+                    locked: "0.0".into(),
+                },
+                Balance {
+                    asset: "BTC".into(),
+                    free: "2.0".into(),
+                    locked: "0.5".into(),
+                },
+            ],
+            1564034500000,
+        );
+        assert_eq!(ws_data.get_balance("ETH").unwrap().free, "10000.000000");
+        assert_eq!(ws_data.get_balance("BTC").unwrap().locked, "0.5");
+
+        let delta: BalanceDeltaEvent = serde_json::from_str(
+            r#"{"e":"balanceUpdate","E":1573200697110,"a":"BTC","d":"100.00000000","T":1573200697068}"#,
+        )
+        .unwrap();
+        ws_data.apply_balance_delta(delta.clone());
+        assert_eq!(ws_data.get_balance("BTC").unwrap().free, "102");
+        // applied once
+        ws_data.apply_balance_delta(delta);
+        assert_eq!(ws_data.get_balance("BTC").unwrap().free, "102");
+        assert_eq!(ws_data.get_balances().len(), 2);
+    }
+
+    #[test]
+    fn test_user_data_events() {
+        let mut events = vec![];
+        let mut web_socket = WebSockets::new(|event: WebsocketEvent| {
+            events.push(event);
+            Ok(())
+        });
+        web_socket
+            .test_handle_msg(
+                r#"{"e":"outboundAccountPosition","E":1564034571105,"u":1564034571073,
+                "B":[{"a":"ETH","f":"10000.000000","l":"0.000000"}]}"#,
+            )
+            .unwrap();
+        web_socket
+            .test_handle_msg(
+                r#"{"e":"balanceUpdate","E":1573200697110,"a":"BTC","d":"100.00000000","T":1573200697068}"#,
+            )
+            .unwrap();
+        drop(web_socket);
+        assert!(matches!(events[0], WebsocketEvent::AccountPosition(_)));
+        assert!(matches!(events[1], WebsocketEvent::BalanceDelta(_)));
+    }
+}
@@ -0,0 +1,64 @@
+//! Stream names, to pass to `connect_multiple_streams` or `subscribe`
+
+use std::fmt;
+
+/// Window of the spot rolling ticker streams
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickerWindow {
+    OneHour,
+    FourHours,
+    OneDay,
+}
+
+impl fmt::Display for TickerWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::OneHour => write!(f, "1h"),
+            Self::FourHours => write!(f, "4h"),
+            Self::OneDay => write!(f, "1d"),
+        }
+    }
+}
+
+// spot and futures
+
+pub fn mini_ticker(symbol: &str) -> String {
+    format!("{}@miniTicker", symbol.to_lowercase())
+}
+
+pub fn all_mini_tickers() -> String {
+    "!miniTicker@arr".into()
+}
+
+// spot
+
+pub fn avg_price(symbol: &str) -> String {
+    format!("{}@avgPrice", symbol.to_lowercase())
+}
+
+pub fn rolling_ticker(symbol: &str, window: TickerWindow) -> String {
+    format!("{}@ticker_{window}", symbol.to_lowercase())
+}
+
+pub fn all_rolling_tickers(window: TickerWindow) -> String {
+    format!("!ticker_{window}@arr")
+}
+
+// futures
+
+pub fn composite_index(symbol: &str) -> String {
+    format!("{}@compositeIndex", symbol.to_lowercase())
+}
+
+pub fn contract_info() -> String {
+    "!contractInfo".into()
+}
+
+/// * `asset_symbol` - e.g. ADAUSD
+pub fn asset_index(asset_symbol: &str) -> String {
+    format!("{}@assetIndex", asset_symbol.to_lowercase())
+}
+
+pub fn all_asset_indexes() -> String {
+    "!assetIndex@arr".into()
+}
@@ -0,0 +1,301 @@
+use crate::commons::errors::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+// https://developers.binance.com/docs/binance-spot-api-docs/web-socket-streams#live-subscribingunsubscribing-to-streams
+
+// longest wait of an event loop on a quiet stream before it sends the queued requests
+const COMMAND_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum StreamMethod {
+    Subscribe,
+    Unsubscribe,
+    ListSubscriptions,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct StreamError {
+    pub code: i64,
+    pub msg: String,
+}
+
+/// Ack of a SUBSCRIBE, UNSUBSCRIBE or LIST_SUBSCRIPTIONS request
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct StreamResponse {
+    pub id: u64,
+    pub method: StreamMethod,
+    /// Streams sent with the request
+    pub params: Vec<String>,
+    /// Active streams for LIST_SUBSCRIPTIONS
+    pub result: Option<Vec<String>>,
+    pub error: Option<StreamError>,
+}
+
+impl StreamResponse {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(Serialize)]
+struct StreamRequest<'a> {
+    method: StreamMethod,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    params: &'a [String],
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct RawResponse {
+    id: Option<u64>,
+    #[serde(default)]
+    result: Option<Vec<String>>,
+    #[serde(default)]
+    error: Option<StreamError>,
+}
+
+enum Command {
+    Request(u64, StreamMethod, Vec<String>),
+    Reconnect,
+}
+
+/// Sends subscription requests to a socket running its event loop on another thread,
+/// the acks are delivered to the event handler. Requests are sent within 100ms, even on
+/// a quiet stream
+#[derive(Debug, Clone)]
+pub struct StreamController {
+    next_id: Arc<AtomicU64>,
+    commands: Sender<Command>,
+}
+
+impl StreamController {
+    /// Returns the request id
+    pub fn subscribe(&self, streams: &[String]) -> Result<u64> {
+        self.send(StreamMethod::Subscribe, streams)
+    }
+
+    /// Returns the request id
+    pub fn unsubscribe(&self, streams: &[String]) -> Result<u64> {
+        self.send(StreamMethod::Unsubscribe, streams)
+    }
+
+    /// Returns the request id
+    pub fn list_subscriptions(&self) -> Result<u64> {
+        self.send(StreamMethod::ListSubscriptions, &[])
+    }
+
+    /// Drops the connection before the next read, a `ManagedWebSockets` then reconnects
+    pub fn reconnect(&self) -> Result<()> {
+        self.commands
+            .send(Command::Reconnect)
+            .map_err(|_| BinanceError::WebSocket(WebSocketError::Disconnected))
+    }
+
+    fn send(&self, method: StreamMethod, streams: &[String]) -> Result<u64> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.commands
+            .send(Command::Request(id, method, streams.to_vec()))
+            .map_err(|_| BinanceError::WebSocket(WebSocketError::Disconnected))?;
+        Ok(id)
+    }
+}
+
+/// Read timeout of an event loop, the configured one capped so the requests of a
+/// `StreamController` are not held until the next message
+pub(crate) fn command_poll_timeout(read_timeout: Option<Duration>) -> Duration {
+    read_timeout.map_or(COMMAND_POLL_INTERVAL, |timeout| {
+        timeout.min(COMMAND_POLL_INTERVAL)
+    })
+}
+
+/// Pending requests and active streams of a combined stream connection
+pub(crate) struct Subscriptions {
+    next_id: Arc<AtomicU64>,
+    pending: HashMap<u64, (StreamMethod, Vec<String>)>,
+    active: Vec<String>,
+    commands: Sender<Command>,
+    queued: Receiver<Command>,
+    reconnect: bool,
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        let (commands, queued) = mpsc::channel();
+        Subscriptions {
+            next_id: Arc::new(AtomicU64::new(1)),
+            pending: HashMap::new(),
+            active: Vec::new(),
+            commands,
+            queued,
+            reconnect: false,
+        }
+    }
+}
+
+impl Subscriptions {
+    pub(crate) fn controller(&self) -> StreamController {
+        StreamController {
+            next_id: self.next_id.clone(),
+            commands: self.commands.clone(),
+        }
+    }
+
+    pub(crate) fn active(&self) -> &[String] {
+        &self.active
+    }
+
+    /// Called on connect, pending requests died with the previous connection
+    pub(crate) fn connected(&mut self, streams: &[String]) {
+        self.pending.clear();
+        self.active = streams.to_vec();
+        self.reconnect = false;
+    }
+
+    /// Registers a request and returns its id and payload
+    pub(crate) fn request(&mut self, method: StreamMethod, streams: &[String]) -> (u64, String) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        (id, self.register(id, method, streams.to_vec()))
+    }
+
+    /// Payloads of the requests sent through a `StreamController`
+    pub(crate) fn queued(&mut self) -> Vec<String> {
+        let mut payloads = vec![];
+        while let Ok(command) = self.queued.try_recv() {
+            match command {
+                Command::Request(id, method, streams) => {
+                    payloads.push(self.register(id, method, streams))
+                }
+                Command::Reconnect => self.reconnect = true,
+            }
+        }
+        payloads
+    }
+
+    /// True once after a `StreamController::reconnect`
+    pub(crate) fn take_reconnect(&mut self) -> bool {
+        std::mem::take(&mut self.reconnect)
+    }
+
+    fn register(&mut self, id: u64, method: StreamMethod, streams: Vec<String>) -> String {
+        let payload = serde_json::to_string(&StreamRequest {
+            method,
+            params: &streams,
+            id,
+        })
+        .unwrap_or_default();
+        self.pending.insert(id, (method, streams));
+        payload
+    }
+
+    /// Matches an ack with its request, `None` if the message is not an ack
+    pub(crate) fn handle_response(&mut self, value: &serde_json::Value) -> Option<StreamResponse> {
+        if value.get("id").is_none()
+            || (value.get("result").is_none() && value.get("error").is_none())
+        {
+            return None;
+        }
+        let response: RawResponse = serde_json::from_value(value.clone()).ok()?;
+        let id = response.id?;
+        let (method, params) = self.pending.remove(&id)?;
+
+        if response.error.is_none() {
+            match method {
+                StreamMethod::Subscribe => {
+                    for stream in &params {
+                        if !self.active.contains(stream) {
+                            self.active.push(stream.clone());
+                        }
+                    }
+                }
+                StreamMethod::Unsubscribe => self.active.retain(|stream| !params.contains(stream)),
+                StreamMethod::ListSubscriptions => {
+                    if let Some(ref streams) = response.result {
+                        self.active = streams.clone();
+                    }
+                }
+            }
+        }
+
+        Some(StreamResponse {
+            id,
+            method,
+            params,
+            result: response.result,
+            error: response.error,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscription_acks() {
+        let mut subscriptions = Subscriptions::default();
+        subscriptions.connected(&["btcusdt@aggTrade".to_string()]);
+
+        let (id, payload) =
+            subscriptions.request(StreamMethod::Subscribe, &["ethusdt@aggTrade".to_string()]);
+        assert_eq!(
+            payload,
+            format!(r#"{{"method":"SUBSCRIBE","params":["ethusdt@aggTrade"],"id":{id}}}"#)
+        );
+        let controller = subscriptions.controller();
+        let unsubscribe_id = controller
+            .unsubscribe(&["btcusdt@aggTrade".to_string()])
+            .unwrap();
+        let list_id = controller.list_subscriptions().unwrap();
+        let queued = subscriptions.queued();
+        assert_eq!(queued.len(), 2);
+        assert_eq!(
+            queued[1],
+            format!(r#"{{"method":"LIST_SUBSCRIPTIONS","id":{list_id}}}"#)
+        );
+
+        let ack: serde_json::Value =
+            serde_json::from_str(&format!(r#"{{"result":null,"id":{unsubscribe_id}}}"#)).unwrap();
+        let response = subscriptions.handle_response(&ack).unwrap();
+        assert_eq!(response.method, StreamMethod::Unsubscribe);
+        assert!(subscriptions.active().is_empty());
+
+        let ack: serde_json::Value =
+            serde_json::from_str(&format!(r#"{{"result":null,"id":{id}}}"#)).unwrap();
+        assert!(subscriptions.handle_response(&ack).unwrap().is_ok());
+        assert_eq!(subscriptions.active(), ["ethusdt@aggTrade".to_string()]);
+
+        let ack: serde_json::Value = serde_json::from_str(&format!(
+            r#"{{"result":["ethusdt@aggTrade","btcusdt@depth"],"id":{list_id}}}"#
+        ))
+        .unwrap();
+        let response = subscriptions.handle_response(&ack).unwrap();
+        assert_eq!(response.result.unwrap().len(), 2);
+        assert_eq!(subscriptions.active().len(), 2);
+
+        // unknown ids and stream data are not acks
+        let ack: serde_json::Value = serde_json::from_str(r#"{"result":null,"id":999}"#).unwrap();
+        assert!(subscriptions.handle_response(&ack).is_none());
+        let data: serde_json::Value = serde_json::from_str(r#"{"e":"aggTrade","s":"X"}"#).unwrap();
+        assert!(subscriptions.handle_response(&data).is_none());
+    }
+
+    #[test]
+    fn test_subscription_error() {
+        let mut subscriptions = Subscriptions::default();
+        let (id, _) = subscriptions.request(StreamMethod::Subscribe, &["bad".to_string()]);
+        let ack: serde_json::Value = serde_json::from_str(&format!(
+            r#"{{"error":{{"code":2,"msg":"Invalid request: unknown stream"}},"id":{id}}}"#
+        ))
+        .unwrap();
+        let response = subscriptions.handle_response(&ack).unwrap();
+        assert!(!response.is_ok());
+        assert_eq!(response.error.unwrap().code, 2);
+        assert!(subscriptions.active().is_empty());
+    }
+}
@@ -0,0 +1,149 @@
+use binance::rest::client::Client;
+use binance::rest::futures::account::{PositionSide, WorkingType};
+use binance::rest::spot::account::OrderSide;
+use binance::testing::fake_binance::FakeBinance;
+use binance::testing::wait_until;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server};
+
+    #[test]
+    fn take_profit_cancels_stop_loss() {
+        let fake = FakeBinance::start(&["BTCUSDT"], 100.0).unwrap();
+        let usdm = fake.usdm_interface("BTCUSDT").unwrap();
+        let bracket = usdm
+            .bracket_order(
+                "BTCUSDT",
+                OrderSide::Buy,
+                1.0,
+                None,
+                110.0,
+                90.0,
+                WorkingType::MarkPrice,
+                None,
+            )
+            .unwrap();
+        wait_until(|| usdm.is_filled_orders_ws(bracket.entry.order_id));
+        wait_until(|| usdm.is_open_orders_ws(bracket.stop_loss.order_id));
+        assert!(bracket.is_active());
+
+        fake.set_mark_price("BTCUSDT", 111.0);
+        wait_until(|| usdm.is_filled_orders_ws(bracket.take_profit.order_id));
+        wait_until(|| usdm.is_canceled_orders_ws(bracket.stop_loss.order_id));
+        assert!(!bracket.is_active());
+        usdm.shutdown();
+    }
+
+    #[test]
+    fn canceled_entry_cancels_exits() {
+        let fake = FakeBinance::start(&["BTCUSDT"], 100.0).unwrap();
+        let usdm = fake.usdm_interface("BTCUSDT").unwrap();
+        let bracket = usdm
+            .bracket_order(
+                "BTCUSDT",
+                OrderSide::Sell,
+                1.0,
+                Some(105.0),
+                90.0,
+                110.0,
+                WorkingType::ContractPrice,
+                None,
+            )
+            .unwrap();
+        wait_until(|| usdm.is_open_orders_ws(bracket.entry.order_id));
+
+        usdm.cancel_order("BTCUSDT", bracket.entry.order_id)
+            .unwrap();
+        wait_until(|| usdm.is_canceled_orders_ws(bracket.take_profit.order_id));
+        wait_until(|| usdm.is_canceled_orders_ws(bracket.stop_loss.order_id));
+        assert!(!bracket.is_active());
+        usdm.shutdown();
+    }
+
+    #[test]
+    fn cancel_bracket_cancels_its_legs_only() {
+        let fake = FakeBinance::start(&["BTCUSDT"], 100.0).unwrap();
+        let usdm = fake.usdm_interface("BTCUSDT").unwrap();
+        let place = |price: f64| {
+            usdm.bracket_order(
+                "BTCUSDT",
+                OrderSide::Buy,
+                1.0,
+                Some(price),
+                110.0,
+                90.0,
+                WorkingType::ContractPrice,
+                None,
+            )
+            .unwrap()
+        };
+        let canceled = place(95.0);
+        let kept = place(96.0);
+        wait_until(|| usdm.is_open_orders_ws(kept.stop_loss.order_id));
+
+        usdm.cancel_bracket(&canceled).unwrap();
+        assert!(!canceled.is_active());
+        for order_id in [
+            canceled.entry.order_id,
+            canceled.take_profit.order_id,
+            canceled.stop_loss.order_id,
+        ] {
+            wait_until(|| usdm.is_canceled_orders_ws(order_id));
+        }
+        assert!(kept.is_active());
+        assert!(usdm.is_open_orders_ws(kept.entry.order_id));
+        assert!(usdm.is_open_orders_ws(kept.take_profit.order_id));
+        usdm.shutdown();
+    }
+
+    #[test]
+    fn hedge_mode_exits_are_sent_without_reduce_only() {
+        let fake = FakeBinance::start(&["BTCUSDT"], 100.0).unwrap();
+        let mut server = Server::new();
+        // Binance rejects reduceOnly with a LONG or SHORT position side
+        let reduce_only = server
+            .mock("POST", "/fapi/v1/order")
+            .match_query(Matcher::Regex("reduceOnly".into()))
+            .with_status(400)
+            .with_body(r#"{"code":-1106,"msg":"Parameter 'reduceonly' sent when not required."}"#)
+            .expect(0)
+            .create();
+        let placed = server
+            .mock("POST", "/fapi/v1/order")
+            .match_query(Matcher::Regex("positionSide=LONG".into()))
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .with_body_from_file("tests/mocks/futures/account/trailing_stop_market_sell.json")
+            .expect(2)
+            .create();
+        let mut usdm = fake.usdm_interface("BTCUSDT").unwrap();
+        usdm.api = Client::new(
+            Some("api-key".into()),
+            Some("api-secret".into()),
+            server.url(),
+        );
+
+        usdm.trailing_stop_market_sell(
+            "BTCUSDT",
+            1.0,
+            1.0,
+            None,
+            WorkingType::MarkPrice,
+            PositionSide::Long,
+        )
+        .unwrap();
+        usdm.take_profit_limit_sell(
+            "BTCUSDT",
+            1.0,
+            110.0,
+            109.0,
+            WorkingType::MarkPrice,
+            PositionSide::Long,
+        )
+        .unwrap();
+        reduce_only.assert();
+        placed.assert();
+        usdm.shutdown();
+    }
+}
@@ -0,0 +1,230 @@
+use binance::interfaces::execution::{
+    ChaseConfig, Execution, ExecutionProgress, ExecutionState, IcebergConfig, TwapConfig,
+};
+use binance::interfaces::usdm::UsdmInterface;
+use binance::rest::spot::account::OrderSide;
+use binance::testing::fake_binance::FakeBinance;
+use binance::testing::wait_until;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    // waits for a child order other than `previous` and returns it with its price
+    fn next_child<F>(usdm: &UsdmInterface, progress: F, previous: Option<u64>) -> (u64, String)
+    where
+        F: Fn() -> ExecutionProgress,
+    {
+        wait_until(|| {
+            progress().working_order.is_some_and(|order_id| {
+                Some(order_id) != previous && usdm.is_open_orders_ws(order_id)
+            })
+        });
+        let order_id = progress().working_order.unwrap();
+        (order_id, usdm.get_order_ws(order_id).unwrap().price)
+    }
+
+    // prints a trade through `price` and one back in the middle of the book, the fake
+    // expires a post only order crossing the last trade
+    fn trade_through(fake: &FakeBinance, price: f64, qty: f64) {
+        fake.trade("BTCUSDT", price, qty);
+        fake.trade("BTCUSDT", 100.0, 0.001);
+    }
+
+    #[test]
+    fn twap_works_one_slice_at_a_time() {
+        let fake = FakeBinance::start(&["BTCUSDT"], 100.0).unwrap();
+        fake.set_book("BTCUSDT", 99.0, 101.0);
+        let usdm = fake.usdm_interface("BTCUSDT").unwrap();
+        wait_until(|| usdm.ws.get_book_ticker().is_some());
+        let handle = Execution::new(usdm.clone())
+            .twap(
+                "BTCUSDT",
+                OrderSide::Buy,
+                2.0,
+                None,
+                TwapConfig::new(2, 2000),
+            )
+            .unwrap();
+
+        let (first, price) = next_child(&usdm, || handle.get_progress(), None);
+        assert_eq!(price, "99");
+        assert_eq!(usdm.get_order_ws(first).unwrap().qty, "1");
+        trade_through(&fake, 98.9, 1.0);
+        wait_until(|| handle.get_progress().filled_qty == 1.0);
+        // the second slice waits for its share of the duration
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(handle.get_progress().child_orders, 1);
+
+        let (second, _) = next_child(&usdm, || handle.get_progress(), Some(first));
+        assert_eq!(usdm.get_order_ws(second).unwrap().qty, "1");
+        trade_through(&fake, 98.9, 1.0);
+        let progress = handle.wait();
+        assert_eq!(progress.state, ExecutionState::Completed);
+        assert_eq!(progress.filled_qty, 2.0);
+        assert_eq!(progress.avg_price, 99.0);
+        assert_eq!(progress.child_orders, 2);
+        // the top of the book comes from the book ticker stream
+        assert!(!fake.requests().contains(&"GET /fapi/v1/depth".to_string()));
+        usdm.shutdown();
+    }
+
+    #[test]
+    fn iceberg_refills_the_displayed_quantity() {
+        let fake = FakeBinance::start(&["BTCUSDT"], 100.0).unwrap();
+        let usdm = fake.usdm_interface("BTCUSDT").unwrap();
+        let handle = Execution::new(usdm.clone())
+            .iceberg(
+                "BTCUSDT",
+                OrderSide::Sell,
+                2.5,
+                None,
+                IcebergConfig::new(101.0, 1.0),
+            )
+            .unwrap();
+
+        let mut previous = None;
+        for (qty, filled) in [("1", 1.0), ("1", 2.0), ("0.5", 2.5)] {
+            let (order_id, price) = next_child(&usdm, || handle.get_progress(), previous);
+            assert_eq!(price, "101");
+            assert_eq!(usdm.get_order_ws(order_id).unwrap().qty, qty);
+            trade_through(&fake, 101.5, 5.0);
+            wait_until(|| handle.get_progress().filled_qty == filled);
+            previous = Some(order_id);
+        }
+        let progress = handle.wait();
+        assert_eq!(progress.state, ExecutionState::Completed);
+        assert_eq!(progress.child_orders, 3);
+        usdm.shutdown();
+    }
+
+    #[test]
+    fn iceberg_stops_after_its_duration() {
+        let fake = FakeBinance::start(&["BTCUSDT"], 100.0).unwrap();
+        let usdm = fake.usdm_interface("BTCUSDT").unwrap();
+        let handle = Execution::new(usdm.clone())
+            .iceberg(
+                "BTCUSDT",
+                OrderSide::Buy,
+                2.0,
+                None,
+                IcebergConfig::new(99.0, 1.0).set_duration(300),
+            )
+            .unwrap();
+        let (order_id, _) = next_child(&usdm, || handle.get_progress(), None);
+
+        let progress = handle.wait();
+        assert_eq!(progress.filled_qty, 0.0);
+        assert_eq!(
+            progress.state,
+            ExecutionState::Expired { remaining_qty: 2.0 }
+        );
+        assert_eq!(progress.child_orders, 1);
+        wait_until(|| usdm.is_canceled_orders_ws(order_id));
+        usdm.shutdown();
+    }
+
+    #[test]
+    fn iceberg_waits_for_the_book_to_move_away() {
+        let fake = FakeBinance::start(&["BTCUSDT"], 100.0).unwrap();
+        fake.set_book("BTCUSDT", 101.5, 102.0);
+        let usdm = fake.usdm_interface("BTCUSDT").unwrap();
+        wait_until(|| {
+            usdm.ws
+                .get_book_ticker()
+                .is_some_and(|book_ticker| book_ticker.best_bid == "101.5")
+        });
+        let handle = Execution::new(usdm.clone())
+            .iceberg(
+                "BTCUSDT",
+                OrderSide::Sell,
+                1.0,
+                None,
+                IcebergConfig::new(101.0, 1.0),
+            )
+            .unwrap();
+
+        // the first child would take the bid, it is not placed again while it would
+        wait_until(|| handle.get_progress().child_orders == 1);
+        thread::sleep(Duration::from_millis(500));
+        assert_eq!(handle.get_progress().child_orders, 1);
+
+        fake.set_book("BTCUSDT", 100.0, 101.5);
+        let (order_id, price) = next_child(&usdm, || handle.get_progress(), None);
+        assert_eq!(price, "101");
+        assert_eq!(handle.get_progress().child_orders, 2);
+        handle.cancel();
+        handle.wait();
+        wait_until(|| usdm.is_canceled_orders_ws(order_id));
+        usdm.shutdown();
+    }
+
+    #[test]
+    fn twap_expires_with_the_unfilled_quantity() {
+        let fake = FakeBinance::start(&["BTCUSDT"], 100.0).unwrap();
+        fake.set_book("BTCUSDT", 99.0, 101.0);
+        let usdm = fake.usdm_interface("BTCUSDT").unwrap();
+        wait_until(|| usdm.ws.get_book_ticker().is_some());
+        let handle = Execution::new(usdm.clone())
+            .twap(
+                "BTCUSDT",
+                OrderSide::Buy,
+                2.0,
+                None,
+                TwapConfig::new(2, 600),
+            )
+            .unwrap();
+
+        let (first, _) = next_child(&usdm, || handle.get_progress(), None);
+        trade_through(&fake, 98.9, 0.5);
+        wait_until(|| usdm.is_canceled_orders_ws(first));
+        let progress = handle.wait();
+        assert_eq!(progress.filled_qty, 0.5);
+        assert_eq!(
+            progress.state,
+            ExecutionState::Expired { remaining_qty: 1.5 }
+        );
+        usdm.shutdown();
+    }
+
+    #[test]
+    fn chase_reprices_when_the_book_moves() {
+        let fake = FakeBinance::start(&["BTCUSDT"], 100.0).unwrap();
+        fake.set_book("BTCUSDT", 99.0, 101.0);
+        let usdm = fake.usdm_interface("BTCUSDT").unwrap();
+        wait_until(|| usdm.ws.get_book_ticker().is_some());
+        let config = ChaseConfig::default()
+            .set_reprice_interval(100)
+            .set_limit_price(99.5);
+        let handle = Execution::new(usdm.clone())
+            .chase("BTCUSDT", OrderSide::Buy, 1.0, None, config)
+            .unwrap();
+
+        let (first, price) = next_child(&usdm, || handle.get_progress(), None);
+        assert_eq!(price, "99");
+        fake.set_book("BTCUSDT", 99.5, 101.0);
+        let (second, price) = next_child(&usdm, || handle.get_progress(), Some(first));
+        assert_eq!(price, "99.5");
+        assert!(usdm.is_canceled_orders_ws(first));
+
+        // beyond the limit price the order is not chased
+        fake.set_book("BTCUSDT", 99.8, 101.0);
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(handle.get_progress().working_order, None);
+        assert_eq!(handle.get_progress().child_orders, 2);
+        assert!(usdm.is_canceled_orders_ws(second));
+
+        fake.set_book("BTCUSDT", 99.2, 101.0);
+        let (third, price) = next_child(&usdm, || handle.get_progress(), Some(second));
+        assert_eq!(price, "99.2");
+        trade_through(&fake, 99.1, 1.0);
+        wait_until(|| usdm.is_filled_orders_ws(third));
+        let progress = handle.wait();
+        assert_eq!(progress.state, ExecutionState::Completed);
+        assert_eq!(progress.avg_price, 99.2);
+        assert_eq!(progress.child_orders, 3);
+        usdm.shutdown();
+    }
+}
@@ -0,0 +1,136 @@
+use binance::interfaces::paper::PaperConfig;
+use binance::interfaces::usdm::UsdmInterface;
+use binance::interfaces::usdm_data::UsdmConfig;
+use binance::testing::fake_binance::FakeBinance;
+use binance::testing::wait_until;
+use binance::websocket::channel::OverflowPolicy;
+use binance::websocket::futures::usdm_events::{UsdmEvent, UsdmEventKind};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_cmp::approx_eq;
+    use std::time::Duration;
+
+    #[test]
+    fn trades_offline() {
+        let fake = FakeBinance::start(&["BTCUSDT"], 100.0).unwrap();
+        let usdm = fake.usdm_interface("btcusdt").unwrap();
+        assert!(usdm.ws.get_mark_price().is_some());
+        assert_eq!(usdm.get_symbol_info("BTCUSDT").unwrap().quote_asset, "USDT");
+
+        let order = usdm.limit_buy("BTCUSDT", 1.0, 99.0, None).unwrap();
+        assert_eq!(order.side, "BUY");
+        wait_until(|| usdm.ws.is_open_order(order.order_id));
+        assert_eq!(
+            usdm.get_order("BTCUSDT", order.order_id).unwrap().status,
+            "NEW"
+        );
+
+        fake.trade("BTCUSDT", 98.5, 2.0);
+        wait_until(|| {
+            usdm.ws
+                .get_position()
+                .is_some_and(|p| p.position_amount == "1")
+        });
+        wait_until(|| !usdm.ws.get_filled_orders().is_empty());
+        assert_eq!(usdm.ws.get_aggr_trades().back().unwrap().price, "98.5");
+        let position = &usdm.position_information("BTCUSDT").unwrap()[0];
+        assert!(approx_eq!(f64, position.entry_price, 99.0));
+
+        usdm.market_sell("BTCUSDT", 1.0, None).unwrap();
+        wait_until(|| {
+            usdm.ws
+                .get_position()
+                .is_some_and(|p| p.position_amount == "0")
+        });
+        let balance = usdm.ws.get_balance().unwrap();
+        assert!(balance.wallet_balance.parse::<f64>().unwrap() < 10000.0);
+
+        let orders = usdm.get_all_orders("BTCUSDT", None, None).unwrap();
+        assert_eq!(orders.len(), 2);
+        let requests = fake.requests();
+        assert!(requests.contains(&"POST /fapi/v1/listenKey".to_string()));
+        assert!(requests.contains(&"GET /fapi/v1/klines".to_string()));
+        usdm.shutdown();
+        assert!(fake
+            .requests()
+            .contains(&"DELETE /fapi/v1/listenKey".to_string()));
+    }
+
+    #[test]
+    fn reconnects_after_dropped_connections() {
+        let fake = FakeBinance::start(&["BTCUSDT", "ETHUSDT"], 100.0).unwrap();
+        let usdm = UsdmInterface::new_multi(
+            vec!["BTCUSDT".into(), "ETHUSDT".into()],
+            Some("api-key".into()),
+            Some("api-secret".into()),
+            &fake.config(),
+            UsdmConfig::default(),
+        )
+        .unwrap();
+        let eth = usdm.get_symbol_ws("ETHUSDT").unwrap();
+        wait_until(|| fake.user_connections() >= 1);
+
+        fake.drop_connections();
+        fake.trade("ETHUSDT", 101.0, 1.0);
+        fake.set_mark_price("ETHUSDT", 102.0);
+        wait_until(|| {
+            eth.get_mark_price_event()
+                .is_some_and(|event| event.price == "102")
+        });
+        wait_until(|| fake.user_connections() >= 2);
+
+        let order = usdm.limit_sell("ETHUSDT", 1.0, 105.0, None).unwrap();
+        wait_until(|| usdm.ws.is_open_order(order.order_id));
+        fake.trade("ETHUSDT", 106.0, 1.0);
+        wait_until(|| eth.get_filled_order(order.order_id).is_some());
+        usdm.shutdown();
+    }
+
+    #[test]
+    fn notifies_changed_balances_only() {
+        let account = PaperConfig::default().set_fees(0.0, 0.0);
+        let fake = FakeBinance::with_account(&["BTCUSDT"], 100.0, account).unwrap();
+        let usdm = fake.usdm_interface("btcusdt").unwrap();
+        let events = usdm.subscribe_ws(
+            &[UsdmEventKind::Position, UsdmEventKind::Balance],
+            16,
+            OverflowPolicy::DropOldest,
+        );
+
+        // the first fill reports the balance
+        let fill = |price: f64| {
+            let order = usdm.limit_buy("BTCUSDT", 1.0, price, None).unwrap();
+            wait_until(|| usdm.ws.is_open_order(order.order_id));
+            fake.trade("BTCUSDT", price - 0.5, 1.0);
+            wait_until(|| usdm.ws.is_filled_order(order.order_id));
+        };
+        fill(99.0);
+        wait_until(|| usdm.ws.get_balance().is_some());
+        while let Some(event) = events.recv_timeout(Duration::from_millis(300)).unwrap() {
+            assert!(matches!(
+                event,
+                UsdmEvent::Position(_) | UsdmEvent::Balance(_)
+            ));
+        }
+
+        // a maker fill without fees leaves the wallet as it was
+        fill(98.0);
+        match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+            Some(UsdmEvent::Position(position)) => assert_eq!(position.position_amount, "2"),
+            event => panic!("unexpected event {event:?}"),
+        }
+
+        // closing at a loss, 98.5 to 97.5, changes it
+        usdm.market_sell("BTCUSDT", 2.0, None).unwrap();
+        let mut balances = vec![];
+        while let Some(event) = events.recv_timeout(Duration::from_secs(1)).unwrap() {
+            if let UsdmEvent::Balance(balance) = event {
+                balances.push(balance.wallet_balance);
+            }
+        }
+        assert_eq!(balances, vec!["9998"]);
+        usdm.shutdown();
+    }
+}
@@ -1,6 +1,8 @@
 use binance::commons::config::Config;
 use binance::rest::api::Binance;
-use binance::rest::futures::account::{CustomOrderRequest, FuturesAccount, OrderType};
+use binance::rest::futures::account::{
+    CustomOrderRequest, FuturesAccount, OrderType, PositionSide, WorkingType,
+};
 use binance::rest::futures::model::{Order, Transaction};
 use binance::rest::spot::account::OrderSide;
 
@@ -63,6 +65,34 @@ mod tests {
         mock.assert();
     }
 
+    #[test]
+    fn auto_cancel_all_open_orders() {
+        let mut server = Server::new();
+        let mock = server
+            .mock("POST", "/fapi/v1/countdownCancelAll")
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .match_query(Matcher::Regex(
+                "countdownTime=100000&recvWindow=1234&symbol=BTCUSDT&timestamp=\\d+&signature=.*"
+                    .into(),
+            ))
+            .with_body_from_file("tests/mocks/futures/account/auto_cancel_all_open_orders.json")
+            .create();
+
+        let config = Config::default()
+            .set_futures_rest_api_endpoint(server.url())
+            .set_recv_window(1234);
+        let account: FuturesAccount = Binance::new_with_config(None, None, &config);
+        let _ = env_logger::try_init();
+        let countdown = account
+            .auto_cancel_all_open_orders("BTCUSDT", 100000)
+            .unwrap();
+
+        mock.assert();
+
+        assert_eq!(countdown.symbol, "BTCUSDT");
+        assert_eq!(countdown.countdown_time, "100000");
+    }
+
     #[test]
     fn change_position_mode() {
         let mut server = Server::new();
@@ -135,6 +165,90 @@ mod tests {
         assert!(approx_eq!(f64, transaction.stop_price, 7.4, ulps = 2));
     }
 
+    #[test]
+    fn trailing_stop_market_sell() {
+        let mut server = Server::new();
+        let mock_trailing_stop = server.mock("POST", "/fapi/v1/order")
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .match_query(Matcher::Regex("activationPrice=30000&callbackRate=1&quantity=0.01&recvWindow=1234&reduceOnly=TRUE&side=SELL&symbol=BTCUSDT&timestamp=\\d+&type=TRAILING_STOP_MARKET&workingType=MARK_PRICE".into()))
+            .with_body_from_file("tests/mocks/futures/account/trailing_stop_market_sell.json")
+            .create();
+
+        let config = Config::default()
+            .set_futures_rest_api_endpoint(server.url())
+            .set_recv_window(1234);
+        let account: FuturesAccount = Binance::new_with_config(None, None, &config);
+        let _ = env_logger::try_init();
+        let transaction: Transaction = account
+            .trailing_stop_market_sell("BTCUSDT", 0.01, 1.0, 30000.0, WorkingType::MarkPrice, None)
+            .unwrap();
+
+        mock_trailing_stop.assert();
+
+        assert_eq!(transaction.symbol, "BTCUSDT");
+        assert_eq!(transaction.side, "SELL");
+        assert_eq!(transaction.orig_type, "TRAILING_STOP_MARKET");
+        assert!(transaction.reduce_only);
+        assert_eq!(transaction.activate_price, Some(30000.0));
+        assert_eq!(transaction.price_rate, Some(1.0));
+    }
+
+    #[test]
+    fn trailing_stop_market_sell_hedge_mode() {
+        let mut server = Server::new();
+        // the position side replaces reduceOnly, which Binance rejects in hedge mode
+        let mock_trailing_stop = server.mock("POST", "/fapi/v1/order")
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .match_query(Matcher::Regex("^activationPrice=30000&callbackRate=1&positionSide=LONG&quantity=0.01&recvWindow=1234&side=SELL&symbol=BTCUSDT&timestamp=\\d+&type=TRAILING_STOP_MARKET&workingType=MARK_PRICE&signature=.*$".into()))
+            .with_body_from_file("tests/mocks/futures/account/trailing_stop_market_sell.json")
+            .create();
+
+        let config = Config::default()
+            .set_futures_rest_api_endpoint(server.url())
+            .set_recv_window(1234);
+        let account: FuturesAccount = Binance::new_with_config(None, None, &config);
+        let _ = env_logger::try_init();
+        account
+            .trailing_stop_market_sell(
+                "BTCUSDT",
+                0.01,
+                1.0,
+                30000.0,
+                WorkingType::MarkPrice,
+                PositionSide::Long,
+            )
+            .unwrap();
+
+        mock_trailing_stop.assert();
+    }
+
+    #[test]
+    fn take_profit_market_close_buy() {
+        let mut server = Server::new();
+        let mock_take_profit = server.mock("POST", "/fapi/v1/order")
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .match_query(Matcher::Regex("closePosition=TRUE&recvWindow=1234&side=BUY&stopPrice=25000&symbol=BTCUSDT&timestamp=\\d+&type=TAKE_PROFIT_MARKET&workingType=MARK_PRICE".into()))
+            .with_body_from_file("tests/mocks/futures/account/take_profit_market_close_buy.json")
+            .create();
+
+        let config = Config::default()
+            .set_futures_rest_api_endpoint(server.url())
+            .set_recv_window(1234);
+        let account: FuturesAccount = Binance::new_with_config(None, None, &config);
+        let _ = env_logger::try_init();
+        let transaction: Transaction = account
+            .take_profit_market_close_buy("BTCUSDT", 25000.0, WorkingType::MarkPrice, None)
+            .unwrap();
+
+        mock_take_profit.assert();
+
+        assert_eq!(transaction.symbol, "BTCUSDT");
+        assert_eq!(transaction.side, "BUY");
+        assert_eq!(transaction.orig_type, "TAKE_PROFIT_MARKET");
+        assert!(transaction.close_position);
+        assert!(approx_eq!(f64, transaction.stop_price, 25000.0, ulps = 2));
+    }
+
     #[test]
     fn custom_order() {
         let mut server = Server::new();
@@ -0,0 +1,84 @@
+use binance::rest::client::Client;
+use binance::testing::fake_binance::FakeBinance;
+use binance::testing::wait_until;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server};
+    use serde_json::{json, Value};
+
+    // realized PnL of 1 per transaction
+    fn incomes(tran_ids: impl Iterator<Item = u64>, time: u64) -> Vec<Value> {
+        tran_ids
+            .map(|tran_id| {
+                json!({"symbol": "BTCUSDT", "incomeType": "REALIZED_PNL", "income": "1",
+                    "asset": "USDT", "info": "", "time": time, "tranId": tran_id,
+                    "tradeId": tran_id.to_string()})
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pages_through_the_income_history() {
+        let fake = FakeBinance::start(&["BTCUSDT"], 100.0).unwrap();
+        let mut server = Server::new();
+        let mut page = |start_time: &str, incomes: Vec<Value>| {
+            server
+                .mock("GET", "/fapi/v1/income")
+                .match_query(Matcher::UrlEncoded("startTime".into(), start_time.into()))
+                .with_header("content-type", "application/json;charset=UTF-8")
+                .with_body(Value::from(incomes).to_string())
+                .expect(1)
+                .create()
+        };
+        let mut first = incomes(1..1000, 5);
+        first.extend(incomes(1000..1001, 6));
+        // the last transaction of the first page is returned again
+        let mut second = incomes(1000..1001, 6);
+        second.extend(incomes(1001..2000, 6));
+        let pages = [
+            page("1", first),
+            page("6", second),
+            // the second page is all at 6
+            page("7", incomes(2000..2001, 8)),
+        ];
+        server
+            .mock("GET", Matcher::Regex("positionRisk".into()))
+            .match_query(Matcher::Any)
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .with_body("[]")
+            .create();
+        let mut usdm = fake.usdm_interface("BTCUSDT").unwrap();
+        usdm.api = Client::new(
+            Some("api-key".into()),
+            Some("api-secret".into()),
+            server.url(),
+        );
+
+        let ledger = usdm.start_ledger(1).unwrap();
+        for page in pages {
+            page.assert();
+        }
+        assert_eq!(ledger.get_symbol("BTCUSDT").unwrap().realized_pnl, 2000.0);
+        usdm.shutdown();
+    }
+
+    #[test]
+    fn seeds_again_after_a_reconnect() {
+        let fake = FakeBinance::start(&["BTCUSDT"], 100.0).unwrap();
+        let usdm = fake.usdm_interface("BTCUSDT").unwrap();
+        let seeds = || {
+            fake.requests()
+                .iter()
+                .filter(|request| *request == "GET /fapi/v1/income")
+                .count()
+        };
+        usdm.start_ledger(0).unwrap();
+        assert_eq!(seeds(), 1);
+
+        fake.drop_connections();
+        wait_until(|| seeds() >= 2);
+        usdm.shutdown();
+    }
+}
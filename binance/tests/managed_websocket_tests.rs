@@ -0,0 +1,226 @@
+use binance::commons::config::Config;
+use binance::websocket::feed::{FeedMonitor, StaleAction};
+use binance::websocket::futures::{FuturesWebSockets, FuturesWebsocketEvent};
+use binance::websocket::managed::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+    use tungstenite::handshake::server::{Request, Response};
+    use tungstenite::Message;
+
+    const AGG_TRADE: &str = r#"{"stream":"btcusdt@aggTrade","data":{"e":"aggTrade","E":123456789,"s":"BTCUSDT","a":5933014,"p":"0.001","q":"100","f":100,"l":105,"T":123456785,"m":true}}"#;
+
+    // accepts `connections` connections, sends one aggTrade on each and drops all but the last
+    #[allow(clippy::result_large_err)]
+    fn stand_in_server(connections: usize) -> (String, mpsc::Receiver<String>) {
+        stand_in_server_dropping(connections, true)
+    }
+
+    // with `drop` unset every connection stays open until the client closes it
+    #[allow(clippy::result_large_err)]
+    fn stand_in_server_dropping(
+        connections: usize,
+        drop: bool,
+    ) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("ws://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for i in 0..connections {
+                let (stream, _) = listener.accept().unwrap();
+                let tx = tx.clone();
+                let mut socket = tungstenite::accept_hdr(stream, |req: &Request, res: Response| {
+                    tx.send(req.uri().to_string()).unwrap();
+                    Ok(res)
+                })
+                .unwrap();
+                socket.send(Message::text(AGG_TRADE)).unwrap();
+                if drop && i + 1 < connections {
+                    socket.close(None).unwrap();
+                    let _ = socket.flush();
+                } else {
+                    // wait for the client to close
+                    while socket.read().is_ok() {}
+                }
+            }
+        });
+        (endpoint, rx)
+    }
+
+    #[test]
+    fn reconnects_and_resubscribes() {
+        let (endpoint, requests) = stand_in_server(2);
+        let config = Config::default().set_futures_ws_endpoint(endpoint);
+        let running = AtomicBool::new(true);
+        let trades = RefCell::new(0);
+        let events = RefCell::new(Vec::new());
+
+        let socket = FuturesWebSockets::new(|event: FuturesWebsocketEvent| {
+            if let FuturesWebsocketEvent::AggrTrades(trade) = event {
+                assert_eq!(trade.symbol, "BTCUSDT");
+                *trades.borrow_mut() += 1;
+                if *trades.borrow() == 2 {
+                    running.store(false, Ordering::Relaxed);
+                }
+            }
+            Ok(())
+        });
+        let streams = vec!["btcusdt@aggTrade".to_string()];
+        let mut web_socket = ManagedWebSockets::new(socket, streams, &config)
+            .set_policy(
+                ReconnectPolicy::default()
+                    .set_backoff(Duration::from_millis(10), Duration::from_millis(10))
+                    .set_jitter(0.0),
+            )
+            .set_connection_handler(|event| events.borrow_mut().push(event));
+
+        web_socket.event_loop(&running).unwrap();
+        drop(web_socket);
+
+        assert_eq!(*trades.borrow(), 2);
+        let first = requests.recv().unwrap();
+        let second = requests.recv().unwrap();
+        assert_eq!(first, "/stream?streams=btcusdt@aggTrade");
+        assert_eq!(first, second);
+
+        let events = events.into_inner();
+        assert_eq!(events[0], ConnectionEvent::Connected);
+        assert!(matches!(events[1], ConnectionEvent::Disconnected(_)));
+        assert_eq!(
+            events[2],
+            ConnectionEvent::Reconnecting {
+                attempt: 1,
+                delay: Duration::from_millis(10)
+            }
+        );
+        assert_eq!(events[3], ConnectionEvent::Connected);
+        assert!(matches!(events[4], ConnectionEvent::Gap { .. }));
+        assert_eq!(events[5], ConnectionEvent::Closed);
+    }
+
+    #[test]
+    fn reconnects_a_stale_feed() {
+        let (endpoint, requests) = stand_in_server_dropping(2, false);
+        let config = Config::default().set_futures_ws_endpoint(endpoint);
+        let running = AtomicBool::new(true);
+        let trades = RefCell::new(0);
+        let events = RefCell::new(Vec::new());
+        let monitor = FeedMonitor::default();
+        monitor.add_threshold(
+            "btcusdt@aggTrade",
+            Duration::from_millis(100),
+            StaleAction::Reconnect,
+        );
+
+        let socket = FuturesWebSockets::new(|event: FuturesWebsocketEvent| {
+            if let FuturesWebsocketEvent::AggrTrades(trade) = event {
+                monitor.record("btcusdt@aggTrade", trade.event_time);
+                *trades.borrow_mut() += 1;
+                if *trades.borrow() == 2 {
+                    running.store(false, Ordering::Relaxed);
+                }
+            }
+            Ok(())
+        });
+        let streams = vec!["btcusdt@aggTrade".to_string()];
+        let mut web_socket = ManagedWebSockets::new(socket, streams, &config)
+            .set_policy(
+                ReconnectPolicy::default()
+                    .set_backoff(Duration::from_millis(10), Duration::from_millis(10))
+                    .set_jitter(0.0),
+            )
+            .set_connection_handler(|event| events.borrow_mut().push(event));
+        web_socket
+            .socket()
+            .set_read_timeout(Some(Duration::from_millis(20)))
+            .unwrap();
+        monitor.set_stream_controller(web_socket.socket().stream_controller());
+
+        let checking = Arc::new(AtomicBool::new(true));
+        let checker = {
+            let monitor = monitor.clone();
+            let checking = checking.clone();
+            thread::spawn(move || {
+                while checking.load(Ordering::Relaxed) {
+                    monitor.check_thresholds();
+                    thread::sleep(Duration::from_millis(10));
+                }
+            })
+        };
+        web_socket.event_loop(&running).unwrap();
+        checking.store(false, Ordering::Relaxed);
+        checker.join().unwrap();
+        drop(web_socket);
+
+        assert_eq!(*trades.borrow(), 2);
+        assert_eq!(requests.recv().unwrap(), requests.recv().unwrap());
+        assert_eq!(
+            monitor.get_last_event_time("btcusdt@aggTrade"),
+            Some(123456789)
+        );
+        assert!(monitor.get_latency("btcusdt@aggTrade").is_some());
+
+        let events = events.into_inner();
+        assert_eq!(events[0], ConnectionEvent::Connected);
+        match &events[1] {
+            ConnectionEvent::Disconnected(reason) => {
+                assert!(reason.contains("Reconnect requested"))
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+        assert_eq!(events[3], ConnectionEvent::Connected);
+        assert_eq!(events.last(), Some(&ConnectionEvent::Closed));
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        // nothing listens on the port once the listener is dropped
+        let endpoint = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("ws://{}", listener.local_addr().unwrap())
+        };
+        let config = Config::default().set_futures_ws_endpoint(endpoint);
+        let running = AtomicBool::new(true);
+        let events = RefCell::new(Vec::new());
+
+        let socket = FuturesWebSockets::new(|_: FuturesWebsocketEvent| Ok(()));
+        let mut web_socket =
+            ManagedWebSockets::new(socket, vec!["btcusdt@aggTrade".to_string()], &config)
+                .set_policy(
+                    ReconnectPolicy::default()
+                        .set_backoff(Duration::from_millis(1), Duration::from_millis(5))
+                        .set_max_attempts(2),
+                )
+                .set_connection_handler(|event| events.borrow_mut().push(event));
+
+        assert!(web_socket.event_loop(&running).is_err());
+        drop(web_socket);
+        let events = events.into_inner();
+        assert_eq!(events.len(), 6);
+        assert_eq!(events.last(), Some(&ConnectionEvent::GaveUp));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let policy = ReconnectPolicy::default()
+            .set_backoff(Duration::from_millis(100), Duration::from_secs(1))
+            .set_jitter(0.0);
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(10), Duration::from_secs(1));
+
+        let policy = policy.set_jitter(0.5);
+        for attempt in 1..10 {
+            let delay = policy.backoff(attempt);
+            assert!(delay <= Duration::from_secs(1));
+            assert!(delay >= Duration::from_millis(50));
+        }
+    }
+}
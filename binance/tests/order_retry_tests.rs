@@ -0,0 +1,105 @@
+use binance::interfaces::retry::RetryPolicy;
+use binance::interfaces::usdm::UsdmInterface;
+use binance::interfaces::usdm_data::UsdmConfig;
+use binance::rest::client::Client;
+use binance::testing::fake_binance::FakeBinance;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server, ServerGuard};
+    use std::time::{Duration, Instant};
+
+    const TRANSACTION: &str = r#"{"clientOrderId":"abc","cumQty":"0","cumQuote":"0",
+        "executedQty":"0","orderId":1917641,"avgPrice":"0.00000","origQty":"1","price":"99",
+        "reduceOnly":false,"side":"BUY","positionSide":"BOTH","status":"NEW","stopPrice":"0",
+        "closePosition":false,"symbol":"BTCUSDT","timeInForce":"GTC","type":"LIMIT",
+        "origType":"LIMIT","updateTime":1579276756075,"workingType":"CONTRACT_PRICE",
+        "priceProtect":false}"#;
+
+    // the market data comes from the fake, the orders go to `server`
+    fn interface(fake: &FakeBinance, server: &ServerGuard, recv_window: u64) -> UsdmInterface {
+        let config = fake.config().set_recv_window(recv_window);
+        let retry_policy =
+            RetryPolicy::default().set_backoff(Duration::from_millis(50), Duration::from_secs(1));
+        let mut usdm = UsdmInterface::new(
+            "BTCUSDT".into(),
+            Some("api-key".into()),
+            Some("api-secret".into()),
+            &config,
+            UsdmConfig::default().set_retry_policy(retry_policy),
+        )
+        .unwrap();
+        usdm.api = Client::new(
+            Some("api-key".into()),
+            Some("api-secret".into()),
+            server.url(),
+        );
+        usdm
+    }
+
+    fn check_order(server: &mut ServerGuard) -> mockito::Mock {
+        server
+            .mock("GET", "/fapi/v1/order")
+            .match_query(Matcher::Regex("origClientOrderId=".into()))
+            .with_header("content-type", "application/json;charset=UTF-8")
+    }
+
+    #[test]
+    fn landed_order_is_not_sent_again() {
+        let fake = FakeBinance::start(&["BTCUSDT"], 100.0).unwrap();
+        let mut server = Server::new();
+        let post = server
+            .mock("POST", "/fapi/v1/order")
+            .match_query(Matcher::Any)
+            .with_status(502)
+            .expect(1)
+            .create();
+        let check = check_order(&mut server)
+            .with_body_from_file("tests/mocks/futures/account/order_status.json")
+            .expect(1)
+            .create();
+        let usdm = interface(&fake, &server, 5000);
+
+        let transaction = usdm.limit_buy("BTCUSDT", 1.0, 99.0, None).unwrap();
+        assert_eq!(transaction.order_id, 1917641);
+        post.assert();
+        check.assert();
+        usdm.shutdown();
+    }
+
+    #[test]
+    fn order_not_found_after_its_recv_window_is_sent_again() {
+        let fake = FakeBinance::start(&["BTCUSDT"], 100.0).unwrap();
+        let mut server = Server::new();
+        let failed = server
+            .mock("POST", "/fapi/v1/order")
+            .match_query(Matcher::Any)
+            .with_status(502)
+            .expect(1)
+            .create();
+        let placed = server
+            .mock("POST", "/fapi/v1/order")
+            .match_query(Matcher::Any)
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .with_body(TRANSACTION)
+            .expect(1)
+            .create();
+        let check = check_order(&mut server)
+            .with_status(400)
+            .with_body(r#"{"code":-2013,"msg":"Order does not exist."}"#)
+            .expect_at_least(2)
+            .create();
+        let usdm = interface(&fake, &server, 200);
+
+        let start = Instant::now();
+        let transaction = usdm.limit_buy("BTCUSDT", 1.0, 99.0, None).unwrap();
+        assert_eq!(transaction.order_id, 1917641);
+        // the first attempt could still land until its recvWindow expired
+        assert!(start.elapsed() >= Duration::from_millis(1200));
+        failed.assert();
+        placed.assert();
+        check.assert();
+        usdm.shutdown();
+    }
+}
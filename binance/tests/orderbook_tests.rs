@@ -0,0 +1,135 @@
+use binance::commons::config::Config;
+use binance::rest::api::Binance;
+use binance::rest::futures;
+use binance::rest::model::DepthOrderBookEvent;
+use binance::rest::spot::account::OrderSide;
+use binance::rest::spot::market::Market;
+use binance::websocket::orderbook::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_cmp::*;
+    use mockito::{Matcher, Server};
+    use std::fs;
+
+    fn load_events(path: &str) -> Vec<DepthOrderBookEvent> {
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn load_futures_snapshot(path: &str) -> OrderBookSnapshot {
+        let book: futures::model::OrderBook =
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        book.into()
+    }
+
+    #[test]
+    fn spot_order_book_sync() {
+        let mut server = Server::new();
+        let mock_get_depth = server
+            .mock("GET", "/api/v3/depth")
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .match_query(Matcher::Regex("limit=1000&symbol=BNBBTC".into()))
+            .with_body_from_file("tests/mocks/orderbook/spot_depth.json")
+            .expect(1)
+            .create();
+
+        let config = Config::default().set_rest_api_endpoint(server.url());
+        let market: Market = Binance::new_with_config(None, None, &config);
+        let mut book = LocalOrderBook::spot("BNBBTC", market, 1000);
+
+        let mut events = load_events("tests/mocks/orderbook/spot_depth_updates.json").into_iter();
+
+        // first event is already part of the snapshot
+        let status = book.handle_event(events.next().unwrap()).unwrap();
+        assert_eq!(status, OrderBookStatus::Syncing);
+        assert_eq!(book.last_update_id(), 160);
+
+        let status = book.handle_event(events.next().unwrap()).unwrap();
+        assert_eq!(status, OrderBookStatus::Synced);
+        let status = book.handle_event(events.next().unwrap()).unwrap();
+        assert_eq!(status, OrderBookStatus::Synced);
+        mock_get_depth.assert();
+
+        assert_eq!(book.last_update_id(), 167);
+        assert_eq!(book.best_bid(), Some((0.0025, 1.0)));
+        assert_eq!(book.best_ask(), Some((0.00255, 3.0)));
+        assert!(approx_eq!(
+            f64,
+            book.depth_at_price(BookSide::Bid, 0.0024),
+            12.0,
+            ulps = 2
+        ));
+        assert!(approx_eq!(
+            f64,
+            book.depth_at_price(BookSide::Ask, 0.0026),
+            0.0,
+            ulps = 2
+        ));
+        assert_eq!(
+            book.levels(BookSide::Ask, 2),
+            vec![(0.00255, 3.0), (0.0027, 6.0)]
+        );
+
+        let vwap = book.vwap_to_size(OrderSide::Buy, 5.0).unwrap();
+        assert!(approx_eq!(f64, vwap, 0.00261, epsilon = 1e-12));
+        let vwap = book.vwap_to_size(OrderSide::Sell, 2.0).unwrap();
+        assert!(approx_eq!(f64, vwap, 0.00245, epsilon = 1e-12));
+        assert_eq!(book.vwap_to_size(OrderSide::Buy, 100.0), None);
+    }
+
+    #[test]
+    fn usdm_order_book_gap_resync() {
+        let mut snapshots = vec![
+            load_futures_snapshot("tests/mocks/orderbook/futures_depth_resync.json"),
+            load_futures_snapshot("tests/mocks/orderbook/futures_depth.json"),
+        ];
+        let mut book = LocalOrderBook::new("BTCUSDT", OrderBookMarket::Usdm, move |_: &str| {
+            Ok(snapshots.pop().unwrap())
+        });
+
+        let mut events =
+            load_events("tests/mocks/orderbook/futures_depth_updates.json").into_iter();
+
+        let status = book.handle_event(events.next().unwrap()).unwrap();
+        assert_eq!(status, OrderBookStatus::Syncing);
+        let status = book.handle_event(events.next().unwrap()).unwrap();
+        assert_eq!(status, OrderBookStatus::Synced);
+        let status = book.handle_event(events.next().unwrap()).unwrap();
+        assert_eq!(status, OrderBookStatus::Synced);
+
+        assert_eq!(book.last_update_id(), 1005);
+        assert_eq!(book.best_bid(), Some((30000.05, 0.5)));
+        assert_eq!(book.best_ask(), Some((30000.10, 1.2)));
+        assert_eq!(book.depth_at_price(BookSide::Bid, 29999.90), 0.0);
+        assert!(approx_eq!(
+            f64,
+            book.mid_price().unwrap(),
+            30000.075,
+            epsilon = 1e-9
+        ));
+
+        // pu does not match the last applied update id
+        let status = book.handle_event(events.next().unwrap()).unwrap();
+        assert_eq!(status, OrderBookStatus::Resync);
+        assert!(book.is_synced());
+        assert_eq!(book.resyncs(), 1);
+        assert_eq!(book.last_update_id(), 1012);
+        assert_eq!(book.best_bid(), Some((30001.00, 0.6)));
+        assert_eq!(book.best_ask(), Some((30001.10, 2.0)));
+    }
+
+    #[test]
+    fn order_book_ignores_other_symbols() {
+        let mut book = LocalOrderBook::new("ETHUSDT", OrderBookMarket::Usdm, |_: &str| {
+            Ok(load_futures_snapshot(
+                "tests/mocks/orderbook/futures_depth.json",
+            ))
+        });
+        let events = load_events("tests/mocks/orderbook/futures_depth_updates.json");
+        for event in events {
+            assert_eq!(book.handle_event(event).unwrap(), OrderBookStatus::Syncing);
+        }
+        assert_eq!(book.best_bid(), None);
+    }
+}
@@ -0,0 +1,77 @@
+use binance::rest::client::Client;
+use binance::testing::fake_binance::FakeBinance;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server};
+    use serde_json::{json, Value};
+
+    // filled orders with these ids
+    fn orders(order_ids: impl Iterator<Item = u64>) -> String {
+        let orders: Vec<Value> = order_ids
+            .map(|order_id| {
+                json!({"avgPrice": "100", "clientOrderId": format!("order-{order_id}"),
+                    "cumQuote": "100", "executedQty": "1", "orderId": order_id, "origQty": "1",
+                    "origType": "LIMIT", "price": "100", "reduceOnly": false, "side": "BUY",
+                    "positionSide": "BOTH", "status": "FILLED", "stopPrice": "0",
+                    "closePosition": false, "symbol": "BTCUSDT", "time": 1, "timeInForce": "GTC",
+                    "type": "LIMIT", "updateTime": 1, "workingType": "CONTRACT_PRICE",
+                    "priceProtect": false})
+            })
+            .collect();
+        Value::from(orders).to_string()
+    }
+
+    #[test]
+    fn pages_through_the_orders_placed_since_the_last_run() {
+        let fake = FakeBinance::start(&["BTCUSDT"], 100.0).unwrap();
+        let mut server = Server::new();
+        let json_mock = |server: &mut Server, path: &str, query: Matcher, body: String| {
+            server
+                .mock("GET", path)
+                .match_query(query)
+                .with_header("content-type", "application/json;charset=UTF-8")
+                .with_body(body)
+                .create()
+        };
+        // the oldest 1000 orders since the last run, then the ones after them
+        let first = json_mock(
+            &mut server,
+            "/fapi/v1/allOrders",
+            Matcher::Regex("startTime=".into()),
+            orders(1..1001),
+        );
+        let second = json_mock(
+            &mut server,
+            "/fapi/v1/allOrders",
+            Matcher::UrlEncoded("orderId".into(), "1001".into()),
+            orders(1001..1003),
+        );
+        json_mock(
+            &mut server,
+            "/fapi/v1/openOrders",
+            Matcher::Any,
+            "[]".into(),
+        );
+        json_mock(
+            &mut server,
+            "/fapi/v2/positionRisk",
+            Matcher::Any,
+            "[]".into(),
+        );
+        let mut usdm = fake.usdm_interface("BTCUSDT").unwrap();
+        usdm.api = Client::new(
+            Some("api-key".into()),
+            Some("api-secret".into()),
+            server.url(),
+        );
+
+        let report = usdm.reconcile().unwrap();
+        assert_eq!(report.orders_checked, 1002);
+        assert!(usdm.is_filled_orders_ws(1002));
+        first.assert();
+        second.assert();
+        usdm.shutdown();
+    }
+}
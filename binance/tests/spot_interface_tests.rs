@@ -0,0 +1,113 @@
+use binance::commons::config::Config;
+use binance::commons::errors::BinanceError;
+use binance::interfaces::spot::SpotInterface;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server};
+    use std::fs;
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use tungstenite::handshake::server::{Request, Response};
+    use tungstenite::Message;
+
+    // sends a book ticker on the market streams and keeps the user stream open
+    #[allow(clippy::result_large_err)]
+    fn stand_in_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("ws://{}", listener.local_addr().unwrap());
+        let book_ticker = fs::read_to_string("tests/mocks/websocket/spot/book_ticker.json")
+            .unwrap()
+            .replace('\n', "");
+        thread::spawn(move || loop {
+            let (stream, _) = listener.accept().unwrap();
+            let book_ticker = book_ticker.clone();
+            thread::spawn(move || {
+                let mut path = String::new();
+                let mut socket = tungstenite::accept_hdr(stream, |req: &Request, res: Response| {
+                    path = req.uri().to_string();
+                    Ok(res)
+                })
+                .unwrap();
+                if path.contains("@bookTicker") {
+                    socket.send(Message::text(book_ticker)).unwrap();
+                }
+                while socket.read().is_ok() {}
+            });
+        });
+        endpoint
+    }
+
+    #[test]
+    fn shutdown_closes_listen_key() {
+        let mut server = Server::new();
+        server
+            .mock("POST", "/api/v3/userDataStream")
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .with_body(r#"{"listenKey":"key1"}"#)
+            .create();
+        server
+            .mock("GET", "/api/v3/account")
+            .match_query(Matcher::Any)
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .with_body_from_file("tests/mocks/account/get_account.json")
+            .create();
+        let close = server
+            .mock("DELETE", "/api/v3/userDataStream")
+            .match_body("listenKey=key1")
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .with_body("{}")
+            .expect(1)
+            .create();
+        let config = Config::default()
+            .set_rest_api_endpoint(server.url())
+            .set_ws_endpoint(stand_in_server());
+
+        let spot = SpotInterface::new(
+            "BTCUSDT".into(),
+            Some("api-key".into()),
+            Some("api-secret".into()),
+            &config,
+        )
+        .unwrap();
+        assert_eq!(spot.get_best_bid_ws(), Some(25.3519));
+        assert!(!spot.get_balances_ws().is_empty());
+
+        let start = Instant::now();
+        spot.shutdown();
+        close.assert();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // the data received so far stays readable
+        assert!(spot.get_book_ticker_ws().is_some());
+    }
+
+    #[test]
+    fn times_out_without_book_ticker() {
+        // nothing listens on the port
+        let endpoint = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        let config = Config::default()
+            .set_rest_api_endpoint(format!("http://{endpoint}"))
+            .set_ws_endpoint(format!("ws://{endpoint}"));
+
+        let start = Instant::now();
+        let result = SpotInterface::with_ready_timeout(
+            "BTCUSDT".into(),
+            None,
+            None,
+            &config,
+            Duration::from_millis(300),
+        );
+        match result {
+            Err(BinanceError::Timeout(msg)) => assert!(msg.contains("book ticker"), "{msg}"),
+            Err(e) => panic!("unexpected error {e}"),
+            Ok(_) => panic!("expected a timeout"),
+        }
+        // the spawned threads are stopped before returning
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,219 @@
+use binance::commons::config::Config;
+use binance::commons::errors::BinanceError;
+use binance::testing::wait_until;
+use binance::websocket::channel::OverflowPolicy;
+use binance::websocket::futures::usdm::{WsInterface, DEFAULT_READY_TIMEOUT};
+use binance::websocket::futures::usdm_events::{UsdmEvent, UsdmEventKind};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Server;
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use tungstenite::handshake::server::{Request, Response};
+    use tungstenite::Message;
+
+    fn mark_price(stream: &str) -> String {
+        let symbol = stream.split('@').next().unwrap().to_uppercase();
+        json!({
+            "stream": stream,
+            "data": {
+                "e": "markPriceUpdate",
+                "E": 1562305380000u64,
+                "s": symbol,
+                "p": "11794.15000000",
+                "i": "11784.62659091",
+                "P": "11784.25641265",
+                "r": "0.00038167",
+                "T": 1562306400000u64
+            }
+        })
+        .to_string()
+    }
+
+    // serves the user stream on /ws/ and the market streams on /stream, sending a mark price
+    // for every subscribed symbol; the subscription requests are forwarded to the receiver
+    #[allow(clippy::result_large_err)]
+    fn stand_in_server() -> (String, mpsc::Receiver<Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("ws://{}", listener.local_addr().unwrap());
+        let order = fs::read_to_string("tests/mocks/websocket/futures/order_trade_update.json")
+            .unwrap()
+            .replace("BTCUSDT", "ETHUSDT");
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || loop {
+            let (stream, _) = listener.accept().unwrap();
+            let (order, tx) = (order.clone(), tx.clone());
+            thread::spawn(move || {
+                let mut path = String::new();
+                let mut socket = tungstenite::accept_hdr(stream, |req: &Request, res: Response| {
+                    path = req.uri().to_string();
+                    Ok(res)
+                })
+                .unwrap();
+                if path.starts_with("/ws/") {
+                    socket.send(Message::text(order)).unwrap();
+                } else {
+                    let streams = path.trim_start_matches("/stream?streams=").split('/');
+                    for stream in streams.filter(|stream| stream.contains("@markPrice")) {
+                        socket.send(Message::text(mark_price(stream))).unwrap();
+                    }
+                }
+                while let Ok(msg) = socket.read() {
+                    let Ok(request) = serde_json::from_str::<Value>(msg.to_text().unwrap()) else {
+                        continue;
+                    };
+                    let ack = json!({"result": null, "id": request["id"]});
+                    socket.send(Message::text(ack.to_string())).unwrap();
+                    if request["method"] == "SUBSCRIBE" {
+                        for stream in request["params"].as_array().unwrap() {
+                            let stream = stream.as_str().unwrap();
+                            if stream.contains("@markPrice") {
+                                socket.send(Message::text(mark_price(stream))).unwrap();
+                            }
+                        }
+                    }
+                    tx.send(request).unwrap();
+                }
+            });
+        });
+        (endpoint, rx)
+    }
+
+    #[test]
+    fn routes_symbols_of_shared_streams() {
+        let mut server = Server::new();
+        let start = server
+            .mock("POST", "/fapi/v1/listenKey")
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .with_body(r#"{"listenKey":"key1"}"#)
+            .expect(1)
+            .create();
+        let (endpoint, requests) = stand_in_server();
+        let config = Config::default()
+            .set_futures_rest_api_endpoint(server.url())
+            .set_futures_ws_endpoint(endpoint);
+
+        let ws = WsInterface::new_multi(
+            vec!["BTCUSDT".to_string(), "ethusdt".to_string()],
+            None,
+            None,
+            &config,
+            DEFAULT_READY_TIMEOUT,
+        )
+        .unwrap();
+        assert_eq!(ws.get_symbols(), vec!["BTCUSDT", "ETHUSDT"]);
+        assert_eq!(ws.get_mark_price().unwrap().pair, "BTCUSDT");
+        let eth = ws.get_symbol_data("ETHUSDT").unwrap();
+        assert_eq!(eth.get_mark_price_event().unwrap().pair, "ETHUSDT");
+
+        // the order of the second symbol is kept in its own data
+        wait_until(|| !eth.get_open_orders().is_empty());
+        start.assert();
+        assert!(ws.get_open_orders().is_empty());
+        assert!(ws.is_open_order(3252769662));
+        assert_eq!(ws.get_order(3252769662).unwrap().symbol, "ETHUSDT");
+
+        let mark_prices = ws.subscribe(&[UsdmEventKind::MarkPrice], 16, OverflowPolicy::Block);
+        ws.add_symbol("solusdt").unwrap();
+        let request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(request["method"], "SUBSCRIBE");
+        assert_eq!(
+            request["params"],
+            json!([
+                "solusdt@aggTrade",
+                "solusdt@markPrice@1s",
+                "solusdt@forceOrder",
+                "solusdt@bookTicker"
+            ])
+        );
+        // observers are notified once the data is updated
+        match mark_prices
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap()
+            .unwrap()
+        {
+            UsdmEvent::MarkPrice(event) => assert_eq!(event.pair, "SOLUSDT"),
+            event => panic!("unexpected event {event:?}"),
+        }
+        let sol = ws.get_symbol_data("SOLUSDT").unwrap();
+        assert!(sol.get_mark_price_event().is_some());
+
+        ws.remove_symbol("ETHUSDT").unwrap();
+        let request = requests.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(request["method"], "UNSUBSCRIBE");
+        assert_eq!(request["params"][0], "ethusdt@aggTrade");
+        assert_eq!(ws.get_symbols(), vec!["BTCUSDT", "SOLUSDT"]);
+        assert!(ws.get_order(3252769662).is_none());
+        assert!(ws.remove_symbol("ETHUSDT").is_err());
+    }
+
+    #[test]
+    fn shutdown_closes_listen_key() {
+        let mut server = Server::new();
+        server
+            .mock("POST", "/fapi/v1/listenKey")
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .with_body(r#"{"listenKey":"key1"}"#)
+            .create();
+        let close = server
+            .mock("DELETE", "/fapi/v1/listenKey")
+            .match_body("listenKey=key1")
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .with_body("{}")
+            .expect(1)
+            .create();
+        let (endpoint, _requests) = stand_in_server();
+        let config = Config::default()
+            .set_futures_rest_api_endpoint(server.url())
+            .set_futures_ws_endpoint(endpoint);
+
+        let ws = WsInterface::new_multi(
+            vec!["BTCUSDT".to_string(), "ETHUSDT".to_string()],
+            None,
+            None,
+            &config,
+            DEFAULT_READY_TIMEOUT,
+        )
+        .unwrap();
+        // the user stream is connected once its first event arrives
+        wait_until(|| ws.is_open_order(3252769662));
+        ws.shutdown();
+        close.assert();
+        // the data received so far stays readable
+        assert!(ws.get_mark_price().is_some());
+    }
+
+    #[test]
+    fn times_out_without_mark_price() {
+        // nothing listens on the port
+        let endpoint = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        let config = Config::default()
+            .set_futures_rest_api_endpoint(format!("http://{endpoint}"))
+            .set_futures_ws_endpoint(format!("ws://{endpoint}"));
+
+        let start = Instant::now();
+        let result = WsInterface::new_multi(
+            vec!["BTCUSDT".to_string()],
+            None,
+            None,
+            &config,
+            Duration::from_millis(300),
+        );
+        match result {
+            Err(BinanceError::Timeout(msg)) => assert!(msg.contains("BTCUSDT"), "{msg}"),
+            Err(e) => panic!("unexpected error {e}"),
+            Ok(_) => panic!("expected a timeout"),
+        }
+        // the spawned threads are stopped before returning
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,201 @@
+use binance::commons::config::Config;
+use binance::commons::errors::BinanceError;
+use binance::commons::util::{build_request, hmac_signature};
+use binance::rest::futures::account::PositionSide;
+use binance::rest::spot::account::TimeInForce;
+use binance::websocket::api::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+    use tungstenite::Message;
+
+    const API_KEY: &str = "api_key";
+    const SECRET_KEY: &str = "secret_key";
+
+    fn query(params: &Value) -> String {
+        let parameters: BTreeMap<String, String> = params
+            .as_object()
+            .unwrap()
+            .iter()
+            .filter(|(key, _)| key.as_str() != "signature")
+            .map(|(key, value)| match value {
+                Value::String(value) => (key.clone(), value.clone()),
+                value => (key.clone(), value.to_string()),
+            })
+            .collect();
+        build_request(parameters)
+    }
+
+    // answers order.place with `fixture`, order.cancel with an error, time late and with an
+    // unknown id first, and ignores order.status
+    fn stand_in_server(fixture: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("ws://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            let mut logged_on = false;
+            while let Ok(message) = socket.read() {
+                let Message::Text(text) = message else {
+                    continue;
+                };
+                let request: Value = serde_json::from_str(&text).unwrap();
+                let params = &request["params"];
+                let id = request["id"].clone();
+
+                if logged_on {
+                    assert!(params.get("apiKey").is_none());
+                    assert!(params.get("signature").is_none());
+                } else if params.get("signature").is_some() {
+                    assert_eq!(params["apiKey"], API_KEY);
+                    assert!(params["timestamp"].is_u64());
+                    let expected = match request["method"].as_str().unwrap() {
+                        "session.logon" => "ed25519_signature".to_string(),
+                        _ => hmac_signature(SECRET_KEY, &query(params)),
+                    };
+                    assert_eq!(params["signature"], expected);
+                }
+
+                let response = match request["method"].as_str().unwrap() {
+                    "session.logon" => {
+                        logged_on = true;
+                        json!({"id": id, "status": 200, "result": {"apiKey": API_KEY}})
+                    }
+                    "order.place" => {
+                        let result: Value =
+                            serde_json::from_str(&fs::read_to_string(fixture).unwrap()).unwrap();
+                        json!({"id": id, "status": 200, "result": result})
+                    }
+                    "order.cancel" => json!({
+                        "id": id,
+                        "status": 400,
+                        "error": {"code": -2011, "msg": "Unknown order sent."}
+                    }),
+                    "time" => {
+                        thread::sleep(Duration::from_millis(300));
+                        let unknown = json!({"id": "unknown", "status": 200, "result": {}});
+                        socket.send(Message::text(unknown.to_string())).unwrap();
+                        json!({"id": id, "status": 200, "result": {"serverTime": 1}})
+                    }
+                    _ => continue,
+                };
+                socket.send(Message::text(response.to_string())).unwrap();
+            }
+        });
+        endpoint
+    }
+
+    #[test]
+    fn futures_order_entry() {
+        let config = Config::default().set_futures_ws_api_endpoint(stand_in_server(
+            "tests/mocks/websocket_api/futures_limit_buy.json",
+        ));
+        let mut ws_api =
+            FuturesWsApi::connect(Some(API_KEY.into()), Some(SECRET_KEY.into()), &config).unwrap();
+
+        let transaction = ws_api
+            .limit_buy("BTCUSDT", 0.1, 43187.0, TimeInForce::GTC, None)
+            .unwrap();
+        assert_eq!(transaction.order_id, 325078477);
+        assert_eq!(transaction.symbol, "BTCUSDT");
+        assert_eq!(transaction.type_name, "LIMIT");
+
+        match ws_api.cancel_order("BTCUSDT", 1) {
+            Err(BinanceError::BinanceError { response }) => assert_eq!(response.code, -2011),
+            _ => panic!("expected an API error"),
+        }
+    }
+
+    #[test]
+    fn responses_are_correlated_by_id() {
+        let config = Config::default().set_futures_ws_api_endpoint(stand_in_server(
+            "tests/mocks/websocket_api/futures_limit_buy.json",
+        ));
+        let mut ws_api =
+            FuturesWsApi::connect(Some(API_KEY.into()), Some(SECRET_KEY.into()), &config).unwrap();
+
+        let mut ids = vec![];
+        for _ in 0..3 {
+            ids.push(
+                ws_api
+                    .client
+                    .send_signed("order.place", BTreeMap::new())
+                    .unwrap(),
+            );
+        }
+        // wait in reverse order, earlier responses are kept until requested
+        for id in ids.iter().rev() {
+            let result: Value = ws_api.client.wait(id).unwrap();
+            assert_eq!(result["orderId"], 325078477);
+        }
+    }
+
+    #[test]
+    fn request_timeout() {
+        let config = Config::default().set_futures_ws_api_endpoint(stand_in_server(
+            "tests/mocks/websocket_api/futures_limit_buy.json",
+        ));
+        let mut ws_api =
+            FuturesWsApi::connect(Some(API_KEY.into()), Some(SECRET_KEY.into()), &config).unwrap();
+        ws_api.client.set_timeout(Duration::from_millis(100));
+
+        // the stand-in server never answers order.status
+        assert!(ws_api.get_order("BTCUSDT", 1).is_err());
+
+        // the connection is still usable after a timeout
+        let transaction = ws_api
+            .market_buy("BTCUSDT", 0.1, PositionSide::Long)
+            .unwrap();
+        assert_eq!(transaction.order_id, 325078477);
+    }
+
+    #[test]
+    fn abandoned_responses_are_dropped() {
+        let config = Config::default().set_futures_ws_api_endpoint(stand_in_server(
+            "tests/mocks/websocket_api/futures_limit_buy.json",
+        ));
+        let mut ws_api =
+            FuturesWsApi::connect(Some(API_KEY.into()), Some(SECRET_KEY.into()), &config).unwrap();
+        ws_api.client.set_timeout(Duration::from_millis(100));
+
+        let id = ws_api.client.send("time", BTreeMap::new()).unwrap();
+        assert!(ws_api.client.wait::<Value>(&id).is_err());
+        // the late response and the one of an unknown id are read, not kept
+        ws_api.client.set_timeout(Duration::from_secs(5));
+        let transaction = ws_api
+            .market_buy("BTCUSDT", 0.1, PositionSide::Long)
+            .unwrap();
+        assert_eq!(transaction.order_id, 325078477);
+        assert_eq!(ws_api.client.buffered_responses(), 0);
+        match ws_api.client.wait::<Value>(&id) {
+            Err(BinanceError::WebSocket(_)) => {}
+            result => panic!("unexpected result {result:?}"),
+        }
+    }
+
+    #[test]
+    fn spot_session_logon() {
+        let config = Config::default()
+            .set_ws_api_endpoint(stand_in_server("tests/mocks/account/limit_buy.json"));
+        let mut ws_api =
+            SpotWsApi::connect(Some(API_KEY.into()), Some(SECRET_KEY.into()), &config).unwrap();
+        ws_api
+            .client
+            .set_signer(|_payload: &str| "ed25519_signature".to_string());
+
+        let result = ws_api.client.logon().unwrap();
+        assert_eq!(result["apiKey"], API_KEY);
+        assert!(ws_api.client.is_logged_on());
+
+        let transaction = ws_api.limit_buy("LTCBTC", 1.0, 0.1).unwrap();
+        assert_eq!(transaction.symbol, "LTCBTC");
+        assert_eq!(transaction.order_id, 1);
+    }
+}
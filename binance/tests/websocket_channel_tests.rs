@@ -0,0 +1,105 @@
+use binance::commons::config::Config;
+use binance::commons::errors::{BinanceError, WebSocketError};
+use binance::websocket::channel::*;
+use binance::websocket::futures::FuturesWebsocketEvent;
+use binance::websocket::spot::WebsocketEvent;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use tungstenite::Message;
+
+    const AGG_TRADE: &str = r#"{"stream":"btcusdt@aggTrade","data":{"e":"aggTrade","E":123456789,"s":"BTCUSDT","a":5933014,"p":"0.001","q":"100","f":100,"l":105,"T":123456785,"m":true}}"#;
+
+    // sends `trades` aggTrades then stays quiet until the client closes
+    fn stand_in_server(trades: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("ws://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            for _ in 0..trades {
+                socket.send(Message::text(AGG_TRADE)).unwrap();
+            }
+            while socket.read().is_ok() {}
+        });
+        endpoint
+    }
+
+    #[test]
+    fn iterate_and_shutdown() {
+        let config = Config::default().set_futures_ws_endpoint(stand_in_server(3));
+        let channel = ChannelConfig::default().set_read_timeout(Duration::from_millis(50));
+        let (mut receiver, handle) =
+            futures_channel(vec!["btcusdt@aggTrade".into()], &config, channel).unwrap();
+
+        let trades: Vec<_> = receiver
+            .by_ref()
+            .take(3)
+            .map(|event| match event {
+                FuturesWebsocketEvent::AggrTrades(trade) => trade.symbol,
+                event => panic!("unexpected event {event:?}"),
+            })
+            .collect();
+        assert_eq!(trades, ["BTCUSDT"; 3]);
+
+        // the stream is quiet, the read timeout lets the thread notice the shutdown
+        let started = Instant::now();
+        handle.shutdown().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn overflow_error_stops_the_socket() {
+        let config = Config::default().set_ws_endpoint(stand_in_server(5));
+        let channel = ChannelConfig::default()
+            .set_capacity(2)
+            .set_overflow(OverflowPolicy::Error)
+            .set_read_timeout(Duration::from_millis(50));
+        let (receiver, handle) =
+            spot_channel(vec!["btcusdt@aggTrade".into()], &config, channel).unwrap();
+
+        let started = Instant::now();
+        while handle.is_running() {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(receiver.len(), 2);
+        for _ in 0..2 {
+            assert!(matches!(
+                receiver.recv().unwrap(),
+                WebsocketEvent::AggrTrades(_)
+            ));
+        }
+        assert!(matches!(
+            receiver.recv(),
+            Err(BinanceError::WebSocket(WebSocketError::ChannelFull(2)))
+        ));
+        handle.shutdown().unwrap();
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_latest_events() {
+        let config = Config::default().set_futures_ws_endpoint(stand_in_server(5));
+        let channel = ChannelConfig::default()
+            .set_capacity(2)
+            .set_overflow(OverflowPolicy::DropOldest)
+            .set_read_timeout(Duration::from_millis(50));
+        let (receiver, handle) =
+            futures_channel(vec!["btcusdt@aggTrade".into()], &config, channel).unwrap();
+
+        let started = Instant::now();
+        while receiver.dropped() < 3 {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(receiver.len(), 2);
+        assert!(receiver.recv_timeout(Duration::ZERO).unwrap().is_some());
+        handle.shutdown().unwrap();
+        // buffered events survive the shutdown, then the iterator ends
+        assert_eq!(receiver.count(), 1);
+    }
+}
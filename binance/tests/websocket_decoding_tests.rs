@@ -0,0 +1,202 @@
+use binance::commons::errors::BinanceError;
+use binance::websocket::futures::{FuturesWebSockets, FuturesWebsocketEvent};
+use binance::websocket::spot::{WebSockets, WebsocketEvent};
+use binance::websocket::streams::{self, TickerWindow};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn spot_event(fixture: &str) -> WebsocketEvent {
+        let msg = fs::read_to_string(format!("tests/mocks/websocket/spot/{fixture}.json")).unwrap();
+        let mut events = vec![];
+        let mut web_socket = WebSockets::new(|event: WebsocketEvent| {
+            events.push(event);
+            Ok(())
+        });
+        web_socket.test_handle_msg(&msg).unwrap();
+        drop(web_socket);
+        assert_eq!(events.len(), 1, "{fixture}");
+        events.pop().unwrap()
+    }
+
+    fn futures_event(fixture: &str) -> FuturesWebsocketEvent {
+        let msg =
+            fs::read_to_string(format!("tests/mocks/websocket/futures/{fixture}.json")).unwrap();
+        let mut events = vec![];
+        let mut web_socket = FuturesWebSockets::new(|event: FuturesWebsocketEvent| {
+            events.push(event);
+            Ok(())
+        });
+        web_socket.test_handle_msg(&msg).unwrap();
+        drop(web_socket);
+        assert_eq!(events.len(), 1, "{fixture}");
+        events.pop().unwrap()
+    }
+
+    #[test]
+    fn spot_events() {
+        use WebsocketEvent::*;
+
+        assert!(matches!(spot_event("agg_trade"), AggrTrades(_)));
+        assert!(matches!(spot_event("trade"), Trade(_)));
+        assert!(matches!(spot_event("day_ticker"), DayTicker(_)));
+        assert!(matches!(spot_event("day_ticker_all"), DayTickerAll(v) if v.len() == 2));
+        assert!(matches!(spot_event("kline"), Kline(_)));
+        assert!(matches!(spot_event("depth_update"), DepthOrderBook(_)));
+        assert!(matches!(spot_event("partial_depth"), OrderBook(_)));
+        assert!(matches!(spot_event("book_ticker"), BookTicker(_)));
+        assert!(matches!(spot_event("execution_report"), OrderTrade(_)));
+        assert!(matches!(spot_event("account_position"), AccountPosition(_)));
+        assert!(matches!(spot_event("balance_update"), BalanceDelta(_)));
+        assert!(matches!(spot_event("mini_ticker"), MiniTicker(_)));
+        assert!(matches!(spot_event("mini_ticker_all"), MiniTickerAll(v) if v.len() == 2));
+        assert!(matches!(spot_event("rolling_ticker"), RollingTicker(_)));
+        assert!(matches!(
+            spot_event("rolling_ticker_all"),
+            RollingTickerAll(v) if v[0].event_type == "4hTicker"
+        ));
+        match spot_event("avg_price") {
+            AvgPrice(event) => {
+                assert_eq!(event.interval, "5m");
+                assert_eq!(event.avg_price, "25776.86000000");
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+    }
+
+    #[test]
+    fn futures_events() {
+        use FuturesWebsocketEvent::*;
+
+        assert!(matches!(futures_event("agg_trade"), AggrTrades(_)));
+        assert!(matches!(futures_event("mini_ticker"), MiniTicker(_)));
+        assert!(matches!(futures_event("mini_ticker_all"), MiniTickerAll(_)));
+        assert!(matches!(futures_event("book_ticker"), BookTicker(_)));
+        assert!(matches!(futures_event("index_price"), IndexPrice(_)));
+        assert!(matches!(futures_event("kline"), Kline(_)));
+        assert!(matches!(
+            futures_event("continuous_kline"),
+            ContinuousKline(_)
+        ));
+        assert!(matches!(futures_event("index_kline"), IndexKline(_)));
+        assert!(matches!(futures_event("liquidation"), Liquidation(_)));
+        assert!(matches!(futures_event("account_update"), AccountUpdate(_)));
+        assert!(matches!(futures_event("order_trade_update"), OrderTrade(_)));
+        assert!(matches!(
+            futures_event("listen_key_expired"),
+            UserDataStreamExpiredEvent(_)
+        ));
+
+        // mark prices used to be decoded as index prices
+        match futures_event("mark_price") {
+            MarkPrice(event) => {
+                assert_eq!(event.symbol, "BTCUSDT");
+                assert_eq!(event.mark_price, "11794.15000000");
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+        assert!(matches!(futures_event("mark_price_all"), MarkPriceAll(v) if v.len() == 2));
+
+        match futures_event("composite_index") {
+            CompositeIndex(event) => {
+                assert_eq!(event.composition.len(), 2);
+                assert_eq!(event.composition[0].base_asset, "BAL");
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+        match futures_event("contract_info") {
+            ContractInfo(event) => {
+                assert_eq!(event.contract_status, "TRADING");
+                assert_eq!(event.brackets[1].max_leverage, 20);
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+        assert!(matches!(futures_event("asset_index"), AssetIndex(_)));
+        assert!(matches!(futures_event("asset_index_all"), AssetIndexAll(v) if v.len() == 2));
+
+        match futures_event("account_config_update") {
+            AccountConfigUpdate(event) => {
+                assert_eq!(event.leverage.unwrap().symbol, "BTCUSDT");
+                assert!(event.asset_mode.is_none());
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+        assert!(matches!(
+            futures_event("account_config_update_multi_assets"),
+            AccountConfigUpdate(event) if event.asset_mode.as_ref().is_some_and(|mode| mode.multi_assets_margin)
+        ));
+        match futures_event("margin_call") {
+            MarginCall(event) => {
+                assert_eq!(event.positions[0].maintenance_margin_required, "1.614445")
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+        assert!(matches!(
+            futures_event("strategy_update"),
+            StrategyUpdate(event) if event.strategy.op_code == Some(8007)
+        ));
+        assert!(matches!(
+            futures_event("grid_update"),
+            GridUpdate(event) if event.grid.strategy_status == "WORKING"
+        ));
+        assert!(matches!(
+            futures_event("conditional_order_trigger_reject"),
+            ConditionalOrderTriggerReject(event) if event.reject.order_id == 155618472834
+        ));
+
+        match futures_event("depth_update") {
+            DepthOrderBook(event) => assert_eq!(event.previous_final_update_id, Some(156)),
+            event => panic!("unexpected event {event:?}"),
+        }
+    }
+
+    #[test]
+    fn stream_names() {
+        assert_eq!(streams::mini_ticker("BNBBTC"), "bnbbtc@miniTicker");
+        assert_eq!(streams::all_mini_tickers(), "!miniTicker@arr");
+        assert_eq!(streams::avg_price("BNBBTC"), "bnbbtc@avgPrice");
+        assert_eq!(
+            streams::rolling_ticker("BNBBTC", TickerWindow::FourHours),
+            "bnbbtc@ticker_4h"
+        );
+        assert_eq!(
+            streams::all_rolling_tickers(TickerWindow::OneDay),
+            "!ticker_1d@arr"
+        );
+        assert_eq!(
+            streams::composite_index("DEFIUSDT"),
+            "defiusdt@compositeIndex"
+        );
+        assert_eq!(streams::contract_info(), "!contractInfo");
+        assert_eq!(streams::asset_index("ADAUSD"), "adausd@assetIndex");
+        assert_eq!(streams::all_asset_indexes(), "!assetIndex@arr");
+    }
+
+    #[test]
+    fn undecodable_events_return_the_decode_error() {
+        let mut events = 0;
+        let mut web_socket = FuturesWebSockets::new(|_event: FuturesWebsocketEvent| {
+            events += 1;
+            Ok(())
+        });
+        // an unknown event type is skipped, a known one with missing fields is schema drift
+        web_socket
+            .test_handle_msg(r#"{"e":"somethingNew","E":1}"#)
+            .unwrap();
+        assert!(matches!(
+            web_socket.test_handle_msg(r#"{"e":"aggTrade","E":1}"#),
+            Err(BinanceError::Json(_))
+        ));
+        assert!(web_socket.test_handle_msg("not json").is_err());
+        drop(web_socket);
+        assert_eq!(events, 0);
+
+        let mut web_socket = WebSockets::new(|_event: WebsocketEvent| Ok(()));
+        assert!(matches!(
+            web_socket.test_handle_msg(r#"{"e":"aggTrade","E":1}"#),
+            Err(BinanceError::Json(_))
+        ));
+    }
+}
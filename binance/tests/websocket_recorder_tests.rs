@@ -0,0 +1,134 @@
+use binance::commons::config::Config;
+use binance::websocket::futures::{FuturesMarket, FuturesWebSockets, FuturesWebsocketEvent};
+use binance::websocket::recorder::{RecordedFrame, Recorder, ReplaySpeed, Replayer};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use tungstenite::Message;
+
+    fn recording(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("binance-{name}-{}.rec", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn fixture(name: &str) -> String {
+        fs::read_to_string(format!("tests/mocks/websocket/futures/{name}.json")).unwrap()
+    }
+
+    #[test]
+    fn replays_through_the_decoder() {
+        let path = recording("replay");
+        let recorder = Recorder::create(&path).unwrap();
+        recorder.record_at(1_000, &fixture("agg_trade")).unwrap();
+        recorder.record_at(2_000, &fixture("mark_price")).unwrap();
+        recorder
+            .record_at(3_000, &fixture("order_trade_update"))
+            .unwrap();
+        drop(recorder);
+
+        let mut events = vec![];
+        let mut web_socket = FuturesWebSockets::new(|event: FuturesWebsocketEvent| {
+            events.push(event);
+            Ok(())
+        });
+        let replayed = Replayer::open(&path)
+            .unwrap()
+            .run(|frame| web_socket.test_handle_msg(frame))
+            .unwrap();
+        drop(web_socket);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(replayed, 3);
+        assert!(matches!(events[0], FuturesWebsocketEvent::AggrTrades(_)));
+        assert!(matches!(events[1], FuturesWebsocketEvent::MarkPrice(_)));
+        assert!(matches!(events[2], FuturesWebsocketEvent::OrderTrade(_)));
+    }
+
+    #[test]
+    fn replays_in_real_time() {
+        let path = recording("real-time");
+        let recorder = Recorder::create(&path).unwrap();
+        recorder.record_at(1_000_000, r#"{"e":"a"}"#).unwrap();
+        recorder.record_at(1_050_000, "{\n\"e\":\"b\"\n}").unwrap();
+        drop(recorder);
+
+        let frames: Vec<RecordedFrame> = Replayer::open(&path)
+            .unwrap()
+            .collect::<binance::commons::errors::Result<_>>()
+            .unwrap();
+        assert_eq!(frames[1].received_at, 1_050_000);
+        assert_eq!(frames[1].frame, r#"{ "e":"b" }"#);
+
+        let start = Instant::now();
+        let replayed = Replayer::open(&path)
+            .unwrap()
+            .set_speed(ReplaySpeed::RealTime)
+            .run(|_| Ok(()))
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(replayed, 2);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn records_received_frames() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("ws://{}", listener.local_addr().unwrap());
+        let msg = fixture("agg_trade");
+        let sent = msg.clone();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            socket.send(Message::text(sent)).unwrap();
+            while socket.read().is_ok() {}
+        });
+
+        let path = recording("live");
+        let config = Config::default().set_futures_ws_endpoint(endpoint);
+        let running = AtomicBool::new(true);
+        let mut web_socket = FuturesWebSockets::new(|_event: FuturesWebsocketEvent| {
+            running.store(false, Ordering::Relaxed);
+            Ok(())
+        });
+        web_socket.set_recorder(Recorder::create(&path).unwrap());
+        web_socket
+            .connect_with_config(FuturesMarket::USDM, "btcusdt@aggTrade", &config)
+            .unwrap();
+        web_socket
+            .event_loop_for(&running, Duration::from_secs(5))
+            .unwrap();
+        web_socket.disconnect().unwrap();
+        drop(web_socket);
+
+        let frames: Vec<RecordedFrame> = Replayer::open(&path)
+            .unwrap()
+            .collect::<binance::commons::errors::Result<_>>()
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].frame, msg.replace('\n', " "));
+    }
+
+    #[test]
+    fn flushes_after_an_interval() {
+        let path = recording("flush");
+        let recorder = Recorder::create(&path).unwrap();
+        recorder.record_at(1_000, &fixture("agg_trade")).unwrap();
+        thread::sleep(Duration::from_millis(1100));
+        recorder.record_at(2_000, &fixture("mark_price")).unwrap();
+
+        // both frames are on disk while the recorder is still open
+        let frames = Replayer::open(&path).unwrap().count();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(frames, 2);
+        drop(recorder);
+    }
+}
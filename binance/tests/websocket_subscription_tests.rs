@@ -0,0 +1,179 @@
+use binance::commons::config::Config;
+use binance::websocket::futures::{FuturesMarket, FuturesWebSockets, FuturesWebsocketEvent};
+use binance::websocket::subscription::StreamMethod;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::cell::RefCell;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+    use tungstenite::Message;
+
+    const AGG_TRADE: &str = r#"{"stream":"btcusdt@aggTrade","data":{"e":"aggTrade","E":123456789,"s":"BTCUSDT","a":5933014,"p":"0.001","q":"100","f":100,"l":105,"T":123456785,"m":true}}"#;
+
+    // acks every request, sending an aggTrade before each ack
+    fn stand_in_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("ws://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            let mut streams = vec!["btcusdt@aggTrade".to_string()];
+            while let Ok(message) = socket.read() {
+                let Message::Text(text) = message else {
+                    continue;
+                };
+                let request: Value = serde_json::from_str(&text).unwrap();
+                let params: Vec<String> = request["params"]
+                    .as_array()
+                    .map(|params| {
+                        params
+                            .iter()
+                            .map(|p| p.as_str().unwrap().to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let result = match request["method"].as_str().unwrap() {
+                    "SUBSCRIBE" => {
+                        streams.extend(params);
+                        Value::Null
+                    }
+                    "UNSUBSCRIBE" => {
+                        streams.retain(|s| !params.contains(s));
+                        Value::Null
+                    }
+                    _ => json!(streams),
+                };
+                socket.send(Message::text(AGG_TRADE)).unwrap();
+                let ack = json!({"result": result, "id": request["id"]});
+                socket.send(Message::text(ack.to_string())).unwrap();
+            }
+        });
+        endpoint
+    }
+
+    #[test]
+    fn live_subscriptions() {
+        let config = Config::default().set_futures_ws_endpoint(stand_in_server());
+        let running = AtomicBool::new(true);
+        let trades = RefCell::new(0);
+        let responses = RefCell::new(Vec::new());
+
+        let mut web_socket = FuturesWebSockets::new(|event: FuturesWebsocketEvent| {
+            match event {
+                FuturesWebsocketEvent::AggrTrades(_) => *trades.borrow_mut() += 1,
+                FuturesWebsocketEvent::StreamResponse(response) => {
+                    responses.borrow_mut().push(response);
+                    running.store(false, Ordering::Relaxed);
+                }
+                _ => (),
+            }
+            Ok(())
+        });
+        web_socket
+            .connect_multiple_streams(
+                FuturesMarket::USDM,
+                &["btcusdt@aggTrade".to_string()],
+                &config,
+            )
+            .unwrap();
+
+        let id = web_socket
+            .subscribe(&["ethusdt@aggTrade".to_string()])
+            .unwrap();
+        let response = web_socket
+            .wait_for_response(id, Duration::from_secs(5))
+            .unwrap();
+        assert!(response.is_ok());
+        assert_eq!(response.method, StreamMethod::Subscribe);
+        assert_eq!(
+            web_socket.subscribed_streams(),
+            [
+                "btcusdt@aggTrade".to_string(),
+                "ethusdt@aggTrade".to_string()
+            ]
+        );
+
+        let id = web_socket.list_subscriptions().unwrap();
+        let response = web_socket
+            .wait_for_response(id, Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(response.result.unwrap().len(), 2);
+
+        // unsubscribe from another thread while the event loop runs
+        let controller = web_socket.stream_controller();
+        let id = thread::spawn(move || {
+            controller
+                .unsubscribe(&["btcusdt@aggTrade".to_string()])
+                .unwrap()
+        })
+        .join()
+        .unwrap();
+        // the queued request is sent before the next read
+        web_socket.event_loop(&running).unwrap_err();
+        let _ = web_socket.disconnect();
+        drop(web_socket);
+
+        let responses = responses.into_inner();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].id, id);
+        assert_eq!(responses[0].method, StreamMethod::Unsubscribe);
+        assert_eq!(*trades.borrow(), 3);
+    }
+
+    #[test]
+    fn controller_requests_are_sent_on_a_quiet_stream() {
+        // acks requests and sends nothing else
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("ws://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            while let Ok(message) = socket.read() {
+                if let Message::Text(text) = message {
+                    let request: Value = serde_json::from_str(&text).unwrap();
+                    let ack = json!({"result": null, "id": request["id"]});
+                    socket.send(Message::text(ack.to_string())).unwrap();
+                }
+            }
+        });
+
+        let (controllers, controller) = mpsc::channel();
+        let (responses, response) = mpsc::channel();
+        thread::spawn(move || {
+            let config = Config::default().set_futures_ws_endpoint(endpoint);
+            let running = AtomicBool::new(true);
+            let mut web_socket = FuturesWebSockets::new(|event: FuturesWebsocketEvent| {
+                if let FuturesWebsocketEvent::StreamResponse(response) = event {
+                    responses.send(response).unwrap();
+                    running.store(false, Ordering::Relaxed);
+                }
+                Ok(())
+            });
+            web_socket
+                .connect_multiple_streams(
+                    FuturesMarket::USDM,
+                    &["btcusdt@aggTrade".to_string()],
+                    &config,
+                )
+                .unwrap();
+            controllers.send(web_socket.stream_controller()).unwrap();
+            let _ = web_socket.event_loop(&running);
+        });
+
+        let controller = controller.recv_timeout(Duration::from_secs(5)).unwrap();
+        // the event loop is blocked in a read when the request is queued
+        thread::sleep(Duration::from_millis(300));
+        let id = controller
+            .subscribe(&["ethusdt@aggTrade".to_string()])
+            .unwrap();
+        let response = response.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(response.id, id);
+        assert_eq!(response.method, StreamMethod::Subscribe);
+    }
+}
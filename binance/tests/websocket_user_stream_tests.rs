@@ -0,0 +1,127 @@
+use binance::commons::config::Config;
+use binance::websocket::futures::user_stream_runner::UserStreamRunner;
+use binance::websocket::futures::FuturesWebsocketEvent;
+use binance::websocket::managed::{ConnectionEvent, ReconnectPolicy};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server};
+    use std::cell::RefCell;
+    use std::fs;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+    use tungstenite::handshake::server::{Request, Response};
+    use tungstenite::Message;
+
+    // sends one fixture on each connection and waits for the client to close it
+    #[allow(clippy::result_large_err)]
+    fn stand_in_server(fixtures: &[&str]) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("ws://{}", listener.local_addr().unwrap());
+        let messages: Vec<String> = fixtures
+            .iter()
+            .map(|name| {
+                fs::read_to_string(format!("tests/mocks/websocket/futures/{name}.json")).unwrap()
+            })
+            .collect();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for msg in messages {
+                let (stream, _) = listener.accept().unwrap();
+                let tx = tx.clone();
+                let mut socket = tungstenite::accept_hdr(stream, |req: &Request, res: Response| {
+                    tx.send(req.uri().to_string()).unwrap();
+                    Ok(res)
+                })
+                .unwrap();
+                socket.send(Message::text(msg)).unwrap();
+                while socket.read().is_ok() {}
+            }
+        });
+        (endpoint, rx)
+    }
+
+    #[test]
+    fn restarts_expired_listen_key_and_closes_it() {
+        let mut server = Server::new();
+        let start = server
+            .mock("POST", "/fapi/v1/listenKey")
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .with_body(r#"{"listenKey":"key1"}"#)
+            .expect(2)
+            .create();
+        let close = server
+            .mock("DELETE", "/fapi/v1/listenKey")
+            .match_body(Matcher::Exact("listenKey=key1".into()))
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .with_body("{}")
+            .expect(1)
+            .create();
+        let (endpoint, requests) =
+            stand_in_server(&["listen_key_expired", "account_config_update"]);
+        let config = Config::default()
+            .set_futures_rest_api_endpoint(server.url())
+            .set_futures_ws_endpoint(endpoint);
+
+        let running = AtomicBool::new(true);
+        let events = RefCell::new(Vec::new());
+        let connection_events = RefCell::new(Vec::new());
+        let mut runner = UserStreamRunner::new(None, None, &config, |event| {
+            if let FuturesWebsocketEvent::AccountConfigUpdate(_) = event {
+                running.store(false, Ordering::Relaxed);
+            }
+            events.borrow_mut().push(event);
+            Ok(())
+        })
+        .set_connection_handler(|event| connection_events.borrow_mut().push(event));
+        runner.run(&running).unwrap();
+        drop(runner);
+
+        start.assert();
+        close.assert();
+        assert_eq!(requests.recv().unwrap(), "/ws/key1");
+        assert_eq!(requests.recv().unwrap(), "/ws/key1");
+        let events = events.into_inner();
+        assert!(matches!(
+            events[0],
+            FuturesWebsocketEvent::UserDataStreamExpiredEvent(_)
+        ));
+        match &events[1] {
+            FuturesWebsocketEvent::AccountConfigUpdate(event) => {
+                assert_eq!(event.leverage.as_ref().unwrap().leverage, 25)
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+        let connection_events = connection_events.into_inner();
+        assert!(
+            connection_events.contains(&ConnectionEvent::Disconnected("Listen key expired".into()))
+        );
+        assert_eq!(connection_events.last(), Some(&ConnectionEvent::Closed));
+    }
+
+    #[test]
+    fn gives_up_when_the_listen_key_cannot_be_started() {
+        let mut server = Server::new();
+        let start = server
+            .mock("POST", "/fapi/v1/listenKey")
+            .with_status(401)
+            .with_header("content-type", "application/json;charset=UTF-8")
+            .with_body(r#"{"code":-2015,"msg":"Invalid API-key, IP, or permissions for action."}"#)
+            .expect(2)
+            .create();
+        let config = Config::default().set_futures_rest_api_endpoint(server.url());
+
+        let running = AtomicBool::new(true);
+        let mut runner = UserStreamRunner::new(None, None, &config, |_| Ok(())).set_policy(
+            ReconnectPolicy::default()
+                .set_backoff(Duration::from_millis(10), Duration::from_millis(10))
+                .set_max_attempts(1),
+        );
+        assert!(runner.run(&running).is_err());
+        start.assert();
+    }
+}
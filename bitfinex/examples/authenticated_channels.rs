@@ -49,13 +49,6 @@ impl EventHandler for WebSocketHandler {
                 "Position update ({}) - Symbol {}, amount {}, price {}",
                 channel, position.symbol, position.amount, position.base_price
             );
-        } else if let DataEvent::PositionsSnapshotEvent(channel, _type, positions) = event {
-            for p in &positions {
-                println!(
-                    "Position snapshot ({}) - Symbol {}, amount {}, price {}",
-                    channel, p.symbol, p.amount, p.base_price
-                );
-            }
         } else if let DataEvent::TradesUpdateEvent(channel, _type, trade) = event {
             println!(
                 "Trade update ({}) - Symbol {}, amount {}, price {}",
@@ -28,6 +28,9 @@ pub enum BitfinexError {
     #[error("JSON parsing error: {0}")]
     JsonError(#[from] serde_json::Error),
 
+    #[error("Timeout: {0}")]
+    Timeout(String),
+
     #[error("Unknown Error: {0}")]
     Unknown(String),
 }
@@ -51,6 +54,9 @@ pub enum WebSocketError {
 
     #[error("Auth error: {0}")]
     AuthError(String),
+
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
 }
 
 impl From<tungstenite::Error> for WebSocketError {
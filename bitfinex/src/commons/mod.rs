@@ -3,3 +3,4 @@ pub mod currency;
 pub mod errors;
 pub mod pairs;
 pub mod precision;
+pub(crate) mod workers;
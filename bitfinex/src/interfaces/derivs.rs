@@ -1,5 +1,6 @@
 use crate::commons::currency::USTF0;
 use crate::commons::errors::*;
+use crate::commons::workers::{sleep, Workers};
 use crate::rest::account::{
     AvailableBalance, AvailableBalanceParams, FeeSummary, Position, TransferWallet,
     TransferWalletParams, Wallet,
@@ -16,13 +17,15 @@ use crate::rest::orders::{
 };
 use crate::rest::ticker::TradingPair;
 use crate::rest::trades::TradingPair as TradesTradingPair;
-use crate::websocket::derivs_ws::DerivsWs;
+use crate::websocket::derivs_ws::{DerivsWs, DEFAULT_READY_TIMEOUT};
 use crate::websocket::model::{BalanceInfo, Wallet as WalletWs};
 use log::error;
 use std::collections::VecDeque;
 use std::sync::{Arc, RwLock};
 use std::thread;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(10);
 
 #[derive(Clone)]
 pub struct Derivs {
@@ -31,35 +34,64 @@ pub struct Derivs {
     pub ws: DerivsWs,
     data: DerivsData,
     client_id: i64,
+    workers: Workers,
 }
 
 impl Derivs {
-    pub fn new(symbol: String, api_key: String, api_secret: String) -> Derivs {
+    /// Bitfinex derivs interface, waits for each of its data sources up to `DEFAULT_READY_TIMEOUT`
+    pub fn new(symbol: String, api_key: String, api_secret: String) -> Result<Derivs> {
+        Derivs::new_with_timeout(symbol, api_key, api_secret, DEFAULT_READY_TIMEOUT)
+    }
+
+    /// Bitfinex derivs interface,
+    /// returns an error if the websocket or the candles are not ready within `ready_timeout`
+    pub fn new_with_timeout(
+        symbol: String,
+        api_key: String,
+        api_secret: String,
+        ready_timeout: Duration,
+    ) -> Result<Derivs> {
         let api = Bitfinex::new(Some(api_key.to_owned()), Some(api_secret.to_owned()));
         let start = SystemTime::now();
         let since_the_epoch = start
             .duration_since(UNIX_EPOCH)
             .expect("Time went backwards");
-        let derivs_ws = DerivsWs::new(symbol.to_owned(), api_key, api_secret);
+        let derivs_ws =
+            DerivsWs::new_with_timeout(symbol.to_owned(), api_key, api_secret, ready_timeout)?;
         let derivs = Derivs {
             symbol,
             api,
             ws: derivs_ws,
             data: DerivsData::default(),
             client_id: since_the_epoch.as_millis() as i64,
+            workers: Workers::default(),
         };
         update_derivs_data(derivs.to_owned());
-        derivs.wait_for_data();
-        derivs
+        if let Err(e) = derivs.wait_for_data(ready_timeout) {
+            derivs.shutdown();
+            return Err(e);
+        }
+        Ok(derivs)
     }
 
-    fn wait_for_data(&self) {
-        loop {
-            if !self.get_last_day_candles_data().is_empty() {
-                break;
+    fn wait_for_data(&self, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        while self.get_last_day_candles_data().is_empty() {
+            if Instant::now() >= deadline {
+                return Err(BitfinexError::Timeout(format!(
+                    "No candles of {} after {timeout:?}",
+                    self.symbol
+                )));
             }
-            thread::yield_now();
+            thread::sleep(READY_POLL_INTERVAL);
         }
+        Ok(())
+    }
+
+    /// Stops the candles polling thread and the websocket, blocks until done
+    pub fn shutdown(&self) {
+        self.workers.shutdown();
+        self.ws.shutdown();
     }
 
     pub fn get_last_day_candles_data(&self) -> Vec<Candle> {
@@ -343,7 +375,8 @@ impl DerivsData {
 
 #[allow(unused_mut)]
 fn update_derivs_data(mut derivs: Derivs) {
-    thread::spawn(move || loop {
+    let workers = derivs.workers.clone();
+    workers.spawn(move |running| loop {
         match derivs.get_candles_1m_last_day() {
             Ok(candles) => {
                 derivs.data.set_last_day_candles(candles);
@@ -352,6 +385,8 @@ fn update_derivs_data(mut derivs: Derivs) {
                 error!("{err:?}");
             }
         }
-        thread::sleep(Duration::from_millis(1000));
+        if !sleep(running, Duration::from_millis(1000)) {
+            break;
+        }
     });
 }
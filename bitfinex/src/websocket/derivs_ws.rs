@@ -1,5 +1,6 @@
 use super::derivs_ws_data::DerivsWsData;
-use crate::commons::errors::WebSocketError;
+use crate::commons::errors::*;
+use crate::commons::workers::{sleep, Workers};
 use crate::rest::account::Position;
 use crate::rest::candles::Candle;
 use crate::rest::orders::OrderData;
@@ -10,43 +11,98 @@ use crate::websocket::model::{BalanceInfo, Wallet};
 use crate::websocket::websockets::{EventHandler, WebSockets};
 use log::{debug, error, warn};
 use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(10);
+pub const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+// None until the auth response arrives, then the error message of a rejected auth
+type AuthStatus = Arc<RwLock<Option<std::result::Result<(), String>>>>;
 
 #[derive(Clone)]
 pub struct DerivsWs {
     ws_data: DerivsWsData,
+    auth: AuthStatus,
+    workers: Workers,
 }
 
 impl DerivsWs {
     /// Bitfinex derivs interface,
-    pub fn new(symbol: String, api_key: String, api_secret: String) -> DerivsWs {
-        let ws_data = DerivsWsData::default();
+    /// waits for a ticker and the auth response up to `DEFAULT_READY_TIMEOUT`
+    pub fn new(symbol: String, api_key: String, api_secret: String) -> Result<DerivsWs> {
+        DerivsWs::new_with_timeout(symbol, api_key, api_secret, DEFAULT_READY_TIMEOUT)
+    }
+
+    /// Bitfinex derivs interface,
+    /// returns an `Unauthorized` error if the credentials are rejected and a `Timeout` error
+    /// if the ticker or the auth response do not arrive within `ready_timeout`
+    pub fn new_with_timeout(
+        symbol: String,
+        api_key: String,
+        api_secret: String,
+        ready_timeout: Duration,
+    ) -> Result<DerivsWs> {
         let ws_int = DerivsWs {
-            ws_data: ws_data.clone(),
+            ws_data: DerivsWsData::default(),
+            auth: Arc::new(RwLock::new(None)),
+            workers: Workers::default(),
         };
         ws_int.run(symbol, api_key, api_secret);
-        ws_int.wait_for_data();
-        fill_price_snaps(ws_data);
-        ws_int
+        if let Err(e) = ws_int.wait_for_data(ready_timeout) {
+            ws_int.shutdown();
+            return Err(e);
+        }
+        fill_price_snaps(ws_int.clone());
+        Ok(ws_int)
     }
 
-    fn wait_for_data(&self) {
-        while self.ws_data.get_trading_pair().is_none() {
-            debug!("Waiting for data");
-            thread::yield_now();
+    fn wait_for_data(&self, timeout: Duration) -> Result<()> {
+        debug!("Waiting for data");
+        let deadline = Instant::now() + timeout;
+        loop {
+            let auth = self.auth.read().unwrap().clone();
+            if let Some(Err(msg)) = auth {
+                return Err(BitfinexError::Unauthorized(msg));
+            }
+            let has_ticker = self.ws_data.get_trading_pair().is_some();
+            if has_ticker && auth.is_some() {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(BitfinexError::Timeout(if has_ticker {
+                    format!("No auth response after {timeout:?}")
+                } else {
+                    format!("No ticker after {timeout:?}")
+                }));
+            }
+            thread::sleep(READY_POLL_INTERVAL);
         }
     }
 
+    /// Stops the websocket and its threads, blocks until done.
+    /// The data received so far stays readable
+    pub fn shutdown(&self) {
+        self.workers.shutdown();
+    }
+
     pub fn run(&self, symbol: String, api_key: String, api_secret: String) {
         let ws_data = self.ws_data.clone();
-        thread::spawn(move || loop {
+        let auth = self.auth.clone();
+        self.workers.spawn(move |running| loop {
             let mut web_socket: WebSockets = WebSockets::default();
 
             web_socket.add_event_handler(WebSocketHandler {
                 ws_data: ws_data.clone(),
+                auth: auth.clone(),
             });
             if web_socket.connect().is_ok() {
+                if let Err(err) = web_socket.set_read_timeout(Some(READ_TIMEOUT)) {
+                    warn!("Unable to set the read timeout: {err}");
+                }
                 web_socket.subscribe_ticker(symbol.to_owned()).unwrap();
                 web_socket.subscribe_trades(symbol.to_owned()).unwrap();
                 web_socket
@@ -56,10 +112,13 @@ impl DerivsWs {
                     .auth(api_key.to_owned(), api_secret.to_owned(), false, &[])
                     .unwrap();
 
-                if let Err(err) = web_socket.event_loop() {
+                if let Err(err) = web_socket.event_loop_while(running) {
                     error!("Websocket error: {err}");
                 }
             }
+            if !sleep(running, RECONNECT_DELAY) {
+                break;
+            }
         });
     }
 
@@ -112,23 +171,27 @@ impl DerivsWs {
     }
 }
 
-fn fill_price_snaps(ws_data: DerivsWsData) {
-    thread::spawn(move || loop {
+fn fill_price_snaps(ws_int: DerivsWs) {
+    let ws_data = ws_int.ws_data.clone();
+    ws_int.workers.spawn(move |running| loop {
         match ws_data.get_trading_pair() {
             Some(trading_pair) => {
                 ws_data.add_price_snap(trading_pair.clone());
                 debug!("Added price snap {trading_pair:?}");
-                thread::sleep(Duration::from_millis(5000));
             }
             None => {
                 warn!("Unable to add price snap")
             }
         }
+        if !sleep(running, Duration::from_millis(5000)) {
+            break;
+        }
     });
 }
 
 struct WebSocketHandler {
     ws_data: DerivsWsData,
+    auth: AuthStatus,
 }
 
 impl EventHandler for WebSocketHandler {
@@ -141,7 +204,16 @@ impl EventHandler for WebSocketHandler {
         }
     }
 
-    fn on_auth(&mut self, _event: NotificationEvent) {}
+    fn on_auth(&mut self, event: NotificationEvent) {
+        if let NotificationEvent::Auth(auth) = event {
+            debug!("Auth status: {}", auth.status);
+            *self.auth.write().unwrap() = Some(if auth.is_ok() {
+                Ok(())
+            } else {
+                Err(auth.msg.unwrap_or(auth.status))
+            });
+        }
+    }
 
     fn on_subscribed(&mut self, _event: NotificationEvent) {}
 
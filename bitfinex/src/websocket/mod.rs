@@ -2,4 +2,5 @@ pub mod derivs_ws;
 pub mod derivs_ws_data;
 pub mod events;
 pub mod model;
+pub mod recorder;
 pub mod websockets;
@@ -1,8 +1,13 @@
 use crate::commons::auth;
 use crate::commons::errors::WebSocketError;
 use crate::websocket::events::*;
+use crate::websocket::recorder::Recorder;
+use log::warn;
 use serde_json::{from_str, json};
+use std::io::ErrorKind;
 use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 use url::Url;
 
 use tungstenite::connect;
@@ -49,6 +54,7 @@ pub struct WebSockets {
     sender: Sender,
     rx: mpsc::Receiver<WsMessage>,
     event_handler: Option<Box<dyn EventHandler>>,
+    recorder: Option<Recorder>,
 }
 
 impl Default for WebSockets {
@@ -61,6 +67,7 @@ impl Default for WebSockets {
             sender,
             rx,
             event_handler: None,
+            recorder: None,
         }
     }
 }
@@ -72,6 +79,18 @@ impl WebSockets {
         Ok(())
     }
 
+    /// Lets `event_loop_while` notice `running` being unset without waiting for a message
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<(), WebSocketError> {
+        if let Some(ref mut socket) = self.socket {
+            match socket.0.get_mut() {
+                MaybeTlsStream::Plain(stream) => stream.set_read_timeout(timeout)?,
+                MaybeTlsStream::NativeTls(stream) => stream.get_ref().set_read_timeout(timeout)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
     pub fn add_event_handler<H>(&mut self, handler: H)
     where
         H: EventHandler + 'static,
@@ -79,6 +98,11 @@ impl WebSockets {
         self.event_handler = Some(Box::new(handler));
     }
 
+    /// Appends every text frame received to `recorder`
+    pub fn set_recorder(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
     pub fn auth<S>(
         &mut self,
         api_key: S,
@@ -169,7 +193,12 @@ impl WebSockets {
     }
 
     pub fn event_loop(&mut self) -> Result<(), WebSocketError> {
-        loop {
+        self.event_loop_while(&AtomicBool::new(true))
+    }
+
+    /// Runs the event loop until `running` is unset, then closes the connection
+    pub fn event_loop_while(&mut self, running: &AtomicBool) -> Result<(), WebSocketError> {
+        while running.load(Ordering::Relaxed) {
             if let Some(ref mut socket) = self.socket {
                 // Handle pending messages
                 while let Ok(msg) = self.rx.try_recv() {
@@ -191,34 +220,66 @@ impl WebSockets {
                     Ok(_) => {}
                 }
 
-                let message = socket.0.read()?;
-
-                if let Some(ref mut handler) = self.event_handler {
-                    match message {
-                        Message::Text(text) => {
-                            if text.contains(INFO) {
-                                handler.on_connect(from_str(&text)?);
-                            } else if text.contains(SUBSCRIBED) {
-                                handler.on_subscribed(from_str(&text)?);
-                            } else if text.contains(AUTH) {
-                                handler.on_auth(from_str(&text)?);
-                            } else {
-                                let event: DataEvent = from_str(&text)?;
-                                if !matches!(event, DataEvent::HeartbeatEvent(_, _)) {
-                                    handler.on_data_event(event);
-                                }
+                let message = match socket.0.read() {
+                    Ok(message) => message,
+                    Err(e) if is_read_timeout(&e) => continue,
+                    Err(e) => return Err(e.into()),
+                };
+
+                match message {
+                    Message::Text(text) => {
+                        if let Some(ref recorder) = self.recorder {
+                            if let Err(e) = recorder.record(&text) {
+                                warn!("Unable to record frame: {e}");
                             }
                         }
-                        Message::Close(e) => {
-                            return Err(WebSocketError::Disconnected(format!(
-                                "Connection closed: {e:?}"
-                            )))
-                        }
-                        _ => {}
+                        self.handle_msg(&text)?;
+                    }
+                    Message::Close(e) if self.event_handler.is_some() => {
+                        return Err(WebSocketError::Disconnected(format!(
+                            "Connection closed: {e:?}"
+                        )))
                     }
+                    _ => {}
+                }
+            }
+        }
+        if let Some(ref mut socket) = self.socket {
+            socket.0.close(None)?;
+        }
+        Ok(())
+    }
+
+    pub fn test_handle_msg(&mut self, text: &str) -> Result<(), WebSocketError> {
+        self.handle_msg(text)
+    }
+
+    fn handle_msg(&mut self, text: &str) -> Result<(), WebSocketError> {
+        if let Some(ref mut handler) = self.event_handler {
+            if text.contains(INFO) {
+                handler.on_connect(from_str(text)?);
+            } else if text.contains(SUBSCRIBED) {
+                handler.on_subscribed(from_str(text)?);
+            } else if text.contains(AUTH) {
+                handler.on_auth(from_str(text)?);
+            } else {
+                let event: DataEvent = from_str(text)?;
+                if !matches!(event, DataEvent::HeartbeatEvent(_, _)) {
+                    handler.on_data_event(event);
                 }
             }
         }
+        Ok(())
+    }
+}
+
+// a read that timed out can be retried, the partial frame is kept by tungstenite
+fn is_read_timeout(err: &tungstenite::Error) -> bool {
+    match err {
+        tungstenite::Error::Io(e) => {
+            matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+        }
+        _ => false,
     }
 }
 
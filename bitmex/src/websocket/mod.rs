@@ -9,12 +9,14 @@ use crate::utils::data::trade_bucketed::TradeBucketed;
 use crate::utils::data::user_margin::Margin;
 use crate::utils::enums::Subscriptions;
 use crate::utils::thread_pool::ThreadPool;
+use crate::websocket::recorder::{Recorder, Replayer};
 use actix_rt::System;
 use chrono::{DateTime, NaiveDateTime, Utc};
 use log::{debug, error};
 use rayon::prelude::*;
 use serde_json::{json, Map, Value};
 use std::collections::HashMap;
+use std::io;
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
 use std::thread::sleep;
@@ -23,6 +25,8 @@ use tungstenite::client::AutoStream;
 use tungstenite::{connect, Message, WebSocket};
 use url::Url;
 
+pub mod recorder;
+
 const URL_MAIN: &str = "wss://www.bitmex.com/realtime";
 const URL_TEST: &str = "wss://testnet.bitmex.com/realtime";
 const LEN_ORDERS: usize = 40;
@@ -33,7 +37,8 @@ const LEN_DEFAULT: usize = 100;
 const CONNECT_ERR_SLEEP_MS: u64 = 1000;
 const ERR_SYS_TIME: &str = "Time went backwards";
 
-type Socket = Arc<Mutex<WebSocket<AutoStream>>>;
+// `None` for replays
+type Socket = Arc<Mutex<Option<WebSocket<AutoStream>>>>;
 type Keys = Arc<RwLock<HashMap<String, Vec<String>>>>;
 type Data = Arc<RwLock<HashMap<String, Vec<Map<String, Value>>>>>;
 type InstrumentSnaps = Arc<RwLock<Vec<Instrument>>>;
@@ -54,6 +59,7 @@ pub struct BitmexWs {
     instrument_snaps: InstrumentSnaps,
     l2_data: L2Data,
     l2_ids: L2ids,
+    recorder: Option<Recorder>,
 }
 
 impl BitmexWs {
@@ -63,14 +69,34 @@ impl BitmexWs {
         thread_pool_size: usize,
         subscriptions: Vec<Subscriptions>,
         auth_data: AuthData,
+    ) -> BitmexWs {
+        Self::new_with_recorder(
+            testnet,
+            symbol,
+            thread_pool_size,
+            subscriptions,
+            auth_data,
+            None,
+        )
+        .await
+    }
+
+    /// Same as `new`, every text frame received is appended to `recorder`
+    pub async fn new_with_recorder(
+        testnet: bool,
+        symbol: String,
+        thread_pool_size: usize,
+        subscriptions: Vec<Subscriptions>,
+        auth_data: AuthData,
+        recorder: Option<Recorder>,
     ) -> BitmexWs {
         let mut ws = BitmexWs {
-            socket: Arc::new(Mutex::new(connect_ws(
+            socket: Arc::new(Mutex::new(Some(connect_ws(
                 if testnet { URL_TEST } else { URL_MAIN },
                 symbol.to_string(),
                 &auth_data,
                 &subscriptions,
-            ))),
+            )))),
             keys: Arc::new(RwLock::new(HashMap::new())),
             data: Arc::new(RwLock::new(HashMap::new())),
             l2_data: Arc::new(RwLock::new(Vec::new())),
@@ -81,6 +107,7 @@ impl BitmexWs {
             testnet,
             subscriptions,
             auth_data,
+            recorder,
         };
         ws.run().await;
         ws.wait_for_data();
@@ -107,6 +134,94 @@ impl BitmexWs {
         ws
     }
 
+    /// Replays a recording into an unconnected `BitmexWs`, partials are stored as received
+    /// and no ticker snaps are taken
+    pub fn from_recording(
+        symbol: String,
+        subscriptions: Vec<Subscriptions>,
+        replayer: Replayer,
+    ) -> io::Result<BitmexWs> {
+        let ws = BitmexWs {
+            socket: Arc::new(Mutex::new(None)),
+            keys: Arc::new(RwLock::new(HashMap::new())),
+            data: Arc::new(RwLock::new(HashMap::new())),
+            l2_data: Arc::new(RwLock::new(Vec::new())),
+            l2_ids: Arc::new(RwLock::new(Vec::new())),
+            instrument_snaps: Arc::new(RwLock::new(Vec::new())),
+            thread_pool_size: 1,
+            symbol,
+            testnet: false,
+            subscriptions,
+            auth_data: AuthData::None,
+            recorder: None,
+        };
+        replayer.run(|frame| ws.apply_msg(frame, false))?;
+        Ok(ws)
+    }
+
+    /// Applies a frame to the tables, frames that are not valid table messages are logged
+    /// and skipped. Partials of `order` and `tradeBin1m` are loaded from the API
+    /// when `load_partials` is set
+    fn apply_msg(&self, msg: &str, load_partials: bool) {
+        let map_msg: Map<String, Value> = match serde_json::from_str(msg) {
+            Ok(map_msg) => map_msg,
+            Err(_) => {
+                debug!("Unknown message: {msg}");
+                return;
+            }
+        };
+        if !map_msg.contains_key("data") {
+            log_control_msg(&map_msg);
+            return;
+        }
+        let table_msg = match TableMsg::parse(&map_msg) {
+            Some(table_msg) => table_msg,
+            None => {
+                error!("Skipping invalid table message: {msg}");
+                return;
+            }
+        };
+        let TableMsg {
+            table,
+            action,
+            keys,
+            data,
+        } = table_msg;
+        if action == "partial" {
+            if load_partials && (table == "order" || table == "tradeBin1m") {
+                System::new("env").block_on(handle_partial(
+                    self.symbol.to_string(),
+                    self.testnet,
+                    self.auth_data.to_owned(),
+                    table,
+                    keys,
+                    Arc::clone(&self.data),
+                    Arc::clone(&self.keys),
+                ));
+            } else {
+                store_partial(
+                    &table[..],
+                    keys,
+                    data,
+                    Arc::clone(&self.data),
+                    Arc::clone(&self.keys),
+                    Arc::clone(&self.l2_ids),
+                    Arc::clone(&self.l2_data),
+                );
+            }
+        } else if self.data.read().unwrap().contains_key(&table) {
+            handle_data_msg(
+                table,
+                action,
+                data,
+                Arc::clone(&self.data),
+                Arc::clone(&self.keys),
+                Arc::clone(&self.l2_ids),
+                Arc::clone(&self.l2_data),
+            );
+        }
+    }
+
     fn wait_for_data(&self) {
         while !self.is_data_available() {
             thread::yield_now();
@@ -121,87 +236,40 @@ impl BitmexWs {
     async fn run(&mut self) {
         let thread_pool = ThreadPool::new(self.thread_pool_size);
         let socket_clone = Arc::clone(&self.socket);
-        let data_clone = Arc::clone(&self.data);
-        let keys_clone = Arc::clone(&self.keys);
-        let l2_data_clone = Arc::clone(&self.l2_data);
-        let l2_ids_clone = Arc::clone(&self.l2_ids);
         let symbol = self.symbol.to_string();
         let auth_data = self.auth_data.to_owned();
         let testnet = self.testnet;
         let subscriptions = self.subscriptions.clone();
+        let recorder = self.recorder.clone();
+        let ws = self.clone();
 
         // data updates are managed by a thread pool asynchronously
         thread::spawn(move || loop {
             let mut socket = socket_clone.lock().unwrap();
-            match socket.read_message() {
-                Ok(msg) => {
-                    debug!("Received ws message: {msg:?}");
-                    let map_msg: Map<String, Value> =
-                        serde_json::from_str(&msg.to_string()[..]).unwrap_or_default();
-                    if let Some(data) = map_msg.get("data") {
-                        let keys_memory = Arc::clone(&keys_clone);
-                        let data_memory = Arc::clone(&data_clone);
-                        let l2_data_memory = Arc::clone(&l2_data_clone);
-                        let l2_ids_memory = Arc::clone(&l2_ids_clone);
-                        let table = map_msg.get("table").unwrap().as_str().unwrap().to_string();
-                        let action = map_msg.get("action").unwrap().as_str().unwrap().to_string();
-                        let symbol2 = symbol.to_string();
-                        let authdata2 = auth_data.to_owned();
-                        let data_rec = data.to_owned();
-                        thread_pool.execute(move || {
-                            if action == "partial" {
-                                let keys_rec = map_msg.get("keys").unwrap().to_owned();
-                                System::new("env").block_on(async move {
-                                    handle_partial(
-                                        symbol2,
-                                        testnet,
-                                        authdata2,
-                                        &table[..],
-                                        keys_rec,
-                                        data_rec,
-                                        data_memory,
-                                        keys_memory,
-                                        l2_ids_memory,
-                                        l2_data_memory,
-                                    )
-                                    .await;
-                                });
-                            } else if data_memory.read().unwrap().contains_key(&table)
-                                || (!l2_data_memory.read().unwrap().is_empty()
-                                    && table == "orderBookL2")
-                            {
-                                handle_data_msg(
-                                    table,
-                                    action,
-                                    data_rec,
-                                    data_memory,
-                                    keys_memory,
-                                    l2_ids_memory,
-                                    l2_data_memory,
-                                );
-                            }
-                        });
-                    } else if let (Some(_), Some(subscription)) =
-                        (map_msg.get("success"), map_msg.get("subscribe"))
-                    {
-                        debug!("Subscribed to: {}", subscription.as_str().unwrap());
-                    } else if let Some(info) = map_msg.get("info") {
-                        debug!("{}", info.as_str().unwrap());
-                    } else if let Some(error) = map_msg.get("error") {
-                        let status = map_msg.get("status").unwrap().as_i64().unwrap();
-                        error!("Code: {status}, Error: {error}");
-                    } else {
-                        debug!("Unknown message: {msg:?}");
+            let message = match socket.as_mut() {
+                Some(socket) => socket.read_message(),
+                None => break,
+            };
+            match message {
+                Ok(Message::Text(text)) => {
+                    debug!("Received ws message: {text}");
+                    if let Some(recorder) = &recorder {
+                        if let Err(e) = recorder.record(&text) {
+                            error!("Unable to record frame: {e}");
+                        }
                     }
+                    let ws = ws.clone();
+                    thread_pool.execute(move || ws.apply_msg(&text, true));
                 }
+                Ok(msg) => debug!("Unknown message: {msg:?}"),
                 Err(err) => {
                     error!("Error reading ws message: {err}");
-                    *socket = connect_ws(
+                    *socket = Some(connect_ws(
                         if testnet { URL_TEST } else { URL_MAIN },
                         symbol.to_string(),
                         &auth_data,
                         &subscriptions,
-                    );
+                    ));
                 }
             };
         });
@@ -447,28 +515,19 @@ pub fn update_ticker_snaps(instrument_snaps: InstrumentSnaps, instrument: Instru
     ticker_snaps.insert(0, instrument);
 }
 
-/// Handles BitMex websocket 'partial' message.
-#[allow(clippy::too_many_arguments)]
+/// Loads the snapshot of an 'order' or 'tradeBin1m' partial from the API.
 async fn handle_partial(
     symbol: String,
     testnet: bool,
     auth_data: AuthData,
-    table: &str,
-    keys: Value,
-    data: Value,
+    table: String,
+    keys: Vec<String>,
     data_memory: Data,
     keys_memory: Keys,
-    l2_ids: L2ids,
-    l2_data: L2Data,
 ) {
-    let data_rec: Vec<Map<String, Value>> = serde_json::from_value(data).unwrap();
-    let keys: Vec<String> = serde_json::from_value(keys).unwrap();
     let rest = BitmexRest::new(testnet, auth_data.to_owned()).await;
 
-    keys_memory
-        .write()
-        .unwrap()
-        .insert(String::from(table), keys);
+    keys_memory.write().unwrap().insert(table.to_string(), keys);
     if table == "order" {
         let mut filter: HashMap<&str, Value> = HashMap::with_capacity(1);
         filter.insert("open", json!(true));
@@ -487,18 +546,26 @@ async fn handle_partial(
             serde_json::from_value(response.unwrap()).unwrap();
         response_vec.reverse();
 
-        data_memory
-            .write()
-            .unwrap()
-            .insert(String::from(table), response_vec);
-    } else if table == "tradeBin1m" {
+        data_memory.write().unwrap().insert(table, response_vec);
+    } else {
         let response_vec =
             get_trade_bin_1m_api(symbol.to_owned(), testnet, auth_data.to_owned()).await;
-        data_memory
-            .write()
-            .unwrap()
-            .insert(String::from(table), response_vec);
-    } else if table == "orderBookL2" {
+        data_memory.write().unwrap().insert(table, response_vec);
+    }
+}
+
+/// Stores the snapshot of a 'partial' message as received.
+fn store_partial(
+    table: &str,
+    keys: Vec<String>,
+    data_rec: Vec<Map<String, Value>>,
+    data_memory: Data,
+    keys_memory: Keys,
+    l2_ids: L2ids,
+    l2_data: L2Data,
+) {
+    keys_memory.write().unwrap().insert(table.to_string(), keys);
+    if table == "orderBookL2" {
         let mut l2_ids_w = l2_ids.write().unwrap();
         let mut l2_data_w = l2_data.write().unwrap();
         let ids: Vec<u64> = data_rec
@@ -571,17 +638,58 @@ async fn get_trade_bin_1m_api(
     data
 }
 
+/// Table message, `keys` are only sent with partials
+struct TableMsg {
+    table: String,
+    action: String,
+    keys: Vec<String>,
+    data: Vec<Map<String, Value>>,
+}
+
+impl TableMsg {
+    fn parse(map_msg: &Map<String, Value>) -> Option<TableMsg> {
+        let action = map_msg.get("action")?.as_str()?.to_string();
+        let keys = match map_msg.get("keys") {
+            Some(keys) => serde_json::from_value(keys.to_owned()).ok()?,
+            None if action == "partial" => return None,
+            None => Vec::new(),
+        };
+        Some(TableMsg {
+            table: map_msg.get("table")?.as_str()?.to_string(),
+            action,
+            keys,
+            data: serde_json::from_value(map_msg.get("data")?.to_owned()).ok()?,
+        })
+    }
+}
+
+/// Logs the replies to commands and the messages that carry no table data
+fn log_control_msg(map_msg: &Map<String, Value>) {
+    if let (Some(_), Some(subscription)) = (map_msg.get("success"), map_msg.get("subscribe")) {
+        debug!("Subscribed to: {subscription}");
+    } else if let Some(info) = map_msg.get("info") {
+        debug!("{info}");
+    } else if let Some(error) = map_msg.get("error") {
+        let status = map_msg
+            .get("status")
+            .and_then(Value::as_i64)
+            .unwrap_or_default();
+        error!("Code: {status}, Error: {error}");
+    } else {
+        debug!("Unknown message: {map_msg:?}");
+    }
+}
+
 /// Handles BitMex websocket `insert`, `update`, `delete` message.
 fn handle_data_msg(
     table: String,
     action: String,
-    data_rec: Value,
+    mut data_rec: Vec<Map<String, Value>>,
     data_memory: Data,
     keys: Keys,
     l2_ids: L2ids,
     l2_data: L2Data,
 ) {
-    let mut data_rec: Vec<Map<String, Value>> = serde_json::from_value(data_rec).unwrap();
     let mut data_memory = data_memory.write().unwrap();
     let mut l2_data_memory = l2_data.write().unwrap();
 
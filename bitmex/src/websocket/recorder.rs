@@ -0,0 +1,221 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const FLUSH_FRAMES: usize = 100;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Appends websocket frames to a file as `<receive time in µs>\t<frame>` lines
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    writer: Arc<Mutex<Writer>>,
+}
+
+#[derive(Debug)]
+struct Writer {
+    file: BufWriter<File>,
+    pending: usize,
+    flushed_at: Instant,
+}
+
+impl Recorder {
+    /// Creates the recording, or appends to it if it exists
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Recorder> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Recorder {
+            writer: Arc::new(Mutex::new(Writer {
+                file: BufWriter::new(file),
+                pending: 0,
+                flushed_at: Instant::now(),
+            })),
+        })
+    }
+
+    /// Appends a frame received now
+    pub fn record(&self, frame: &str) -> io::Result<()> {
+        self.record_at(now_micros(), frame)
+    }
+
+    /// Appends a frame received at `received_at`, in microseconds since the epoch
+    pub fn record_at(&self, received_at: u64, frame: &str) -> io::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        // raw newlines can only be whitespace in a JSON frame
+        writeln!(writer.file, "{}\t{}", received_at, frame.replace('\n', " "))?;
+        writer.pending += 1;
+        if writer.pending >= FLUSH_FRAMES || writer.flushed_at.elapsed() >= FLUSH_INTERVAL {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Frames are otherwise flushed every `FLUSH_FRAMES` frames or `FLUSH_INTERVAL`
+    pub fn flush(&self) -> io::Result<()> {
+        self.writer.lock().unwrap().flush()
+    }
+}
+
+impl Writer {
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.pending = 0;
+        self.flushed_at = Instant::now();
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedFrame {
+    /// Receive time in microseconds since the epoch
+    pub received_at: u64,
+    pub frame: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ReplaySpeed {
+    /// Frames are fed with their recorded spacing
+    RealTime,
+    /// Frames are fed back to back
+    #[default]
+    AsFastAsPossible,
+}
+
+/// Feeds a recording back, e.g. to `BitmexWs::from_recording`
+pub struct Replayer {
+    lines: std::io::Lines<BufReader<File>>,
+    speed: ReplaySpeed,
+}
+
+impl Replayer {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Replayer> {
+        Ok(Replayer {
+            lines: BufReader::new(File::open(path)?).lines(),
+            speed: ReplaySpeed::default(),
+        })
+    }
+
+    pub fn set_speed(mut self, speed: ReplaySpeed) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Calls `handler` with every frame and returns the number of frames replayed
+    pub fn run<F>(self, mut handler: F) -> io::Result<usize>
+    where
+        F: FnMut(&str),
+    {
+        let speed = self.speed;
+        let mut previous: Option<u64> = None;
+        let mut count = 0;
+        for frame in self {
+            let frame = frame?;
+            if let (ReplaySpeed::RealTime, Some(previous)) = (speed, previous) {
+                thread::sleep(Duration::from_micros(
+                    frame.received_at.saturating_sub(previous),
+                ));
+            }
+            previous = Some(frame.received_at);
+            handler(&frame.frame);
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl Iterator for Replayer {
+    type Item = io::Result<RecordedFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = match self.lines.next()? {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(parse_line(&line))
+    }
+}
+
+fn parse_line(line: &str) -> io::Result<RecordedFrame> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid frame: {line}"));
+    let (received_at, frame) = line.split_once('\t').ok_or_else(invalid)?;
+    Ok(RecordedFrame {
+        received_at: received_at.parse().map_err(|_| invalid())?,
+        frame: frame.to_string(),
+    })
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_micros() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::enums::Subscriptions;
+    use crate::websocket::BitmexWs;
+
+    #[test]
+    fn test_replay() {
+        let path = std::env::temp_dir().join(format!("bitmex-{}.rec", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let recorder = Recorder::create(&path).unwrap();
+        recorder
+            .record_at(
+                1_000,
+                r#"{"table":"orderBookL2","action":"partial","keys":["symbol","id","side"],"data":[
+                {"symbol":"XBTUSD","id":1,"side":"Sell","size":10,"price":101.0},
+                {"symbol":"XBTUSD","id":2,"side":"Buy","size":20,"price":100.0}]}"#,
+            )
+            .unwrap();
+        recorder
+            .record_at(
+                2_000,
+                r#"{"table":"orderBookL2","action":"update","data":[{"symbol":"XBTUSD","id":2,"side":"Buy","size":50}]}"#,
+            )
+            .unwrap();
+        // invalid frames are skipped
+        recorder
+            .record_at(
+                2_500,
+                r#"{"table":"orderBookL2","data":[{"id":2,"size":0}]}"#,
+            )
+            .unwrap();
+        recorder.record_at(2_600, r#"{"info":"Welcome"}"#).unwrap();
+        recorder
+            .record_at(
+                3_000,
+                r#"{"table":"orderBookL2","action":"delete","data":[{"symbol":"XBTUSD","id":1,"side":"Sell"}]}"#,
+            )
+            .unwrap();
+        drop(recorder);
+
+        let ws = BitmexWs::from_recording(
+            "XBTUSD".into(),
+            vec![Subscriptions::OrderBookL2],
+            Replayer::open(&path).unwrap(),
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(ws.get_order_book_size(100.0), 50);
+        assert_eq!(ws.get_order_book_size(101.0), 0);
+    }
+
+    #[test]
+    fn test_periodic_flush() {
+        let path = std::env::temp_dir().join(format!("bitmex-flush-{}.rec", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let recorder = Recorder::create(&path).unwrap();
+        for i in 0..FLUSH_FRAMES as u64 {
+            recorder.record_at(i, r#"{"info":"Welcome"}"#).unwrap();
+        }
+        // written out without an explicit flush
+        let recorded = Replayer::open(&path).unwrap().count();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(recorded, FLUSH_FRAMES);
+    }
+}